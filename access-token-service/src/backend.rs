@@ -0,0 +1,97 @@
+use crate::{
+    postgres::PostgresAccessTokenService,
+    service::{AccessTokenService, KeycloakAccessTokenService},
+};
+use async_trait::async_trait;
+use drogue_cloud_database_common::{error::ServiceError, postgres};
+use drogue_cloud_service_api::{
+    auth::user::{UserDetails, UserInformation},
+    token::{AccessToken, AccessTokenCreated, AccessTokenCreationOptions},
+    webapp::{HttpResponse, ResponseError},
+};
+use drogue_cloud_service_common::keycloak::{self, KeycloakAdminClientConfig, KeycloakClient};
+
+/// The backend storing access tokens, selected at runtime.
+#[derive(Clone)]
+pub enum AccessTokenBackend<K: KeycloakClient> {
+    /// Store tokens as Keycloak user attributes.
+    Keycloak(KeycloakAccessTokenService<K>),
+    /// Store tokens in PostgreSQL.
+    Postgres(PostgresAccessTokenService),
+}
+
+impl<K: KeycloakClient> AccessTokenBackend<K> {
+    /// Use Keycloak if it is configured, PostgreSQL otherwise.
+    pub fn from_config(
+        keycloak: Option<KeycloakAdminClientConfig>,
+        pg: Option<postgres::Config>,
+    ) -> anyhow::Result<Self> {
+        Ok(match (keycloak, pg) {
+            (Some(keycloak), _) => Self::Keycloak(KeycloakAccessTokenService {
+                client: K::new(keycloak)?,
+            }),
+            (None, Some(pg)) => Self::Postgres(PostgresAccessTokenService::new(pg)?),
+            (None, None) => {
+                anyhow::bail!("Access tokens require either a Keycloak or a database configuration")
+            }
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error(transparent)]
+    Keycloak(#[from] keycloak::error::Error),
+    #[error(transparent)]
+    Database(#[from] ServiceError),
+}
+
+impl ResponseError for BackendError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Keycloak(err) => err.error_response(),
+            Self::Database(err) => err.error_response(),
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($self:ident, $s:ident => $call:expr) => {
+        match $self {
+            Self::Keycloak($s) => Ok($call.await?),
+            Self::Postgres($s) => Ok($call.await?),
+        }
+    };
+}
+
+#[async_trait]
+impl<K> AccessTokenService for AccessTokenBackend<K>
+where
+    K: KeycloakClient + std::marker::Sync + std::marker::Send,
+{
+    type Error = BackendError;
+
+    async fn create(
+        &self,
+        identity: &UserInformation,
+        opts: AccessTokenCreationOptions,
+    ) -> Result<AccessTokenCreated, Self::Error> {
+        delegate!(self, s => s.create(identity, opts))
+    }
+
+    async fn delete(&self, identity: &UserInformation, prefix: String) -> Result<(), Self::Error> {
+        delegate!(self, s => s.delete(identity, prefix))
+    }
+
+    async fn list(&self, identity: &UserInformation) -> Result<Vec<AccessToken>, Self::Error> {
+        delegate!(self, s => s.list(identity))
+    }
+
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<UserDetails>, Self::Error> {
+        delegate!(self, s => s.authenticate(username, password))
+    }
+}
@@ -1,4 +1,6 @@
+pub mod backend;
 pub mod endpoints;
 pub mod mock;
+pub mod postgres;
 mod rng;
 pub mod service;
@@ -0,0 +1,156 @@
+use crate::service::AccessTokenService;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{error::ServiceError, postgres, DatabaseService};
+use drogue_cloud_service_api::{
+    auth::user::{UserDetails, UserInformation},
+    health::{HealthCheckError, HealthChecked},
+    token::{AccessToken, AccessTokenCreated, AccessTokenCreationOptions},
+};
+
+/// Access tokens, stored in PostgreSQL.
+///
+/// Tokens are stored by user ID. When authenticating, the username is used to look up the tokens,
+/// so this only works with an identity provider using the username as subject, like the embedded
+/// OpenID Connect issuer.
+#[derive(Clone)]
+pub struct PostgresAccessTokenService {
+    pool: Pool,
+}
+
+impl PostgresAccessTokenService {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+}
+
+impl DatabaseService for PostgresAccessTokenService {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl HealthChecked for PostgresAccessTokenService {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        Ok(DatabaseService::is_ready(self)
+            .await
+            .map_err(HealthCheckError::from)?)
+    }
+}
+
+#[async_trait]
+impl AccessTokenService for PostgresAccessTokenService {
+    type Error = ServiceError;
+
+    async fn create(
+        &self,
+        identity: &UserInformation,
+        opts: AccessTokenCreationOptions,
+    ) -> Result<AccessTokenCreated, Self::Error> {
+        let user_id = identity.user_id().ok_or(ServiceError::NotAuthorized)?;
+
+        let token = crate::rng::generate_access_token();
+
+        let c = self.pool.get().await?;
+        c.execute(
+            r#"
+INSERT INTO access_tokens (
+    USER_ID, PREFIX, HASHED_TOKEN, DESCRIPTION, CREATED
+) VALUES (
+    $1, $2, $3, $4, $5
+)
+"#,
+            &[
+                &user_id,
+                &token.0.prefix,
+                &token.1,
+                &opts.description,
+                &Utc::now(),
+            ],
+        )
+        .await?;
+
+        Ok(token.0)
+    }
+
+    async fn delete(&self, identity: &UserInformation, prefix: String) -> Result<(), Self::Error> {
+        let user_id = identity.user_id().ok_or(ServiceError::NotAuthorized)?;
+
+        let c = self.pool.get().await?;
+        c.execute(
+            "DELETE FROM access_tokens WHERE USER_ID = $1 AND PREFIX = $2",
+            &[&user_id, &prefix],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, identity: &UserInformation) -> Result<Vec<AccessToken>, Self::Error> {
+        let user_id = identity.user_id().ok_or(ServiceError::NotAuthorized)?;
+
+        let c = self.pool.get().await?;
+        let rows = c
+            .query(
+                r#"
+SELECT
+    PREFIX, DESCRIPTION, CREATED
+FROM
+    access_tokens
+WHERE
+    USER_ID = $1
+ORDER BY
+    CREATED
+"#,
+                &[&user_id],
+            )
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| AccessToken {
+                prefix: row.get("PREFIX"),
+                description: row.get("DESCRIPTION"),
+                created: row.get::<_, DateTime<Utc>>("CREATED"),
+            })
+            .collect())
+    }
+
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<UserDetails>, Self::Error> {
+        // check if the token appears valid (format, checksum, ...)
+
+        let prefix = match crate::rng::is_valid(password) {
+            Some(prefix) => prefix,
+            None => return Ok(None),
+        };
+
+        let c = self.pool.get().await?;
+        let row = c
+            .query_opt(
+                "SELECT HASHED_TOKEN FROM access_tokens WHERE USER_ID = $1 AND PREFIX = $2",
+                &[&username, &prefix],
+            )
+            .await?;
+
+        let expected_hash: String = match row {
+            Some(row) => row.get("HASHED_TOKEN"),
+            None => return Ok(None),
+        };
+
+        Ok(match crate::rng::hash_token(password) == expected_hash {
+            true => Some(UserDetails {
+                user_id: username.to_string(),
+                roles: vec![],
+            }),
+            false => None,
+        })
+    }
+}
@@ -0,0 +1,154 @@
+mod server;
+mod service;
+
+pub use crate::service::ServiceConfig;
+
+use drogue_cloud_endpoint_common::{
+    sender::{ExternalClientPoolConfig, UpstreamSender},
+    sink::MessagingSink,
+};
+use drogue_cloud_mqtt_common::server::TlsConfig;
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use drogue_cloud_service_common::{
+    app::Startup, auth::openid::AuthenticatorConfig, client::ClientConfig, defaults,
+    reqwest::ClientFactory,
+};
+use futures::TryFutureExt;
+use lazy_static::lazy_static;
+use prometheus::{labels, opts, register_int_gauge, IntGauge};
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+lazy_static! {
+    pub static ref CONNECTIONS_COUNTER: IntGauge = register_int_gauge!(opts!(
+        "drogue_connections",
+        "Connections",
+        labels! {
+            "protocol" => "amqp",
+            "type" => "integration",
+        }
+    ))
+    .unwrap();
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AmqpServerOptions {
+    #[serde(default)]
+    pub max_size: Option<u32>,
+    #[serde(default)]
+    pub bind_addr: Option<String>,
+
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub handshake_timeout: Option<Duration>,
+
+    #[serde(default)]
+    pub workers: Option<usize>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub disable_tls: bool,
+
+    #[serde(default)]
+    pub cert_bundle_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub amqp: AmqpServerOptions,
+
+    pub registry: ClientConfig,
+
+    #[serde(default)]
+    pub service: ServiceConfig,
+
+    #[serde(default)]
+    pub user_auth: Option<ClientConfig>,
+
+    pub oauth: AuthenticatorConfig,
+
+    pub command_kafka_sink: KafkaClientConfig,
+
+    #[serde(default = "defaults::check_kafka_topic_ready")]
+    pub check_kafka_topic_ready: bool,
+
+    #[serde(default = "defaults::instance")]
+    pub instance: String,
+
+    #[serde(default)]
+    pub endpoint_pool: ExternalClientPoolConfig,
+}
+
+impl TlsConfig for Config {
+    fn is_disabled(&self) -> bool {
+        self.disable_tls
+    }
+
+    fn disable_psk(&self) -> bool {
+        true
+    }
+
+    fn disable_client_certs(&self) -> bool {
+        true
+    }
+
+    fn key_file(&self) -> Option<&str> {
+        self.key_file.as_deref()
+    }
+
+    fn cert_bundle_file(&self) -> Option<&str> {
+        self.cert_bundle_file.as_deref()
+    }
+}
+
+pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
+    log::debug!("Config: {:#?}", config);
+
+    let app_config = config.clone();
+
+    log::info!("Kafka servers: {}", config.service.kafka.bootstrap_servers);
+
+    // set up security
+
+    let authenticator = config.oauth.into_client().await?;
+    let user_auth = if let Some(user_auth) = config.user_auth {
+        Some(Arc::new(user_auth.into_client().await?))
+    } else {
+        None
+    };
+
+    let registry = config.registry.into_client().await?;
+
+    let sender = UpstreamSender::new(
+        config.instance,
+        MessagingSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
+        config.endpoint_pool,
+    )?;
+
+    log::info!("Authenticator: {:?}", authenticator);
+    log::info!("User auth: {:?}", user_auth);
+
+    // creating the application
+
+    let app = service::App {
+        authenticator,
+        user_auth,
+        config: config.service.clone(),
+        sender,
+        client: ClientFactory::new().build()?,
+        registry,
+    };
+
+    // create server
+
+    let srv = server::build(config.amqp.clone(), app, &app_config)?.run();
+
+    // run
+
+    startup.spawn(srv.err_into());
+
+    // exiting
+
+    Ok(())
+}
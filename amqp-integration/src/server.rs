@@ -0,0 +1,148 @@
+use crate::{
+    service::{self, App, Connection},
+    AmqpServerOptions,
+};
+use drogue_cloud_mqtt_common::server::TlsConfig;
+use ntex::{
+    io::{Filter, Io},
+    server::ServerBuilder,
+    service::{fn_factory_with_config, fn_service, pipeline_factory},
+    time::Seconds,
+    util::Ready,
+    ServiceFactory,
+};
+use ntex_amqp::{server, State};
+
+const DEFAULT_MAX_SIZE: u32 = 64 * 1024;
+
+fn handshake_timeout(opts: &AmqpServerOptions) -> Seconds {
+    opts.handshake_timeout
+        .map(|s| Seconds(s.as_secs() as u16))
+        .unwrap_or(Seconds(15))
+}
+
+/// Create a new AMQP server
+fn create_server<F: Filter>(
+    opts: &AmqpServerOptions,
+    app: App,
+) -> impl ServiceFactory<Io<F>, Response = (), InitError = (), Error = ()> {
+    server::Server::build(move |handshake: server::Handshake| {
+        let app = app.clone();
+        async move { app.handshake(handshake).await }
+    })
+    .max_size(opts.max_size.unwrap_or(DEFAULT_MAX_SIZE))
+    .handshake_timeout(handshake_timeout(opts))
+    .control(fn_factory_with_config(|state: State<Connection>| {
+        Ready::Ok::<_, service::ServerError>(fn_service(move |frame| {
+            service::control(state.clone(), frame)
+        }))
+    }))
+    .finish(
+        server::Router::<Connection>::new()
+            .service(
+                "command/{application}/{device}/{command}",
+                fn_factory_with_config(service::command_link),
+            )
+            .finish(),
+    )
+    .map_err(|err| log::debug!("AMQP server error: {:?}", err))
+    .map_init_err(|_| ())
+}
+
+fn bind_addr(addr: &Option<String>, default: &str, debug: &str) -> String {
+    let addr = addr.clone().unwrap_or_else(|| default.into());
+    log::info!("Starting {} server: {}", debug, addr);
+
+    addr
+}
+
+fn build_server<F, R>(
+    opts: AmqpServerOptions,
+    tls: bool,
+    factory: F,
+) -> anyhow::Result<ServerBuilder>
+where
+    F: Fn(&AmqpServerOptions) -> R + Send + Clone + 'static,
+    R: ServiceFactory<Io>,
+{
+    let mut builder = ServerBuilder::new();
+    if let Some(workers) = opts.workers {
+        builder = builder.workers(workers);
+    }
+
+    let addr = match tls {
+        true => bind_addr(&opts.bind_addr, "127.0.0.1:5671", "AMQP (TLS)"),
+        false => bind_addr(&opts.bind_addr, "127.0.0.1:5672", "AMQP (non-TLS)"),
+    };
+
+    Ok(builder.bind("amqp", addr, move |_| factory(&opts))?)
+}
+
+#[cfg(feature = "rustls")]
+fn build_rustls(
+    opts: AmqpServerOptions,
+    app: App,
+    tls_config: rust_tls::server::ServerConfig,
+) -> anyhow::Result<ServerBuilder> {
+    log::info!("TLS based on rustls");
+
+    build_server(opts, true, move |opts| {
+        pipeline_factory(
+            ntex::tls::rustls::Acceptor::new(std::sync::Arc::new(tls_config.clone()))
+                .timeout(handshake_timeout(opts)),
+        )
+        .map_err(|err| log::debug!("Connect error: {}", err))
+        .and_then(create_server(opts, app.clone()))
+    })
+}
+
+#[cfg(feature = "openssl")]
+fn build_openssl(
+    opts: AmqpServerOptions,
+    app: App,
+    tls_config: open_ssl::ssl::SslAcceptor,
+) -> anyhow::Result<ServerBuilder> {
+    log::info!("TLS based on openssl");
+
+    build_server(opts, true, move |opts| {
+        pipeline_factory(
+            ntex::tls::openssl::Acceptor::new(tls_config.clone()).timeout(handshake_timeout(opts)),
+        )
+        .map_err(|err| log::debug!("Connect error: {}", err))
+        .and_then(create_server(opts, app.clone()))
+    })
+}
+
+pub fn build(
+    opts: AmqpServerOptions,
+    app: App,
+    config: &dyn TlsConfig,
+) -> anyhow::Result<ServerBuilder> {
+    if config.is_disabled() {
+        return build_server(opts, false, move |opts| create_server(opts, app.clone()));
+    }
+
+    if cfg!(feature = "rustls") {
+        // with rustls
+        #[cfg(feature = "rustls")]
+        return build_rustls(
+            opts,
+            app,
+            drogue_cloud_mqtt_common::tls::rustls_config(config)?,
+        );
+    } else if cfg!(feature = "openssl") {
+        // with openssl
+        #[cfg(feature = "openssl")]
+        return build_openssl(
+            opts,
+            app,
+            drogue_cloud_mqtt_common::tls::openssl_config(
+                config,
+                None::<fn(Option<&[u8]>, &mut [u8]) -> Result<usize, std::io::Error>>,
+            )?,
+        );
+    }
+
+    // no implementation available
+    anyhow::bail!("Requested TLS configuration, but no TLS implementation is present")
+}
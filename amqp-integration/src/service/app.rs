@@ -0,0 +1,126 @@
+use crate::service::{Connection, ServerError, ServiceConfig};
+use drogue_client::{registry, user};
+use drogue_cloud_endpoint_common::sender::UpstreamSender;
+use drogue_cloud_integration_common::auth::authenticate;
+use drogue_cloud_service_api::auth::user::UserInformation;
+use drogue_cloud_service_common::auth::openid::Authenticator;
+use ntex_amqp::{codec::protocol::SaslCode, server};
+use std::sync::Arc;
+
+const MECHANISM_PLAIN: &str = "PLAIN";
+const MECHANISM_ANONYMOUS: &str = "ANONYMOUS";
+
+#[derive(Clone, Debug)]
+pub struct App {
+    pub authenticator: Option<Authenticator>,
+    pub user_auth: Option<Arc<user::v1::Client>>,
+    pub config: ServiceConfig,
+    pub sender: UpstreamSender,
+    pub client: reqwest::Client,
+    pub registry: registry::v1::Client,
+}
+
+impl App {
+    /// Authenticate a connection from its SASL credentials
+    async fn authenticate(
+        &self,
+        username: Option<&str>,
+        password: Option<&[u8]>,
+    ) -> Result<UserInformation, ServerError> {
+        match &self.authenticator {
+            Some(auth) => authenticate(username, password, auth, self.user_auth.as_deref())
+                .await
+                .map_err(|err| {
+                    log::debug!("Failed to perform authentication: {err}");
+                    ServerError::AuthenticationFailed
+                }),
+            // we are running without authentication
+            None => Ok(UserInformation::Anonymous),
+        }
+    }
+
+    pub async fn handshake(
+        &self,
+        handshake: server::Handshake,
+    ) -> Result<server::HandshakeAck<Connection>, ServerError> {
+        match handshake {
+            server::Handshake::Amqp(handshake) => {
+                log::debug!("Processing AMQP handshake");
+
+                let user = self.authenticate(None, None).await?;
+                let handshake = handshake.open().await?;
+                let container_id = handshake.frame().container_id.to_string();
+
+                Ok(handshake.ack(Connection::new(self.clone(), user, container_id)))
+            }
+            server::Handshake::Sasl(sasl) => {
+                log::debug!("Processing SASL handshake");
+
+                let init = sasl
+                    .mechanism(MECHANISM_PLAIN)
+                    .mechanism(MECHANISM_ANONYMOUS)
+                    .init()
+                    .await?;
+
+                let mechanism = init.mechanism().to_string();
+                let result = match mechanism.as_str() {
+                    MECHANISM_PLAIN => match init.initial_response().and_then(parse_plain) {
+                        Some((username, password)) => self.authenticate(username, password).await,
+                        None => Err(ServerError::AuthenticationFailed),
+                    },
+                    MECHANISM_ANONYMOUS => self.authenticate(None, None).await,
+                    _ => Err(ServerError::UnsupportedMechanism(mechanism)),
+                };
+
+                match result {
+                    Ok(user) => {
+                        let handshake = init.outcome(SaslCode::Ok).await?.open().await?;
+                        let container_id = handshake.frame().container_id.to_string();
+
+                        Ok(handshake.ack(Connection::new(self.clone(), user, container_id)))
+                    }
+                    Err(err) => {
+                        init.outcome(SaslCode::Auth).await?;
+                        Err(err)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parse the response of the `PLAIN` mechanism: `[authzid] NUL authcid NUL passwd`.
+///
+/// Empty values are treated as missing, so that either can be used to provide a token.
+fn parse_plain(response: &[u8]) -> Option<(Option<&str>, Option<&[u8]>)> {
+    let mut parts = response.splitn(3, |b| *b == 0);
+    let (_, username, password) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let username = std::str::from_utf8(username).ok()?;
+
+    Some((
+        Some(username).filter(|username| !username.is_empty()),
+        Some(password).filter(|password| !password.is_empty()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain() {
+        assert_eq!(
+            parse_plain(b"\0user\0token"),
+            Some((Some("user"), Some(&b"token"[..])))
+        );
+        assert_eq!(
+            parse_plain(b"authz\0user\0token"),
+            Some((Some("user"), Some(&b"token"[..])))
+        );
+        assert_eq!(parse_plain(b"\0\0token"), Some((None, Some(&b"token"[..]))));
+        assert_eq!(parse_plain(b"\0user\0"), Some((Some("user"), None)));
+        assert_eq!(parse_plain(b"user"), None);
+        assert_eq!(parse_plain(b"\0\xff\0token"), None);
+    }
+}
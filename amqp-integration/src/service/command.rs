@@ -0,0 +1,128 @@
+use crate::service::Connection;
+use drogue_client::user;
+use drogue_cloud_integration_common::commands::{process_command, CommandOptions};
+use ntex::{service::fn_service, Service};
+use ntex_amqp::{codec::Message, error::AmqpError, types, State};
+use std::rc::Rc;
+
+/// Commands sent by a client, on a link attached to `command/<application>/<device>/<command>`.
+struct Command {
+    state: State<Connection>,
+    application: String,
+    device: String,
+    command: String,
+}
+
+/// Accept a link for sending commands.
+pub async fn command_link(
+    link: types::Link<Connection>,
+) -> Result<impl Service<types::Transfer, Response = types::Outcome, Error = AmqpError>, AmqpError>
+{
+    let param = |name: &str| link.path().get(name).unwrap_or_default().to_string();
+
+    let command = Rc::new(Command {
+        state: link.state().clone(),
+        application: param("application"),
+        device: param("device"),
+        command: param("command"),
+    });
+
+    log::info!(
+        "Request to send command {:?} to {:?}/{:?}",
+        command.command,
+        command.application,
+        command.device
+    );
+
+    command
+        .state
+        .get_ref()
+        .authorize(&command.application, user::v1::authz::Permission::Write)
+        .await
+        .map_err(|_| AmqpError::unauthorized_access())?;
+
+    Ok(fn_service(move |transfer: types::Transfer| {
+        command.clone().process(transfer)
+    }))
+}
+
+impl Command {
+    async fn process(
+        self: Rc<Self>,
+        transfer: types::Transfer,
+    ) -> Result<types::Outcome, AmqpError> {
+        let message = match transfer.load_message::<Message>() {
+            Ok(message) => message,
+            Err(err) => {
+                return Ok(types::Outcome::Error(
+                    AmqpError::decode_error().description(err.to_string()),
+                ))
+            }
+        };
+
+        let connection = self.state.get_ref();
+        let registry = &connection.app.registry;
+
+        let response = futures::try_join!(
+            registry.get_app(&self.application),
+            registry.get_device_and_gateways(&self.application, &self.device)
+        );
+
+        let (application, device_gateways) = match response {
+            Ok((Some(application), Some(device_gateways))) => (application, device_gateways),
+            Ok(_) => {
+                return Ok(types::Outcome::Error(
+                    AmqpError::not_found().description("Application or device not found"),
+                ))
+            }
+            Err(err) => {
+                log::info!("Error looking up registry info {:?}", err);
+                return Ok(types::Outcome::Error(
+                    AmqpError::internal_error().description("Error looking up registry info"),
+                ));
+            }
+        };
+
+        let opts = CommandOptions {
+            application: self.application.clone(),
+            device: self.device.clone(),
+            command: self.command.clone(),
+            content_type: message
+                .properties()
+                .and_then(|p| p.content_type.as_ref())
+                .map(|content_type| content_type.to_string()),
+            correlation_id: None,
+            response_channel: None,
+        };
+
+        let payload = message
+            .body()
+            .data()
+            .map(|data| bytes::Bytes::from(data.to_vec()))
+            .unwrap_or_default();
+
+        match process_command(
+            application,
+            device_gateways.0,
+            device_gateways.1,
+            &connection.app.sender,
+            connection.app.client.clone(),
+            opts,
+            payload,
+        )
+        .await
+        {
+            Ok(response) if response.status().is_success() => Ok(types::Outcome::Accept),
+            Ok(response) => Ok(types::Outcome::Error(
+                AmqpError::precondition_failed()
+                    .description(format!("Command not accepted: {}", response.status())),
+            )),
+            Err(err) => {
+                log::info!("Error sending command {:?}", err);
+                Ok(types::Outcome::Error(
+                    AmqpError::internal_error().description("Error sending command"),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{service::App, CONNECTIONS_COUNTER};
+use drogue_client::user;
+use drogue_cloud_integration_common::auth::authorize;
+use drogue_cloud_service_api::auth::user::UserInformation;
+use ntex::util::ByteString;
+use std::{cell::RefCell, collections::HashMap};
+use tokio::task::JoinHandle;
+
+/// The state of an AMQP connection.
+pub struct Connection {
+    pub app: App,
+    pub user: UserInformation,
+    /// The container ID of the client, used as consumer group.
+    pub container_id: String,
+
+    /// Event streams, by the name of their link.
+    streams: RefCell<HashMap<ByteString, JoinHandle<()>>>,
+}
+
+impl Connection {
+    pub fn new(app: App, user: UserInformation, container_id: String) -> Self {
+        CONNECTIONS_COUNTER.inc();
+        Self {
+            app,
+            user,
+            container_id,
+            streams: Default::default(),
+        }
+    }
+
+    /// Authorize the user of the connection for an application.
+    pub async fn authorize(
+        &self,
+        application: &str,
+        permission: user::v1::authz::Permission,
+    ) -> Result<(), ()> {
+        match &self.app.user_auth {
+            Some(user_auth) => {
+                authorize(&self.user, application.to_string(), user_auth, permission).await
+            }
+            // authorization disabled ... nothing to do
+            None => Ok(()),
+        }
+    }
+
+    pub fn attach_stream(&self, link: ByteString, handle: JoinHandle<()>) {
+        log::debug!("Attaching: {}", link);
+
+        if let Some(previous) = self.streams.borrow_mut().insert(link, handle) {
+            previous.abort();
+        }
+    }
+
+    pub fn detach_stream(&self, link: &ByteString) {
+        log::debug!("Detaching: {}", link);
+
+        if let Some(stream) = self.streams.borrow_mut().remove(link) {
+            stream.abort();
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        CONNECTIONS_COUNTER.dec();
+        for (_, stream) in self.streams.get_mut().drain() {
+            stream.abort();
+        }
+    }
+}
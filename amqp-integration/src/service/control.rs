@@ -0,0 +1,102 @@
+use crate::service::{stream::Stream, Connection, ServerError};
+use drogue_client::user;
+use drogue_cloud_event_common::stream::CustomAck;
+use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
+use drogue_cloud_service_api::kafka::{KafkaConfigExt, KafkaEventType};
+use ntex::util::Either;
+use ntex_amqp::{
+    codec::protocol::Attach, error::AmqpError, ControlFrame, ControlFrameKind, SenderLink, State,
+};
+
+/// Handle control frames of a connection.
+pub async fn control(state: State<Connection>, frame: ControlFrame) -> Result<(), ServerError> {
+    match frame.kind() {
+        ControlFrameKind::AttachSender(attach, link) => {
+            if let Err(err) = attach_sender(&state, attach, link.clone()).await {
+                log::info!("Rejecting link: {:?}", err);
+                let _ = link.close_with_error(err).await;
+            }
+        }
+        ControlFrameKind::DetachSender(_, link) => {
+            state.get_ref().detach_stream(link.name());
+        }
+        ControlFrameKind::SessionEnded(links) => {
+            for link in links {
+                if let Either::Left(link) = link {
+                    state.get_ref().detach_stream(link.name());
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Start streaming the events of an application to a link, attached by a receiver.
+async fn attach_sender(
+    state: &State<Connection>,
+    attach: &Attach,
+    link: SenderLink,
+) -> Result<(), AmqpError> {
+    let connection = state.get_ref();
+
+    let address = attach
+        .source
+        .as_ref()
+        .and_then(|source| source.address.as_ref())
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+
+    let application = match address.split('/').collect::<Vec<_>>().as_slice() {
+        ["a" | "app" | "application", application] => application.to_string(),
+        _ => return Err(AmqpError::not_found().description(format!("Unknown address: {address}"))),
+    };
+
+    log::debug!(
+        "Request to attach to app: {} (container: {})",
+        application,
+        connection.container_id
+    );
+
+    // authorize application for user
+
+    connection
+        .authorize(&application, user::v1::authz::Permission::Read)
+        .await
+        .map_err(|_| AmqpError::unauthorized_access())?;
+
+    // find kafka info
+
+    let app_res = connection
+        .app
+        .registry
+        .get_app(&application)
+        .await
+        .map_err(|err| AmqpError::internal_error().description(err.to_string()))?
+        .ok_or_else(|| AmqpError::not_found().description("Application not found"))?;
+
+    // create stream
+
+    let stream_config = EventStreamConfig {
+        kafka: app_res
+            .kafka_target(KafkaEventType::Events, &connection.app.config.kafka)
+            .map(|target| target.into())
+            .map_err(|err| AmqpError::internal_error().description(err.to_string()))?,
+        consumer_group: Some(format!("{application}.{}", connection.container_id)),
+    };
+    let event_stream = EventStream::<CustomAck>::new(stream_config).map_err(|err| {
+        log::info!("Failed to subscribe to Kafka topic: {}", err);
+        AmqpError::internal_error().description("Failed to subscribe to events")
+    })?;
+
+    let stream = Stream {
+        link: link.clone(),
+        event_stream,
+        max_in_flight: connection.app.config.max_in_flight,
+    };
+
+    connection.attach_stream(link.name().clone(), ntex_rt::spawn(stream.run()));
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use ntex_amqp::error::{AmqpProtocolError, HandshakeError};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ServerError {
+    #[error("handshake error: {0}")]
+    Handshake(#[from] HandshakeError),
+    #[error("protocol error: {0}")]
+    Protocol(#[from] AmqpProtocolError),
+    #[error("authentication failed")]
+    AuthenticationFailed,
+    #[error("unsupported SASL mechanism: {0}")]
+    UnsupportedMechanism(String),
+}
@@ -0,0 +1,37 @@
+mod app;
+mod command;
+mod connection;
+mod control;
+mod error;
+mod stream;
+
+pub use app::App;
+pub use command::command_link;
+pub use connection::Connection;
+pub use control::control;
+pub use error::ServerError;
+
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use serde::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServiceConfig {
+    #[serde(default)]
+    pub kafka: KafkaClientConfig,
+    /// The maximum number of events sent on a link, before their dispositions are received.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+const fn default_max_in_flight() -> usize {
+    100
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            kafka: Default::default(),
+            max_in_flight: default_max_in_flight(),
+        }
+    }
+}
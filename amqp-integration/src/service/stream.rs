@@ -0,0 +1,89 @@
+use cloudevents::{AttributesReader, Event};
+use drogue_cloud_event_common::stream::{CustomAck, Handle};
+use drogue_cloud_integration_common::stream::EventStream;
+use futures::stream::{FuturesOrdered, StreamExt};
+use ntex::util::ByteString;
+use ntex_amqp::{
+    codec::{
+        protocol::{DeliveryState, MessageId},
+        Message,
+    },
+    SenderLink,
+};
+
+const CONTENT_TYPE: &str = "application/cloudevents+json; charset=utf-8";
+
+/// Events of an application, sent to a sender link.
+pub struct Stream {
+    pub link: SenderLink,
+    pub event_stream: EventStream<'static, CustomAck>,
+    pub max_in_flight: usize,
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        log::info!("Dropped stream - link: {}", self.link.name());
+    }
+}
+
+impl Stream {
+    pub async fn run(self) {
+        let link = self.link.clone();
+        match self.run_link().await {
+            Ok(()) => log::debug!("Stream processor finished"),
+            Err(err) => {
+                log::info!("Stream processor failed: {}", err);
+                let _ = link.close().await;
+            }
+        }
+    }
+
+    /// Send events, as long as the link has credit.
+    ///
+    /// Transfers are only sent while the receiver granted credit, so consuming from Kafka stops
+    /// once the maximum number of events is in flight. Events are committed in the order they were
+    /// received, once they are accepted or rejected. An event which got released or modified stops
+    /// the stream, so that it gets delivered again when the client re-attaches.
+    async fn run_link(mut self) -> Result<(), anyhow::Error> {
+        let mut in_flight = FuturesOrdered::new();
+
+        loop {
+            tokio::select! {
+                next = self.event_stream.next(), if in_flight.len() < self.max_in_flight => {
+                    let handle = match next {
+                        Some(handle) => handle?,
+                        None => break,
+                    };
+                    log::debug!("Event: {:?}", *handle);
+
+                    let message = to_message(&handle)?;
+                    let link = self.link.clone();
+                    let handle: Handle<'static, ()> = handle.replace(());
+                    in_flight.push_back(async move { (link.send(message).await, handle) });
+                }
+                Some((disposition, handle)) = in_flight.next(), if !in_flight.is_empty() => {
+                    match disposition?.state {
+                        None | Some(DeliveryState::Accepted(_)) | Some(DeliveryState::Rejected(_)) => {
+                            self.event_stream.ack(handle)?;
+                        }
+                        Some(state) => {
+                            anyhow::bail!("Event not accepted: {state:?}");
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert an event into a structured mode message.
+fn to_message(event: &Event) -> Result<Message, serde_json::Error> {
+    let payload = serde_json::to_vec(event)?;
+    Ok(Message::with_body(payload.into()).set_properties(|p| {
+        p.message_id = Some(MessageId::String(ByteString::from(event.id())));
+        p.subject = event.subject().map(ByteString::from);
+        p.content_type = Some(CONTENT_TYPE.into());
+    }))
+}
@@ -1,10 +1,10 @@
 use crate::{command::wait_for_command, error::CoapEndpointError};
 use async_trait::async_trait;
-use coap_lite::{CoapRequest, CoapResponse, ResponseType};
+use coap_lite::{CoapRequest, CoapResponse, ContentFormat, ResponseType};
 use drogue_cloud_endpoint_common::{
     command::{CommandFilter, Commands},
     error::EndpointError,
-    sender::{DownstreamSender, Publish, PublishOutcome, Publisher},
+    sender::{BatchItem, BatchItemOutcome, DownstreamSender, Publish, PublishOutcome, Publisher},
 };
 use std::net::SocketAddr;
 
@@ -49,6 +49,12 @@ impl CoapCommandSender for DownstreamSender {
                 v
             })),
 
+            // ok, but quota exceeded
+            Ok(PublishOutcome::RateLimited) => Ok(req.response.map(|mut v| {
+                v.set_status(ResponseType::TooManyRequests);
+                v
+            })),
+
             // internal error
             Err(err) => Err(CoapEndpointError(EndpointError::ConfigurationError {
                 details: err.to_string(),
@@ -56,3 +62,28 @@ impl CoapCommandSender for DownstreamSender {
         }
     }
 }
+
+/// Publish a batch of events, responding with the outcome of each item.
+///
+/// The response is always `2.04 Changed`, with a JSON payload containing the outcome of each
+/// item. Batches don't wait for commands.
+pub async fn publish_batch(
+    sender: &DownstreamSender,
+    publish: Publish<'_>,
+    items: Vec<BatchItem>,
+    req: CoapRequest<SocketAddr>,
+) -> Option<CoapResponse> {
+    let outcomes = sender
+        .publish_batch(publish, items)
+        .await
+        .iter()
+        .map(BatchItemOutcome::from)
+        .collect::<Vec<_>>();
+
+    req.response.map(|mut v| {
+        v.set_status(ResponseType::Changed);
+        v.message.set_content_format(ContentFormat::ApplicationJSON);
+        v.message.payload = serde_json::to_vec(&outcomes).unwrap_or_default();
+        v
+    })
+}
@@ -10,6 +10,7 @@ impl CoapEndpointError {
         match self.0 {
             EndpointError::InvalidFormat { .. } => ResponseType::BadRequest,
             EndpointError::InvalidRequest { .. } => ResponseType::BadRequest,
+            EndpointError::PayloadTooLarge { .. } => ResponseType::RequestEntityTooLarge,
             EndpointError::ConfigurationError { .. } => ResponseType::InternalServerError,
             EndpointError::AuthenticationServiceError { .. } => ResponseType::ServiceUnavailable,
             EndpointError::AuthenticationError { .. } => ResponseType::Forbidden,
@@ -14,14 +14,16 @@ use drogue_cloud_endpoint_common::{
     auth::AuthConfig,
     command::{Commands, KafkaCommandSource, KafkaCommandSourceConfig},
     error::EndpointError,
-    sender::{DownstreamSender, ExternalClientPoolConfig},
-    sink::KafkaSink,
+    sender::{BatchConfig, DownstreamSender, ExternalClientPoolConfig, QuotaConfig},
+    sink::MessagingSink,
 };
 use drogue_cloud_service_api::auth::device::authn::PreSharedKeyOutcome;
 use drogue_cloud_service_api::kafka::KafkaClientConfig;
 use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
+    client::ClientConfig,
     defaults,
+    state::{PresenceConfiguration, PresenceReporter},
 };
 use tokio_dtls_stream_sink::Server as DtlsServer;
 
@@ -64,6 +66,22 @@ pub struct Config {
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
 
+    /// Registry access, enables processing of the device twin
+    #[serde(default)]
+    pub registry: Option<ClientConfig>,
+
+    /// Report the presence of devices to the device state service
+    #[serde(default)]
+    pub presence: Option<PresenceConfiguration>,
+
+    /// Quotas for ingesting events
+    #[serde(default)]
+    pub quota: QuotaConfig,
+
+    /// Limits for batches of events
+    #[serde(default)]
+    pub batch: BatchConfig,
+
     #[serde(default)]
     pub disable_dtls: bool,
 
@@ -99,15 +117,23 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         .unwrap_or_else(|| "[::]:5683".to_string());
     let coap_server_commands = commands.clone();
 
-    let sender = DownstreamSender::new(
-        KafkaSink::from_config(
+    let mut sender = DownstreamSender::new(
+        MessagingSink::from_config(
             config.kafka_downstream_config,
             config.check_kafka_topic_ready,
         )?,
         config.instance,
         config.endpoint_pool,
     )?;
+    if let Some(registry) = config.registry {
+        sender = sender.with_twin(registry.into_client().await?);
+    }
+    if let Some(presence) = config.presence {
+        sender = sender.with_presence(PresenceReporter::new(presence).await?);
+    }
+    sender = sender.with_quota(config.quota).with_batch(config.batch);
 
+    let downstream = sender.clone();
     let app = App {
         downstream: sender,
         authenticator: DeviceAuthenticator(
@@ -194,8 +220,14 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     log::info!("CoAP server up on {}", addr);
     startup.spawn(async move {
         let mut server = DtlsServer::new(server);
+        let terminated = terminated();
+        tokio::pin!(terminated);
         loop {
-            match server.accept(dtls.as_ref()).await {
+            let session = tokio::select! {
+                _ = &mut terminated => break,
+                session = server.accept(dtls.as_ref()) => session,
+            };
+            match session {
                 Ok(session) => {
                     let expired = Instant::now() + expiry;
                     let app = app.clone();
@@ -209,12 +241,35 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
                 }
             }
         }
+
+        log::info!("CoAP server shutting down");
+        downstream.close().await;
+        Ok(())
     });
     startup.check(command_source);
 
     Ok(())
 }
 
+/// Wait for the process to be asked to terminate.
+async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = terminate.recv() => {},
+                _ = tokio::signal::ctrl_c() => {},
+            }
+            return;
+        }
+    }
+
+    if tokio::signal::ctrl_c().await.is_err() {
+        futures::future::pending::<()>().await;
+    }
+}
+
 pub(crate) async fn publish_handler(
     mut request: CoapRequest<SocketAddr>,
     certs: Option<ClientCertificateChain>,
@@ -555,6 +610,7 @@ pub mod test {
                 },
                 r#as: Some("device#2".to_string()),
                 ct: Some(30),
+                batch: false,
             }
         );
 
@@ -574,6 +630,7 @@ pub mod test {
                 },
                 r#as: Some("device#2".to_string()),
                 ct: Some(30),
+                batch: false,
             }
         );
 
@@ -593,6 +650,7 @@ pub mod test {
                 },
                 r#as: None,
                 ct: Some(30),
+                batch: false,
             }
         );
 
@@ -612,7 +670,18 @@ pub mod test {
                 },
                 r#as: None,
                 ct: None,
+                batch: false,
             }
         );
+
+        // batch=true
+        req = CoapRequestBuilder::new("coap://test-url/v1/Rust/test?batch=true").req;
+        let (_, queries, _) = params(&req).unwrap();
+        assert!(
+            queries
+                .and_then(|x| serde_urlencoded::from_bytes::<PublishOptions>(x).ok())
+                .unwrap_or_default()
+                .batch
+        );
     }
 }
@@ -1,4 +1,8 @@
-use crate::{auth::DeviceAuthenticator, downstream::CoapCommandSender, error::CoapEndpointError};
+use crate::{
+    auth::DeviceAuthenticator,
+    downstream::{publish_batch, CoapCommandSender},
+    error::CoapEndpointError,
+};
 use coap_lite::{CoapRequest, CoapResponse, ContentFormat};
 use drogue_cloud_endpoint_common::{
     command::Commands,
@@ -29,6 +33,10 @@ pub struct PublishOptions {
 
     #[serde(alias = "commandTimeout")]
     pub ct: Option<u64>,
+
+    /// The payload is a batch of events.
+    #[serde(default)]
+    pub batch: bool,
 }
 
 pub async fn publish_plain(
@@ -155,6 +163,13 @@ pub async fn publish(
         },
     };
 
+    if opts.batch {
+        let items = sender
+            .parse_batch(&req.message.payload)
+            .map_err(|err| CoapEndpointError(err.into()))?;
+        return Ok(publish_batch(&sender, publish, items, req).await);
+    }
+
     // Send response
     sender
         .publish_and_await(publish, commands, opts.ct, req)
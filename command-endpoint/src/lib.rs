@@ -4,8 +4,9 @@ use actix_web::{web, HttpResponse, Responder};
 use drogue_client::{registry, user::v1::authz::Permission};
 use drogue_cloud_endpoint_common::{
     sender::{ExternalClientPoolConfig, UpstreamSender},
-    sink::KafkaSink,
+    sink::MessagingSink,
 };
+use drogue_cloud_integration_common::commands::ResponseRouter;
 use drogue_cloud_service_api::{
     health::HealthChecked,
     kafka::KafkaClientConfig,
@@ -66,9 +67,10 @@ pub async fn configurator(
     impl Fn(&mut ServiceConfig) + Send + Sync + Clone,
     Vec<Box<dyn HealthChecked>>,
 )> {
+    let responses = ResponseRouter::new(config.command_kafka_sink.clone());
     let sender = UpstreamSender::new(
         config.instance,
-        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
+        MessagingSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
         config.endpoint_pool,
     )?;
 
@@ -89,6 +91,7 @@ pub async fn configurator(
             cfg.app_data(web::Data::new(sender.clone()))
                 .app_data(web::Data::new(registry.clone()))
                 .app_data(web::Data::new(client.clone()))
+                .app_data(web::Data::new(responses.clone()))
                 .service(web::resource("/").route(web::get().to(index)))
                 .service(
                     web::scope("/api/command/v1alpha1/apps/{application}/devices/{deviceId}")
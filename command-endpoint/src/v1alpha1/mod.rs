@@ -1,12 +1,30 @@
+use cloudevents::{AttributesReader, Data, Event};
 use drogue_client::registry;
-use drogue_cloud_endpoint_common::{error::HttpEndpointError, sender::UpstreamSender};
-use drogue_cloud_integration_common::{self, commands::CommandOptions};
-use drogue_cloud_service_api::webapp::{http::header, web, HttpRequest, HttpResponse};
+use drogue_cloud_endpoint_common::{
+    error::{EndpointError, HttpEndpointError},
+    sender::UpstreamSender,
+};
+use drogue_cloud_integration_common::{
+    self,
+    commands::{CommandOptions, ResponseRouter},
+};
+use drogue_cloud_service_api::webapp::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse,
+};
 use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::oneshot;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct CommandQuery {
     pub command: String,
+    /// Wait for the response of the device, up to this duration.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// The channel the device should publish its response to.
+    #[serde(default)]
+    pub response_channel: Option<String>,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -18,6 +36,7 @@ pub async fn command(
     req: HttpRequest,
     body: web::Bytes,
     registry: web::Data<registry::v1::Client>,
+    responses: web::Data<ResponseRouter>,
 ) -> Result<HttpResponse, HttpEndpointError> {
     let (app_name, device_name) = path.into_inner();
 
@@ -40,7 +59,25 @@ pub async fn command(
                 .get(header::CONTENT_TYPE)
                 .and_then(|v| v.to_str().ok())
                 .map(|s| s.to_string());
-            drogue_cloud_integration_common::commands::process_command(
+
+            // when waiting for a response, we need to expect it before sending the command
+            let (expected, correlation_id) = match opts.timeout {
+                Some(timeout) => {
+                    let correlation_id = uuid::Uuid::new_v4().to_string();
+                    let expected = responses
+                        .expect(&application, &device_name, correlation_id.clone(), timeout)
+                        .map_err(|err| {
+                            log::info!("Failed to subscribe to responses: {}", err);
+                            HttpEndpointError(EndpointError::ConfigurationError {
+                                details: "Failed to subscribe to events".to_string(),
+                            })
+                        })?;
+                    (Some(expected), Some(correlation_id))
+                }
+                None => (None, None),
+            };
+
+            let response = drogue_cloud_integration_common::commands::process_command(
                 application,
                 device_gateways.0,
                 device_gateways.1,
@@ -51,10 +88,19 @@ pub async fn command(
                     device: device_name,
                     command: opts.command,
                     content_type,
+                    correlation_id,
+                    response_channel: opts.response_channel,
                 },
                 body,
             )
-            .await
+            .await?;
+
+            match (expected, opts.timeout) {
+                (Some(expected), Some(timeout)) if response.status() == StatusCode::ACCEPTED => {
+                    Ok(wait_for_response(expected, timeout).await)
+                }
+                _ => Ok(response),
+            }
         }
         Ok(_) => Ok(HttpResponse::NotAcceptable().finish()),
         Err(err) => {
@@ -63,3 +109,25 @@ pub async fn command(
         }
     }
 }
+
+async fn wait_for_response(response: oneshot::Receiver<Event>, timeout: Duration) -> HttpResponse {
+    match tokio::time::timeout(timeout, response).await {
+        Ok(Ok(event)) => to_response(event),
+        // either timed out, or the request expired before we noticed
+        _ => HttpResponse::GatewayTimeout().finish(),
+    }
+}
+
+fn to_response(mut event: Event) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if let Some(content_type) = event.datacontenttype() {
+        response.content_type(content_type);
+    }
+
+    match event.take_data().2 {
+        Some(Data::Binary(data)) => response.body(data),
+        Some(Data::String(data)) => response.body(data),
+        Some(Data::Json(data)) => response.body(data.to_string()),
+        None => response.finish(),
+    }
+}
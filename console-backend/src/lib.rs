@@ -10,7 +10,8 @@ use actix_web::{
 };
 use anyhow::Context;
 use drogue_client::{registry, user};
-use drogue_cloud_access_token_service::{endpoints as keys, service::KeycloakAccessTokenService};
+use drogue_cloud_access_token_service::{backend::AccessTokenBackend, endpoints as keys};
+use drogue_cloud_database_common::postgres;
 use drogue_cloud_service_api::{
     endpoints::Endpoints, health::HealthChecked, kafka::KafkaClientConfig,
     webapp::web::ServiceConfig,
@@ -26,7 +27,7 @@ use drogue_cloud_service_common::{
     client::ClientConfig,
     defaults,
     endpoints::create_endpoint_source,
-    keycloak::{client::KeycloakAdminClient, KeycloakAdminClientConfig, KeycloakClient},
+    keycloak::{client::KeycloakAdminClient, KeycloakAdminClientConfig},
 };
 use info::DemoFetcher;
 use k8s_openapi::api::core::v1::ConfigMap;
@@ -59,7 +60,13 @@ async fn index(
 pub struct Config {
     pub kafka: KafkaClientConfig,
 
-    pub keycloak: KeycloakAdminClientConfig,
+    /// Keycloak instance storing the access tokens.
+    #[serde(default)]
+    pub keycloak: Option<KeycloakAdminClientConfig>,
+
+    /// Database storing the access tokens, used when Keycloak is not configured.
+    #[serde(default)]
+    pub pg: Option<postgres::Config>,
 
     /// External OpenID configuration, required to discover external OpenID endpoints
     #[serde(rename = "ui", default)]
@@ -156,12 +163,9 @@ pub async fn configurator(
 
     let openid_client = openid_client.map(web::Data::new);
 
-    let keycloak_admin_client =
-        KeycloakAdminClient::new(config.keycloak).context("Creating keycloak admin client")?;
-    let keycloak_service = web::Data::new(keys::WebData {
-        service: KeycloakAccessTokenService {
-            client: keycloak_admin_client,
-        },
+    let access_token_service = web::Data::new(keys::WebData {
+        service: AccessTokenBackend::<KeycloakAdminClient>::from_config(config.keycloak, config.pg)
+            .context("Creating access token service")?,
     });
 
     let registry: registry::v1::Client = config
@@ -199,7 +203,7 @@ pub async fn configurator(
                 app
             };
 
-            let app = app.app_data(keycloak_service.clone());
+            let app = app.app_data(access_token_service.clone());
             let app = app.app_data(web::Data::new(registry.clone()));
             let app = app.app_data(web::Data::new(config_maps.clone()));
 
@@ -209,18 +213,22 @@ pub async fn configurator(
                         .wrap(auth.clone())
                         .service(
                             web::resource("")
-                                .route(web::post().to(keys::create::<
-                                    KeycloakAccessTokenService<KeycloakAdminClient>,
-                                >))
-                                .route(web::get().to(keys::list::<
-                                    KeycloakAccessTokenService<KeycloakAdminClient>,
-                                >)),
+                                .route(
+                                    web::post().to(keys::create::<
+                                        AccessTokenBackend<KeycloakAdminClient>,
+                                    >),
+                                )
+                                .route(
+                                    web::get()
+                                        .to(keys::list::<AccessTokenBackend<KeycloakAdminClient>>),
+                                ),
                         )
-                        .service(web::resource("/{prefix}").route(
-                            web::delete().to(keys::delete::<
-                                KeycloakAccessTokenService<KeycloakAdminClient>,
-                            >),
-                        )),
+                        .service(
+                            web::resource("/{prefix}").route(
+                                web::delete()
+                                    .to(keys::delete::<AccessTokenBackend<KeycloakAdminClient>>),
+                            ),
+                        ),
                 )
                 .service(
                     web::scope("/api/admin/v1alpha1")
@@ -8,7 +8,7 @@ use crate::{
     html_prop,
     pages::{
         apps::{self, ApplicationContext},
-        devices::{debug, delete::DeleteConfirmation, CloneDialog, DetailsSection},
+        devices::{debug, delete::DeleteConfirmation, CloneDialog, DetailsSection, LastValues},
     },
     utils::{context::ContextListener, url_encode},
 };
@@ -251,6 +251,7 @@ impl Details {
                             <TabRouterItem<DetailsSection> to={DetailsSection::Overview} label="Overview"/>
                             <TabRouterItem<DetailsSection> to={DetailsSection::Yaml} label="YAML"/>
                             <TabRouterItem<DetailsSection> to={DetailsSection::Debug} label="Events"/>
+                            <TabRouterItem<DetailsSection> to={DetailsSection::Values} label="Last values"/>
                         </TabsRouter<DetailsSection>>
                     </PageSection>
                     <PageSection>
@@ -259,6 +260,7 @@ impl Details {
                             DetailsSection::Overview => self.render_overview(ctx, device),
                             DetailsSection::Yaml => self.render_editor(ctx),
                             DetailsSection::Debug => self.render_debug(ctx),
+                            DetailsSection::Values => self.render_values(ctx),
                         }
                     }
                     </PageSection>
@@ -331,4 +333,14 @@ impl Details {
                 />
         )
     }
+
+    fn render_values(&self, ctx: &Context<Self>) -> Html {
+        html! (
+            <LastValues
+                backend={ctx.props().backend.clone()}
+                application={ctx.props().app.clone()}
+                device={ctx.props().name.clone()}
+                />
+        )
+    }
 }
@@ -4,12 +4,14 @@ mod debug;
 mod delete;
 mod details;
 mod index;
+mod values;
 
 pub use clone::*;
 pub use create::*;
 pub use debug::*;
 pub use details::*;
 pub use index::*;
+pub use values::*;
 
 use crate::console::AppRoute;
 use crate::pages::apps::ApplicationContext;
@@ -32,6 +34,7 @@ pub enum Pages {
 pub enum DetailsSection {
     Yaml,
     Debug,
+    Values,
     #[target(index)]
     Overview,
 }
@@ -0,0 +1,136 @@
+use crate::{
+    backend::{
+        ApiResponse, AuthenticatedBackend, Json, JsonHandlerScopeExt, Nothing, RequestHandle,
+    },
+    components::spy::Entry,
+    error::{error, ErrorNotification, ErrorNotifier},
+    utils::{context::ContextListener, url_encode},
+};
+use cloudevents::Event;
+use http::Method;
+use patternfly_yew::*;
+use std::collections::BTreeMap;
+use yew::prelude::*;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub backend: AuthenticatedBackend,
+    pub application: String,
+    pub device: String,
+}
+
+pub enum Msg {
+    Load,
+    SetData(Vec<Entry>),
+    Error(ErrorNotification),
+}
+
+/// Show the last known value of each channel of a device.
+pub struct LastValues {
+    values: SharedTableModel<Entry>,
+
+    fetch_task: Option<RequestHandle>,
+
+    toaster: ContextListener<Toaster>,
+}
+
+impl Component for LastValues {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_message(Msg::Load);
+        Self {
+            values: Default::default(),
+            fetch_task: None,
+
+            toaster: ContextListener::unwrap(ctx),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Load => match self.load(ctx) {
+                Ok(task) => self.fetch_task = Some(task),
+                Err(err) => error(&self.toaster.get(), "Failed to fetch", err),
+            },
+            Msg::SetData(values) => {
+                self.values = SharedTableModel::from(values);
+                self.fetch_task = None;
+            }
+            Msg::Error(msg) => {
+                self.fetch_task = None;
+                msg.toast(&self.toaster.get());
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let header = html_nested! {
+            <TableHeader>
+                <TableColumn label="Timestamp (UTC)"/>
+                <TableColumn label="Device ID"/>
+                <TableColumn label="Channel"/>
+                <TableColumn label="Payload"/>
+            </TableHeader>
+        };
+
+        html! {
+            <>
+                <Toolbar>
+                    <ToolbarItem>
+                        <Button
+                            disabled={self.fetch_task.is_some()}
+                            label="Reload"
+                            icon={Icon::Redo}
+                            variant={Variant::Secondary}
+                            onclick={ctx.link().callback(|_|Msg::Load)}
+                            />
+                    </ToolbarItem>
+                </Toolbar>
+
+                <Table<SharedTableModel<Entry>>
+                    entries={self.values.clone()}
+                    mode={TableMode::CompactExpandable}
+                    header={header}
+                    >
+                </Table<SharedTableModel<Entry>>>
+
+                if self.values.is_empty() {
+                    <Bullseye>
+                        <EmptyState
+                            title="No values"
+                            icon={Icon::Pending}
+                            size={Size::XLarge}
+                            >
+                            { "The device did not yet publish any events." }
+                        </EmptyState>
+                    </Bullseye>
+                }
+            </>
+        }
+    }
+}
+
+impl LastValues {
+    fn load(&self, ctx: &Context<Self>) -> Result<RequestHandle, anyhow::Error> {
+        Ok(ctx.props().backend.request(
+            Method::GET,
+            format!(
+                "/api/state/v1alpha1/apps/{}/devices/{}/values",
+                url_encode(&ctx.props().application),
+                url_encode(&ctx.props().device)
+            ),
+            vec![],
+            Nothing,
+            vec![],
+            ctx.callback_api::<Json<BTreeMap<String, Event>>, _>(move |response| match response {
+                ApiResponse::Success(values, _) => {
+                    Msg::SetData(values.into_values().map(Entry).collect())
+                }
+                ApiResponse::Failure(err) => Msg::Error(err.notify("Failed to load")),
+            }),
+        )?)
+    }
+}
@@ -0,0 +1,11 @@
+CREATE TABLE last_values
+(
+    APPLICATION VARCHAR(64)              NOT NULL,
+    DEVICE      VARCHAR(255)             NOT NULL,
+    CHANNEL     VARCHAR(255)             NOT NULL,
+
+    TIME        TIMESTAMP WITH TIME ZONE NOT NULL,
+    EVENT       JSONB                    NOT NULL,
+
+    PRIMARY KEY (APPLICATION, DEVICE, CHANNEL)
+);
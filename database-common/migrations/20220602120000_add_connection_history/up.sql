@@ -0,0 +1,17 @@
+CREATE TABLE connection_history
+(
+    ID           BIGSERIAL                NOT NULL,
+
+    APPLICATION  VARCHAR(64)              NOT NULL,
+    DEVICE       VARCHAR(255)             NOT NULL,
+    ENDPOINT     VARCHAR(255)             NOT NULL,
+
+    CONNECTED    TIMESTAMP WITH TIME ZONE NOT NULL,
+    DISCONNECTED TIMESTAMP WITH TIME ZONE,
+    REASON       VARCHAR(64),
+    LWT_SENT     BOOLEAN                  NOT NULL DEFAULT false,
+
+    PRIMARY KEY (ID)
+);
+
+CREATE INDEX connection_history_device ON connection_history (APPLICATION, DEVICE, CONNECTED);
@@ -0,0 +1,15 @@
+CREATE TABLE presence
+(
+    APPLICATION VARCHAR(64)              NOT NULL,
+    DEVICE      VARCHAR(255)             NOT NULL,
+    DEVICE_UID  VARCHAR(64)              NOT NULL,
+    ENDPOINT    VARCHAR(255)             NOT NULL,
+
+    CREATED     TIMESTAMP WITH TIME ZONE NOT NULL,
+    LAST_SEEN   TIMESTAMP WITH TIME ZONE NOT NULL,
+    EXPIRES     TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (APPLICATION, DEVICE)
+);
+
+CREATE INDEX presence_expires ON presence (EXPIRES);
@@ -0,0 +1,22 @@
+CREATE TABLE local_users
+(
+    USERNAME      VARCHAR(255)             NOT NULL,
+    PASSWORD_HASH VARCHAR(255)             NOT NULL,
+    ROLES         VARCHAR(64)[]            NOT NULL DEFAULT '{}',
+
+    CREATED       TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (USERNAME)
+);
+
+CREATE TABLE access_tokens
+(
+    USER_ID      VARCHAR(255)             NOT NULL,
+    PREFIX       VARCHAR(64)              NOT NULL,
+    HASHED_TOKEN VARCHAR(255)             NOT NULL,
+    DESCRIPTION  VARCHAR(1024),
+
+    CREATED      TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    PRIMARY KEY (USER_ID, PREFIX)
+);
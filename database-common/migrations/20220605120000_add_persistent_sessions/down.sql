@@ -0,0 +1,2 @@
+DROP TABLE queued_commands;
+DROP TABLE persistent_sessions;
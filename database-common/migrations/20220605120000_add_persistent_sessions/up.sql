@@ -0,0 +1,28 @@
+CREATE TABLE persistent_sessions
+(
+    APPLICATION   VARCHAR(64)              NOT NULL,
+    DEVICE        VARCHAR(255)             NOT NULL,
+
+    SUBSCRIPTIONS JSONB                    NOT NULL,
+    QUEUE         BOOLEAN                  NOT NULL DEFAULT false,
+    EXPIRY        INTEGER,
+    DISCONNECTED  TIMESTAMP WITH TIME ZONE,
+
+    PRIMARY KEY (APPLICATION, DEVICE)
+);
+
+CREATE TABLE queued_commands
+(
+    ID          BIGSERIAL                NOT NULL,
+
+    APPLICATION VARCHAR(64)              NOT NULL,
+    DEVICE      VARCHAR(255)             NOT NULL,
+
+    TIME        TIMESTAMP WITH TIME ZONE NOT NULL,
+    EVENT       JSONB                    NOT NULL,
+
+    PRIMARY KEY (ID),
+    FOREIGN KEY (APPLICATION, DEVICE) REFERENCES persistent_sessions (APPLICATION, DEVICE) ON DELETE CASCADE
+);
+
+CREATE INDEX queued_commands_device ON queued_commands (APPLICATION, DEVICE, ID);
@@ -0,0 +1,26 @@
+CREATE TABLE timeseries
+(
+    TIME        TIMESTAMP WITH TIME ZONE NOT NULL,
+
+    APPLICATION VARCHAR(64)              NOT NULL,
+    DEVICE      VARCHAR(255)             NOT NULL,
+    CHANNEL     VARCHAR(255)             NOT NULL,
+
+    MEASUREMENT VARCHAR(255)             NOT NULL,
+    FIELD       VARCHAR(255)             NOT NULL,
+    VALUE       DOUBLE PRECISION         NOT NULL,
+    TAGS        JSONB                    NOT NULL
+);
+
+CREATE INDEX timeseries_measurement ON timeseries (APPLICATION, MEASUREMENT, TIME DESC);
+CREATE INDEX timeseries_device ON timeseries (APPLICATION, DEVICE, TIME DESC);
+
+-- use a hypertable, if the TimescaleDB extension is available
+DO
+$$
+    BEGIN
+        IF EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') THEN
+            PERFORM create_hypertable('timeseries', 'time');
+        END IF;
+    END
+$$;
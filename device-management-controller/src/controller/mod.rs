@@ -1,4 +1,5 @@
 pub mod resource;
+pub mod twin;
 
 use crate::controller::resource::{ApplicationAndDevice, ApplicationAndDeviceKey};
 use async_trait::async_trait;
@@ -109,7 +110,7 @@ impl EventController {
 
         match outcome {
             Ok(PublishOutcome::Accepted) => ProcessOutcome::Complete(()),
-            Ok(PublishOutcome::QueueFull) => {
+            Ok(PublishOutcome::QueueFull | PublishOutcome::RateLimited) => {
                 ProcessOutcome::Retry((), Some(self.config.retry_full))
             }
             Ok(PublishOutcome::Rejected) => {
@@ -40,16 +40,16 @@ pub struct ApplicationAndDevice {
     pub device: Option<registry::v1::Device>,
 }
 
-#[async_trait]
-impl ResourceOperations<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for EventController {
-    async fn get(
-        &self,
+impl ApplicationAndDevice {
+    /// Look up the application, and the device if present.
+    pub async fn get(
+        registry: &registry::v1::Client,
         key: &ApplicationAndDeviceKey,
-    ) -> Result<Option<ApplicationAndDevice>, ClientError> {
+    ) -> Result<Option<Self>, ClientError> {
         Ok(
             match try_join!(
-                self.get_app(&key.application,),
-                self.get_device(&key.application, &key.device,),
+                registry.get_app(&key.application,),
+                registry.get_device(&key.application, &key.device,),
             )? {
                 // app present, maybe device too
                 (Some(application), device) => Some(ApplicationAndDevice {
@@ -61,6 +61,16 @@ impl ResourceOperations<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for E
             },
         )
     }
+}
+
+#[async_trait]
+impl ResourceOperations<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for EventController {
+    async fn get(
+        &self,
+        key: &ApplicationAndDeviceKey,
+    ) -> Result<Option<ApplicationAndDevice>, ClientError> {
+        ApplicationAndDevice::get(self, key).await
+    }
 
     async fn update_if(&self, _original: &(), mut _current: ()) -> Result<(), ReconcileError> {
         Ok(())
@@ -0,0 +1,114 @@
+use crate::controller::{
+    resource::{ApplicationAndDevice, ApplicationAndDeviceKey},
+    ControllerConfig,
+};
+use async_trait::async_trait;
+use drogue_client::{error::ClientError, registry, Translator};
+use drogue_cloud_endpoint_common::sender::{send_delta, DownstreamSender, UpstreamSender};
+use drogue_cloud_operator_common::controller::{
+    base::{ControllerOperation, ProcessOutcome, ResourceOperations},
+    reconciler::ReconcileError,
+};
+use drogue_cloud_service_api::services::twin::{DeviceTwinSpec, DeviceTwinStatus};
+use std::ops::Deref;
+
+/// Sends the delta of the device twin, when the desired state changes.
+pub struct TwinController {
+    config: ControllerConfig,
+    registry: registry::v1::Client,
+    events: DownstreamSender,
+    commands: UpstreamSender,
+}
+
+impl TwinController {
+    pub fn new(
+        config: ControllerConfig,
+        registry: registry::v1::Client,
+        events: DownstreamSender,
+        commands: UpstreamSender,
+    ) -> Self {
+        Self {
+            config,
+            registry,
+            events,
+            commands,
+        }
+    }
+}
+
+impl Deref for TwinController {
+    type Target = registry::v1::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.registry
+    }
+}
+
+#[async_trait]
+impl ControllerOperation<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for TwinController {
+    async fn process_resource(
+        &self,
+        resource: ApplicationAndDevice,
+    ) -> Result<ProcessOutcome<()>, ReconcileError> {
+        let device = match &resource.device {
+            Some(device) if device.metadata.deletion_timestamp.is_none() => device,
+            _ => return Ok(ProcessOutcome::Complete(())),
+        };
+
+        let spec: DeviceTwinSpec = match device.section() {
+            Some(Ok(spec)) => spec,
+            Some(Err(err)) => {
+                log::info!("Invalid twin spec: {err}");
+                return Ok(ProcessOutcome::Complete(()));
+            }
+            None => return Ok(ProcessOutcome::Complete(())),
+        };
+        let status: DeviceTwinStatus = device.section().and_then(|s| s.ok()).unwrap_or_default();
+
+        let delta = spec.delta(&status);
+        if delta.is_empty() {
+            return Ok(ProcessOutcome::Complete(()));
+        }
+
+        log::debug!("Twin delta for {}: {:?}", device.metadata.name, delta);
+
+        match send_delta(
+            &self.events,
+            &self.commands,
+            &resource.application,
+            device,
+            &device.metadata.name,
+            delta,
+        )
+        .await
+        {
+            Ok(()) => Ok(ProcessOutcome::Complete(())),
+            Err(err) => {
+                log::warn!("Failed to send twin delta: {err}");
+                Ok(ProcessOutcome::Retry((), Some(self.config.retry_failed)))
+            }
+        }
+    }
+
+    async fn recover(&self, _message: &str, _resource: ApplicationAndDevice) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResourceOperations<ApplicationAndDeviceKey, ApplicationAndDevice, ()> for TwinController {
+    async fn get(
+        &self,
+        key: &ApplicationAndDeviceKey,
+    ) -> Result<Option<ApplicationAndDevice>, ClientError> {
+        ApplicationAndDevice::get(&self.registry, key).await
+    }
+
+    async fn update_if(&self, _original: &(), mut _current: ()) -> Result<(), ReconcileError> {
+        Ok(())
+    }
+
+    fn ref_output(_input: &ApplicationAndDevice) -> &() {
+        &()
+    }
+}
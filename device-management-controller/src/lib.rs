@@ -1,12 +1,15 @@
 mod controller;
 
-use crate::controller::{resource::ApplicationAndDeviceKey, ControllerConfig, EventController};
+use crate::controller::{
+    resource::ApplicationAndDeviceKey, twin::TwinController, ControllerConfig, EventController,
+};
+use async_trait::async_trait;
 use drogue_cloud_endpoint_common::{
-    sender::{DownstreamSender, ExternalClientPoolConfig},
-    sink::KafkaSink,
+    sender::{DownstreamSender, ExternalClientPoolConfig, UpstreamSender},
+    sink::MessagingSink,
 };
 use drogue_cloud_operator_common::controller::base::{
-    queue::WorkQueueConfig, BaseController, EventDispatcher, FnEventProcessor,
+    queue::WorkQueueConfig, BaseController, EventDispatcher, EventProcessor, FnEventProcessor,
 };
 use drogue_cloud_registry_events::{
     stream::{KafkaEventStream, KafkaStreamConfig},
@@ -22,6 +25,9 @@ use serde::Deserialize;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// The path of registry events, announcing a change of the desired state of the device twin.
+const TWIN_DESIRED_PATH: &str = ".spec.twin";
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     #[serde(default = "defaults::bind_addr")]
@@ -47,14 +53,16 @@ pub struct Config {
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     // downstream sender
 
+    let sink = MessagingSink::from_config(
+        config.kafka_downstream_config,
+        config.check_kafka_topic_ready,
+    )?;
     let sender = DownstreamSender::new(
-        KafkaSink::from_config(
-            config.kafka_downstream_config,
-            config.check_kafka_topic_ready,
-        )?,
-        config.instance,
-        config.endpoint_pool,
+        sink.clone(),
+        config.instance.clone(),
+        config.endpoint_pool.clone(),
     )?;
+    let commands = UpstreamSender::new(config.instance, sink, config.endpoint_pool)?;
 
     // registry client
 
@@ -63,15 +71,39 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // event source
 
     let controller = Arc::new(Mutex::new(BaseController::new(
-        config.work_queue,
+        config.work_queue.clone(),
         "mgmt-events",
-        EventController::new(config.controller, registry, sender),
+        EventController::new(config.controller.clone(), registry.clone(), sender.clone()),
+    )?));
+
+    let twin_controller = Arc::new(Mutex::new(BaseController::new(
+        config.work_queue,
+        "twin-events",
+        TwinController::new(config.controller, registry, sender, commands),
     )?));
 
     // event source - device registry
 
-    let registry_dispatcher =
-        EventDispatcher::one(FnEventProcessor::new(controller, |evt| match evt {
+    let registry_dispatcher = EventDispatcher::new(vec![
+        // changes of the desired state, also passed on to the next processor
+        Box::new(Inspect(FnEventProcessor::new(
+            twin_controller,
+            |evt| match evt {
+                Event::Device {
+                    application,
+                    uid,
+                    device,
+                    path,
+                    ..
+                } if path == TWIN_DESIRED_PATH => Some(ApplicationAndDeviceKey {
+                    application: application.clone(),
+                    device: device.clone(),
+                    device_uid: uid.clone(),
+                }),
+                _ => None,
+            },
+        ))),
+        Box::new(FnEventProcessor::new(controller, |evt| match evt {
             Event::Device {
                 application,
                 uid,
@@ -83,7 +115,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
                 device_uid: uid.clone(),
             }),
             _ => None,
-        }));
+        })),
+    ]);
     let registry = KafkaEventStream::new(config.kafka_source)?;
     let registry = registry.run(registry_dispatcher);
 
@@ -95,3 +128,18 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     Ok(())
 }
+
+/// Processes an event, but doesn't consume it, so that the next processor will see it too.
+struct Inspect<P>(P);
+
+#[async_trait]
+impl<E, P> EventProcessor<E> for Inspect<P>
+where
+    E: Send + Sync,
+    P: EventProcessor<E>,
+{
+    async fn handle(&self, event: &E) -> Result<bool, ()> {
+        self.0.handle(event).await?;
+        Ok(false)
+    }
+}
@@ -19,7 +19,7 @@ use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
     auth::{openid, pat},
     client::ClientConfig,
-    keycloak::{client::KeycloakAdminClient, KeycloakAdminClientConfig, KeycloakClient},
+    keycloak::{directory::UserDirectory, KeycloakAdminClientConfig},
 };
 use serde::Deserialize;
 use service::PostgresManagementServiceConfig;
@@ -42,7 +42,9 @@ pub struct Config {
 
     pub kafka_sender: KafkaSenderConfig,
 
-    pub keycloak: KeycloakAdminClientConfig,
+    /// Keycloak instance to look up users, uses the local user directory when missing.
+    #[serde(default)]
+    pub keycloak: Option<KeycloakAdminClientConfig>,
 
     #[serde(default)]
     pub http: HttpConfig,
@@ -174,13 +176,9 @@ pub async fn configurator(
     let sender = KafkaEventSender::new(config.kafka_sender)
         .context("Unable to create Kafka event sender")?;
 
-    let keycloak_admin_client = KeycloakAdminClient::new(config.keycloak)?;
+    let users = UserDirectory::from_config(config.keycloak)?;
 
-    let service = service::PostgresManagementService::new(
-        config.database_config,
-        sender,
-        keycloak_admin_client,
-    )?;
+    let service = service::PostgresManagementService::new(config.database_config, sender, users)?;
 
     let data = web::Data::new(WebData {
         authenticator: authenticator.as_ref().cloned(),
@@ -197,7 +195,7 @@ pub async fn configurator(
                 authenticator.clone(),
                 user_auth.clone().map(pat::Authenticator::new),
             ));
-            app!(cfg, KafkaEventSender, KeycloakAdminClient, auth)
+            app!(cfg, KafkaEventSender, UserDirectory, auth)
                 // for the management service
                 .app_data(data.clone())
                 // for the admin service
@@ -0,0 +1,73 @@
+use crate::service::history::PostgresConnectionHistoryService;
+use drogue_cloud_service_api::webapp::{web, *};
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+const fn default_limit() -> u32 {
+    100
+}
+
+/// The maximum number of entries returned by a single history request.
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PeriodQuery {
+    #[serde(with = "humantime_serde", default = "default_period")]
+    pub period: Duration,
+}
+
+const fn default_period() -> Duration {
+    Duration::from_secs(24 * 60 * 60)
+}
+
+/// The maximum period of uptime and inactivity requests.
+pub const MAX_PERIOD: Duration = Duration::from_secs(366 * 24 * 60 * 60);
+
+impl PeriodQuery {
+    /// Get the requested period, rejecting periods exceeding the maximum.
+    pub fn period(&self) -> Result<chrono::Duration, Error> {
+        if self.period > MAX_PERIOD {
+            return Err(error::ErrorBadRequest("Period must not exceed 366 days"));
+        }
+        chrono::Duration::from_std(self.period).map_err(error::ErrorBadRequest)
+    }
+}
+
+pub async fn history(
+    service: web::Data<PostgresConnectionHistoryService>,
+    path: web::Path<(String, String)>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    let history = service
+        .history(&application, &device, query.limit.min(MAX_LIMIT))
+        .await?;
+    Ok(HttpResponse::Ok().json(history))
+}
+
+pub async fn uptime(
+    service: web::Data<PostgresConnectionHistoryService>,
+    path: web::Path<(String, String)>,
+    query: web::Query<PeriodQuery>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    let uptime = service
+        .uptime(&application, &device, query.period()?)
+        .await?;
+    Ok(HttpResponse::Ok().json(uptime))
+}
+
+pub async fn inactive(
+    service: web::Data<PostgresConnectionHistoryService>,
+    application: web::Path<String>,
+    query: web::Query<PeriodQuery>,
+) -> Result<HttpResponse, Error> {
+    let inactive = service.inactive(&application, query.period()?).await?;
+    Ok(HttpResponse::Ok().json(inactive))
+}
@@ -1,3 +1,7 @@
+pub mod history;
+pub mod persistent;
+pub mod values;
+
 use crate::service::DeviceStateService;
 use drogue_cloud_service_api::{
     services::device_state::*,
@@ -37,6 +41,16 @@ pub async fn delete(
     Ok(HttpResponse::NoContent().finish())
 }
 
+pub async fn refresh(
+    service: web::Data<dyn DeviceStateService>,
+    path: web::Path<(String, String)>,
+    body: web::Json<PresenceRequest>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    service.refresh(application, device, body.0).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub async fn get(
     service: web::Data<dyn DeviceStateService>,
     path: web::Path<(String, String)>,
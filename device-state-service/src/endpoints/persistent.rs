@@ -0,0 +1,66 @@
+use crate::service::persistent::PostgresPersistentSessionService;
+use drogue_cloud_service_api::{
+    services::device_state::PersistentSession,
+    webapp::{web, *},
+};
+
+pub async fn get(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    Ok(match service.get(&application, &device).await? {
+        Some(session) => HttpResponse::Ok().json(session),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
+pub async fn put(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String)>,
+    body: web::Json<PersistentSession>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    service.put(&application, &device, &body.0).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn delete(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    service.delete(&application, &device).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn disconnect(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    service.disconnect(&application, &device).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub async fn get_commands(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    let commands = service.get_commands(&application, &device).await?;
+    Ok(HttpResponse::Ok().json(commands))
+}
+
+pub async fn ack_command(
+    service: web::Data<PostgresPersistentSessionService>,
+    path: web::Path<(String, String, i64)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device, id) = path.into_inner();
+    Ok(
+        match service.ack_command(&application, &device, id).await? {
+            true => HttpResponse::NoContent().finish(),
+            false => HttpResponse::NotFound().finish(),
+        },
+    )
+}
@@ -0,0 +1,63 @@
+use crate::service::values::PostgresLastValueService;
+use drogue_cloud_service_api::webapp::{web, *};
+use serde::Deserialize;
+
+/// A page of the last values of an application.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApplicationQuery {
+    /// The device of the last value of the previous page.
+    #[serde(default)]
+    pub after_device: Option<String>,
+    /// The channel of the last value of the previous page.
+    #[serde(default)]
+    pub after_channel: Option<String>,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+const fn default_limit() -> u32 {
+    100
+}
+
+/// The maximum number of values returned by a single request.
+const MAX_LIMIT: u32 = 1000;
+
+pub async fn get_all(
+    service: web::Data<PostgresLastValueService>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device) = path.into_inner();
+    let values = service.get_all(&application, &device).await?;
+    Ok(HttpResponse::Ok().json(values))
+}
+
+pub async fn get(
+    service: web::Data<PostgresLastValueService>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (application, device, channel) = path.into_inner();
+    Ok(match service.get(&application, &device, &channel).await? {
+        Some(value) => HttpResponse::Ok().json(value),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
+pub async fn get_application(
+    service: web::Data<PostgresLastValueService>,
+    path: web::Path<String>,
+    query: web::Query<ApplicationQuery>,
+) -> Result<HttpResponse, Error> {
+    let after = match (&query.after_device, &query.after_channel) {
+        (Some(device), Some(channel)) => Some((device.as_str(), channel.as_str())),
+        (None, None) => None,
+        _ => {
+            return Err(error::ErrorBadRequest(
+                "'after_device' and 'after_channel' must be provided together",
+            ))
+        }
+    };
+    let values = service
+        .get_application(&path.into_inner(), after, query.limit.min(MAX_LIMIT))
+        .await?;
+    Ok(HttpResponse::Ok().json(values))
+}
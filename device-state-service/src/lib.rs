@@ -1,21 +1,33 @@
 pub mod endpoints;
 pub mod service;
 
-use crate::service::{postgres::PostgresServiceConfiguration, DeviceStateService};
+use crate::service::{
+    history::{ConnectionHistoryConfig, PostgresConnectionHistoryService},
+    persistent::{CommandQueueConfig, PostgresPersistentSessionService},
+    postgres::PostgresServiceConfiguration,
+    values::{LastValuesConfig, PostgresLastValueService},
+    DeviceStateService,
+};
 use actix_web::web;
-use drogue_client::registry;
+use drogue_client::{registry, user, user::v1::authz::Permission};
+use drogue_cloud_database_common::postgres;
 use drogue_cloud_endpoint_common::{
     sender::{DownstreamSender, ExternalClientPoolConfig},
-    sink::KafkaSink,
+    sink::MessagingSink,
 };
 use drogue_cloud_service_api::{
+    health::{BoxedHealthChecked, HealthChecked},
     kafka::KafkaClientConfig,
-    webapp::{self as actix_web},
+    webapp::{self as actix_web, web::ServiceConfig},
 };
 use drogue_cloud_service_common::{
     actix::http::{HttpBuilder, HttpConfig},
+    actix_auth::{authentication::AuthN, authorization::ApplicationAuthorizer},
     app::{Startup, StartupExt},
-    auth::openid::{Authenticator, AuthenticatorConfig},
+    auth::{
+        openid::{Authenticator, AuthenticatorConfig},
+        pat,
+    },
     client::ClientConfig,
     defaults, openid_auth,
 };
@@ -38,10 +50,35 @@ pub struct Config {
 
     pub registry: ClientConfig,
 
+    #[serde(default)]
+    pub user_auth: Option<ClientConfig>,
+
+    /// Collect the last known values of devices
+    #[serde(default)]
+    pub last_values: Option<LastValuesConfig>,
+
+    #[serde(default)]
+    pub history: ConnectionHistoryConfig,
+
+    /// Queue commands for disconnected persistent sessions
+    #[serde(default)]
+    pub command_queue: Option<CommandQueueConfig>,
+
     #[serde(default)]
     pub http: HttpConfig,
 }
 
+/// Configuration for serving the user facing API, as part of a different HTTP server.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserApiConfig {
+    pub pg: postgres::Config,
+
+    pub oauth: AuthenticatorConfig,
+
+    #[serde(default)]
+    pub user_auth: Option<ClientConfig>,
+}
+
 #[macro_export]
 macro_rules! app {
     ($cfg:expr, $data:expr, $auth: expr) => {{
@@ -54,6 +91,10 @@ macro_rules! app {
                     web::resource("/states/{application}/{device}")
                         .route(web::get().to(endpoints::get)),
                 )
+                .service(
+                    web::resource("/presence/{application}/{device}")
+                        .route(web::put().to(endpoints::refresh)),
+                )
                 .service(web::resource("/sessions").route(web::put().to(endpoints::init)))
                 .service(
                     web::resource("/sessions/{session}").route(web::post().to(endpoints::ping)),
@@ -62,16 +103,118 @@ macro_rules! app {
                     web::resource("/sessions/{session}/states/{application}/{device}")
                         .route(web::put().to(endpoints::create))
                         .route(web::delete().to(endpoints::delete)),
+                )
+                .service(
+                    web::resource("/values/{application}")
+                        .route(web::get().to(endpoints::values::get_application)),
+                )
+                .service(
+                    web::resource("/persistent/{application}/{device}")
+                        .route(web::get().to(endpoints::persistent::get))
+                        .route(web::put().to(endpoints::persistent::put))
+                        .route(web::delete().to(endpoints::persistent::delete)),
+                )
+                .service(
+                    web::resource("/persistent/{application}/{device}/disconnect")
+                        .route(web::post().to(endpoints::persistent::disconnect)),
+                )
+                .service(
+                    web::resource("/persistent/{application}/{device}/commands")
+                        .route(web::get().to(endpoints::persistent::get_commands)),
+                )
+                .service(
+                    web::resource("/persistent/{application}/{device}/commands/{id}")
+                        .route(web::delete().to(endpoints::persistent::ack_command)),
                 ),
         )
     }};
 }
 
+/// Configure the user facing API for retrieving the last known values and the connection history.
+pub fn configure_user_api(
+    cfg: &mut ServiceConfig,
+    values: web::Data<PostgresLastValueService>,
+    history: web::Data<PostgresConnectionHistoryService>,
+    authenticator: Option<Authenticator>,
+    user_auth: Option<user::v1::Client>,
+) {
+    cfg.app_data(values).app_data(history).service(
+        web::scope("/api/state/v1alpha1/apps/{application}")
+            .wrap(ApplicationAuthorizer::wrapping(
+                user_auth.clone(),
+                Permission::Read,
+            ))
+            .wrap(AuthN::from((
+                authenticator,
+                user_auth.map(pat::Authenticator::new),
+            )))
+            .route("/inactive", web::get().to(endpoints::history::inactive))
+            .route(
+                "/devices/{device}/values",
+                web::get().to(endpoints::values::get_all),
+            )
+            .route(
+                "/devices/{device}/values/{channel}",
+                web::get().to(endpoints::values::get),
+            )
+            .route(
+                "/devices/{device}/connections",
+                web::get().to(endpoints::history::history),
+            )
+            .route(
+                "/devices/{device}/uptime",
+                web::get().to(endpoints::history::uptime),
+            ),
+    );
+}
+
+pub async fn user_api_configurator(
+    config: UserApiConfig,
+) -> anyhow::Result<(
+    impl Fn(&mut ServiceConfig) + Send + Sync + Clone,
+    Vec<Box<dyn HealthChecked>>,
+)> {
+    let authenticator = config.oauth.into_client().await?;
+    let user_auth = if let Some(user_auth) = config.user_auth {
+        Some(user_auth.into_client().await?)
+    } else {
+        None
+    };
+
+    let values = PostgresLastValueService::new(config.pg.clone())?;
+    let history = PostgresConnectionHistoryService::new(config.pg)?;
+
+    let health = vec![values.clone().boxed(), history.clone().boxed()];
+
+    let values = web::Data::new(values);
+    let history = web::Data::new(history);
+
+    Ok((
+        move |cfg: &mut ServiceConfig| {
+            configure_user_api(
+                cfg,
+                values.clone(),
+                history.clone(),
+                authenticator.clone(),
+                user_auth.clone(),
+            );
+        },
+        health,
+    ))
+}
+
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     // set up authentication
 
+    let user_auth = if let Some(user_auth) = config.user_auth {
+        Some(user_auth.into_client::<user::v1::Client>().await?)
+    } else {
+        None
+    };
+
     let authenticator = config.oauth.into_client().await?;
     log::info!("Authenticator: {authenticator:?}");
+    let values_authenticator = authenticator.clone();
     let authenticator = authenticator.map(web::Data::new);
 
     // set up registry client
@@ -80,8 +223,8 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // downstream sender
 
     let sender = DownstreamSender::new(
-        KafkaSink::from_config(
-            config.kafka_downstream_config,
+        MessagingSink::from_config(
+            config.kafka_downstream_config.clone(),
             config.check_kafka_topic_ready,
         )?,
         config.instance,
@@ -90,12 +233,60 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // service
 
-    let service =
-        service::postgres::PostgresDeviceStateService::new(config.service, sender, registry)?;
+    let service = service::postgres::PostgresDeviceStateService::new(
+        config.service.clone(),
+        sender,
+        registry,
+    )?;
     startup.check(service.clone());
 
     let pruner = service::postgres::run_pruner(service.clone()).boxed();
 
+    // last values
+
+    let values = PostgresLastValueService::new(config.service.pg.clone())?;
+    startup.check(values.clone());
+
+    if let Some(last_values) = config.last_values {
+        startup.spawn(
+            service::values::run_collector(
+                values.clone(),
+                last_values,
+                config.kafka_downstream_config.clone(),
+            )
+            .boxed(),
+        );
+    }
+
+    let values = web::Data::new(values);
+
+    // connection history
+
+    let history = PostgresConnectionHistoryService::new(config.service.pg.clone())?;
+    startup.check(history.clone());
+    startup.spawn(service::history::run_pruner(history.clone(), config.history).boxed());
+
+    let history = web::Data::new(history);
+
+    // persistent sessions
+
+    let persistent = PostgresPersistentSessionService::new(config.service.pg.clone())?;
+    startup.check(persistent.clone());
+    startup.spawn(service::persistent::run_pruner(persistent.clone()).boxed());
+
+    if let Some(command_queue) = config.command_queue {
+        startup.spawn(
+            service::persistent::run_collector(
+                persistent.clone(),
+                command_queue,
+                config.kafka_downstream_config.clone(),
+            )
+            .boxed(),
+        );
+    }
+
+    let persistent = web::Data::new(persistent);
+
     let service: Arc<dyn DeviceStateService> = Arc::new(service);
     let service: web::Data<dyn DeviceStateService> = web::Data::from(service);
 
@@ -104,6 +295,15 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // main server
 
     let main = HttpBuilder::new(config.http, Some(startup.runtime_config()), move |cfg| {
+        // must be registered before the internal API, as that shares the same prefix
+        configure_user_api(
+            cfg,
+            values.clone(),
+            history.clone(),
+            values_authenticator.clone(),
+            user_auth.clone(),
+        );
+
         let auth = openid_auth!(req -> {
             req
                 .app_data::<web::Data<Authenticator>>().as_ref().map(|s|s.get_ref())
@@ -114,7 +314,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
             app = app.app_data(auth.clone())
         }
 
-        app.app_data(service.clone());
+        app.app_data(service.clone()).app_data(persistent.clone());
     })
     .run()?;
 
@@ -8,6 +8,8 @@ pub enum ServiceError {
     NotInitialized,
     #[error("application not found")]
     ApplicationNotFound,
+    #[error("period out of range")]
+    InvalidPeriod,
     #[error("internal error: {0}")]
     Internal(String),
     #[error("connection pool error: {0}")]
@@ -33,6 +35,10 @@ impl ResponseError for ServiceError {
                 error: "ApplicationNotFound".into(),
                 message: self.to_string(),
             }),
+            Self::InvalidPeriod => HttpResponse::BadRequest().json(ErrorInformation {
+                error: "InvalidPeriod".into(),
+                message: self.to_string(),
+            }),
             Self::Internal(_) => HttpResponse::InternalServerError().json(ErrorInformation {
                 error: "Internal".into(),
                 message: self.to_string(),
@@ -0,0 +1,345 @@
+use super::ServiceError;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Pool, Transaction};
+use drogue_cloud_database_common::{postgres, DatabaseService};
+use drogue_cloud_service_api::{
+    health::HealthChecked,
+    services::device_state::{
+        ConnectionHistoryEntry, DeviceUptime, DisconnectReason, InactiveDevice,
+    },
+};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_postgres::types::Type;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConnectionHistoryConfig {
+    /// The time to keep the history of connections.
+    ///
+    /// The most recent connection of a device is always kept.
+    #[serde(with = "humantime_serde", default = "default_retention")]
+    pub retention: Duration,
+}
+
+const fn default_retention() -> Duration {
+    Duration::from_secs(30 * 24 * 60 * 60)
+}
+
+impl Default for ConnectionHistoryConfig {
+    fn default() -> Self {
+        Self {
+            retention: default_retention(),
+        }
+    }
+}
+
+/// Record a new connection of a device.
+pub(crate) async fn record_connect(
+    t: &Transaction<'_>,
+    application: &str,
+    device: &str,
+    endpoint: &str,
+    time: DateTime<Utc>,
+) -> Result<(), ServiceError> {
+    t.execute(
+        r#"
+INSERT INTO
+    connection_history
+(
+    APPLICATION,
+    DEVICE,
+    ENDPOINT,
+    CONNECTED
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4
+)
+"#,
+        &[&application, &device, &endpoint, &time],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Record the end of the current connection of a device, through an endpoint.
+///
+/// Only connections of the provided endpoint will be closed, as a device might be connected to
+/// different endpoints at the same time. If the endpoint is unknown, all open connections will
+/// be closed.
+pub(crate) async fn record_disconnect(
+    t: &Transaction<'_>,
+    application: &str,
+    device: &str,
+    endpoint: Option<&str>,
+    time: DateTime<Utc>,
+    reason: DisconnectReason,
+    lwt_sent: bool,
+) -> Result<(), ServiceError> {
+    t.execute(
+        r#"
+UPDATE
+    connection_history
+SET
+    DISCONNECTED = $4,
+    REASON = $5,
+    LWT_SENT = $6
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        ($3::text IS NULL OR ENDPOINT = $3)
+    AND
+        DISCONNECTED IS NULL
+"#,
+        &[
+            &application,
+            &device,
+            &endpoint,
+            &time,
+            &reason.as_str(),
+            &lwt_sent,
+        ],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Access to the connection history of devices.
+#[derive(Clone)]
+pub struct PostgresConnectionHistoryService {
+    pool: Pool,
+}
+
+impl PostgresConnectionHistoryService {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+
+    /// Get the most recent connections of a device, newest first.
+    pub async fn history(
+        &self,
+        application: &str,
+        device: &str,
+        limit: u32,
+    ) -> Result<Vec<ConnectionHistoryEntry>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT ENDPOINT, CONNECTED, DISCONNECTED, REASON, LWT_SENT FROM
+    connection_history
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+ORDER BY
+    CONNECTED DESC
+LIMIT
+    $3
+"#,
+                &[Type::VARCHAR, Type::VARCHAR, Type::INT8],
+            )
+            .await?;
+
+        let mut result = Vec::new();
+        for row in c
+            .query(&stmt, &[&application, &device, &i64::from(limit)])
+            .await?
+        {
+            let reason: Option<String> = row.try_get("REASON")?;
+            result.push(ConnectionHistoryEntry {
+                endpoint: row.try_get("ENDPOINT")?,
+                connected: row.try_get("CONNECTED")?,
+                disconnected: row.try_get("DISCONNECTED")?,
+                reason: reason.and_then(|reason| {
+                    reason
+                        .parse()
+                        .map_err(|err| log::info!("Ignoring stored reason: {err}"))
+                        .ok()
+                }),
+                lwt_sent: row.try_get("LWT_SENT")?,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Evaluate the uptime of a device, for the provided period up to now.
+    pub async fn uptime(
+        &self,
+        application: &str,
+        device: &str,
+        period: chrono::Duration,
+    ) -> Result<DeviceUptime, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let now = Utc::now();
+        let since = now
+            .checked_sub_signed(period)
+            .ok_or(ServiceError::InvalidPeriod)?;
+
+        let row = c
+            .query_one(
+                r#"
+SELECT
+    BOOL_OR(DISCONNECTED IS NULL) AS CONNECTED,
+    MAX(COALESCE(DISCONNECTED, $3)) AS LAST_SEEN
+FROM
+    connection_history
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+"#,
+                &[&application, &device, &now],
+            )
+            .await?;
+
+        let connected: Option<bool> = row.try_get("CONNECTED")?;
+        let last_seen: Option<DateTime<Utc>> = row.try_get("LAST_SEEN")?;
+
+        let row = c
+            .query_one(
+                r#"
+SELECT
+    COALESCE(SUM(EXTRACT(EPOCH FROM (COALESCE(DISCONNECTED, $3) - GREATEST(CONNECTED, $4)))::float8), 0) AS UPTIME
+FROM
+    connection_history
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        (DISCONNECTED IS NULL OR DISCONNECTED > $4)
+"#,
+                &[&application, &device, &now, &since],
+            )
+            .await?;
+
+        let uptime_seconds: f64 = row.try_get("UPTIME")?;
+        let period_seconds = period.num_milliseconds() as f64 / 1000f64;
+        let uptime_ratio = if period_seconds > 0f64 {
+            (uptime_seconds / period_seconds).clamp(0f64, 1f64)
+        } else {
+            0f64
+        };
+
+        Ok(DeviceUptime {
+            connected: connected.unwrap_or_default(),
+            last_seen,
+            since,
+            uptime_seconds,
+            uptime_ratio,
+        })
+    }
+
+    /// Find all disconnected devices of an application, which were not seen for the provided
+    /// duration, longest not seen first.
+    ///
+    /// Devices which never connected are not reported.
+    pub async fn inactive(
+        &self,
+        application: &str,
+        duration: chrono::Duration,
+    ) -> Result<Vec<InactiveDevice>, ServiceError> {
+        let since = Utc::now()
+            .checked_sub_signed(duration)
+            .ok_or(ServiceError::InvalidPeriod)?;
+
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT
+    DEVICE,
+    MAX(DISCONNECTED) AS LAST_SEEN
+FROM
+    connection_history
+WHERE
+    APPLICATION = $1
+GROUP BY
+    DEVICE
+HAVING
+        BOOL_AND(DISCONNECTED IS NOT NULL)
+    AND
+        MAX(DISCONNECTED) < $2
+ORDER BY
+    LAST_SEEN ASC
+"#,
+                &[Type::VARCHAR, Type::TIMESTAMPTZ],
+            )
+            .await?;
+
+        let mut result = Vec::new();
+        for row in c.query(&stmt, &[&application, &since]).await? {
+            result.push(InactiveDevice {
+                device: row.try_get("DEVICE")?,
+                last_seen: row.try_get("LAST_SEEN")?,
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Delete all closed connections older than the retention time, keeping the most recent
+    /// connection of each device.
+    pub async fn prune(&self, retention: chrono::Duration) -> Result<u64, ServiceError> {
+        let c = self.pool.get().await?;
+
+        Ok(c.execute(
+            r#"
+DELETE FROM
+    connection_history H
+WHERE
+        H.DISCONNECTED < $1
+    AND
+        EXISTS (
+            SELECT 1 FROM
+                connection_history N
+            WHERE
+                    N.APPLICATION = H.APPLICATION
+                AND
+                    N.DEVICE = H.DEVICE
+                AND
+                    N.CONNECTED > H.CONNECTED
+        )
+"#,
+            &[&(Utc::now() - retention)],
+        )
+        .await?)
+    }
+}
+
+impl DatabaseService for PostgresConnectionHistoryService {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl HealthChecked for PostgresConnectionHistoryService {}
+
+pub async fn run_pruner(
+    service: PostgresConnectionHistoryService,
+    config: ConnectionHistoryConfig,
+) -> anyhow::Result<()> {
+    let retention = chrono::Duration::from_std(config.retention)?;
+
+    loop {
+        match service.prune(retention).await {
+            Ok(pruned) => log::info!("Pruned {pruned} connection history entries"),
+            Err(err) => log::warn!("Failed to prune connection history: {err}"),
+        }
+        sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
@@ -1,6 +1,9 @@
 mod error;
 
+pub mod history;
+pub mod persistent;
 pub mod postgres;
+pub mod values;
 
 pub use error::*;
 
@@ -51,7 +54,20 @@ pub trait DeviceStateService: Send + Sync {
     /// limiting the response size.
     async fn ping(&self, instance: String) -> Result<PingResponse, ServiceError>;
 
+    /// Refresh the presence of a device, which is not connected through a session.
+    ///
+    /// If the device was not present before, it will be considered connected. If it doesn't get
+    /// refreshed within the presence timeout, it will be considered disconnected.
+    async fn refresh(
+        &self,
+        application: String,
+        device: String,
+        presence: PresenceRequest,
+    ) -> Result<(), ServiceError>;
+
     /// Get the current state of a device.
+    ///
+    /// This includes devices which are present, but not connected through a session.
     async fn get(
         &self,
         application: String,
@@ -0,0 +1,487 @@
+use super::ServiceError;
+
+use chrono::Utc;
+use cloudevents::{AttributesReader, Event};
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{postgres, DatabaseService};
+use drogue_cloud_endpoint_common::command::CommandAddress;
+use drogue_cloud_event_common::stream::{AutoAck, EventStream, EventStreamConfig};
+use drogue_cloud_service_api::{
+    health::HealthChecked,
+    kafka::{KafkaClientConfig, KafkaConfig},
+    services::device_state::{PersistentSession, QueuedCommand, SessionSubscription},
+};
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter, IntCounter};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_postgres::types::{Json, Type};
+
+lazy_static! {
+    pub static ref DROPPED_COMMANDS: IntCounter = register_int_counter!(
+        "drogue_persistent_session_dropped_commands",
+        "Commands dropped because the queue of a persistent session was full"
+    )
+    .unwrap();
+}
+
+/// A session is considered expired when the device is disconnected for longer than its expiry.
+const NOT_EXPIRED: &str = r#"(
+        DISCONNECTED IS NULL
+    OR
+        EXPIRY IS NULL
+    OR
+        DISCONNECTED + EXPIRY * INTERVAL '1 second' > NOW()
+)"#;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CommandQueueConfig {
+    /// The topic to consume commands from.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    #[serde(default = "default_consumer_group")]
+    pub consumer_group: String,
+    /// The maximum number of commands queued for a single session.
+    #[serde(default = "default_max_commands")]
+    pub max_commands: u32,
+}
+
+fn default_topic() -> String {
+    "iot-commands".into()
+}
+
+fn default_consumer_group() -> String {
+    "command-queue".into()
+}
+
+const fn default_max_commands() -> u32 {
+    100
+}
+
+impl Default for CommandQueueConfig {
+    fn default() -> Self {
+        Self {
+            topic: default_topic(),
+            consumer_group: default_consumer_group(),
+            max_commands: default_max_commands(),
+        }
+    }
+}
+
+/// The outcome of queuing a command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Enqueued {
+    /// The command was queued.
+    Queued,
+    /// There is no disconnected session which queues commands.
+    NoSession,
+    /// The queue of the session is full, the command was dropped.
+    Full,
+}
+
+/// Stores persistent sessions of devices, and the commands queued for them while the device
+/// is disconnected.
+#[derive(Clone)]
+pub struct PostgresPersistentSessionService {
+    pool: Pool,
+}
+
+impl PostgresPersistentSessionService {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+
+    /// Get the persistent session of a device, unless it is expired.
+    pub async fn get(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<PersistentSession>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                &format!(
+                    r#"
+SELECT SUBSCRIPTIONS, EXPIRY FROM
+    persistent_sessions
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        {NOT_EXPIRED}
+"#
+                ),
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let row = match c.query_opt(&stmt, &[&application, &device]).await? {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let Json(subscriptions): Json<Vec<SessionSubscription>> = row.try_get("SUBSCRIPTIONS")?;
+        let expiry: Option<i32> = row.try_get("EXPIRY")?;
+
+        Ok(Some(PersistentSession {
+            subscriptions,
+            expiry: expiry.map(|expiry| expiry.max(0) as u32),
+        }))
+    }
+
+    /// Create or update the persistent session of a connected device.
+    ///
+    /// An expired session gets replaced, dropping its queued commands.
+    pub async fn put(
+        &self,
+        application: &str,
+        device: &str,
+        session: &PersistentSession,
+    ) -> Result<(), ServiceError> {
+        let mut c = self.pool.get().await?;
+        let t = c.transaction().await?;
+
+        t.execute(
+            &format!(
+                r#"
+DELETE FROM
+    persistent_sessions
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        NOT {NOT_EXPIRED}
+"#
+            ),
+            &[&application, &device],
+        )
+        .await?;
+
+        let expiry = session
+            .expiry
+            .map(|expiry| i32::try_from(expiry).unwrap_or(i32::MAX));
+
+        t.execute(
+            r#"
+INSERT INTO
+    persistent_sessions
+(
+    APPLICATION,
+    DEVICE,
+    SUBSCRIPTIONS,
+    QUEUE,
+    EXPIRY
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5
+)
+ON CONFLICT (APPLICATION, DEVICE)
+    DO UPDATE
+        SET
+            SUBSCRIPTIONS = EXCLUDED.SUBSCRIPTIONS,
+            QUEUE = EXCLUDED.QUEUE,
+            EXPIRY = EXCLUDED.EXPIRY,
+            DISCONNECTED = NULL
+"#,
+            &[
+                &application,
+                &device,
+                &Json(&session.subscriptions),
+                &session.queue_commands(),
+                &expiry,
+            ],
+        )
+        .await?;
+
+        t.commit().await?;
+
+        Ok(())
+    }
+
+    /// Mark the device of a persistent session as disconnected, starting the expiry.
+    pub async fn disconnect(&self, application: &str, device: &str) -> Result<(), ServiceError> {
+        let c = self.pool.get().await?;
+
+        c.execute(
+            r#"
+UPDATE
+    persistent_sessions
+SET
+    DISCONNECTED = NOW()
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        DISCONNECTED IS NULL
+"#,
+            &[&application, &device],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete the persistent session of a device, including its queued commands.
+    pub async fn delete(&self, application: &str, device: &str) -> Result<(), ServiceError> {
+        let c = self.pool.get().await?;
+
+        c.execute(
+            r#"
+DELETE FROM
+    persistent_sessions
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+"#,
+            &[&application, &device],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Queue a command event, in case the target device has a disconnected persistent session,
+    /// with at least one QoS 1 subscription.
+    ///
+    /// The session is locked while counting its queued commands, so that concurrent collectors
+    /// can't exceed the limit.
+    pub async fn enqueue(
+        &self,
+        event: &Event,
+        max_commands: u32,
+    ) -> Result<Enqueued, ServiceError> {
+        let address = match CommandAddress::from_event(event) {
+            Some(address) => address,
+            None => return Ok(Enqueued::NoSession),
+        };
+
+        let time = event.time().cloned().unwrap_or_else(Utc::now);
+
+        let mut c = self.pool.get().await?;
+        let t = c.transaction().await?;
+
+        let session = t
+            .query_opt(
+                &format!(
+                    r#"
+SELECT 1 FROM
+    persistent_sessions S
+WHERE
+        S.APPLICATION = $1
+    AND
+        S.DEVICE = $2
+    AND
+        S.QUEUE
+    AND
+        S.DISCONNECTED IS NOT NULL
+    AND
+        {NOT_EXPIRED}
+FOR UPDATE
+"#
+                ),
+                &[&address.app_id, &address.gateway_id],
+            )
+            .await?;
+
+        if session.is_none() {
+            return Ok(Enqueued::NoSession);
+        }
+
+        let queued: i64 = t
+            .query_one(
+                r#"
+SELECT COUNT(*) FROM
+    queued_commands
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+"#,
+                &[&address.app_id, &address.gateway_id],
+            )
+            .await?
+            .try_get(0)?;
+
+        if queued >= i64::from(max_commands) {
+            return Ok(Enqueued::Full);
+        }
+
+        t.execute(
+            r#"
+INSERT INTO
+    queued_commands
+(
+    APPLICATION,
+    DEVICE,
+    TIME,
+    EVENT
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4
+)
+"#,
+            &[&address.app_id, &address.gateway_id, &time, &Json(event)],
+        )
+        .await?;
+
+        t.commit().await?;
+
+        Ok(Enqueued::Queued)
+    }
+
+    /// Get the queued commands of a device, oldest first.
+    ///
+    /// Commands stay queued until they are acknowledged.
+    pub async fn get_commands(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Vec<QueuedCommand>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT ID, EVENT FROM
+    queued_commands
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+ORDER BY
+    ID
+"#,
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let mut commands = Vec::new();
+        for row in c.query(&stmt, &[&application, &device]).await? {
+            let Json(event): Json<Value> = row.try_get("EVENT")?;
+            commands.push(QueuedCommand {
+                id: row.try_get("ID")?,
+                event,
+            });
+        }
+
+        Ok(commands)
+    }
+
+    /// Acknowledge a queued command, once it was delivered to the device.
+    ///
+    /// Returns `false` if the command was not queued (anymore).
+    pub async fn ack_command(
+        &self,
+        application: &str,
+        device: &str,
+        id: i64,
+    ) -> Result<bool, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let deleted = c
+            .execute(
+                r#"
+DELETE FROM
+    queued_commands
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        ID = $3
+"#,
+                &[&application, &device, &id],
+            )
+            .await?;
+
+        Ok(deleted > 0)
+    }
+
+    /// Delete all expired sessions.
+    pub async fn prune(&self) -> Result<u64, ServiceError> {
+        let c = self.pool.get().await?;
+
+        Ok(c.execute(
+            &format!(
+                r#"
+DELETE FROM
+    persistent_sessions
+WHERE
+    NOT {NOT_EXPIRED}
+"#
+            ),
+            &[],
+        )
+        .await?)
+    }
+}
+
+impl DatabaseService for PostgresPersistentSessionService {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl HealthChecked for PostgresPersistentSessionService {}
+
+pub async fn run_pruner(service: PostgresPersistentSessionService) -> anyhow::Result<()> {
+    loop {
+        match service.prune().await {
+            Ok(pruned) => log::info!("Pruned {pruned} expired persistent sessions"),
+            Err(err) => log::warn!("Failed to prune persistent sessions: {err}"),
+        }
+        sleep(Duration::from_secs(60)).await;
+    }
+}
+
+/// Consume commands and queue them for disconnected persistent sessions.
+pub async fn run_collector(
+    service: PostgresPersistentSessionService,
+    config: CommandQueueConfig,
+    client: KafkaClientConfig,
+) -> anyhow::Result<()> {
+    let mut stream = EventStream::<AutoAck>::new(EventStreamConfig {
+        kafka: KafkaConfig {
+            topic: config.topic,
+            client,
+        },
+        consumer_group: Some(config.consumer_group),
+    })?;
+
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(event) => match service.enqueue(&event, config.max_commands).await {
+                Ok(Enqueued::Queued) => log::debug!("Queued command: {}", event.id()),
+                Ok(Enqueued::NoSession) => {}
+                Ok(Enqueued::Full) => {
+                    DROPPED_COMMANDS.inc();
+                    log::warn!(
+                        "Dropped command {}, the queue of the session is full",
+                        event.id()
+                    );
+                }
+                Err(err) => log::info!("Failed to queue command: {err}"),
+            },
+            Err(err) => {
+                log::info!("Failed to read next event: {err}");
+            }
+        }
+    }
+
+    anyhow::bail!("Event stream closed")
+}
@@ -1,7 +1,7 @@
 use super::*;
 
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use deadpool_postgres::{Pool, Transaction};
 use drogue_client::registry::v1::Application;
 use drogue_cloud_database_common::{postgres, Client, DatabaseService};
@@ -23,6 +23,10 @@ use uuid::Uuid;
 pub struct PostgresServiceConfiguration {
     #[serde(with = "humantime_serde", default = "default_session_timeout")]
     pub session_timeout: Duration,
+    /// The time after which a device, not connected through a session, is considered
+    /// disconnected when it wasn't seen.
+    #[serde(with = "humantime_serde", default = "default_presence_timeout")]
+    pub presence_timeout: Duration,
     pub pg: postgres::Config,
 }
 
@@ -30,12 +34,17 @@ const fn default_session_timeout() -> Duration {
     Duration::from_secs(10)
 }
 
+const fn default_presence_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
 #[derive(Clone)]
 pub struct PostgresDeviceStateService {
     pool: Pool,
     sender: DownstreamSender,
     registry: Arc<dyn ApplicationLookup>,
     timeout: chrono::Duration,
+    presence_timeout: chrono::Duration,
 }
 
 impl PostgresDeviceStateService {
@@ -47,12 +56,14 @@ impl PostgresDeviceStateService {
         let pool = config.pg.create_pool()?;
 
         let timeout = chrono::Duration::from_std(config.session_timeout)?;
+        let presence_timeout = chrono::Duration::from_std(config.presence_timeout)?;
 
         Ok(Self {
             pool,
             sender,
             registry: Arc::new(registry),
             timeout,
+            presence_timeout,
         })
     }
 }
@@ -151,6 +162,8 @@ RETURNING
                 let lost: bool = row.try_get("LOST")?;
                 Ok(match lost {
                     false => {
+                        history::record_connect(&t, &application, &device, &state.endpoint, now)
+                            .await?;
                         self.send_connection_event(
                             &app,
                             PublishId {
@@ -207,7 +220,8 @@ RETURNING
         log::debug!("Delete result: {row:?}");
 
         if let Some(row) = row {
-            self.send_disconnect_from_delete(row, opts).await?;
+            self.send_disconnect_from_delete(&t, row, opts, DisconnectReason::Disconnected)
+                .await?;
         }
 
         t.commit().await?;
@@ -282,6 +296,83 @@ WHERE
         }
     }
 
+    async fn refresh(
+        &self,
+        application: String,
+        device: String,
+        presence: PresenceRequest,
+    ) -> Result<(), ServiceError> {
+        let app = match self.registry.lookup(&application).await? {
+            Some(app) => app,
+            None => {
+                log::info!("Application not found: {application}");
+                return Err(ServiceError::ApplicationNotFound);
+            }
+        };
+
+        let mut c = self.pool.get().await?;
+        let t = c.transaction().await?;
+
+        let now = Utc::now();
+
+        let row = t
+            .query_one(
+                r#"
+INSERT INTO
+    presence
+(
+    APPLICATION,
+    DEVICE,
+    DEVICE_UID,
+    ENDPOINT,
+    CREATED,
+    LAST_SEEN,
+    EXPIRES
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $5,
+    $6
+)
+ON CONFLICT (APPLICATION, DEVICE)
+    DO UPDATE
+        SET ENDPOINT = EXCLUDED.ENDPOINT, LAST_SEEN = EXCLUDED.LAST_SEEN, EXPIRES = EXCLUDED.EXPIRES
+RETURNING
+    (xmax = 0) AS INSERTED
+"#,
+                &[
+                    &application,
+                    &device,
+                    &presence.device_uid,
+                    &presence.endpoint,
+                    &now,
+                    &(now + self.presence_timeout),
+                ],
+            )
+            .await?;
+
+        let inserted: bool = row.try_get("INSERTED")?;
+        if inserted {
+            history::record_connect(&t, &application, &device, &presence.endpoint, now).await?;
+            self.send_connection_event(
+                &app,
+                PublishId {
+                    name: device,
+                    uid: Some(presence.device_uid),
+                },
+                true,
+            )
+            .await?;
+        }
+
+        t.commit().await?;
+
+        Ok(())
+    }
+
     async fn get(
         &self,
         application: String,
@@ -306,7 +397,7 @@ WHERE
         let row = c.query_opt(&stmt, &[&application, &device]).await?;
 
         match row {
-            None => Ok(None),
+            None => self.get_presence(&c, &application, &device).await,
             Some(row) => {
                 let lost: bool = row.try_get("LOST")?;
 
@@ -330,6 +421,119 @@ WHERE
 }
 
 impl PostgresDeviceStateService {
+    /// Get the state of a device which is present, but not connected through a session.
+    async fn get_presence<C: Client>(
+        &self,
+        c: &C,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<DeviceStateResponse>, ServiceError> {
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT DEVICE_UID, ENDPOINT, CREATED FROM
+    presence
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        EXPIRES > $3
+"#,
+                &[Type::VARCHAR, Type::VARCHAR, Type::TIMESTAMPTZ],
+            )
+            .await?;
+
+        let row = c
+            .query_opt(&stmt, &[&application, &device, &Utc::now()])
+            .await?;
+
+        Ok(match row {
+            Some(row) => Some(DeviceStateResponse {
+                created: row.try_get("CREATED")?,
+                state: DeviceState {
+                    device_uid: row.try_get("DEVICE_UID")?,
+                    endpoint: row.try_get("ENDPOINT")?,
+                    lwt: None,
+                },
+            }),
+            None => None,
+        })
+    }
+
+    /// Remove all devices which were not seen within the presence timeout.
+    pub async fn prune_presence(&self) -> Result<(), ServiceError> {
+        let mut c = self.pool.get().await?;
+
+        loop {
+            let t = c.build_transaction().start().await?;
+            let now = Utc::now();
+
+            let row = match t
+                .query_opt(
+                    r#"
+DELETE FROM
+    presence
+WHERE
+    (APPLICATION, DEVICE) = (
+        SELECT
+            APPLICATION, DEVICE
+        FROM
+            presence
+        WHERE
+            EXPIRES <= $1
+        LIMIT
+            1
+        FOR UPDATE SKIP LOCKED
+    )
+RETURNING
+    APPLICATION, DEVICE, DEVICE_UID, ENDPOINT, LAST_SEEN
+"#,
+                    &[&now],
+                )
+                .await?
+            {
+                None => break,
+                Some(row) => row,
+            };
+
+            let application: String = row.try_get("APPLICATION")?;
+            let device: String = row.try_get("DEVICE")?;
+            let device_uid: String = row.try_get("DEVICE_UID")?;
+            let endpoint: String = row.try_get("ENDPOINT")?;
+            let last_seen: DateTime<Utc> = row.try_get("LAST_SEEN")?;
+
+            log::info!("Device no longer present: {application}/{device}");
+
+            history::record_disconnect(
+                &t,
+                &application,
+                &device,
+                Some(&endpoint),
+                last_seen,
+                DisconnectReason::Inactive,
+                false,
+            )
+            .await?;
+
+            if let Some(app) = self.registry.lookup(&application).await? {
+                self.send_connection_event(
+                    &app,
+                    PublishId {
+                        name: device,
+                        uid: Some(device_uid),
+                    },
+                    false,
+                )
+                .await?;
+            }
+
+            t.commit().await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn prune(&self) -> Result<(), ServiceError> {
         log::info!("Start pruning sessions");
 
@@ -390,8 +594,13 @@ RETURNING
         let mut deleted = Box::pin(deleted);
 
         while let Some(row) = deleted.next().await.transpose()? {
-            self.send_disconnect_from_delete(row, DeleteOptions { skip_lwt: false })
-                .await?;
+            self.send_disconnect_from_delete(
+                &t,
+                row,
+                DeleteOptions { skip_lwt: false },
+                DisconnectReason::SessionExpired,
+            )
+            .await?;
         }
 
         t.execute(
@@ -410,13 +619,15 @@ WHERE
         Ok(())
     }
 
-    /// Send a disconnected event, from a delete operation.
+    /// Send a disconnected event, from a delete operation, and record it in the connection history.
     ///
     /// The provided row must contain the following fields: APPLICATION, DEVICE, DATA.
     async fn send_disconnect_from_delete(
         &self,
+        t: &Transaction<'_>,
         row: Row,
         opts: DeleteOptions,
+        reason: DisconnectReason,
     ) -> Result<(), ServiceError> {
         let application: String = row.try_get("APPLICATION")?;
         let device: String = row.try_get("DEVICE")?;
@@ -437,31 +648,48 @@ WHERE
             None
         };
 
-        let application = match self.registry.lookup(&application).await? {
+        let now = Utc::now();
+        let endpoint = state.as_ref().map(|state| state.endpoint.as_str());
+
+        let app = match self.registry.lookup(&application).await? {
             Some(app) => app,
             None => {
                 log::info!("Application no longer found: {application}");
+                history::record_disconnect(t, &application, &device, endpoint, now, reason, false)
+                    .await?;
                 return Ok(());
             }
         };
 
+        // send LWT event, if we have some
+        let lwt = match opts.skip_lwt {
+            false => state.as_ref().and_then(|s| s.lwt.as_ref()),
+            true => None,
+        };
+
+        history::record_disconnect(
+            t,
+            &application,
+            &device,
+            endpoint,
+            now,
+            reason,
+            lwt.is_some(),
+        )
+        .await?;
+
         let device = PublishId {
             name: device,
             uid: state.as_ref().map(|state| state.device_uid.clone()),
         };
 
-        if !opts.skip_lwt {
-            // send LWT event, if we have some
-            if let Some(lwt) = state.as_ref().and_then(|s| s.lwt.as_ref()) {
-                self.send_lwt_event(&application, device.clone(), lwt)
-                    .await?;
-            }
+        if let Some(lwt) = lwt {
+            self.send_lwt_event(&app, device.clone(), lwt).await?;
         }
 
         // send connection event
 
-        self.send_connection_event(&application, device, false)
-            .await?;
+        self.send_connection_event(&app, device, false).await?;
 
         // done
 
@@ -529,9 +757,11 @@ WHERE
             }
             Err(err) => Err(ServiceError::Publish(err)),
             Ok(PublishOutcome::Accepted) => Ok(()),
-            Ok(PublishOutcome::Rejected | PublishOutcome::QueueFull) => Err(
-                ServiceError::Internal(format!("Unable to send event: {outcome:?}")),
-            ),
+            Ok(
+                PublishOutcome::Rejected | PublishOutcome::QueueFull | PublishOutcome::RateLimited,
+            ) => Err(ServiceError::Internal(format!(
+                "Unable to send event: {outcome:?}"
+            ))),
         }
     }
 }
@@ -541,6 +771,12 @@ pub async fn run_pruner(service: PostgresDeviceStateService) -> anyhow::Result<(
 
     loop {
         sleep(period).await;
-        service.prune().await?;
+        // one failing must not prevent the other from running
+        if let Err(err) = service.prune().await {
+            log::warn!("Failed to prune sessions: {err}");
+        }
+        if let Err(err) = service.prune_presence().await {
+            log::warn!("Failed to prune presence: {err}");
+        }
     }
 }
@@ -0,0 +1,257 @@
+use super::ServiceError;
+
+use chrono::Utc;
+use cloudevents::{AttributesReader, Event};
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{postgres, DatabaseService};
+use drogue_cloud_event_common::{
+    ext::extension,
+    stream::{AutoAck, EventStream, EventStreamConfig},
+};
+use drogue_cloud_service_api::{
+    health::HealthChecked,
+    kafka::{KafkaClientConfig, KafkaConfig, EVENTS_TOPIC_PATTERN},
+    EXT_APPLICATION, EXT_DEVICE,
+};
+use futures::StreamExt;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_postgres::types::{Json, Type};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LastValuesConfig {
+    /// The topic to consume events from.
+    ///
+    /// If the topic starts with `^`, it is used as a pattern.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+    #[serde(default = "default_consumer_group")]
+    pub consumer_group: String,
+}
+
+fn default_topic() -> String {
+    EVENTS_TOPIC_PATTERN.into()
+}
+
+fn default_consumer_group() -> String {
+    "last-values".into()
+}
+
+impl Default for LastValuesConfig {
+    fn default() -> Self {
+        Self {
+            topic: default_topic(),
+            consumer_group: default_consumer_group(),
+        }
+    }
+}
+
+/// Stores the last known event, per application, device, and channel.
+#[derive(Clone)]
+pub struct PostgresLastValueService {
+    pool: Pool,
+}
+
+impl PostgresLastValueService {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+
+    /// Store an event as the last value of its channel.
+    ///
+    /// Events without an application, device, or channel are ignored. Events older than the
+    /// currently stored one don't replace it.
+    pub async fn store(&self, event: &Event) -> Result<(), ServiceError> {
+        let (application, device, channel) = match (
+            extension(event, EXT_APPLICATION),
+            extension(event, EXT_DEVICE),
+            event.subject(),
+        ) {
+            (Some(application), Some(device), Some(channel)) => (application, device, channel),
+            _ => return Ok(()),
+        };
+
+        let time = event.time().cloned().unwrap_or_else(Utc::now);
+
+        let c = self.pool.get().await?;
+
+        c.execute(
+            r#"
+INSERT INTO
+    last_values
+(
+    APPLICATION,
+    DEVICE,
+    CHANNEL,
+    TIME,
+    EVENT
+) VALUES (
+    $1,
+    $2,
+    $3,
+    $4,
+    $5
+)
+ON CONFLICT (APPLICATION, DEVICE, CHANNEL)
+    DO UPDATE
+        SET TIME = EXCLUDED.TIME, EVENT = EXCLUDED.EVENT
+        WHERE last_values.TIME <= EXCLUDED.TIME
+"#,
+            &[&application, &device, &channel, &time, &Json(event)],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Get the last values of all channels of a device.
+    pub async fn get_all(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<IndexMap<String, Value>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT CHANNEL, EVENT FROM
+    last_values
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+ORDER BY
+    CHANNEL ASC
+"#,
+                &[Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        let mut result = IndexMap::new();
+        for row in c.query(&stmt, &[&application, &device]).await? {
+            let channel: String = row.try_get("CHANNEL")?;
+            let Json(event): Json<Value> = row.try_get("EVENT")?;
+            result.insert(channel, event);
+        }
+
+        Ok(result)
+    }
+
+    /// Get a page of the last values of all devices of an application, ordered by device and
+    /// channel.
+    ///
+    /// The page starts after the provided device and channel, and contains at most `limit` values.
+    pub async fn get_application(
+        &self,
+        application: &str,
+        after: Option<(&str, &str)>,
+        limit: u32,
+    ) -> Result<Vec<Value>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT EVENT FROM
+    last_values
+WHERE
+        APPLICATION = $1
+    AND
+        ($2::VARCHAR IS NULL OR (DEVICE, CHANNEL) > ($2, $3))
+ORDER BY
+    DEVICE ASC,
+    CHANNEL ASC
+LIMIT $4
+"#,
+                &[Type::VARCHAR, Type::VARCHAR, Type::VARCHAR, Type::INT8],
+            )
+            .await?;
+
+        let (device, channel) = after.unzip();
+
+        let mut result = Vec::new();
+        for row in c
+            .query(&stmt, &[&application, &device, &channel, &i64::from(limit)])
+            .await?
+        {
+            let Json(event): Json<Value> = row.try_get("EVENT")?;
+            result.push(event);
+        }
+
+        Ok(result)
+    }
+
+    /// Get the last value of a single channel.
+    pub async fn get(
+        &self,
+        application: &str,
+        device: &str,
+        channel: &str,
+    ) -> Result<Option<Value>, ServiceError> {
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+SELECT EVENT FROM
+    last_values
+WHERE
+        APPLICATION = $1
+    AND
+        DEVICE = $2
+    AND
+        CHANNEL = $3
+"#,
+                &[Type::VARCHAR, Type::VARCHAR, Type::VARCHAR],
+            )
+            .await?;
+
+        Ok(c.query_opt(&stmt, &[&application, &device, &channel])
+            .await?
+            .map(|row| row.try_get::<_, Json<Value>>("EVENT"))
+            .transpose()?
+            .map(|Json(event)| event))
+    }
+}
+
+impl DatabaseService for PostgresLastValueService {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl HealthChecked for PostgresLastValueService {}
+
+/// Consume events and store them as last values.
+pub async fn run_collector(
+    service: PostgresLastValueService,
+    config: LastValuesConfig,
+    client: KafkaClientConfig,
+) -> anyhow::Result<()> {
+    let mut stream = EventStream::<AutoAck>::new(EventStreamConfig {
+        kafka: KafkaConfig {
+            topic: config.topic,
+            client,
+        },
+        consumer_group: Some(config.consumer_group),
+    })?;
+
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(event) => {
+                if let Err(err) = service.store(&event).await {
+                    log::info!("Failed to store last value: {err}");
+                }
+            }
+            Err(err) => {
+                log::info!("Failed to read next event: {err}");
+            }
+        }
+    }
+
+    anyhow::bail!("Event stream closed")
+}
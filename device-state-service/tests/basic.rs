@@ -321,3 +321,52 @@ async fn test_timeout_lost() -> anyhow::Result<()> {
         assert_eq!(events.len(), 2);
     })
 }
+
+#[actix_rt::test]
+#[serial]
+async fn test_presence() -> anyhow::Result<()> {
+    test!((REGISTRY.clone() => app, service, _pool, sink) => {
+        let application = "app1";
+        let device = "device1";
+        let presence = PresenceRequest {
+            device_uid: "device_uid".into(),
+            endpoint: "http".into(),
+        };
+
+        // refresh -> must succeed, and announce the device
+        let resp = call_http(&app, &user("foo"), TestRequest::put().uri(&format!("/api/state/v1alpha1/presence/{}/{}", application, device))
+            .set_json(presence.clone())
+        ).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // refresh again -> must succeed, but not announce again
+        let resp = call_http(&app, &user("foo"), TestRequest::put().uri(&format!("/api/state/v1alpha1/presence/{}/{}", application, device))
+            .set_json(presence.clone())
+        ).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        // get -> must report the device
+        let resp = call_http(&app, &user("foo"), TestRequest::get().uri(&format!("/api/state/v1alpha1/states/{}/{}", application, device))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let response: DeviceStateResponse = read_body_json(resp).await;
+        assert_eq!(response.state.endpoint, "http");
+
+        // prune -> must not remove the device
+        service.prune_presence().await?;
+        assert_eq!(sink.events().await.len(), 1);
+
+        // now sleep, to time out
+        sleep(std::time::Duration::from_secs(5)).await;
+
+        // prune -> must remove the device
+        service.prune_presence().await?;
+
+        // get -> must no longer report the device
+        let resp = call_http(&app, &user("foo"), TestRequest::get().uri(&format!("/api/state/v1alpha1/states/{}/{}", application, device))).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        // check events
+        let events = sink.events().await;
+        assert_eq!(events.len(), 2);
+    })
+}
@@ -23,6 +23,7 @@ macro_rules! test {
         let db = drogue_cloud_test_common::db(&cli, |pg| service::postgres::PostgresServiceConfiguration {
             pg,
             session_timeout: std::time::Duration::from_secs(10),
+            presence_timeout: std::time::Duration::from_secs(5),
         })?;
 
         let $pool = db.config.pg.create_pool()?;
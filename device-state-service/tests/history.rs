@@ -0,0 +1,241 @@
+mod common;
+
+use drogue_client::registry;
+use drogue_cloud_device_state_service::{
+    endpoints::history::{PeriodQuery, MAX_PERIOD},
+    service::{
+        history::PostgresConnectionHistoryService,
+        postgres::{PostgresDeviceStateService, PostgresServiceConfiguration},
+        DeviceStateService, ServiceError,
+    },
+};
+use drogue_cloud_endpoint_common::sender::DownstreamSender;
+use drogue_cloud_service_api::services::device_state::*;
+use drogue_cloud_test_common::sink::MockSink;
+use maplit::hashmap;
+use serial_test::serial;
+
+fn state() -> DeviceState {
+    DeviceState {
+        device_uid: "device_uid".into(),
+        endpoint: "pod1".into(),
+        lwt: Some(LastWillTestament {
+            channel: "lwt".into(),
+            payload: b"bye".to_vec(),
+            content_type: None,
+        }),
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_history() -> anyhow::Result<()> {
+    common::init();
+
+    let cli = drogue_cloud_test_common::client();
+    let db = drogue_cloud_test_common::db(&cli, |pg| PostgresServiceConfiguration {
+        pg,
+        session_timeout: std::time::Duration::from_secs(10),
+        presence_timeout: std::time::Duration::from_secs(5),
+    })?;
+
+    let registry = hashmap! {
+        "app1".to_string() => registry::v1::Application::default(),
+    };
+
+    let sink = MockSink::new();
+    let sender =
+        DownstreamSender::new(sink.clone(), "drogue".to_string(), Default::default()).unwrap();
+    let service = PostgresDeviceStateService::new(db.config.clone(), sender, registry)?;
+    let history = PostgresConnectionHistoryService::new(db.config.pg.clone())?;
+
+    // no history yet
+
+    assert!(history.history("app1", "device1", 10).await?.is_empty());
+    let uptime = history
+        .uptime("app1", "device1", chrono::Duration::hours(1))
+        .await?;
+    assert!(!uptime.connected);
+    assert!(uptime.last_seen.is_none());
+
+    // connect, and disconnect without LWT
+
+    let session = service.init().await?.session;
+    let r = service
+        .create(
+            session.clone(),
+            "app1".into(),
+            "device1".into(),
+            "token".into(),
+            state(),
+        )
+        .await?;
+    assert!(matches!(r, CreateResponse::Created));
+
+    let uptime = history
+        .uptime("app1", "device1", chrono::Duration::hours(1))
+        .await?;
+    assert!(uptime.connected);
+    assert!(uptime.last_seen.is_some());
+
+    service
+        .delete(
+            session.clone(),
+            "app1".into(),
+            "device1".into(),
+            "token".into(),
+            DeleteOptions { skip_lwt: true },
+        )
+        .await?;
+
+    // connect again, and expire the session
+
+    let r = service
+        .create(
+            session.clone(),
+            "app1".into(),
+            "device1".into(),
+            "token".into(),
+            state(),
+        )
+        .await?;
+    assert!(matches!(r, CreateResponse::Created));
+
+    tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+    service.prune().await?;
+
+    // check history, newest first
+
+    let entries = history.history("app1", "device1", 10).await?;
+    assert_eq!(entries.len(), 2);
+
+    assert_eq!(entries[0].endpoint, "pod1");
+    assert!(entries[0].disconnected.is_some());
+    assert_eq!(entries[0].reason, Some(DisconnectReason::SessionExpired));
+    assert!(entries[0].lwt_sent);
+
+    assert!(entries[1].disconnected.is_some());
+    assert_eq!(entries[1].reason, Some(DisconnectReason::Disconnected));
+    assert!(!entries[1].lwt_sent);
+
+    // check uptime
+
+    let uptime = history
+        .uptime("app1", "device1", chrono::Duration::hours(1))
+        .await?;
+    assert!(!uptime.connected);
+    assert!(uptime.last_seen.is_some());
+    assert!(uptime.uptime_seconds > 0f64);
+    assert!(uptime.uptime_ratio > 0f64 && uptime.uptime_ratio < 1f64);
+
+    // inactive devices
+
+    assert!(history
+        .inactive("app1", chrono::Duration::hours(1))
+        .await?
+        .is_empty());
+    let inactive = history.inactive("app1", chrono::Duration::zero()).await?;
+    assert_eq!(inactive.len(), 1);
+    assert_eq!(inactive[0].device, "device1");
+
+    // periods which can't be evaluated
+
+    assert!(matches!(
+        history
+            .uptime("app1", "device1", chrono::Duration::max_value())
+            .await,
+        Err(ServiceError::InvalidPeriod)
+    ));
+    assert!(matches!(
+        history
+            .inactive("app1", chrono::Duration::max_value())
+            .await,
+        Err(ServiceError::InvalidPeriod)
+    ));
+
+    // prune, must keep the most recent entry
+
+    history.prune(chrono::Duration::zero()).await?;
+    let entries = history.history("app1", "device1", 10).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].reason, Some(DisconnectReason::SessionExpired));
+
+    Ok(())
+}
+
+#[test]
+fn test_period_limit() {
+    let query = PeriodQuery { period: MAX_PERIOD };
+    assert!(query.period().is_ok());
+
+    for period in [
+        MAX_PERIOD + std::time::Duration::from_secs(1),
+        std::time::Duration::MAX,
+    ] {
+        assert!(PeriodQuery { period }.period().is_err());
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_history_endpoints() -> anyhow::Result<()> {
+    common::init();
+
+    let cli = drogue_cloud_test_common::client();
+    let db = drogue_cloud_test_common::db(&cli, |pg| PostgresServiceConfiguration {
+        pg,
+        session_timeout: std::time::Duration::from_secs(10),
+        presence_timeout: std::time::Duration::from_secs(1),
+    })?;
+
+    let registry = hashmap! {
+        "app1".to_string() => registry::v1::Application::default(),
+    };
+
+    let sink = MockSink::new();
+    let sender =
+        DownstreamSender::new(sink.clone(), "drogue".to_string(), Default::default()).unwrap();
+    let service = PostgresDeviceStateService::new(db.config.clone(), sender, registry)?;
+    let history = PostgresConnectionHistoryService::new(db.config.pg.clone())?;
+
+    // connect through a session, and through HTTP
+
+    let session = service.init().await?.session;
+    let r = service
+        .create(
+            session.clone(),
+            "app1".into(),
+            "device1".into(),
+            "token".into(),
+            state(),
+        )
+        .await?;
+    assert!(matches!(r, CreateResponse::Created));
+
+    service
+        .refresh(
+            "app1".into(),
+            "device1".into(),
+            PresenceRequest {
+                device_uid: "device_uid".into(),
+                endpoint: "http".into(),
+            },
+        )
+        .await?;
+
+    // let the presence expire, must only close the HTTP connection
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    service.prune_presence().await?;
+
+    let entries = history.history("app1", "device1", 10).await?;
+    assert_eq!(entries.len(), 2);
+
+    let http = entries.iter().find(|e| e.endpoint == "http").unwrap();
+    assert_eq!(http.reason, Some(DisconnectReason::Inactive));
+
+    let session = entries.iter().find(|e| e.endpoint == "pod1").unwrap();
+    assert!(session.disconnected.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use cloudevents::{EventBuilder, EventBuilderV10};
+use drogue_cloud_device_state_service::service::persistent::{
+    Enqueued, PostgresPersistentSessionService,
+};
+use drogue_cloud_service_api::services::device_state::{PersistentSession, SessionSubscription};
+use serde_json::json;
+use serial_test::serial;
+
+fn command(device: &str, command: &str) -> cloudevents::Event {
+    EventBuilderV10::new()
+        .id(uuid::Uuid::new_v4().to_string())
+        .ty("io.drogue.command.v1")
+        .source("drogue://app1/device1")
+        .subject(command)
+        .extension("application", "app1")
+        .extension("device", device)
+        .data("application/json", json!({ "command": command }))
+        .build()
+        .unwrap()
+}
+
+fn session(qos: u8) -> PersistentSession {
+    PersistentSession {
+        subscriptions: vec![SessionSubscription {
+            topic: "command/inbox/#".into(),
+            qos,
+            id: None,
+        }],
+        expiry: None,
+    }
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_persistent_session() -> anyhow::Result<()> {
+    common::init();
+
+    let cli = drogue_cloud_test_common::client();
+    let db = drogue_cloud_test_common::db(&cli, |pg| pg)?;
+
+    let service = PostgresPersistentSessionService::new(db.config.clone())?;
+
+    // no session yet, nothing gets queued
+
+    assert!(service.get("app1", "device1").await?.is_none());
+    assert_eq!(
+        service.enqueue(&command("device1", "set"), 10).await?,
+        Enqueued::NoSession
+    );
+
+    // connected, commands are not queued
+
+    service.put("app1", "device1", &session(1)).await?;
+    assert_eq!(service.get("app1", "device1").await?, Some(session(1)));
+    assert_eq!(
+        service.enqueue(&command("device1", "set"), 10).await?,
+        Enqueued::NoSession
+    );
+
+    // disconnected, commands get queued, up to the limit
+
+    service.disconnect("app1", "device1").await?;
+    assert_eq!(service.get("app1", "device1").await?, Some(session(1)));
+    assert_eq!(
+        service.enqueue(&command("device1", "first"), 2).await?,
+        Enqueued::Queued
+    );
+    assert_eq!(
+        service.enqueue(&command("device1", "second"), 2).await?,
+        Enqueued::Queued
+    );
+    assert_eq!(
+        service.enqueue(&command("device1", "third"), 2).await?,
+        Enqueued::Full
+    );
+    // other devices are not affected
+    assert_eq!(
+        service.enqueue(&command("device2", "set"), 2).await?,
+        Enqueued::NoSession
+    );
+
+    // reconnect, commands stay queued until they are acknowledged
+
+    service.put("app1", "device1", &session(1)).await?;
+    let commands = service.get_commands("app1", "device1").await?;
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].event["subject"], json!("first"));
+    assert_eq!(commands[1].event["subject"], json!("second"));
+
+    assert!(
+        service
+            .ack_command("app1", "device1", commands[0].id)
+            .await?
+    );
+    assert!(
+        !service
+            .ack_command("app1", "device1", commands[0].id)
+            .await?
+    );
+    // commands of other devices can't be acknowledged
+    assert!(
+        !service
+            .ack_command("app1", "device2", commands[1].id)
+            .await?
+    );
+
+    let commands = service.get_commands("app1", "device1").await?;
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].event["subject"], json!("second"));
+
+    // QoS 0 subscriptions don't queue commands
+
+    service.put("app1", "device1", &session(0)).await?;
+    service.disconnect("app1", "device1").await?;
+    assert_eq!(
+        service.enqueue(&command("device1", "set"), 10).await?,
+        Enqueued::NoSession
+    );
+
+    // delete
+
+    service.delete("app1", "device1").await?;
+    assert!(service.get("app1", "device1").await?.is_none());
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_expired_session() -> anyhow::Result<()> {
+    common::init();
+
+    let cli = drogue_cloud_test_common::client();
+    let db = drogue_cloud_test_common::db(&cli, |pg| pg)?;
+
+    let service = PostgresPersistentSessionService::new(db.config.clone())?;
+
+    service
+        .put(
+            "app1",
+            "device1",
+            &PersistentSession {
+                expiry: Some(0),
+                ..session(1)
+            },
+        )
+        .await?;
+    service.disconnect("app1", "device1").await?;
+
+    // expired sessions are gone, and don't queue commands
+
+    assert!(service.get("app1", "device1").await?.is_none());
+    assert_eq!(
+        service.enqueue(&command("device1", "set"), 10).await?,
+        Enqueued::NoSession
+    );
+    assert_eq!(service.prune().await?, 1);
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use cloudevents::{EventBuilder, EventBuilderV10};
+use drogue_cloud_device_state_service::service::values::PostgresLastValueService;
+use serde_json::json;
+use serial_test::serial;
+
+fn event(channel: &str, time: &str, value: i64) -> cloudevents::Event {
+    EventBuilderV10::new()
+        .id(uuid::Uuid::new_v4().to_string())
+        .ty("io.drogue.event.v1")
+        .source("drogue://app1/device1")
+        .subject(channel)
+        .time(
+            DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .with_timezone(&Utc),
+        )
+        .extension("application", "app1")
+        .extension("device", "device1")
+        .data("application/json", json!({ "value": value }))
+        .build()
+        .unwrap()
+}
+
+#[actix_rt::test]
+#[serial]
+async fn test_last_values() -> anyhow::Result<()> {
+    common::init();
+
+    let cli = drogue_cloud_test_common::client();
+    let db = drogue_cloud_test_common::db(&cli, |pg| pg)?;
+
+    let service = PostgresLastValueService::new(db.config.clone())?;
+
+    // nothing stored yet
+
+    assert!(service.get_all("app1", "device1").await?.is_empty());
+    assert!(service.get("app1", "device1", "temp").await?.is_none());
+
+    // store some values
+
+    service
+        .store(&event("temp", "2022-01-01T00:00:00Z", 1))
+        .await?;
+    service
+        .store(&event("temp", "2022-01-01T00:00:02Z", 2))
+        .await?;
+    service
+        .store(&event("humidity", "2022-01-01T00:00:01Z", 3))
+        .await?;
+    // older event must not replace the newer one
+    service
+        .store(&event("temp", "2022-01-01T00:00:01Z", 4))
+        .await?;
+
+    let values = service.get_all("app1", "device1").await?;
+    assert_eq!(values.len(), 2);
+    assert_eq!(values["humidity"]["data"], json!({"value": 3}));
+    assert_eq!(values["temp"]["data"], json!({"value": 2}));
+
+    let value = service.get("app1", "device1", "temp").await?;
+    assert_eq!(value.unwrap()["data"], json!({"value": 2}));
+
+    let values = service.get_application("app1", None, 10).await?;
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[0]["subject"], json!("humidity"));
+    assert_eq!(values[1]["subject"], json!("temp"));
+    assert!(service.get_application("app2", None, 10).await?.is_empty());
+
+    // pages continue after the last value of the previous page
+
+    let values = service.get_application("app1", None, 1).await?;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0]["subject"], json!("humidity"));
+    let values = service
+        .get_application("app1", Some(("device1", "humidity")), 1)
+        .await?;
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0]["subject"], json!("temp"));
+    assert!(service
+        .get_application("app1", Some(("device1", "temp")), 1)
+        .await?
+        .is_empty());
+
+    // other devices are not affected
+
+    assert!(service.get_all("app1", "device2").await?.is_empty());
+
+    Ok(())
+}
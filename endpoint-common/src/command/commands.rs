@@ -21,6 +21,8 @@ pub struct CommandFilter {
     pub gateway: String,
     pub device: Option<String>,
     pub command_filter: Option<String>,
+    /// The name of a shared subscription group.
+    pub group: Option<String>,
 }
 
 impl CommandFilter {
@@ -35,6 +37,7 @@ impl CommandFilter {
             gateway: gateway.into(),
             device: None,
             command_filter: None,
+            group: None,
         }
     }
 
@@ -50,6 +53,7 @@ impl CommandFilter {
             gateway: gateway.into(),
             device: Some(device.into()),
             command_filter: None,
+            group: None,
         }
     }
 
@@ -65,6 +69,7 @@ impl CommandFilter {
             gateway: device.clone(),
             device: Some(device),
             command_filter: None,
+            group: None,
         }
     }
 
@@ -80,15 +85,64 @@ impl CommandFilter {
             ..self
         }
     }
+
+    /// Share the subscription with other subscriptions of the same group.
+    ///
+    /// Each command is received by only one member of the group: the one which subscribed first,
+    /// out of the members matching the command. Once it unsubscribes, the next member takes over.
+    pub fn with_group<T>(self, group: T) -> Self
+    where
+        T: Into<Option<String>>,
+    {
+        Self {
+            group: group.into(),
+            ..self
+        }
+    }
+
+    /// Check if a command matches the filter, the same way it is dispatched to a subscription.
+    ///
+    /// A device which is equal to the gateway matches all devices of the gateway.
+    pub fn matches(&self, command: &Command) -> bool {
+        let address = &command.address;
+
+        let device = match &self.device {
+            Some(device) if *device != self.gateway => *device == address.device_id,
+            _ => true,
+        };
+
+        device
+            && self.application == address.app_id
+            && self.gateway == address.gateway_id
+            && CommandNameFilter::from(&self.command_filter).matches(&command.command)
+    }
+
+    fn group_key(&self) -> Option<GroupKey> {
+        self.group.as_ref().map(|group| GroupKey {
+            application: self.application.clone(),
+            group: group.clone(),
+        })
+    }
+}
+
+/// A shared subscription group, scoped to an application.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+struct GroupKey {
+    application: String,
+    group: String,
 }
 
 type CommandMap<T> = Arc<Mutex<HashMap<T, HashMap<usize, CommandTarget>>>>;
 
+/// Members of shared subscription groups, in the order they subscribed.
+type GroupMap = Arc<Mutex<HashMap<GroupKey, Vec<usize>>>>;
+
 /// Command dispatching implementation.
 #[derive(Clone, Debug)]
 pub struct Commands {
     devices: CommandMap<CommandAddress>,
     wildcards: CommandMap<Id>,
+    groups: GroupMap,
 }
 
 impl Default for Commands {
@@ -114,6 +168,7 @@ impl Commands {
         Self {
             devices: Arc::new(Mutex::new(HashMap::new())),
             wildcards: Arc::new(Mutex::new(HashMap::new())),
+            groups: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -151,6 +206,7 @@ impl Commands {
                     CommandTarget {
                         tx,
                         filter: command_filter,
+                        group: filter.group.clone(),
                     },
                 )
             }
@@ -164,11 +220,16 @@ impl Commands {
                     CommandTarget {
                         tx,
                         filter: command_filter,
+                        group: filter.group.clone(),
                     },
                 )
             }
         };
 
+        if let Some(group) = filter.group_key() {
+            self.groups.lock().await.entry(group).or_default().push(id);
+        }
+
         Subscription {
             receiver: rx,
             handle: SubscriptionHandle { id, filter },
@@ -210,6 +271,62 @@ impl Commands {
                 );
             }
         }
+
+        if let Some(group) = handle.filter.group_key() {
+            if let Entry::Occupied(mut entry) = self.groups.lock().await.entry(group) {
+                entry.get_mut().retain(|id| *id != handle.id);
+                if entry.get().is_empty() {
+                    entry.remove();
+                }
+            }
+        }
+    }
+
+    /// Select the targets receiving a command, out of the targets matching it.
+    ///
+    /// Of each shared subscription group, only the member which subscribed first receives the
+    /// command.
+    async fn select(
+        &self,
+        application: &str,
+        targets: Vec<(usize, CommandTarget)>,
+    ) -> Vec<CommandTarget> {
+        let groups = self.groups.lock().await;
+
+        let mut result = Vec::new();
+        let mut shared = HashMap::<String, (usize, CommandTarget)>::new();
+
+        for (id, target) in targets {
+            let group = match &target.group {
+                Some(group) => group.clone(),
+                None => {
+                    result.push(target);
+                    continue;
+                }
+            };
+
+            let position = groups
+                .get(&GroupKey {
+                    application: application.to_string(),
+                    group: group.clone(),
+                })
+                .and_then(|members| members.iter().position(|member| *member == id))
+                .unwrap_or(usize::MAX);
+
+            match shared.entry(group) {
+                Entry::Occupied(mut entry) => {
+                    if position < entry.get().0 {
+                        entry.insert((position, target));
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((position, target));
+                }
+            }
+        }
+
+        result.extend(shared.into_values().map(|(_, target)| target));
+        result
     }
 
     fn add_entry<K, V>(map: &mut HashMap<K, HashMap<usize, V>>, key: K, value: V) -> usize
@@ -259,11 +376,9 @@ impl Commands {
 #[async_trait]
 impl CommandDispatcher for Commands {
     async fn send(&self, msg: Command) {
-        // TODO: try to reduce cloning
-
         log::debug!("Dispatching command to {:?}", msg.address);
 
-        let mut num: usize = 0;
+        let mut targets = Vec::new();
 
         if let Some(senders) = self.devices.lock().await.get(&msg.address) {
             log::debug!(
@@ -271,7 +386,7 @@ impl CommandDispatcher for Commands {
                 msg.command,
                 msg.address
             );
-            num += dispatch_command(senders.values(), &msg).await;
+            targets.extend(matching(senders, &msg));
         }
 
         if let Some(senders) = self.wildcards.lock().await.get(&Id::new(
@@ -283,25 +398,32 @@ impl CommandDispatcher for Commands {
                 msg.command,
                 msg.address
             );
-            num += dispatch_command(senders.values(), &msg).await;
+            targets.extend(matching(senders, &msg));
         }
 
+        let targets = self.select(&msg.address.app_id, targets).await;
+        let num = dispatch_command(targets, &msg).await;
+
         log::debug!("Sent to {} receivers", num);
     }
 }
 
+/// The targets with a command filter matching the command.
+fn matching<'a>(
+    senders: &'a HashMap<usize, CommandTarget>,
+    msg: &'a Command,
+) -> impl Iterator<Item = (usize, CommandTarget)> + 'a {
+    senders
+        .iter()
+        .filter(|(_, sender)| sender.filter.matches(&msg.command))
+        .map(|(id, sender)| (*id, sender.clone()))
+}
+
 /// Dispatch a command to a list of senders/devices.
-async fn dispatch_command<'a, I>(senders: I, msg: &Command) -> usize
-where
-    I: IntoIterator<Item = &'a CommandTarget>,
-{
+async fn dispatch_command(senders: Vec<CommandTarget>, msg: &Command) -> usize {
     let mut num = 0;
 
     for sender in senders {
-        if !sender.filter.matches(&msg.command) {
-            continue;
-        }
-
         num += 1;
         match sender.tx.send(msg.clone()).await {
             Ok(_) => {
@@ -651,4 +773,88 @@ mod test {
             "d1f4 outcome"
         );
     }
+
+    #[test]
+    fn test_matches() {
+        let cmd = cmd("gw1", "d1", "set");
+
+        assert!(CommandFilter::wildcard(APP, "gw1").matches(&cmd));
+        assert!(CommandFilter::proxied_device(APP, "gw1", "d1").matches(&cmd));
+        assert!(CommandFilter::proxied_device(APP, "gw1", "gw1").matches(&cmd));
+        assert!(CommandFilter::wildcard(APP, "gw1")
+            .with_filter("#".to_string())
+            .matches(&cmd));
+
+        assert!(!CommandFilter::wildcard("other", "gw1").matches(&cmd));
+        assert!(!CommandFilter::wildcard(APP, "gw2").matches(&cmd));
+        assert!(!CommandFilter::proxied_device(APP, "gw1", "d2").matches(&cmd));
+        assert!(!CommandFilter::device(APP, "d1").matches(&cmd));
+        assert!(!CommandFilter::wildcard(APP, "gw1")
+            .with_filter("get".to_string())
+            .matches(&cmd));
+    }
+
+    #[tokio::test]
+    async fn test_shared() {
+        let _ = env_logger::try_init();
+
+        let (handles, gw1a, gw1b, gw2) = {
+            let commands = Commands::new();
+
+            let gw1 = CommandFilter::wildcard(APP, "gw1").with_group("ha".to_string());
+            let gw2 = CommandFilter::wildcard(APP, "gw2").with_group("ha".to_string());
+
+            let mut handles = vec![];
+
+            let (gw1a, gw1a_handle, handle) = mock_receiver(commands.subscribe(gw1.clone()).await);
+            handles.push(handle);
+            let (gw1b, _, handle) = mock_receiver(commands.subscribe(gw1).await);
+            handles.push(handle);
+            let (gw2, _, handle) = mock_receiver(commands.subscribe(gw2).await);
+            handles.push(handle);
+
+            // the first matching member receives the command
+
+            commands.send(cmd("gw1", "d1", "first")).await;
+            commands.send(cmd("gw2", "d1", "first")).await;
+
+            // when it leaves, the next one takes over
+
+            commands.unsubscribe(gw1a_handle).await;
+
+            commands.send(cmd("gw1", "d1", "second")).await;
+            commands.send(cmd("gw2", "d1", "second")).await;
+
+            (handles, gw1a, gw1b, gw2)
+        };
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let gw1a = gw1a.lock().await;
+        let gw1b = gw1b.lock().await;
+        let gw2 = gw2.lock().await;
+
+        assert!(gw1a.finished);
+        assert_eq!(
+            gw1a.commands,
+            vec![cmd("gw1", "d1", "first")],
+            "gw1a outcome"
+        );
+
+        assert!(gw1b.finished);
+        assert_eq!(
+            gw1b.commands,
+            vec![cmd("gw1", "d1", "second")],
+            "gw1b outcome"
+        );
+
+        assert!(gw2.finished);
+        assert_eq!(
+            gw2.commands,
+            cmds("gw2", "d1", ["first", "second"]),
+            "gw2 outcome"
+        );
+    }
 }
@@ -8,7 +8,9 @@ pub use target::*;
 
 use async_trait::async_trait;
 use cloudevents::{event::ExtensionValue, AttributesReader, Event};
-use drogue_cloud_service_api::{EXT_APPLICATION, EXT_DEVICE, EXT_SENDER};
+use drogue_cloud_service_api::{
+    EXT_APPLICATION, EXT_CORRELATION_ID, EXT_DEVICE, EXT_RESPONSE_CHANNEL, EXT_SENDER,
+};
 use std::convert::{TryFrom, TryInto};
 use thiserror::Error;
 
@@ -61,6 +63,10 @@ pub struct Command {
     pub address: CommandAddress,
     pub command: String,
     pub payload: Option<Vec<u8>>,
+    /// The correlation id, in case the requester waits for a response.
+    pub correlation_id: Option<String>,
+    /// The channel the device should publish the response to.
+    pub response_channel: Option<String>,
 }
 
 impl Command {
@@ -74,8 +80,35 @@ impl Command {
             address,
             command: command.into(),
             payload,
+            correlation_id: None,
+            response_channel: None,
         }
     }
+
+    /// Request a correlated response from the device.
+    pub fn with_response<C, R>(self, correlation_id: C, response_channel: R) -> Self
+    where
+        C: Into<String>,
+        R: Into<Option<String>>,
+    {
+        Self {
+            correlation_id: Some(correlation_id.into()),
+            response_channel: response_channel.into(),
+            ..self
+        }
+    }
+
+    /// The channel the device should publish its response to, if a response is requested.
+    ///
+    /// Defaults to the name of the command.
+    pub fn response_channel(&self) -> Option<&str> {
+        self.correlation_id.as_ref()?;
+        Some(
+            self.response_channel
+                .as_deref()
+                .unwrap_or(self.command.as_str()),
+        )
+    }
 }
 
 #[derive(Clone, Debug, Error)]
@@ -103,7 +136,26 @@ impl TryFrom<Event> for Command {
             .subject()
             .ok_or(ParseCommandError::Missing("Command"))?;
 
-        Ok(Command::new(address, command, payload))
+        let mut command = Command::new(address, command, payload);
+        if let Some(ExtensionValue::String(correlation_id)) = event.extension(EXT_CORRELATION_ID) {
+            let response_channel = match event.extension(EXT_RESPONSE_CHANNEL) {
+                Some(ExtensionValue::String(channel)) => Some(channel.clone()),
+                _ => None,
+            };
+            command = command.with_response(correlation_id.clone(), response_channel);
+        }
+
+        Ok(command)
+    }
+}
+
+/// Convert MQTT v5 correlation data into a correlation id.
+///
+/// UTF-8 data is used as is, other data is base64 encoded.
+pub fn correlation_id_from_data(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(data) => data.to_string(),
+        Err(_) => base64::encode(data),
     }
 }
 
@@ -112,3 +164,37 @@ impl TryFrom<Event> for Command {
 pub trait CommandDispatcher {
     async fn send(&self, msg: Command);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    #[test]
+    fn test_correlation_id_from_data() {
+        assert_eq!(correlation_id_from_data(b"abc"), "abc");
+        assert_eq!(correlation_id_from_data(&[0xff, 0x00]), "/wA=");
+    }
+
+    #[test]
+    fn test_from_event() {
+        let event = EventBuilderV10::new()
+            .id("id1")
+            .ty("type")
+            .source("source")
+            .subject("set-temp")
+            .extension(EXT_APPLICATION, "app")
+            .extension(EXT_DEVICE, "device")
+            .extension(EXT_CORRELATION_ID, "4711")
+            .build()
+            .unwrap();
+
+        let command = Command::try_from(event).unwrap();
+        assert_eq!(command.correlation_id.as_deref(), Some("4711"));
+        assert_eq!(command.response_channel(), Some("set-temp"));
+        assert_eq!(
+            command.address,
+            CommandAddress::new("app", "device", "device")
+        );
+    }
+}
@@ -8,6 +8,8 @@ pub struct CommandTarget {
     pub tx: Sender<Command>,
     /// an additional command filter, in the form of an MQTT topic filter
     pub filter: CommandNameFilter,
+    /// the shared subscription group the target is part of
+    pub group: Option<String>,
 }
 
 #[derive(Clone, Debug)]
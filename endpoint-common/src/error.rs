@@ -1,3 +1,4 @@
+use crate::sender::BatchError;
 use drogue_client::error::ClientError;
 use drogue_cloud_service_api::webapp::{
     error::PayloadError, http::StatusCode, HttpResponse, ResponseError,
@@ -11,6 +12,8 @@ pub enum EndpointError {
     InvalidFormat { source: Box<dyn std::error::Error> },
     #[error("Invalid data: {}", details)]
     InvalidRequest { details: String },
+    #[error("Payload too large: {}", details)]
+    PayloadTooLarge { details: String },
     #[error("Endpoint configuration error: {}", details)]
     ConfigurationError { details: String },
     /// The authentication process failed to evaluate an outcome.
@@ -26,6 +29,7 @@ impl EndpointError {
         match self {
             EndpointError::InvalidFormat { .. } => "InvalidFormat",
             EndpointError::InvalidRequest { .. } => "InvalidRequest",
+            EndpointError::PayloadTooLarge { .. } => "PayloadTooLarge",
             EndpointError::ConfigurationError { .. } => "ConfigurationError",
             EndpointError::AuthenticationServiceError { .. } => "AuthenticationServiceError",
             EndpointError::AuthenticationError { .. } => "AuthenticationError",
@@ -41,6 +45,19 @@ impl From<ClientError> for EndpointError {
     }
 }
 
+impl From<BatchError> for EndpointError {
+    fn from(err: BatchError) -> Self {
+        match err {
+            BatchError::TooLarge(_) => Self::PayloadTooLarge {
+                details: err.to_string(),
+            },
+            _ => Self::InvalidRequest {
+                details: err.to_string(),
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub code: u16,
@@ -62,6 +79,7 @@ impl ResponseError for HttpEndpointError {
         match self.0 {
             EndpointError::InvalidFormat { .. } => StatusCode::BAD_REQUEST,
             EndpointError::InvalidRequest { .. } => StatusCode::BAD_REQUEST,
+            EndpointError::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
             EndpointError::ConfigurationError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             EndpointError::AuthenticationServiceError { .. } => StatusCode::SERVICE_UNAVAILABLE,
             EndpointError::AuthenticationError { .. } => StatusCode::FORBIDDEN,
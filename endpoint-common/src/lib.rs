@@ -7,3 +7,8 @@ pub mod sink;
 pub mod x509;
 
 const EXT_PARTITIONKEY: &str = "partitionkey";
+
+/// The reason an event was sent to the dead-letter topic: `rejected`, `error`, `failed`, or `undelivered`.
+pub const EXT_DEAD_LETTER_REASON: &str = "deadletterreason";
+/// Details of why an event was sent to the dead-letter topic.
+pub const EXT_DEAD_LETTER_ERROR: &str = "deadlettererror";
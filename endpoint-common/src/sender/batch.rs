@@ -0,0 +1,252 @@
+use super::{Publish, PublishError, PublishOutcome};
+use chrono::{DateTime, Utc};
+use cloudevents::{event::Data, AttributesReader, Event};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The content type of the CloudEvents JSON batch format.
+pub const CONTENT_TYPE_CLOUDEVENTS_BATCH: &str = "application/cloudevents-batch+json";
+
+const fn default_max_items() -> usize {
+    100
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchConfig {
+    /// The maximum number of items of a batch, larger batches get rejected.
+    #[serde(default = "default_max_items")]
+    pub max_items: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_items: default_max_items(),
+        }
+    }
+}
+
+/// A single item of a batch, published as its own event.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchItem {
+    /// The channel, overriding the channel of the request.
+    pub channel: Option<String>,
+    /// The time the item was recorded by the device.
+    pub time: Option<DateTime<Utc>>,
+    pub content_type: Option<String>,
+    pub data_schema: Option<String>,
+    pub r#type: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+impl BatchItem {
+    /// Apply the item to the publish information, returning the payload.
+    pub fn apply(self, publish: &mut Publish<'_>) -> Vec<u8> {
+        if let Some(channel) = self.channel {
+            publish.channel = channel;
+        }
+        publish.options.time = self.time;
+        publish.options.content_type = self.content_type;
+        if self.data_schema.is_some() {
+            publish.options.data_schema = self.data_schema;
+        }
+        if self.r#type.is_some() {
+            publish.options.r#type = self.r#type;
+        }
+        self.payload
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("Invalid batch: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("Empty batch")]
+    Empty,
+    #[error("Batch exceeds the maximum of {0} items")]
+    TooLarge(usize),
+}
+
+/// A simple batch item, using JSON as payload.
+#[derive(Clone, Debug, Deserialize)]
+struct JsonItem {
+    #[serde(default)]
+    channel: Option<String>,
+    #[serde(default)]
+    time: Option<DateTime<Utc>>,
+    data: Value,
+}
+
+impl From<JsonItem> for BatchItem {
+    fn from(item: JsonItem) -> Self {
+        Self {
+            channel: item.channel,
+            time: item.time,
+            content_type: Some(mime::APPLICATION_JSON.to_string()),
+            data_schema: None,
+            r#type: None,
+            payload: item.data.to_string().into_bytes(),
+        }
+    }
+}
+
+impl From<Event> for BatchItem {
+    fn from(event: Event) -> Self {
+        let payload = match event.data() {
+            Some(Data::Json(value)) => value.to_string().into_bytes(),
+            Some(Data::String(value)) => value.clone().into_bytes(),
+            Some(Data::Binary(value)) => value.clone(),
+            None => vec![],
+        };
+
+        Self {
+            channel: event.subject().map(ToString::to_string),
+            time: event.time().cloned(),
+            content_type: event.datacontenttype().map(ToString::to_string),
+            data_schema: event.dataschema().map(ToString::to_string),
+            r#type: Some(event.ty().to_string()),
+            payload,
+        }
+    }
+}
+
+/// Parse a batch of items.
+///
+/// A batch is a JSON array, containing either simple items (`channel`, `time`, `data`) or
+/// CloudEvents in the JSON format, which are detected by their `specversion` attribute.
+pub fn parse_batch(body: &[u8], max_items: usize) -> Result<Vec<BatchItem>, BatchError> {
+    let items: Vec<Value> = serde_json::from_slice(body)?;
+    if items.is_empty() {
+        return Err(BatchError::Empty);
+    }
+    if items.len() > max_items {
+        return Err(BatchError::TooLarge(max_items));
+    }
+
+    items
+        .into_iter()
+        .map(|item| {
+            Ok(match item.get("specversion").is_some() {
+                true => serde_json::from_value::<Event>(item)?.into(),
+                false => serde_json::from_value::<JsonItem>(item)?.into(),
+            })
+        })
+        .collect()
+}
+
+/// The outcome of publishing a single item of a batch.
+#[derive(Clone, Debug, Serialize)]
+pub struct BatchItemOutcome {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<PublishOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchItemOutcome {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.outcome, Some(PublishOutcome::Accepted))
+    }
+}
+
+impl From<&Result<PublishOutcome, PublishError>> for BatchItemOutcome {
+    fn from(result: &Result<PublishOutcome, PublishError>) -> Self {
+        match result {
+            Ok(outcome) => Self {
+                outcome: Some(*outcome),
+                error: None,
+            },
+            Err(err) => Self {
+                outcome: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_json() {
+        let items = parse_batch(
+            br#"[
+                {"channel": "temp", "time": "2022-01-01T12:00:00Z", "data": {"value": 1.5}},
+                {"data": 42}
+            ]"#,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            items,
+            vec![
+                BatchItem {
+                    channel: Some("temp".into()),
+                    time: Some("2022-01-01T12:00:00Z".parse().unwrap()),
+                    content_type: Some("application/json".into()),
+                    data_schema: None,
+                    r#type: None,
+                    payload: br#"{"value":1.5}"#.to_vec(),
+                },
+                BatchItem {
+                    channel: None,
+                    time: None,
+                    content_type: Some("application/json".into()),
+                    data_schema: None,
+                    r#type: None,
+                    payload: b"42".to_vec(),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_cloudevents() {
+        let items = parse_batch(
+            br#"[{
+                "specversion": "1.0",
+                "id": "1",
+                "source": "device",
+                "type": "reading",
+                "subject": "temp",
+                "time": "2022-01-01T12:00:00Z",
+                "datacontenttype": "application/json",
+                "data": {"value": 1.5}
+            }]"#,
+            10,
+        )
+        .unwrap();
+
+        assert_eq!(
+            items,
+            vec![BatchItem {
+                channel: Some("temp".into()),
+                time: Some("2022-01-01T12:00:00Z".parse().unwrap()),
+                content_type: Some("application/json".into()),
+                data_schema: None,
+                r#type: Some("reading".into()),
+                payload: br#"{"value":1.5}"#.to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(matches!(parse_batch(b"[]", 10), Err(BatchError::Empty)));
+        assert!(matches!(
+            parse_batch(br#"{"data": 1}"#, 10),
+            Err(BatchError::Format(_))
+        ));
+        assert!(matches!(
+            parse_batch(br#"[{"channel": "temp"}]"#, 10),
+            Err(BatchError::Format(_))
+        ));
+        assert!(matches!(
+            parse_batch(br#"[{"data": 1}, {"data": 2}]"#, 1),
+            Err(BatchError::TooLarge(1))
+        ));
+    }
+}
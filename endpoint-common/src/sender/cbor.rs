@@ -0,0 +1,132 @@
+//! Converting payloads between CBOR and JSON.
+
+use ciborium::value::Value as CborValue;
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+pub const CONTENT_TYPE_CBOR: &str = "application/cbor";
+
+#[derive(Debug, Error)]
+pub enum ConversionError {
+    #[error("Invalid CBOR: {0}")]
+    Cbor(String),
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("CBOR value has no JSON representation: {0}")]
+    Unsupported(String),
+}
+
+/// Check if the content type is CBOR.
+pub fn is_cbor(content_type: &str) -> bool {
+    content_type.split(';').next().unwrap_or_default().trim() == CONTENT_TYPE_CBOR
+}
+
+/// Transcode a CBOR payload to JSON.
+///
+/// Byte strings are encoded as base64, map keys are converted to strings, and tags are dropped.
+pub fn cbor_to_json(payload: &[u8]) -> Result<Vec<u8>, ConversionError> {
+    let value: CborValue =
+        ciborium::de::from_reader(payload).map_err(|err| ConversionError::Cbor(err.to_string()))?;
+    Ok(serde_json::to_vec(&to_json(value)?)?)
+}
+
+/// Transcode a JSON payload to CBOR.
+pub fn json_to_cbor(payload: &[u8]) -> Result<Vec<u8>, ConversionError> {
+    let value: Value = serde_json::from_slice(payload)?;
+    let mut result = Vec::with_capacity(payload.len());
+    ciborium::ser::into_writer(&value, &mut result)
+        .map_err(|err| ConversionError::Cbor(err.to_string()))?;
+    Ok(result)
+}
+
+/// Convert a CBOR value to JSON.
+pub(crate) fn to_json(value: CborValue) -> Result<Value, ConversionError> {
+    Ok(match value {
+        CborValue::Null => Value::Null,
+        CborValue::Bool(value) => Value::Bool(value),
+        CborValue::Integer(value) => {
+            let value = i128::from(value);
+            if let Ok(value) = i64::try_from(value) {
+                Value::Number(value.into())
+            } else if let Ok(value) = u64::try_from(value) {
+                Value::Number(value.into())
+            } else {
+                return Err(ConversionError::Unsupported(format!(
+                    "Integer out of range: {value}"
+                )));
+            }
+        }
+        CborValue::Float(value) => Number::from_f64(value)
+            .map(Value::Number)
+            .ok_or_else(|| ConversionError::Unsupported(format!("Float value: {value}")))?,
+        CborValue::Text(value) => Value::String(value),
+        CborValue::Bytes(value) => Value::String(base64::encode(value)),
+        CborValue::Array(values) => {
+            Value::Array(values.into_iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        CborValue::Map(entries) => {
+            let mut map = Map::with_capacity(entries.len());
+            for (key, value) in entries {
+                let key = match key {
+                    CborValue::Text(key) => key,
+                    CborValue::Integer(key) => i128::from(key).to_string(),
+                    key => return Err(ConversionError::Unsupported(format!("Map key: {key:?}"))),
+                };
+                map.insert(key, to_json(value)?);
+            }
+            Value::Object(map)
+        }
+        CborValue::Tag(_, value) => to_json(*value)?,
+        value => return Err(ConversionError::Unsupported(format!("{value:?}"))),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({"temp": 21.5, "count": -3, "ok": true, "tags": ["a", null]});
+
+        let cbor = json_to_cbor(value.to_string().as_bytes()).unwrap();
+        let json = cbor_to_json(&cbor).unwrap();
+
+        assert_eq!(serde_json::from_slice::<Value>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_cbor_only_types() {
+        let value = CborValue::Map(vec![
+            (
+                CborValue::Integer(1i64.into()),
+                CborValue::Bytes(vec![1, 2, 3]),
+            ),
+            (
+                CborValue::Text("time".into()),
+                CborValue::Tag(1, Box::new(CborValue::Integer(1654084800i64.into()))),
+            ),
+        ]);
+        let mut cbor = vec![];
+        ciborium::ser::into_writer(&value, &mut cbor).unwrap();
+
+        assert_eq!(
+            serde_json::from_slice::<Value>(&cbor_to_json(&cbor).unwrap()).unwrap(),
+            json!({"1": "AQID", "time": 1654084800})
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(cbor_to_json(&[0xff]).is_err());
+        assert!(json_to_cbor(b"{").is_err());
+    }
+
+    #[test]
+    fn test_is_cbor() {
+        assert!(is_cbor("application/cbor"));
+        assert!(is_cbor("application/cbor; foo=bar"));
+        assert!(!is_cbor("application/senml+cbor"));
+    }
+}
@@ -1,24 +1,40 @@
+mod batch;
+mod cbor;
 mod process;
-
-pub use process::ExternalClientPoolConfig;
+mod quota;
+mod senml;
+mod twin;
+
+pub use batch::*;
+pub use cbor::ConversionError;
+pub use process::{
+    ExternalClient, ExternalClientPool, ExternalClientPoolConfig, ExternalError, IntoPayload,
+    RequestPayload,
+};
+pub use quota::*;
+pub use senml::{SenmlError, SenmlFormat};
+pub use twin::{send_delta, TwinError};
 
 use crate::{
-    sender::process::{ExternalClientPool, Outcome},
+    sender::process::Outcome,
     sink::{Sink, SinkError, SinkTarget},
-    EXT_PARTITIONKEY,
+    EXT_DEAD_LETTER_ERROR, EXT_DEAD_LETTER_REASON, EXT_PARTITIONKEY,
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use cloudevents::{event::Data, Event, EventBuilder, EventBuilderV10};
+use cloudevents::{event::Data, AttributesReader, Event, EventBuilder, EventBuilderV10};
 use drogue_client::{
     meta::v1::{NonScopedMetadata, ScopedMetadata},
-    registry,
+    registry, Translator,
 };
+use drogue_cloud_event_common::ext::extension;
 use drogue_cloud_service_api::{
-    webapp::HttpResponse, EXT_APPLICATION_UID, EXT_DEVICE_UID, EXT_INSTANCE, EXT_SENDER,
-    EXT_SENDER_UID,
+    kafka::{EventsSpec, KafkaTopicSpec, PartitionKey},
+    services::twin::TWIN_REPORTED_CHANNEL,
+    webapp::HttpResponse,
+    EXT_APPLICATION_UID, EXT_DEVICE_UID, EXT_INSTANCE, EXT_SENDER, EXT_SENDER_UID,
 };
-use drogue_cloud_service_common::{Id, IdInjector};
+use drogue_cloud_service_common::{state::PresenceReporter, Id, IdInjector};
 use lazy_static::lazy_static;
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use process::Processor;
@@ -29,6 +45,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
 use tracing::instrument;
+use twin::DeviceTwin;
 
 lazy_static! {
     pub static ref DOWNSTREAM_EVENTS_COUNTER: IntCounterVec = register_int_counter_vec!(
@@ -197,6 +214,32 @@ pub enum PublishOutcome {
     Rejected,
     /// Input queue full
     QueueFull,
+    /// Quota of the application or device exceeded
+    RateLimited,
+}
+
+/// The reason an event was sent to the dead-letter topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeadLetterReason {
+    /// Rejected by a publish step.
+    Rejected,
+    /// Processing the publish steps failed.
+    Error,
+    /// Sending the event failed permanently.
+    Failed,
+    /// Delivering the event to an integration failed permanently.
+    Undelivered,
+}
+
+impl DeadLetterReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rejected => "rejected",
+            Self::Error => "error",
+            Self::Failed => "failed",
+            Self::Undelivered => "undelivered",
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -235,6 +278,10 @@ pub struct DownstreamSender {
     sink: Arc<dyn Sink>,
     instance: String,
     pool: ExternalClientPool,
+    twin: Option<DeviceTwin>,
+    presence: Option<PresenceReporter>,
+    limiter: Option<RateLimiter>,
+    batch: BatchConfig,
 }
 
 impl DownstreamSender {
@@ -247,8 +294,67 @@ impl DownstreamSender {
             sink: Arc::new(sink),
             instance,
             pool: ExternalClientPool::new(config),
+            twin: None,
+            presence: None,
+            limiter: None,
+            batch: Default::default(),
         })
     }
+
+    /// Enable processing of the device twin.
+    ///
+    /// Events on the "reported" channel will update the reported state of the device, using the
+    /// registry client. This happens in the background, after the event was sent. A non-empty
+    /// delta to the desired state will be sent as command and event.
+    pub fn with_twin(mut self, registry: registry::v1::Client) -> Self {
+        let commands = UpstreamSender {
+            sink: self.sink.clone(),
+            instance: self.instance.clone(),
+            pool: self.pool.clone(),
+        };
+        self.twin = Some(DeviceTwin::new(registry, self.clone(), commands));
+        self
+    }
+
+    /// Enable reporting the presence of devices.
+    ///
+    /// Each event will refresh the presence of the device sending it. This is intended for
+    /// endpoints which don't keep a connection state for devices.
+    pub fn with_presence(mut self, presence: PresenceReporter) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
+    /// Enforce ingestion quotas for applications and devices.
+    ///
+    /// Events exceeding the quota will not be sent, but result in [`PublishOutcome::RateLimited`].
+    pub fn with_quota(mut self, config: QuotaConfig) -> Self {
+        self.limiter = match (&config.application, &config.device) {
+            (None, None) => None,
+            _ => Some(RateLimiter::new(config)),
+        };
+        self
+    }
+
+    /// Set the limits for batches of events.
+    pub fn with_batch(mut self, config: BatchConfig) -> Self {
+        self.batch = config;
+        self
+    }
+
+    /// Finish background work, before shutting down.
+    ///
+    /// This stores the reported state of device twins, which is still waiting for further reports.
+    pub async fn close(&self) {
+        if let Some(twin) = &self.twin {
+            twin.close().await;
+        }
+    }
+
+    /// Parse a batch of events, enforcing the configured limits.
+    pub fn parse_batch(&self, body: &[u8]) -> Result<Vec<BatchItem>, BatchError> {
+        parse_batch(body, self.batch.max_items)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -278,12 +384,70 @@ impl Publisher for DownstreamSender {
         Direction::Downstream
     }
 
+    fn admit(&self, publish: &Publish<'_>, size: usize) -> bool {
+        match &self.limiter {
+            Some(limiter) => limiter.admit(
+                &publish.application.metadata.name,
+                &publish.sender.name,
+                size,
+            ),
+            None => true,
+        }
+    }
+
+    fn dead_letters(&self, app: &registry::v1::Application) -> bool {
+        app.section::<KafkaTopicSpec>()
+            .and_then(|s| s.ok())
+            .map(|spec| spec.dead_letters)
+            .unwrap_or_default()
+    }
+
+    async fn dead_letter(
+        &self,
+        app: &registry::v1::Application,
+        mut event: Event,
+        reason: DeadLetterReason,
+        error: String,
+    ) {
+        event.set_extension(EXT_DEAD_LETTER_REASON, reason.as_str());
+        event.set_extension(EXT_DEAD_LETTER_ERROR, error);
+
+        match self.sink.publish(SinkTarget::DeadLetters(app), event).await {
+            Ok(PublishOutcome::Accepted) => {}
+            Ok(outcome) => log::info!("Dead letter not accepted: {outcome:?}"),
+            Err(err) => log::info!("Failed to send dead letter: {}", error_details(&err)),
+        }
+    }
+
     async fn send(
         &self,
         app: &registry::v1::Application,
         event: Event,
     ) -> Result<PublishOutcome, SinkError> {
-        self.sink.publish(SinkTarget::Events(app), event).await
+        if let Some(presence) = &self.presence {
+            if let (Some(sender), Some(sender_uid)) = (
+                extension(&event, EXT_SENDER),
+                extension(&event, EXT_SENDER_UID),
+            ) {
+                presence.seen(&app.metadata.name, sender, sender_uid);
+            }
+        }
+
+        match &self.twin {
+            Some(twin) if event.subject() == Some(TWIN_REPORTED_CHANNEL) => {
+                let outcome = self
+                    .sink
+                    .publish(SinkTarget::Events(app), event.clone())
+                    .await?;
+                if let PublishOutcome::Accepted = outcome {
+                    if let Err(err) = twin.reported(app, &event) {
+                        log::warn!("Failed to process reported state: {err}");
+                    }
+                }
+                Ok(outcome)
+            }
+            _ => self.sink.publish(SinkTarget::Events(app), event).await,
+        }
     }
 }
 
@@ -311,6 +475,113 @@ impl Publisher for UpstreamSender {
     }
 }
 
+/// Evaluate the partition key of an event, according to the publish spec of the application.
+fn partition_key(publish: &Publish<'_>) -> String {
+    let spec = publish
+        .application
+        .section::<EventsSpec>()
+        .and_then(|s| s.ok())
+        .unwrap_or_default();
+
+    let encode = |s: &str| utf8_percent_encode(s, NON_ALPHANUMERIC).to_string();
+    let app = encode(&publish.application.metadata.name);
+
+    match spec.partition_key {
+        PartitionKey::Device => format!("{}/{}", app, encode(&publish.device.name)),
+        PartitionKey::Channel => format!(
+            "{}/{}/{}",
+            app,
+            encode(&publish.device.name),
+            encode(&publish.channel)
+        ),
+        PartitionKey::Extension(name) if publish.options.extensions.contains_key(&name) => {
+            format!("{}/{}", app, encode(&publish.options.extensions[&name]))
+        }
+        PartitionKey::Sender | PartitionKey::Extension(_) => {
+            format!("{}/{}", app, encode(&publish.sender.name))
+        }
+    }
+}
+
+/// Normalize a SenML payload, if requested by the publish spec of the application.
+///
+/// Only events get normalized, commands are sent to the device as they are.
+fn normalize_senml(
+    direction: Direction,
+    publish: &Publish<'_>,
+    body: &[u8],
+) -> Option<Result<Vec<BatchItem>, SenmlError>> {
+    if !matches!(direction, Direction::Downstream) {
+        return None;
+    }
+
+    let format = publish
+        .options
+        .content_type
+        .as_deref()
+        .and_then(SenmlFormat::from_content_type)?;
+
+    let normalization = publish
+        .application
+        .section::<EventsSpec>()
+        .and_then(|s| s.ok())
+        .and_then(|spec| spec.senml)?;
+
+    Some(senml::normalize(format, body, normalization, Utc::now()))
+}
+
+/// Convert between CBOR and JSON, if requested by the publish spec of the application.
+///
+/// Events are converted from CBOR to JSON, commands from JSON to CBOR. The result contains the
+/// new content type and payload.
+fn convert_cbor(
+    direction: Direction,
+    publish: &Publish<'_>,
+    body: &[u8],
+) -> Option<Result<(String, Vec<u8>), ConversionError>> {
+    let content_type = publish.options.content_type.as_deref()?;
+
+    let convert = publish
+        .application
+        .section::<EventsSpec>()
+        .and_then(|s| s.ok())
+        .map(|spec| spec.convert_cbor)
+        .unwrap_or_default();
+    if !convert {
+        return None;
+    }
+
+    match direction {
+        Direction::Downstream if cbor::is_cbor(content_type) => Some(
+            cbor::cbor_to_json(body).map(|payload| (mime::APPLICATION_JSON.to_string(), payload)),
+        ),
+        Direction::Upstream if is_json(content_type) => Some(
+            cbor::json_to_cbor(body).map(|payload| (cbor::CONTENT_TYPE_CBOR.to_string(), payload)),
+        ),
+        _ => None,
+    }
+}
+
+/// Combine the outcomes of multiple events into a single outcome.
+///
+/// The first error, or the first outcome other than accepted, wins.
+fn aggregate_outcomes(
+    outcomes: Vec<Result<PublishOutcome, PublishError>>,
+) -> Result<PublishOutcome, PublishError> {
+    let mut result = PublishOutcome::Accepted;
+    for outcome in outcomes {
+        match outcome? {
+            PublishOutcome::Accepted => {}
+            outcome => {
+                if let PublishOutcome::Accepted = result {
+                    result = outcome;
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
 #[async_trait]
 pub trait Publisher {
     fn instance(&self) -> String;
@@ -319,6 +590,28 @@ pub trait Publisher {
 
     fn direction() -> Direction;
 
+    /// Check if an event may be published, in regard to any quotas.
+    fn admit(&self, _publish: &Publish<'_>, _size: usize) -> bool {
+        true
+    }
+
+    /// Check if failed events should be sent to the dead-letter topic of the application.
+    fn dead_letters(&self, _app: &registry::v1::Application) -> bool {
+        false
+    }
+
+    /// Send the original event to the dead-letter topic, adding the reason.
+    ///
+    /// This is best effort, failing to do so will only be logged.
+    async fn dead_letter(
+        &self,
+        _app: &registry::v1::Application,
+        _event: Event,
+        _reason: DeadLetterReason,
+        _error: String,
+    ) {
+    }
+
     async fn send(
         &self,
         app: &registry::v1::Application,
@@ -347,12 +640,40 @@ pub trait Publisher {
     where
         B: AsRef<[u8]> + Send + Sync,
     {
+        if let Some(items) = normalize_senml(Self::direction(), &publish, body.as_ref()) {
+            return match items {
+                // publish each item on its own, which will also check quotas
+                Ok(items) => aggregate_outcomes(self.publish_batch(publish, items).await),
+                Err(err) => {
+                    log::debug!("Rejecting invalid SenML payload: {err}");
+                    Ok(PublishOutcome::Rejected)
+                }
+            };
+        }
+
+        if let Some(converted) = convert_cbor(Self::direction(), &publish, body.as_ref()) {
+            return match converted {
+                Ok((content_type, payload)) => {
+                    let mut publish = publish;
+                    publish.options.content_type = Some(content_type);
+                    self.publish(publish, payload).await
+                }
+                Err(err) => {
+                    log::debug!("Rejecting payload which failed to convert: {err}");
+                    Ok(PublishOutcome::Rejected)
+                }
+            };
+        }
+
+        if !self.admit(&publish, body.as_ref().len()) {
+            log::debug!("Event rejected due to exceeded quota");
+            return Ok(PublishOutcome::RateLimited);
+        }
+
         let app_id = publish.application.metadata.name.clone();
-        let app_enc = utf8_percent_encode(&app_id, NON_ALPHANUMERIC);
         let device_enc = utf8_percent_encode(&publish.device.name, NON_ALPHANUMERIC);
-        let sender_enc = utf8_percent_encode(&publish.sender.name, NON_ALPHANUMERIC);
 
-        let key = format!("{}/{}", app_enc, sender_enc);
+        let key = partition_key(&publish);
 
         let mut event = EventBuilderV10::new()
             .id(uuid::Uuid::new_v4().to_string())
@@ -366,7 +687,7 @@ pub trait Publisher {
             .source(format!("drogue://{app_id}/{device_enc}"))
             .inject(Id::new(app_id, publish.device.name))
             .subject(&publish.channel)
-            .time(Utc::now());
+            .time(publish.options.time.unwrap_or_else(Utc::now));
 
         event = event.extension(
             EXT_APPLICATION_UID,
@@ -427,24 +748,85 @@ pub trait Publisher {
 
         let processor = Processor::try_from((Self::direction(), publish.application, self.pool()))
             .map_err(PublishError::Spec)?;
-        match processor.process(event).await? {
-            Outcome::Rejected(reason) => {
+
+        // keep the original event, in case it needs to go to the dead-letter topic
+        let original = match self.dead_letters(publish.application) {
+            true => Some(event.clone()),
+            false => None,
+        };
+
+        match processor.process(event).await {
+            Ok(Outcome::Rejected(reason)) => {
                 // event was rejected
                 log::debug!("Event rejected: {}", reason);
+                if let Some(original) = original {
+                    self.dead_letter(
+                        publish.application,
+                        original,
+                        DeadLetterReason::Rejected,
+                        reason,
+                    )
+                    .await;
+                }
                 Ok(PublishOutcome::Rejected)
             }
-            Outcome::Accepted(event) => {
+            Ok(Outcome::Accepted(event)) => {
                 // event was accepted, send it
-                Ok(self.send(publish.application, event).await?)
+                match self.send(publish.application, event).await {
+                    Err(err) if err.is_permanent() => {
+                        if let Some(original) = original {
+                            self.dead_letter(
+                                publish.application,
+                                original,
+                                DeadLetterReason::Failed,
+                                error_details(&err),
+                            )
+                            .await;
+                        }
+                        Err(err.into())
+                    }
+                    result => Ok(result?),
+                }
             }
-            Outcome::Dropped => {
+            Ok(Outcome::Dropped) => {
                 // event was dropped, skip it
                 log::debug!("Outcome is to drop event");
                 Ok(PublishOutcome::Accepted)
             }
+            Err(err) => {
+                if let Some(original) = original {
+                    self.dead_letter(
+                        publish.application,
+                        original,
+                        DeadLetterReason::Error,
+                        error_details(&err),
+                    )
+                    .await;
+                }
+                Err(err.into())
+            }
         }
     }
 
+    /// Publish a batch, one event for each item.
+    ///
+    /// Items are published in order, each one using the information of the request, overridden
+    /// by the information of the item. The outcome is reported for each item individually.
+    #[allow(clippy::needless_lifetimes)]
+    async fn publish_batch<'a>(
+        &self,
+        publish: Publish<'a>,
+        items: Vec<BatchItem>,
+    ) -> Vec<Result<PublishOutcome, PublishError>> {
+        let mut outcomes = Vec::with_capacity(items.len());
+        for item in items {
+            let mut publish = publish.clone();
+            let payload = item.apply(&mut publish);
+            outcomes.push(self.publish(publish, payload).await);
+        }
+        outcomes
+    }
+
     #[allow(clippy::needless_lifetimes)]
     #[allow(clippy::async_yields_async)]
     async fn publish_http_default<'a, B>(&self, publish: Publish<'a>, body: B) -> HttpResponse
@@ -470,6 +852,12 @@ pub trait Publisher {
                     .inc();
                 HttpResponse::ServiceUnavailable().finish()
             }
+            Ok(PublishOutcome::RateLimited) => {
+                DOWNSTREAM_EVENTS_COUNTER
+                    .with_label_values(&["http", "RateLimited"])
+                    .inc();
+                HttpResponse::TooManyRequests().finish()
+            }
             Err(err) => {
                 DOWNSTREAM_EVENTS_COUNTER
                     .with_label_values(&["http", "Error"])
@@ -482,8 +870,73 @@ pub trait Publisher {
     }
 }
 
+/// Format an error, including its sources.
+fn error_details(err: &(dyn std::error::Error + 'static)) -> String {
+    let mut details = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        details.push_str(": ");
+        details.push_str(&err.to_string());
+        source = err.source();
+    }
+    details
+}
+
 pub(crate) fn is_json(content_type: &str) -> bool {
     content_type.starts_with("application/json")
         || content_type.starts_with("text/json")
         || content_type.ends_with("+json")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn publish(app: &registry::v1::Application) -> Publish<'_> {
+        Publish {
+            application: app,
+            device: PublishId {
+                name: "device 1".into(),
+                uid: None,
+            },
+            sender: PublishId {
+                name: "gateway".into(),
+                uid: None,
+            },
+            channel: "temp".into(),
+            options: PublishOptions {
+                extensions: [("region".to_string(), "eu".to_string())].into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn key(strategy: PartitionKey) -> String {
+        let mut app = registry::v1::Application::default();
+        app.metadata.name = "app".into();
+        app.set_section(EventsSpec {
+            partition_key: strategy,
+            ..Default::default()
+        })
+        .unwrap();
+        partition_key(&publish(&app))
+    }
+
+    #[test]
+    fn test_error_details() {
+        let err = SinkError::Transport(Box::new(crate::sink::KafkaSinkError::NotReady));
+        assert_eq!(
+            error_details(&err),
+            "Transport error: Kafka topic is not ready"
+        );
+    }
+
+    #[test]
+    fn test_partition_key() {
+        assert_eq!(key(PartitionKey::Sender), "app/gateway");
+        assert_eq!(key(PartitionKey::Device), "app/device%201");
+        assert_eq!(key(PartitionKey::Channel), "app/device%201/temp");
+        assert_eq!(key(PartitionKey::Extension("region".into())), "app/eu");
+        assert_eq!(key(PartitionKey::Extension("other".into())), "app/gateway");
+    }
+}
@@ -1,22 +1,30 @@
 mod external;
+mod protobuf;
+mod wasm;
 
-pub use external::{ExternalClientPool, ExternalClientPoolConfig};
+pub use external::{
+    ExternalClient, ExternalClientPool, ExternalClientPoolConfig, ExternalError, IntoPayload,
+    RequestPayload,
+};
+pub use protobuf::{ProtobufSpec, ProtobufStep, CONTENT_TYPE_PROTOBUF};
+pub use wasm::{WasmSpec, WasmStep};
 
 use crate::sender::{
     is_json,
-    process::external::{ExternalError, IntoPayload},
+    process::{protobuf::Schema, wasm::Codecs},
     Direction,
 };
 use cloudevents::{event::ExtensionValue, AttributesReader, AttributesWriter};
 use drogue_client::{
     registry::{
         self,
-        v1::{Application, EnrichSpec, ResponseType, Rule, Step, ValidateSpec, When},
+        v1::{Application, EnrichSpec, ResponseType, ValidateSpec, When},
     },
-    Translator,
+    Dialect,
 };
 use http::{header::CONTENT_TYPE, StatusCode};
 use reqwest::Url;
+use serde::Deserialize;
 use serde_json::Value;
 use thiserror::Error;
 use tracing::instrument;
@@ -55,15 +63,67 @@ pub enum Outcome {
     Dropped,
 }
 
+/// A processing rule.
+///
+/// This mirrors the rules of the registry, adding the steps built into the endpoints.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rule {
+    pub when: When,
+    #[serde(default)]
+    pub then: Vec<Step>,
+}
+
+impl From<registry::v1::Rule> for Rule {
+    fn from(rule: registry::v1::Rule) -> Self {
+        Self {
+            when: rule.when,
+            then: rule.then.into_iter().map(Step::Client).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    Builtin(BuiltinStep),
+    Client(registry::v1::Step),
+}
+
+/// Steps which are handled by the endpoints themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BuiltinStep {
+    /// Decode a protobuf payload into JSON.
+    DecodeProtobuf(ProtobufStep),
+    /// Encode a JSON payload into protobuf.
+    EncodeProtobuf(ProtobufStep),
+    /// Run a WebAssembly codec of the application.
+    Wasm(WasmStep),
+}
+
+/// The part of the publish and command sections holding the rules.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct RulesSpec {
+    #[serde(default)]
+    rules: Vec<Rule>,
+}
+
 pub struct Processor {
     pool: ExternalClientPool,
     rules: Vec<Rule>,
+    schema: Option<Schema>,
+    codecs: Option<Codecs>,
 }
 
 impl Processor {
     #[inline]
     pub fn new(pool: ExternalClientPool, rules: Vec<Rule>) -> Self {
-        Self { pool, rules }
+        Self {
+            pool,
+            rules,
+            schema: None,
+            codecs: None,
+        }
     }
 
     #[instrument(level = "debug", skip_all, err, fields(num_rules=self.rules.len()))]
@@ -141,7 +201,28 @@ impl Processor {
         Ok(StepOutcome::Continue(event))
     }
 
-    async fn step(&self, step: &Step, mut event: cloudevents::Event) -> Result<StepOutcome, Error> {
+    async fn step(&self, step: &Step, event: cloudevents::Event) -> Result<StepOutcome, Error> {
+        match step {
+            Step::Builtin(BuiltinStep::DecodeProtobuf(spec)) => {
+                protobuf::decode(self.schema.as_ref(), spec, event)
+            }
+            Step::Builtin(BuiltinStep::EncodeProtobuf(spec)) => {
+                protobuf::encode(self.schema.as_ref(), spec, event)
+            }
+            Step::Builtin(BuiltinStep::Wasm(spec)) => {
+                wasm::run(self.codecs.as_ref(), spec, event).await
+            }
+            Step::Client(step) => self.client_step(step, event).await,
+        }
+    }
+
+    async fn client_step(
+        &self,
+        step: &registry::v1::Step,
+        mut event: cloudevents::Event,
+    ) -> Result<StepOutcome, Error> {
+        use registry::v1::Step;
+
         match step {
             Step::Drop => Ok(StepOutcome::Drop),
             Step::Break => Ok(StepOutcome::Accept(event)),
@@ -321,22 +402,24 @@ impl TryFrom<(Direction, &registry::v1::Application, ExternalClientPool)> for Pr
     type Error = serde_json::Error;
 
     fn try_from(value: (Direction, &Application, ExternalClientPool)) -> Result<Self, Self::Error> {
-        Ok(Self::new(
-            value.2,
-            match value.0 {
-                Direction::Upstream => value
-                    .1
-                    .section::<registry::v1::CommandSpec>()
-                    .transpose()?
-                    .map(|spec| spec.rules),
-                Direction::Downstream => value
-                    .1
-                    .section::<registry::v1::PublishSpec>()
-                    .transpose()?
-                    .map(|spec| spec.rules),
-            }
-            .unwrap_or_default(),
-        ))
+        let key = match value.0 {
+            Direction::Upstream => registry::v1::CommandSpec::key(),
+            Direction::Downstream => registry::v1::PublishSpec::key(),
+        };
+        let rules = value
+            .1
+            .spec
+            .get(key)
+            .map(RulesSpec::deserialize)
+            .transpose()?
+            .unwrap_or_default()
+            .rules;
+
+        Ok(Self {
+            schema: Schema::from_app(value.1)?,
+            codecs: Codecs::from_app(value.1)?,
+            ..Self::new(value.2, rules)
+        })
     }
 }
 
@@ -344,7 +427,7 @@ impl TryFrom<(Direction, &registry::v1::Application, ExternalClientPool)> for Pr
 mod test {
     use super::*;
     use cloudevents::EventBuilder;
-    use drogue_client::registry::v1::PublishSpec;
+    use drogue_client::registry::v1::{PublishSpec, Step};
     use serde_json::json;
 
     impl TryFrom<serde_json::Value> for Processor {
@@ -486,9 +569,35 @@ mod test {
         ));
     }
 
+    #[test]
+    fn test_parse_builtin() {
+        let rules: Vec<Rule> = serde_json::from_value(json!([
+            {
+                "when": "always",
+                "then": [
+                    { "decodeProtobuf": { "message": "test.Reading" } },
+                    { "encodeProtobuf": {} },
+                    "drop",
+                ]
+            }
+        ]))
+        .unwrap();
+
+        assert!(matches!(
+            &rules[0].then[..],
+            [
+                super::Step::Builtin(BuiltinStep::DecodeProtobuf(ProtobufStep {
+                    message: Some(_)
+                })),
+                super::Step::Builtin(BuiltinStep::EncodeProtobuf(ProtobufStep { message: None })),
+                super::Step::Client(Step::Drop),
+            ]
+        ));
+    }
+
     async fn assert_process(spec: serde_json::Value, input: cloudevents::Event, expected: Outcome) {
         let spec: PublishSpec = serde_json::from_value(spec).unwrap();
-        let processor = Processor::from(spec.rules);
+        let processor = Processor::from(spec.rules.into_iter().map(Rule::from).collect::<Vec<_>>());
         let output = processor.process(input.clone()).await.unwrap();
 
         assert_eq!(output, expected);
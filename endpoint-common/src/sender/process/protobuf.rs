@@ -0,0 +1,293 @@
+//! Decoding and encoding protobuf payloads, using per-application schemas.
+
+use super::{Error, StepOutcome};
+use cloudevents::{event::Data, AttributesReader};
+use drogue_client::{dialect, registry::v1::Application, Section, Translator};
+use lru::LruCache;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{num::NonZeroUsize, sync::Mutex};
+
+pub const CONTENT_TYPE_PROTOBUF: &str = "application/protobuf";
+
+const CACHE_SIZE: usize = 64;
+
+lazy_static::lazy_static! {
+    /// Decoded descriptor pools, by application and resource version.
+    static ref POOLS: Mutex<LruCache<String, DescriptorPool>> =
+        Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap()));
+}
+
+/// The protobuf schema of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtobufSpec {
+    /// A base64 encoded, serialized `FileDescriptorSet`.
+    pub descriptor_set: String,
+}
+
+dialect!(ProtobufSpec [Section::Spec => "protobuf"]);
+
+/// Settings of the protobuf steps.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProtobufStep {
+    /// The fully qualified name of the message type.
+    ///
+    /// If not set, the last segment of the event's `dataschema` attribute is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The schema of an application, decoded on first use.
+#[derive(Clone, Debug)]
+pub struct Schema {
+    key: String,
+    descriptor_set: String,
+}
+
+impl Schema {
+    pub fn from_app(app: &Application) -> Result<Option<Self>, serde_json::Error> {
+        Ok(app.section::<ProtobufSpec>().transpose()?.map(|spec| Self {
+            key: format!("{}/{}", app.metadata.uid, app.metadata.resource_version),
+            descriptor_set: spec.descriptor_set,
+        }))
+    }
+
+    fn pool(&self) -> Result<DescriptorPool, Error> {
+        let mut pools = POOLS.lock().unwrap();
+        if let Some(pool) = pools.get(&self.key) {
+            return Ok(pool.clone());
+        }
+
+        let data = base64::decode(&self.descriptor_set)
+            .map_err(|err| Error::Config(format!("Invalid descriptor set encoding: {err}")))?;
+        let pool = DescriptorPool::decode(data.as_slice())
+            .map_err(|err| Error::Config(format!("Invalid descriptor set: {err}")))?;
+
+        pools.put(self.key.clone(), pool.clone());
+        Ok(pool)
+    }
+
+    fn message(
+        &self,
+        step: &ProtobufStep,
+        event: &cloudevents::Event,
+    ) -> Result<MessageDescriptor, Error> {
+        let name = match (&step.message, event.dataschema()) {
+            (Some(name), _) => name.as_str(),
+            (None, Some(schema)) => schema
+                .as_str()
+                .rsplit(|c| c == '/' || c == ':')
+                .next()
+                .unwrap_or_default(),
+            (None, None) => {
+                return Err(Error::Config(
+                    "Missing message type, and event has no 'dataschema' attribute".to_string(),
+                ))
+            }
+        };
+
+        self.pool()?
+            .get_message_by_name(name)
+            .ok_or_else(|| Error::Config(format!("Unknown message type: {name}")))
+    }
+}
+
+/// Decode a protobuf payload into JSON.
+pub fn decode(
+    schema: Option<&Schema>,
+    step: &ProtobufStep,
+    mut event: cloudevents::Event,
+) -> Result<StepOutcome, Error> {
+    let schema = schema.ok_or_else(missing_schema)?;
+    let descriptor = schema.message(step, &event)?;
+
+    let message = match event.data() {
+        Some(Data::Binary(data)) => DynamicMessage::decode(descriptor, data.as_slice()),
+        Some(Data::String(data)) => DynamicMessage::decode(descriptor, data.as_bytes()),
+        Some(Data::Json(_)) => {
+            return Ok(StepOutcome::Reject(
+                "Expected a protobuf payload, but found JSON".to_string(),
+            ))
+        }
+        None => DynamicMessage::decode(descriptor, &[][..]),
+    };
+
+    let message = match message {
+        Ok(message) => message,
+        Err(err) => {
+            return Ok(StepOutcome::Reject(format!(
+                "Invalid protobuf payload: {err}"
+            )))
+        }
+    };
+
+    let value = serde_json::to_value(&message).map_err(|err| Error::Internal(Box::new(err)))?;
+    event.set_data(mime::APPLICATION_JSON.to_string(), value);
+
+    Ok(StepOutcome::Continue(event))
+}
+
+/// Encode a JSON payload into protobuf.
+pub fn encode(
+    schema: Option<&Schema>,
+    step: &ProtobufStep,
+    mut event: cloudevents::Event,
+) -> Result<StepOutcome, Error> {
+    let schema = schema.ok_or_else(missing_schema)?;
+    let descriptor = schema.message(step, &event)?;
+
+    let message = match event.data() {
+        Some(Data::Json(value)) => DynamicMessage::deserialize(descriptor, value),
+        Some(Data::String(data)) => {
+            DynamicMessage::deserialize(descriptor, &mut serde_json::Deserializer::from_str(data))
+        }
+        Some(Data::Binary(data)) => {
+            DynamicMessage::deserialize(descriptor, &mut serde_json::Deserializer::from_slice(data))
+        }
+        None => DynamicMessage::deserialize(descriptor, &Value::Object(Default::default())),
+    };
+
+    let message = match message {
+        Ok(message) => message,
+        Err(err) => {
+            return Ok(StepOutcome::Reject(format!(
+                "Unable to encode payload as protobuf: {err}"
+            )))
+        }
+    };
+
+    event.set_data(CONTENT_TYPE_PROTOBUF, message.encode_to_vec());
+
+    Ok(StepOutcome::Continue(event))
+}
+
+fn missing_schema() -> Error {
+    Error::Config("Application has no protobuf schema".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+    use serde_json::json;
+
+    fn field(name: &str, number: i32, r#type: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.into()),
+            number: Some(number),
+            label: Some(Label::Optional as i32),
+            r#type: Some(r#type as i32),
+            json_name: Some(name.into()),
+            ..Default::default()
+        }
+    }
+
+    fn schema() -> Schema {
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("reading.proto".into()),
+                package: Some("test".into()),
+                syntax: Some("proto3".into()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Reading".into()),
+                    field: vec![
+                        field("temperature", 1, Type::Double),
+                        field("unit", 2, Type::String),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        Schema {
+            key: "app/1".into(),
+            descriptor_set: base64::encode(set.encode_to_vec()),
+        }
+    }
+
+    fn event() -> EventBuilderV10 {
+        EventBuilderV10::new()
+            .id("id1")
+            .ty("type")
+            .source("source")
+            .subject("chan1")
+    }
+
+    fn step(message: &str) -> ProtobufStep {
+        ProtobufStep {
+            message: Some(message.into()),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let schema = schema();
+        let value = json!({"temperature": 21.5, "unit": "C"});
+
+        let event = event()
+            .data("application/json", value.clone())
+            .build()
+            .unwrap();
+        let event = match encode(Some(&schema), &step("test.Reading"), event).unwrap() {
+            StepOutcome::Continue(event) => event,
+            _ => panic!("Expected to continue"),
+        };
+        assert_eq!(event.datacontenttype(), Some(CONTENT_TYPE_PROTOBUF));
+
+        let event = match decode(Some(&schema), &step("test.Reading"), event).unwrap() {
+            StepOutcome::Continue(event) => event,
+            _ => panic!("Expected to continue"),
+        };
+        assert_eq!(event.datacontenttype(), Some("application/json"));
+        assert_eq!(event.data(), Some(&Data::Json(value)));
+    }
+
+    #[test]
+    fn test_message_from_dataschema() {
+        let event = event()
+            .data_with_schema(
+                CONTENT_TYPE_PROTOBUF,
+                "urn:protobuf:test.Reading",
+                vec![0x12, 0x01, b'C'],
+            )
+            .build()
+            .unwrap();
+
+        match decode(Some(&schema()), &Default::default(), event).unwrap() {
+            StepOutcome::Continue(event) => {
+                assert_eq!(event.data(), Some(&Data::Json(json!({"unit": "C"}))))
+            }
+            _ => panic!("Expected to continue"),
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let event = event()
+            .data(CONTENT_TYPE_PROTOBUF, vec![0xff])
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            decode(Some(&schema()), &step("test.Reading"), event.clone()),
+            Ok(StepOutcome::Reject(_))
+        ));
+        assert!(matches!(
+            decode(Some(&schema()), &step("test.Unknown"), event.clone()),
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            decode(None, &step("test.Reading"), event),
+            Err(Error::Config(_))
+        ));
+    }
+}
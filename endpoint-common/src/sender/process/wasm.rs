@@ -0,0 +1,295 @@
+//! Running WebAssembly codecs, provided by the application.
+//!
+//! A codec module must export its `memory`, an `alloc(len: i32) -> i32` function, and the
+//! functions referenced by the rules. Those receive a pointer and the length of the event,
+//! serialized as structured JSON CloudEvent, and return the pointer (upper 32 bits) and length
+//! (lower 32 bits) of the resulting event. Returning an empty result drops the event.
+//!
+//! Modules get no imports, and run with limited fuel and memory. Compiling and running modules
+//! happens on the blocking thread pool.
+
+use super::{Error, StepOutcome};
+use drogue_client::{dialect, registry::v1::Application, Section, Translator};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+};
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// The fuel available to a single invocation.
+const MAX_FUEL: u64 = 10_000_000;
+/// The maximum size of the linear memory, in bytes.
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+const CACHE_SIZE: usize = 64;
+
+lazy_static::lazy_static! {
+    static ref ENGINE: Engine = {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Engine::new(&config).expect("Valid engine configuration")
+    };
+    /// Compiled modules, by application, resource version, and module name.
+    static ref MODULES: Mutex<LruCache<String, Module>> =
+        Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_SIZE).unwrap()));
+}
+
+/// The WebAssembly codecs of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WasmSpec {
+    /// Base64 encoded WebAssembly modules, by name.
+    #[serde(default)]
+    pub modules: HashMap<String, String>,
+}
+
+dialect!(WasmSpec [Section::Spec => "wasm"]);
+
+/// Settings of the WebAssembly step.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WasmStep {
+    /// The name of the module.
+    pub module: String,
+    /// The name of the function to call.
+    pub function: String,
+}
+
+/// The codecs of an application, compiled on first use.
+#[derive(Clone, Debug)]
+pub struct Codecs {
+    key: String,
+    modules: Arc<HashMap<String, String>>,
+}
+
+struct State {
+    limits: StoreLimits,
+}
+
+impl Codecs {
+    pub fn from_app(app: &Application) -> Result<Option<Self>, serde_json::Error> {
+        Ok(app.section::<WasmSpec>().transpose()?.map(|spec| Self {
+            key: format!("{}/{}", app.metadata.uid, app.metadata.resource_version),
+            modules: Arc::new(spec.modules),
+        }))
+    }
+
+    fn module(&self, name: &str) -> Result<Module, Error> {
+        let key = format!("{}/{}", self.key, name);
+
+        if let Some(module) = MODULES.lock().unwrap().get(&key) {
+            return Ok(module.clone());
+        }
+
+        // compile without holding the lock, so that other modules can be used in the meantime
+
+        let data = self
+            .modules
+            .get(name)
+            .ok_or_else(|| Error::Config(format!("Unknown WebAssembly module: {name}")))?;
+        let data = base64::decode(data)
+            .map_err(|err| Error::Config(format!("Invalid module encoding: {err}")))?;
+        let module = Module::new(&ENGINE, data)
+            .map_err(|err| Error::Config(format!("Invalid WebAssembly module: {err}")))?;
+
+        MODULES.lock().unwrap().put(key, module.clone());
+        Ok(module)
+    }
+}
+
+/// Run a WebAssembly function on the event.
+pub async fn run(
+    codecs: Option<&Codecs>,
+    step: &WasmStep,
+    event: cloudevents::Event,
+) -> Result<StepOutcome, Error> {
+    let codecs = codecs
+        .ok_or_else(|| Error::Config("Application has no WebAssembly modules".to_string()))?
+        .clone();
+    let step = step.clone();
+
+    tokio::task::spawn_blocking(move || run_blocking(&codecs, &step, event))
+        .await
+        .map_err(|err| Error::Internal(Box::new(err)))?
+}
+
+fn run_blocking(
+    codecs: &Codecs,
+    step: &WasmStep,
+    event: cloudevents::Event,
+) -> Result<StepOutcome, Error> {
+    let module = codecs.module(&step.module)?;
+
+    let input = serde_json::to_vec(&event).map_err(|err| Error::Internal(Box::new(err)))?;
+
+    let output = match call(&module, &step.function, &input) {
+        Ok(output) => output,
+        Err(err) => {
+            return Ok(StepOutcome::Reject(format!(
+                "Codec '{}' failed: {err}",
+                step.module
+            )))
+        }
+    };
+
+    if output.is_empty() {
+        return Ok(StepOutcome::Drop);
+    }
+
+    match serde_json::from_slice(&output) {
+        Ok(event) => Ok(StepOutcome::Continue(event)),
+        Err(err) => Ok(StepOutcome::Reject(format!(
+            "Codec '{}' returned an invalid event: {err}",
+            step.module
+        ))),
+    }
+}
+
+fn call(module: &Module, function: &str, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut store = Store::new(
+        &ENGINE,
+        State {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY)
+                .instances(1)
+                .build(),
+        },
+    );
+    store.limiter(|state| &mut state.limits);
+    store.add_fuel(MAX_FUEL)?;
+
+    let instance = Linker::new(&ENGINE).instantiate(&mut store, module)?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| anyhow::anyhow!("Module does not export 'memory'"))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+    let function = instance.get_typed_func::<(i32, i32), i64>(&mut store, function)?;
+
+    let len = i32::try_from(input.len())?;
+    let ptr = alloc.call(&mut store, len)?;
+    memory.write(&mut store, ptr as u32 as usize, input)?;
+
+    let result = function.call(&mut store, (ptr, len))?;
+    let ptr = (result >> 32) as u32 as usize;
+    let len = result as u32 as usize;
+
+    // check the result before allocating anything, the module controls the length
+    let data = memory.data(&store);
+    match ptr.checked_add(len) {
+        Some(end) if end <= data.len() => Ok(data[ptr..end].to_vec()),
+        _ => anyhow::bail!("Result out of bounds: {ptr} + {len}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use serde_json::json;
+
+    /// Returns the event as it was received.
+    const IDENTITY: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) i32.const 1024)
+        (func (export "identity") (param i32 i32) (result i64)
+            local.get 0
+            i64.extend_i32_u
+            i64.const 32
+            i64.shl
+            local.get 1
+            i64.extend_i32_u
+            i64.or)
+        (func (export "drop") (param i32 i32) (result i64) i64.const 0)
+        (func (export "overflow") (param i32 i32) (result i64) i64.const 0x0000fff0ffffffff)
+        (func (export "spin") (param i32 i32) (result i64)
+            (loop $l (br $l))
+            i64.const 0)
+    )"#;
+
+    fn codecs() -> Codecs {
+        Codecs {
+            key: "app/1".into(),
+            modules: Arc::new([("codec".to_string(), base64::encode(IDENTITY))].into()),
+        }
+    }
+
+    fn step(function: &str) -> WasmStep {
+        WasmStep {
+            module: "codec".into(),
+            function: function.into(),
+        }
+    }
+
+    fn event() -> cloudevents::Event {
+        EventBuilderV10::new()
+            .id("id1")
+            .ty("type")
+            .source("source")
+            .subject("chan1")
+            .data("application/json", json!({"value": 1}))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_identity() {
+        match run(Some(&codecs()), &step("identity"), event())
+            .await
+            .unwrap()
+        {
+            StepOutcome::Continue(result) => assert_eq!(result, event()),
+            _ => panic!("Expected to continue"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop() {
+        assert!(matches!(
+            run(Some(&codecs()), &step("drop"), event()).await,
+            Ok(StepOutcome::Drop)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_out_of_bounds() {
+        assert!(matches!(
+            run(Some(&codecs()), &step("overflow"), event()).await,
+            Ok(StepOutcome::Reject(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_out_of_fuel() {
+        assert!(matches!(
+            run(Some(&codecs()), &step("spin"), event()).await,
+            Ok(StepOutcome::Reject(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid() {
+        assert!(matches!(
+            run(Some(&codecs()), &step("missing"), event()).await,
+            Ok(StepOutcome::Reject(_))
+        ));
+        assert!(matches!(
+            run(
+                Some(&codecs()),
+                &WasmStep {
+                    module: "other".into(),
+                    function: "identity".into()
+                },
+                event()
+            )
+            .await,
+            Err(Error::Config(_))
+        ));
+        assert!(matches!(
+            run(None, &step("identity"), event()).await,
+            Err(Error::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,396 @@
+use lazy_static::lazy_static;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+lazy_static! {
+    pub static ref RATE_LIMITED_COUNTER: IntCounterVec = register_int_counter_vec!(
+        "drogue_downstream_rate_limited",
+        "Downstream events rejected due to rate limiting",
+        &["level"],
+    )
+    .unwrap();
+}
+
+/// Number of entries, after which idle buckets get cleaned up.
+const CLEANUP_THRESHOLD: usize = 10_000;
+
+/// A quota for ingesting events.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Quota {
+    /// Maximum number of messages per second.
+    #[serde(default, deserialize_with = "deserialize_positive_opt")]
+    pub messages_per_second: Option<f64>,
+    /// Maximum number of payload bytes per second.
+    #[serde(default, deserialize_with = "deserialize_positive_opt")]
+    pub bytes_per_second: Option<f64>,
+    /// The number of seconds, worth of the rate, which may be consumed at once.
+    #[serde(default = "default_burst", deserialize_with = "deserialize_positive")]
+    pub burst: f64,
+}
+
+const fn default_burst() -> f64 {
+    1f64
+}
+
+/// Ensure a rate or burst is a positive, finite number.
+fn positive(value: f64) -> Result<f64, String> {
+    if value.is_finite() && value > 0f64 {
+        Ok(value)
+    } else {
+        Err(format!("must be a positive number, was: {value}"))
+    }
+}
+
+fn deserialize_positive<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    positive(f64::deserialize(deserializer)?).map_err(D::Error::custom)
+}
+
+fn deserialize_positive_opt<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<f64>::deserialize(deserializer)?
+        .map(positive)
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct QuotaConfig {
+    /// The quota for each application.
+    #[serde(default)]
+    pub application: Option<Quota>,
+    /// The quota for each device.
+    ///
+    /// This is applied to the device connected to the endpoint. Events sent by a gateway on
+    /// behalf of other devices count towards the quota of the gateway.
+    #[serde(default)]
+    pub device: Option<Quota>,
+}
+
+/// A token bucket.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: f64, now: Instant) -> Self {
+        Self {
+            tokens: capacity,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, capacity: f64, now: Instant) {
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.last = now;
+    }
+}
+
+/// The buckets for a single application or device.
+#[derive(Debug)]
+struct Buckets {
+    messages: Bucket,
+    bytes: Bucket,
+}
+
+impl Buckets {
+    fn new(quota: &Quota, now: Instant) -> Self {
+        Self {
+            messages: Bucket::new(quota.messages_capacity(), now),
+            bytes: Bucket::new(quota.bytes_capacity(), now),
+        }
+    }
+
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        if let Some(rate) = quota.messages_per_second {
+            self.messages.refill(rate, quota.messages_capacity(), now);
+        }
+        if let Some(rate) = quota.bytes_per_second {
+            self.bytes.refill(rate, quota.bytes_capacity(), now);
+        }
+    }
+
+    /// Check if there are enough tokens left.
+    ///
+    /// Events bigger than the capacity of the bucket are accepted if the bucket is full, leaving
+    /// it in debt.
+    fn has(&self, quota: &Quota, size: f64) -> bool {
+        (quota.messages_per_second.is_none() || self.messages.tokens >= 1f64)
+            && (quota.bytes_per_second.is_none()
+                || self.bytes.tokens >= size.min(quota.bytes_capacity()))
+    }
+
+    /// Consume the tokens of an event, only for the rates which are limited.
+    fn consume(&mut self, quota: &Quota, size: f64) {
+        if quota.messages_per_second.is_some() {
+            self.messages.tokens -= 1f64;
+        }
+        if quota.bytes_per_second.is_some() {
+            self.bytes.tokens -= size;
+        }
+    }
+
+    /// Check if the buckets are full, and so are not needed anymore.
+    fn is_full(&self, quota: &Quota) -> bool {
+        self.messages.tokens >= quota.messages_capacity()
+            && self.bytes.tokens >= quota.bytes_capacity()
+    }
+}
+
+impl Quota {
+    /// The capacity of the messages bucket, which can hold at least one message.
+    fn messages_capacity(&self) -> f64 {
+        self.messages_per_second
+            .map(|rate| (rate * self.burst).max(1f64))
+            .unwrap_or_default()
+    }
+
+    fn bytes_capacity(&self) -> f64 {
+        self.bytes_per_second.unwrap_or_default() * self.burst
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    applications: HashMap<String, Buckets>,
+    devices: HashMap<(String, String), Buckets>,
+}
+
+/// Enforces ingestion quotas, for applications and devices.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    config: Arc<QuotaConfig>,
+    state: Arc<Mutex<State>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: Default::default(),
+        }
+    }
+
+    /// Check if an event of the provided size may be accepted, and consume the quota if it is.
+    pub fn admit(&self, application: &str, device: &str, size: usize) -> bool {
+        self.admit_at(application, device, size, Instant::now())
+    }
+
+    fn admit_at(&self, application: &str, device: &str, size: usize, now: Instant) -> bool {
+        let size = size as f64;
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+
+        if state.applications.len() > CLEANUP_THRESHOLD || state.devices.len() > CLEANUP_THRESHOLD {
+            self.cleanup(state, now);
+        }
+
+        let app = self.config.application.as_ref().map(|quota| {
+            let buckets = state
+                .applications
+                .entry(application.to_string())
+                .or_insert_with(|| Buckets::new(quota, now));
+            buckets.refill(quota, now);
+            (quota, buckets)
+        });
+
+        if let Some((quota, buckets)) = &app {
+            if !buckets.has(quota, size) {
+                RATE_LIMITED_COUNTER
+                    .with_label_values(&["application"])
+                    .inc();
+                return false;
+            }
+        }
+
+        let device = self.config.device.as_ref().map(|quota| {
+            let buckets = state
+                .devices
+                .entry((application.to_string(), device.to_string()))
+                .or_insert_with(|| Buckets::new(quota, now));
+            buckets.refill(quota, now);
+            (quota, buckets)
+        });
+
+        if let Some((quota, buckets)) = &device {
+            if !buckets.has(quota, size) {
+                RATE_LIMITED_COUNTER.with_label_values(&["device"]).inc();
+                return false;
+            }
+        }
+
+        if let Some((quota, buckets)) = app {
+            buckets.consume(quota, size);
+        }
+        if let Some((quota, buckets)) = device {
+            buckets.consume(quota, size);
+        }
+
+        true
+    }
+
+    fn cleanup(&self, state: &mut State, now: Instant) {
+        if let Some(quota) = &self.config.application {
+            state.applications.retain(|_, buckets| {
+                buckets.refill(quota, now);
+                !buckets.is_full(quota)
+            });
+        }
+        if let Some(quota) = &self.config.device {
+            state.devices.retain(|_, buckets| {
+                buckets.refill(quota, now);
+                !buckets.is_full(quota)
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn quota(messages: Option<f64>, bytes: Option<f64>) -> Quota {
+        Quota {
+            messages_per_second: messages,
+            bytes_per_second: bytes,
+            burst: 2f64,
+        }
+    }
+
+    #[test]
+    fn test_device_messages() {
+        let limiter = RateLimiter::new(QuotaConfig {
+            application: None,
+            device: Some(quota(Some(1f64), None)),
+        });
+        let now = Instant::now();
+
+        // burst of two
+        assert!(limiter.admit_at("app", "dev1", 10, now));
+        assert!(limiter.admit_at("app", "dev1", 10, now));
+        assert!(!limiter.admit_at("app", "dev1", 10, now));
+
+        // other devices are not affected
+        assert!(limiter.admit_at("app", "dev2", 10, now));
+
+        // refilled after one second
+        let now = now + Duration::from_secs(1);
+        assert!(limiter.admit_at("app", "dev1", 10, now));
+        assert!(!limiter.admit_at("app", "dev1", 10, now));
+    }
+
+    #[test]
+    fn test_application_bytes() {
+        let limiter = RateLimiter::new(QuotaConfig {
+            application: Some(quota(None, Some(100f64))),
+            device: None,
+        });
+        let now = Instant::now();
+
+        assert!(limiter.admit_at("app", "dev1", 150, now));
+        assert!(!limiter.admit_at("app", "dev2", 100, now));
+        assert!(limiter.admit_at("app", "dev2", 50, now));
+
+        // other applications are not affected
+        assert!(limiter.admit_at("app2", "dev1", 200, now));
+    }
+
+    #[test]
+    fn test_rejected_does_not_consume() {
+        let limiter = RateLimiter::new(QuotaConfig {
+            application: Some(quota(Some(1f64), None)),
+            device: Some(quota(Some(100f64), None)),
+        });
+        let now = Instant::now();
+
+        assert!(limiter.admit_at("app", "dev1", 0, now));
+        assert!(limiter.admit_at("app", "dev1", 0, now));
+        // application quota exceeded
+        assert!(!limiter.admit_at("app", "dev1", 0, now));
+
+        let limiter = RateLimiter::new(QuotaConfig {
+            application: Some(quota(Some(1f64), None)),
+            device: Some(quota(Some(0.5f64), None)),
+        });
+
+        assert!(limiter.admit_at("app", "dev1", 0, now));
+        // device quota exceeded, must not consume application quota
+        assert!(!limiter.admit_at("app", "dev1", 0, now));
+        assert!(limiter.admit_at("app", "dev2", 0, now));
+    }
+
+    #[test]
+    fn test_small_rate() {
+        let limiter = RateLimiter::new(QuotaConfig {
+            application: None,
+            device: Some(Quota {
+                messages_per_second: Some(0.5f64),
+                bytes_per_second: None,
+                burst: 1f64,
+            }),
+        });
+        let now = Instant::now();
+
+        // capacity is at least one message
+        assert!(limiter.admit_at("app", "dev1", 10, now));
+        assert!(!limiter.admit_at("app", "dev1", 10, now));
+
+        let now = now + Duration::from_secs(2);
+        assert!(limiter.admit_at("app", "dev1", 10, now));
+    }
+
+    #[test]
+    fn test_positive() {
+        assert_eq!(positive(0.5f64), Ok(0.5f64));
+
+        for value in [0f64, -1f64, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(positive(value).is_err(), "{value} must be rejected");
+        }
+    }
+
+    #[test]
+    fn test_parse_quota() {
+        let quota: Quota = serde_json::from_value(serde_json::json!({
+            "messages_per_second": 10,
+        }))
+        .unwrap();
+        assert_eq!(quota.messages_per_second, Some(10f64));
+        assert_eq!(quota.bytes_per_second, None);
+        assert_eq!(quota.burst, 1f64);
+
+        for invalid in [
+            serde_json::json!({"messages_per_second": 0}),
+            serde_json::json!({"bytes_per_second": -100}),
+            serde_json::json!({"messages_per_second": 10, "burst": 0}),
+        ] {
+            assert!(serde_json::from_value::<Quota>(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_unlimited_not_consumed() {
+        let quota = quota(Some(1f64), None);
+        let now = Instant::now();
+
+        let mut buckets = Buckets::new(&quota, now);
+        buckets.consume(&quota, 100f64);
+        assert_eq!(buckets.bytes.tokens, 0f64);
+
+        // full again after refilling the limited bucket
+        buckets.refill(&quota, now + Duration::from_secs(1));
+        assert!(buckets.is_full(&quota));
+    }
+}
@@ -0,0 +1,425 @@
+//! Decoding and normalizing SenML packs, as defined by RFC 8428.
+
+use super::{cbor, BatchItem};
+use chrono::{DateTime, TimeZone, Utc};
+use ciborium::value::Value as CborValue;
+use drogue_cloud_service_api::kafka::SenmlNormalization;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// Times below this value are relative to the time the pack was received.
+const RELATIVE_TIME_LIMIT: f64 = 268_435_456.0; // 2^28
+
+#[derive(Debug, Error)]
+pub enum SenmlError {
+    #[error("Invalid SenML JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid SenML CBOR: {0}")]
+    Cbor(String),
+    #[error("Invalid SenML record: {0}")]
+    Record(String),
+}
+
+/// The SenML format of a content type, if it is a supported SenML content type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SenmlFormat {
+    Json,
+    Cbor,
+}
+
+impl SenmlFormat {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        match media_type {
+            "application/senml+json" | "application/sensml+json" => Some(Self::Json),
+            "application/senml+cbor" | "application/sensml+cbor" => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+}
+
+/// A record, as it is encoded in a pack.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct RawRecord {
+    bn: Option<String>,
+    bt: Option<f64>,
+    bu: Option<String>,
+    bv: Option<f64>,
+    bs: Option<f64>,
+    n: Option<String>,
+    u: Option<String>,
+    v: Option<f64>,
+    vs: Option<String>,
+    vb: Option<bool>,
+    vd: Option<String>,
+    s: Option<f64>,
+    t: Option<f64>,
+    ut: Option<f64>,
+}
+
+/// A resolved record, with base values applied and an absolute time.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Record {
+    pub n: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub u: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub v: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vs: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vb: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vd: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s: Option<f64>,
+    pub t: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ut: Option<f64>,
+}
+
+impl Record {
+    /// The time of the record.
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        let secs = self.t.floor();
+        let nanos = ((self.t - secs) * 1_000_000_000f64) as u32;
+        Utc.timestamp_opt(secs as i64, nanos).single()
+    }
+}
+
+/// Decode a pack, without resolving it.
+fn decode(format: SenmlFormat, payload: &[u8]) -> Result<Vec<RawRecord>, SenmlError> {
+    match format {
+        SenmlFormat::Json => Ok(serde_json::from_slice(payload)?),
+        SenmlFormat::Cbor => {
+            let value: CborValue = ciborium::de::from_reader(payload)
+                .map_err(|err| SenmlError::Cbor(err.to_string()))?;
+            Ok(serde_json::from_value(cbor_to_json(value)?)?)
+        }
+    }
+}
+
+/// Convert a CBOR pack to its JSON representation.
+///
+/// Integer labels are replaced by their names, values are converted using the common CBOR
+/// conversion.
+fn cbor_to_json(value: CborValue) -> Result<Value, SenmlError> {
+    let records = match value {
+        CborValue::Array(records) => records
+            .into_iter()
+            .map(label_record)
+            .collect::<Result<_, _>>()?,
+        _ => return Err(SenmlError::Cbor("Pack must be an array".into())),
+    };
+
+    cbor::to_json(CborValue::Array(records)).map_err(|err| SenmlError::Cbor(err.to_string()))
+}
+
+fn label_record(record: CborValue) -> Result<CborValue, SenmlError> {
+    let fields = match record {
+        CborValue::Map(fields) => fields,
+        _ => return Err(SenmlError::Cbor("Record must be a map".into())),
+    };
+
+    let mut result = Vec::with_capacity(fields.len());
+    for (label, value) in fields {
+        let label = match label {
+            CborValue::Integer(label) => match i128::from(label) {
+                -1 => "bver",
+                -2 => "bn",
+                -3 => "bt",
+                -4 => "bu",
+                -5 => "bv",
+                -6 => "bs",
+                0 => "n",
+                1 => "u",
+                2 => "v",
+                3 => "vs",
+                4 => "vb",
+                5 => "s",
+                6 => "t",
+                7 => "ut",
+                8 => "vd",
+                // ignore unknown labels
+                _ => continue,
+            }
+            .to_string(),
+            CborValue::Text(label) => label,
+            _ => return Err(SenmlError::Cbor("Invalid label type".into())),
+        };
+
+        let value = match value {
+            // data values are base64url encoded in JSON
+            CborValue::Bytes(value) => {
+                CborValue::Text(base64::encode_config(value, base64::URL_SAFE_NO_PAD))
+            }
+            value => value,
+        };
+
+        result.push((CborValue::Text(label), value));
+    }
+
+    Ok(CborValue::Map(result))
+}
+
+/// Resolve the records of a pack, applying base values.
+///
+/// Relative times are resolved using the provided time.
+fn resolve(records: Vec<RawRecord>, now: DateTime<Utc>) -> Result<Vec<Record>, SenmlError> {
+    let now = now.timestamp_millis() as f64 / 1000f64;
+
+    let mut base = RawRecord::default();
+    let mut result = Vec::with_capacity(records.len());
+
+    for record in records {
+        // base values apply to all following records, until they get replaced
+
+        if record.bn.is_some() {
+            base.bn = record.bn;
+        }
+        if record.bt.is_some() {
+            base.bt = record.bt;
+        }
+        if record.bu.is_some() {
+            base.bu = record.bu;
+        }
+        if record.bv.is_some() {
+            base.bv = record.bv;
+        }
+        if record.bs.is_some() {
+            base.bs = record.bs;
+        }
+
+        let n = format!(
+            "{}{}",
+            base.bn.as_deref().unwrap_or_default(),
+            record.n.as_deref().unwrap_or_default()
+        );
+        if n.is_empty() {
+            return Err(SenmlError::Record("Missing name".into()));
+        }
+
+        let v = record.v.map(|v| v + base.bv.unwrap_or_default());
+        let s = record.s.map(|s| s + base.bs.unwrap_or_default());
+
+        if v.is_none()
+            && s.is_none()
+            && record.vs.is_none()
+            && record.vb.is_none()
+            && record.vd.is_none()
+        {
+            return Err(SenmlError::Record(format!("Missing value for '{n}'")));
+        }
+
+        let mut t = base.bt.unwrap_or_default() + record.t.unwrap_or_default();
+        if t < RELATIVE_TIME_LIMIT {
+            t += now;
+        }
+
+        result.push(Record {
+            n,
+            u: record.u.or_else(|| base.bu.clone()),
+            v,
+            vs: record.vs,
+            vb: record.vb,
+            vd: record.vd,
+            s,
+            t,
+            ut: record.ut,
+        });
+    }
+
+    Ok(result)
+}
+
+/// Decode and resolve a pack, converting it into items to publish.
+pub fn normalize(
+    format: SenmlFormat,
+    payload: &[u8],
+    normalization: SenmlNormalization,
+    now: DateTime<Utc>,
+) -> Result<Vec<BatchItem>, SenmlError> {
+    let records = resolve(decode(format, payload)?, now)?;
+    if records.is_empty() {
+        return Err(SenmlError::Record("Empty pack".into()));
+    }
+
+    Ok(match normalization {
+        SenmlNormalization::Pack => vec![BatchItem {
+            channel: None,
+            time: records[0].time(),
+            content_type: Some(mime::APPLICATION_JSON.to_string()),
+            data_schema: None,
+            r#type: None,
+            payload: serde_json::to_vec(&records)?,
+        }],
+        SenmlNormalization::Records => records
+            .into_iter()
+            .map(|record| {
+                Ok(BatchItem {
+                    channel: None,
+                    time: record.time(),
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    data_schema: None,
+                    r#type: None,
+                    payload: serde_json::to_vec(&record)?,
+                })
+            })
+            .collect::<Result<_, SenmlError>>()?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn now() -> DateTime<Utc> {
+        "2022-06-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        // example from RFC 8428, section 5.1.2
+        let records = resolve(
+            decode(
+                SenmlFormat::Json,
+                br#"[
+                    {"bn":"urn:dev:ow:10e2073a01080063:","bt":1.320067464e+09,"bu":"%RH","v":20},
+                    {"u":"lon","v":24.30621},
+                    {"u":"lat","v":60.07965},
+                    {"t":60,"v":20.3},
+                    {"n":"door","vb":true}
+                ]"#,
+            )
+            .unwrap(),
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[0].n, "urn:dev:ow:10e2073a01080063:");
+        assert_eq!(records[0].u.as_deref(), Some("%RH"));
+        assert_eq!(records[0].t, 1320067464.0);
+        assert_eq!(records[1].u.as_deref(), Some("lon"));
+        assert_eq!(records[3].t, 1320067524.0);
+        assert_eq!(records[4].n, "urn:dev:ow:10e2073a01080063:door");
+        assert_eq!(records[4].vb, Some(true));
+        assert_eq!(
+            records[0].time(),
+            Some("2011-10-31T13:24:24Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let records = resolve(
+            decode(SenmlFormat::Json, br#"[{"n":"temp","v":1,"t":-10}]"#).unwrap(),
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            records[0].time(),
+            Some("2022-06-01T11:59:50Z".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(resolve(decode(SenmlFormat::Json, br#"[{"v":1}]"#).unwrap(), now()).is_err());
+        assert!(resolve(
+            decode(SenmlFormat::Json, br#"[{"n":"temp"}]"#).unwrap(),
+            now()
+        )
+        .is_err());
+        assert!(decode(SenmlFormat::Json, br#"{"n":"temp"}"#).is_err());
+    }
+
+    #[test]
+    fn test_cbor() {
+        let pack = CborValue::Array(vec![
+            CborValue::Map(vec![
+                (
+                    CborValue::Integer((-2i64).into()),
+                    CborValue::Text("dev:".into()),
+                ),
+                (
+                    CborValue::Integer(0i64.into()),
+                    CborValue::Text("temp".into()),
+                ),
+                (
+                    CborValue::Integer(1i64.into()),
+                    CborValue::Text("Cel".into()),
+                ),
+                (CborValue::Integer(2i64.into()), CborValue::Float(21.5)),
+            ]),
+            CborValue::Map(vec![
+                (
+                    CborValue::Integer(0i64.into()),
+                    CborValue::Text("raw".into()),
+                ),
+                (
+                    CborValue::Integer(8i64.into()),
+                    CborValue::Bytes(vec![1, 2, 3]),
+                ),
+            ]),
+        ]);
+        let mut payload = vec![];
+        ciborium::ser::into_writer(&pack, &mut payload).unwrap();
+
+        let items = normalize(
+            SenmlFormat::Cbor,
+            &payload,
+            SenmlNormalization::Records,
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].time, Some(now()));
+        assert_eq!(
+            serde_json::from_slice::<Value>(&items[0].payload).unwrap(),
+            json!({"n": "dev:temp", "u": "Cel", "v": 21.5, "t": 1654084800.0})
+        );
+        assert_eq!(
+            serde_json::from_slice::<Value>(&items[1].payload).unwrap(),
+            json!({"n": "dev:raw", "vd": "AQID", "t": 1654084800.0})
+        );
+    }
+
+    #[test]
+    fn test_pack() {
+        let items = normalize(
+            SenmlFormat::Json,
+            br#"[{"bn":"dev:","n":"a","v":1,"bt":1654084800},{"n":"b","v":2,"t":1}]"#,
+            SenmlNormalization::Pack,
+            now(),
+        )
+        .unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].time, Some(now()));
+        assert_eq!(
+            serde_json::from_slice::<Value>(&items[0].payload).unwrap(),
+            json!([
+                {"n": "dev:a", "v": 1.0, "t": 1654084800.0},
+                {"n": "dev:b", "v": 2.0, "t": 1654084801.0},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            SenmlFormat::from_content_type("application/senml+json"),
+            Some(SenmlFormat::Json)
+        );
+        assert_eq!(
+            SenmlFormat::from_content_type("application/senml+cbor; charset=binary"),
+            Some(SenmlFormat::Cbor)
+        );
+        assert_eq!(SenmlFormat::from_content_type("application/json"), None);
+    }
+}
@@ -0,0 +1,272 @@
+use super::{
+    DownstreamSender, Publish, PublishError, PublishOptions, PublishOutcome, Publisher,
+    ToPublishId, UpstreamSender,
+};
+use chrono::Utc;
+use cloudevents::{event::Data, Event};
+use drogue_client::{error::ClientError, registry, Translator};
+use drogue_cloud_event_common::ext::extension;
+use drogue_cloud_service_api::{
+    services::twin::{
+        DeviceTwinSpec, DeviceTwinStatus, ReportedStates, TWIN_DELTA_CHANNEL, TWIN_DELTA_TYPE_EVENT,
+    },
+    EXT_DEVICE, EXT_SENDER,
+};
+use http::StatusCode;
+use serde_json::{Map, Value};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The time to wait for further reports of the same device, before updating the registry.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+/// The number of attempts to store the reported state, when running into conflicting updates.
+const MAX_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum TwinError {
+    #[error("Registry error")]
+    Registry(#[from] ClientError),
+    #[error("Invalid twin data")]
+    Data(#[from] serde_json::Error),
+    #[error("Failed to publish delta")]
+    Publish(#[from] PublishError),
+}
+
+/// Reports of a device, waiting to be stored.
+#[derive(Debug)]
+struct Pending {
+    application: registry::v1::Application,
+    sender: String,
+    reports: ReportedStates,
+}
+
+/// Maintains the reported state of a device twin, and announces the delta to the desired state.
+///
+/// Reports are stored in the background. Reports of the same device, arriving within a short
+/// time, are combined and applied with a single update of the device.
+#[derive(Clone, Debug)]
+pub(crate) struct DeviceTwin {
+    registry: registry::v1::Client,
+    events: Arc<DownstreamSender>,
+    commands: UpstreamSender,
+    pending: Arc<Mutex<HashMap<(String, String), Pending>>>,
+}
+
+impl DeviceTwin {
+    pub fn new(
+        registry: registry::v1::Client,
+        events: DownstreamSender,
+        commands: UpstreamSender,
+    ) -> Self {
+        Self {
+            registry,
+            events: Arc::new(events),
+            commands,
+            pending: Default::default(),
+        }
+    }
+
+    /// Process an event on the "reported" channel.
+    pub fn reported(
+        &self,
+        app: &registry::v1::Application,
+        event: &Event,
+    ) -> Result<(), TwinError> {
+        let (device, sender) = match (extension(event, EXT_DEVICE), extension(event, EXT_SENDER)) {
+            (Some(device), Some(sender)) => (device, sender),
+            _ => return Ok(()),
+        };
+
+        let reported = match event.data() {
+            Some(Data::Json(value)) => value.clone(),
+            Some(Data::Binary(data)) => serde_json::from_slice(data)?,
+            Some(Data::String(data)) => serde_json::from_str(data)?,
+            None => return Ok(()),
+        };
+        let reported = match reported {
+            Value::Object(reported) => reported,
+            _ => {
+                log::debug!("Reported state must be a JSON object");
+                return Ok(());
+            }
+        };
+
+        let key = (app.metadata.name.clone(), device.to_string());
+
+        match self.pending.lock().unwrap().entry(key) {
+            Entry::Occupied(mut entry) => {
+                let pending = entry.get_mut();
+                pending.sender = sender.to_string();
+                pending.reports.push(reported);
+            }
+            Entry::Vacant(entry) => {
+                let key = entry.key().clone();
+                let mut reports = ReportedStates::default();
+                reports.push(reported);
+                entry.insert(Pending {
+                    application: app.clone(),
+                    sender: sender.to_string(),
+                    reports,
+                });
+
+                let twin = self.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(DEBOUNCE).await;
+                    twin.flush(key).await;
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store all pending reports, without waiting for further reports.
+    pub async fn close(&self) {
+        let pending: Vec<_> = self.pending.lock().unwrap().drain().collect();
+        for (key, pending) in pending {
+            self.process(key, pending).await;
+        }
+    }
+
+    async fn flush(&self, key: (String, String)) {
+        let pending = match self.pending.lock().unwrap().remove(&key) {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        self.process(key, pending).await;
+    }
+
+    async fn process(&self, key: (String, String), pending: Pending) {
+        if let Err(err) = self.store(&key.1, pending).await {
+            log::warn!(
+                "Failed to process reported state of {}/{}: {err}",
+                key.0,
+                key.1
+            );
+        }
+    }
+
+    /// Store the reported state, and send the delta to the desired state.
+    async fn store(&self, device: &str, pending: Pending) -> Result<(), TwinError> {
+        let Pending {
+            application,
+            sender,
+            reports,
+        } = pending;
+
+        let mut attempt = 0;
+        let device = loop {
+            attempt += 1;
+
+            let mut device = match self
+                .registry
+                .get_device(&application.metadata.name, device)
+                .await?
+            {
+                Some(device) => device,
+                None => return Ok(()),
+            };
+
+            device.update_section(|mut twin: DeviceTwinStatus| {
+                twin.apply_all(&reports);
+                twin.last_update = Some(Utc::now());
+                twin
+            })?;
+
+            match self.registry.update_device(&device).await {
+                Ok(_) => break device,
+                Err(ClientError::Service { code, .. })
+                    if code == StatusCode::CONFLICT && attempt < MAX_ATTEMPTS =>
+                {
+                    log::debug!(
+                        "Conflict storing the reported state of {} (attempt {attempt}), retrying",
+                        device.metadata.name
+                    );
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+
+        let spec: DeviceTwinSpec = device.section().transpose()?.unwrap_or_default();
+        let status: DeviceTwinStatus = device.section().transpose()?.unwrap_or_default();
+        let delta = spec.delta(&status);
+        if delta.is_empty() {
+            return Ok(());
+        }
+
+        log::debug!("Twin delta for {}: {:?}", device.metadata.name, delta);
+
+        send_delta(
+            &self.events,
+            &self.commands,
+            &application,
+            &device,
+            &sender,
+            delta,
+        )
+        .await
+    }
+}
+
+/// Send the delta between the desired and the reported state, to the device as a command and to
+/// the integrations as an event.
+pub async fn send_delta(
+    events: &DownstreamSender,
+    commands: &UpstreamSender,
+    app: &registry::v1::Application,
+    device: &registry::v1::Device,
+    sender: &str,
+    delta: Map<String, Value>,
+) -> Result<(), TwinError> {
+    let body = serde_json::to_vec(&delta)?;
+
+    // to the device, as a command
+
+    let outcome = commands
+        .publish(
+            Publish {
+                application: app,
+                device: device.metadata.to_id(),
+                sender: sender.to_id(),
+                channel: TWIN_DELTA_CHANNEL.to_string(),
+                options: PublishOptions {
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    ..Default::default()
+                },
+            },
+            &body,
+        )
+        .await?;
+    if !matches!(outcome, PublishOutcome::Accepted) {
+        log::info!("Twin delta command not accepted: {outcome:?}");
+    }
+
+    // to the integrations, as an event
+
+    let outcome = events
+        .publish(
+            Publish {
+                application: app,
+                device: device.metadata.to_id(),
+                sender: sender.to_id(),
+                channel: TWIN_DELTA_CHANNEL.to_string(),
+                options: PublishOptions {
+                    content_type: Some(mime::APPLICATION_JSON.to_string()),
+                    r#type: Some(TWIN_DELTA_TYPE_EVENT.to_string()),
+                    ..Default::default()
+                },
+            },
+            &body,
+        )
+        .await?;
+    if !matches!(outcome, PublishOutcome::Accepted) {
+        log::info!("Twin delta event not accepted: {outcome:?}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use super::*;
+use async_trait::async_trait;
+use cloudevents::AttributesReader;
+use drogue_cloud_event_common::bus::EventBus;
+use drogue_cloud_service_api::kafka::{KafkaConfigExt, KafkaEventType};
+use tracing::instrument;
+
+/// A sink publishing to the in-process event bus.
+#[derive(Clone, Debug)]
+pub struct EventBusSink {
+    bus: EventBus,
+}
+
+impl EventBusSink {
+    pub fn new(bus: EventBus) -> Self {
+        Self { bus }
+    }
+}
+
+#[async_trait]
+impl Sink for EventBusSink {
+    #[allow(clippy::needless_lifetimes)]
+    #[instrument(level = "debug", skip_all, fields(
+        application=%target.metadata.name,
+        id=%event.id(),
+    ))]
+    async fn publish<'a>(
+        &self,
+        target: SinkTarget<'a>,
+        event: Event,
+    ) -> Result<PublishOutcome, SinkError> {
+        let topic = match target {
+            SinkTarget::Commands(app) => app.kafka_topic(KafkaEventType::Commands),
+            SinkTarget::Events(app) => app.kafka_topic(KafkaEventType::Events),
+            SinkTarget::DeadLetters(app) => app.kafka_topic(KafkaEventType::DeadLetters),
+        }
+        .map_err(|err| SinkError::Target(Box::new(err)))?;
+
+        log::debug!("Event bus topic: {:?}", topic);
+
+        self.bus
+            .publish(&topic, event)
+            .map_err(|err| SinkError::Transport(Box::new(err)))?;
+
+        Ok(PublishOutcome::Accepted)
+    }
+}
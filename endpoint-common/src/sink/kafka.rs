@@ -163,6 +163,7 @@ impl Sink for KafkaSink {
         let topic = match target {
             SinkTarget::Commands(app) => app.kafka_topic(KafkaEventType::Commands),
             SinkTarget::Events(app) => app.kafka_topic(KafkaEventType::Events),
+            SinkTarget::DeadLetters(app) => app.kafka_topic(KafkaEventType::DeadLetters),
         }
         .map_err(|err| SinkError::Target(Box::new(err)))?;
 
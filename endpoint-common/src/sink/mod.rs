@@ -1,13 +1,18 @@
+mod bus;
 mod http;
 mod kafka;
 
 pub use self::http::HttpSink;
+pub use bus::*;
 pub use kafka::*;
 
 use crate::sender::PublishOutcome;
 use async_trait::async_trait;
 use cloudevents::Event;
 use drogue_client::registry;
+use drogue_cloud_event_common::bus;
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
 use std::{fmt::Debug, ops::Deref};
 use thiserror::Error;
 
@@ -15,6 +20,7 @@ use thiserror::Error;
 pub enum SinkTarget<'a> {
     Events(&'a registry::v1::Application),
     Commands(&'a registry::v1::Application),
+    DeadLetters(&'a registry::v1::Application),
 }
 
 impl<'a> Deref for SinkTarget<'a> {
@@ -24,6 +30,7 @@ impl<'a> Deref for SinkTarget<'a> {
         match self {
             SinkTarget::Commands(app) => app,
             SinkTarget::Events(app) => app,
+            SinkTarget::DeadLetters(app) => app,
         }
     }
 }
@@ -39,6 +46,38 @@ pub trait Sink: Send + Sync + Debug + 'static {
     ) -> Result<PublishOutcome, SinkError>;
 }
 
+/// A sink for the messaging backend selected by the configuration.
+#[derive(Clone, Debug)]
+pub enum MessagingSink {
+    Kafka(KafkaSink),
+    EventBus(EventBusSink),
+}
+
+impl MessagingSink {
+    /// Create a new sink, using the event bus or Kafka, depending on the configuration.
+    pub fn from_config(config: KafkaClientConfig, check_ready: bool) -> anyhow::Result<Self> {
+        Ok(match bus::from_config(&config)? {
+            Some(bus) => Self::EventBus(EventBusSink::new(bus)),
+            None => Self::Kafka(KafkaSink::from_config(config, check_ready)?),
+        })
+    }
+}
+
+#[async_trait]
+impl Sink for MessagingSink {
+    #[allow(clippy::needless_lifetimes)]
+    async fn publish<'a>(
+        &self,
+        target: SinkTarget<'a>,
+        event: Event,
+    ) -> Result<PublishOutcome, SinkError> {
+        match self {
+            Self::Kafka(sink) => sink.publish(target, event).await,
+            Self::EventBus(sink) => sink.publish(target, event).await,
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum SinkError {
     #[error("Event error")]
@@ -48,3 +87,27 @@ pub enum SinkError {
     #[error("Target error")]
     Target(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
+
+impl SinkError {
+    /// Check if the error is permanent, so that retrying to send the same event won't help.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Event(_) => true,
+            Self::Transport(err) => err
+                .downcast_ref::<KafkaError>()
+                .and_then(|err| err.rdkafka_error_code())
+                .map(|code| {
+                    matches!(
+                        code,
+                        RDKafkaErrorCode::BadMessage
+                            | RDKafkaErrorCode::InvalidMessage
+                            | RDKafkaErrorCode::InvalidMessageSize
+                            | RDKafkaErrorCode::MessageSizeTooLarge
+                            | RDKafkaErrorCode::InvalidRecord
+                    )
+                })
+                .unwrap_or_default(),
+            Self::Target(_) => false,
+        }
+    }
+}
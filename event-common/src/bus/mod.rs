@@ -0,0 +1,643 @@
+//! An in-process event bus, which can be used instead of Kafka.
+//!
+//! The bus is selected by using a bootstrap servers value of `memory:`, or `file:<path>` for a
+//! disk backed bus. All clients of the same process, using the same value, share a single bus.
+
+mod writer;
+
+use cloudevents::Event;
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{Debug, Formatter},
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use uuid::Uuid;
+use writer::Writer;
+
+/// Bootstrap servers value, selecting the in-memory bus.
+pub const MEMORY_SCHEME: &str = "memory:";
+/// Bootstrap servers prefix, selecting the disk backed bus.
+pub const FILE_SCHEME: &str = "file:";
+
+/// The number of events retained per topic.
+pub const DEFAULT_RETENTION: usize = 10_000;
+/// The number of events which can be queued for a single subscription.
+const SUBSCRIPTION_CAPACITY: usize = 128;
+
+lazy_static! {
+    static ref BUSES: Mutex<HashMap<Option<PathBuf>, EventBus>> = Default::default();
+}
+
+#[derive(Debug, Error)]
+pub enum BusError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Invalid topic pattern: {0}")]
+    Pattern(#[from] regex::Error),
+}
+
+/// The messaging backend, selected by a client configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// An external Kafka cluster.
+    Kafka,
+    /// The in-process bus, optionally backed by the provided directory.
+    Bus(Option<PathBuf>),
+}
+
+impl Backend {
+    pub fn from_config(config: &KafkaClientConfig) -> Self {
+        let servers = config.bootstrap_servers.trim();
+        if servers == MEMORY_SCHEME {
+            Self::Bus(None)
+        } else if let Some(path) = servers.strip_prefix(FILE_SCHEME) {
+            Self::Bus(Some(PathBuf::from(path)))
+        } else {
+            Self::Kafka
+        }
+    }
+}
+
+/// Get the event bus selected by the configuration, or `None` if it selects Kafka.
+pub fn from_config(config: &KafkaClientConfig) -> Result<Option<EventBus>, BusError> {
+    match Backend::from_config(config) {
+        Backend::Kafka => Ok(None),
+        Backend::Bus(path) => {
+            let mut buses = BUSES.lock().unwrap();
+            if let Some(bus) = buses.get(&path) {
+                return Ok(Some(bus.clone()));
+            }
+            let bus = EventBus::new(path.clone())?;
+            buses.insert(path, bus.clone());
+            Ok(Some(bus))
+        }
+    }
+}
+
+/// An event, and its position in the topic.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub topic: String,
+    pub offset: u64,
+    pub event: Event,
+}
+
+struct Group {
+    members: Vec<mpsc::Sender<Record>>,
+    next_member: usize,
+    /// The offset of the next event to deliver.
+    delivered: u64,
+    /// The offset of the first event which is not acknowledged.
+    committed: u64,
+    /// Durable groups are kept, even without members.
+    durable: bool,
+}
+
+impl Group {
+    /// Deliver a record to one of the members of the group.
+    ///
+    /// Returns `false` if no member can currently take the record.
+    fn deliver(&mut self, record: &Record) -> bool {
+        self.members.retain(|member| !member.is_closed());
+
+        for i in 0..self.members.len() {
+            let idx = (self.next_member + i) % self.members.len();
+            match self.members[idx].try_send(record.clone()) {
+                Ok(()) => {
+                    self.next_member = idx + 1;
+                    return true;
+                }
+                // try the next one
+                Err(TrySendError::Full(_) | TrySendError::Closed(_)) => {}
+            }
+        }
+
+        false
+    }
+}
+
+struct Topic {
+    name: String,
+    /// The offset of the first retained record.
+    first: u64,
+    records: VecDeque<Record>,
+    groups: HashMap<String, Group>,
+}
+
+impl Topic {
+    fn new(name: &str, records: VecDeque<Record>) -> Self {
+        Self {
+            name: name.to_string(),
+            first: records.front().map(|r| r.offset).unwrap_or_default(),
+            records,
+            groups: Default::default(),
+        }
+    }
+
+    /// The offset of the next record.
+    fn next(&self) -> u64 {
+        self.first + self.records.len() as u64
+    }
+
+    fn append(&mut self, event: Event, retention: usize) -> Record {
+        let record = Record {
+            topic: self.name.clone(),
+            offset: self.next(),
+            event,
+        };
+
+        self.records.push_back(record.clone());
+        while self.records.len() > retention {
+            self.records.pop_front();
+            self.first += 1;
+        }
+
+        record
+    }
+
+    /// Check if a consumer group has records which are not yet delivered.
+    fn is_pending(&self, group: &str) -> bool {
+        self.groups
+            .get(group)
+            .map(|group| group.delivered < self.next())
+            .unwrap_or_default()
+    }
+
+    /// Deliver the records, which are not yet delivered, to the consumer groups.
+    ///
+    /// Delivery of a group stops once none of its members can take more records, and continues
+    /// with the next call.
+    fn pump(&mut self) {
+        let next = self.next();
+        let Topic {
+            name,
+            first,
+            records,
+            groups,
+        } = self;
+
+        for (group_name, group) in groups.iter_mut() {
+            if group.delivered < *first {
+                log::warn!(
+                    "Consumer group '{group_name}' missed {} events of topic '{name}', which exceeded the retention",
+                    *first - group.delivered
+                );
+                group.delivered = *first;
+            }
+
+            while group.delivered < next {
+                let record = &records[(group.delivered - *first) as usize];
+                if !group.deliver(record) {
+                    break;
+                }
+                group.delivered += 1;
+            }
+        }
+
+        groups.retain(|_, group| group.durable || !group.members.is_empty());
+    }
+
+    /// Add a member to a consumer group of the topic.
+    ///
+    /// New groups start with the next record, unless they committed an offset before.
+    fn join(&mut self, group: &Membership, member: mpsc::Sender<Record>, committed: Option<u64>) {
+        let next = self.next();
+        let state = self.groups.entry(group.name.clone()).or_insert_with(|| {
+            let committed = committed.unwrap_or(next);
+            Group {
+                members: vec![],
+                next_member: 0,
+                delivered: committed,
+                committed,
+                durable: group.durable,
+            }
+        });
+
+        state.members.retain(|member| !member.is_closed());
+        if state.members.is_empty() {
+            // first member, deliver again the records which were not yet acknowledged
+            state.delivered = state.committed;
+        }
+        state.members.push(member);
+
+        self.pump();
+    }
+}
+
+/// The consumer group of a subscription.
+#[derive(Clone, Debug)]
+struct Membership {
+    name: String,
+    /// Durable groups keep their position, even without members.
+    durable: bool,
+}
+
+/// A subscription to all topics matching a pattern.
+struct PatternSubscription {
+    pattern: Regex,
+    group: Membership,
+    member: mpsc::Sender<Record>,
+}
+
+struct Inner {
+    writer: Option<Writer>,
+    retention: usize,
+    topics: HashMap<String, Topic>,
+    patterns: Vec<PatternSubscription>,
+    /// Offsets committed before the bus was started.
+    offsets: HashMap<(String, String), u64>,
+}
+
+impl Inner {
+    fn topic(&mut self, name: &str) -> &mut Topic {
+        if !self.topics.contains_key(name) {
+            let mut topic = Topic::new(name, Default::default());
+
+            // add all pattern subscriptions matching the new topic
+            self.patterns.retain(|p| !p.member.is_closed());
+            for p in &self.patterns {
+                if p.pattern.is_match(name) {
+                    let committed = committed(&self.offsets, name, &p.group.name);
+                    topic.join(&p.group, p.member.clone(), committed);
+                }
+            }
+
+            self.topics.insert(name.to_string(), topic);
+        }
+        self.topics.get_mut(name).expect("Topic must exist")
+    }
+}
+
+fn committed(offsets: &HashMap<(String, String), u64>, topic: &str, group: &str) -> Option<u64> {
+    offsets
+        .get(&(topic.to_string(), group.to_string()))
+        .copied()
+}
+
+/// An in-process event bus.
+///
+/// Each event of a topic is delivered to one member of each consumer group subscribed to the
+/// topic. Every topic retains a number of events, so that named consumer groups can continue
+/// with the first event they didn't acknowledge, even if they had no members for some time.
+///
+/// If the bus is disk backed, events and acknowledgements are stored by a dedicated thread, and
+/// are loaded again when the bus gets created.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Debug for EventBus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus").finish()
+    }
+}
+
+impl EventBus {
+    /// Create a new event bus, which is disk backed if a directory is provided.
+    pub fn new(dir: Option<PathBuf>) -> Result<Self, BusError> {
+        Self::with_retention(dir, DEFAULT_RETENTION)
+    }
+
+    /// Create a new event bus, retaining the provided number of events per topic.
+    pub fn with_retention(dir: Option<PathBuf>, retention: usize) -> Result<Self, BusError> {
+        let retention = retention.max(1);
+
+        let (writer, stored) = match dir {
+            Some(dir) => {
+                fs::create_dir_all(&dir)?;
+                let stored = writer::load(&dir, retention)?;
+                (Some(Writer::new(dir, retention)?), stored)
+            }
+            None => (None, Default::default()),
+        };
+
+        let topics = stored
+            .topics
+            .into_iter()
+            .map(|(name, records)| {
+                let topic = Topic::new(&name, records);
+                (name, topic)
+            })
+            .collect();
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                writer,
+                retention,
+                topics,
+                patterns: Default::default(),
+                offsets: stored.offsets,
+            })),
+        })
+    }
+
+    /// Publish an event to a topic, returning the offset of the event.
+    pub fn publish(&self, topic: &str, event: Event) -> Result<u64, BusError> {
+        let mut inner = self.inner.lock().unwrap();
+        let retention = inner.retention;
+
+        let topic = inner.topic(topic);
+        let record = topic.append(event, retention);
+        topic.pump();
+
+        if let Some(writer) = &inner.writer {
+            writer.append(&record)?;
+        }
+
+        Ok(record.offset)
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// If the topic starts with `^`, it is used as a pattern, subscribing to all matching
+    /// topics. Without a consumer group, the subscription will receive all events.
+    pub fn subscribe(
+        &self,
+        topic: &str,
+        consumer_group: Option<String>,
+    ) -> Result<Subscription, BusError> {
+        let group = match consumer_group {
+            Some(name) => Membership {
+                name,
+                durable: true,
+            },
+            None => Membership {
+                name: format!("anonymous.{}", Uuid::new_v4()),
+                durable: false,
+            },
+        };
+
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_CAPACITY);
+
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        if topic.starts_with('^') {
+            let pattern = Regex::new(topic)?;
+            for topic in inner.topics.values_mut() {
+                if pattern.is_match(&topic.name) {
+                    let committed = committed(&inner.offsets, &topic.name, &group.name);
+                    topic.join(&group, tx.clone(), committed);
+                }
+            }
+            inner.patterns.push(PatternSubscription {
+                pattern,
+                group: group.clone(),
+                member: tx,
+            });
+        } else {
+            let committed = committed(&inner.offsets, topic, &group.name);
+            inner.topic(topic).join(&group, tx, committed);
+        }
+
+        Ok(Subscription {
+            bus: self.clone(),
+            group: group.name,
+            receiver: rx,
+        })
+    }
+
+    /// Wait until all events and acknowledgements, which happened before, are stored.
+    pub fn sync(&self) {
+        let writer = self.inner.lock().unwrap().writer.clone();
+        if let Some(writer) = writer {
+            writer.sync();
+        }
+    }
+
+    /// Continue delivering records to a consumer group, which has records pending.
+    fn pump(&self, group: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        for topic in inner.topics.values_mut() {
+            if topic.is_pending(group) {
+                topic.pump();
+            }
+        }
+    }
+
+    fn commit(&self, topic: &str, group: &str, offset: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        if let Some(state) = inner
+            .topics
+            .get_mut(topic)
+            .and_then(|topic| topic.groups.get_mut(group))
+        {
+            if offset >= state.committed {
+                state.committed = offset + 1;
+                if let (true, Some(writer)) = (state.durable, &inner.writer) {
+                    writer.commit(topic, group, offset + 1);
+                }
+            }
+        }
+    }
+}
+
+/// A subscription to the event bus.
+pub struct Subscription {
+    bus: EventBus,
+    group: String,
+    receiver: mpsc::Receiver<Record>,
+}
+
+impl Debug for Subscription {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Subscription")
+            .field("group", &self.group)
+            .finish()
+    }
+}
+
+impl Subscription {
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Record>> {
+        match self.receiver.poll_recv(cx) {
+            Poll::Ready(Some(record)) => {
+                // we made room for more records
+                self.bus.pump(&self.group);
+                Poll::Ready(Some(record))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                self.bus.pump(&self.group);
+                self.receiver.poll_recv(cx)
+            }
+        }
+    }
+
+    /// Acknowledge all events of the topic for the consumer group, up to and including the
+    /// provided offset.
+    pub fn ack(&self, topic: &str, offset: u64) -> Result<(), BusError> {
+        self.bus.commit(topic, &self.group, offset);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{AttributesReader, EventBuilder, EventBuilderV10};
+    use futures::future::poll_fn;
+
+    fn event(id: &str) -> Event {
+        EventBuilderV10::new()
+            .id(id)
+            .source("test")
+            .ty("test")
+            .build()
+            .unwrap()
+    }
+
+    async fn next(sub: &mut Subscription) -> Record {
+        poll_fn(|cx| sub.poll_recv(cx)).await.unwrap()
+    }
+
+    #[test]
+    fn test_backend() {
+        let mut config = KafkaClientConfig::default();
+        assert_eq!(Backend::from_config(&config), Backend::Kafka);
+
+        config.bootstrap_servers = "memory:".into();
+        assert_eq!(Backend::from_config(&config), Backend::Bus(None));
+
+        config.bootstrap_servers = "file:/var/lib/drogue".into();
+        assert_eq!(
+            Backend::from_config(&config),
+            Backend::Bus(Some(PathBuf::from("/var/lib/drogue")))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let bus = EventBus::new(None).unwrap();
+
+        let mut a1 = bus.subscribe("topic", Some("a".into())).unwrap();
+        let mut a2 = bus.subscribe("topic", Some("a".into())).unwrap();
+        let mut b = bus.subscribe("topic", None).unwrap();
+
+        bus.publish("topic", event("1")).unwrap();
+        bus.publish("topic", event("2")).unwrap();
+
+        // each group receives every event once
+        assert_eq!(next(&mut a1).await.event.id(), "1");
+        assert_eq!(next(&mut a2).await.event.id(), "2");
+        assert_eq!(next(&mut b).await.offset, 0);
+        assert_eq!(next(&mut b).await.offset, 1);
+    }
+
+    #[tokio::test]
+    async fn test_pattern() {
+        let bus = EventBus::new(None).unwrap();
+
+        bus.publish("events-foo", event("1")).unwrap();
+        let mut sub = bus.subscribe("^events-.*", Some("group".into())).unwrap();
+
+        bus.publish("events-foo", event("2")).unwrap();
+        bus.publish("commands-foo", event("3")).unwrap();
+        // topics created later are matched too
+        bus.publish("events-bar", event("4")).unwrap();
+
+        let record = next(&mut sub).await;
+        assert_eq!(record.topic, "events-foo");
+        assert_eq!(record.event.id(), "2");
+        let record = next(&mut sub).await;
+        assert_eq!(record.topic, "events-bar");
+        assert_eq!(record.event.id(), "4");
+    }
+
+    #[tokio::test]
+    async fn test_disk_backed() {
+        let dir = std::env::temp_dir().join(format!("drogue-bus-{}", Uuid::new_v4()));
+
+        {
+            let bus = EventBus::new(Some(dir.clone())).unwrap();
+            let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+            bus.publish("topic", event("1")).unwrap();
+            bus.publish("topic", event("2")).unwrap();
+
+            let record = next(&mut sub).await;
+            sub.ack(&record.topic, record.offset).unwrap();
+            bus.sync();
+        }
+
+        // a new bus resumes after the last acknowledged event
+        let bus = EventBus::new(Some(dir.clone())).unwrap();
+        let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+        assert_eq!(next(&mut sub).await.event.id(), "2");
+        bus.publish("topic", event("3")).unwrap();
+        assert_eq!(next(&mut sub).await.offset, 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_group_without_members() {
+        let bus = EventBus::new(None).unwrap();
+
+        let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+        bus.publish("topic", event("1")).unwrap();
+        let record = next(&mut sub).await;
+        sub.ack(&record.topic, record.offset).unwrap();
+        drop(sub);
+
+        // published while the group has no members
+        bus.publish("topic", event("2")).unwrap();
+
+        let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+        assert_eq!(next(&mut sub).await.event.id(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_bounded() {
+        let bus = EventBus::new(None).unwrap();
+        let mut sub = bus.subscribe("topic", None).unwrap();
+
+        let count = SUBSCRIPTION_CAPACITY as u64 * 3;
+        for i in 0..count {
+            bus.publish("topic", event(&i.to_string())).unwrap();
+        }
+
+        // records exceeding the capacity are delivered once there is room
+        for i in 0..count {
+            assert_eq!(next(&mut sub).await.offset, i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let dir = std::env::temp_dir().join(format!("drogue-bus-{}", Uuid::new_v4()));
+
+        {
+            let bus = EventBus::with_retention(Some(dir.clone()), 2).unwrap();
+            let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+            for i in 0..10 {
+                bus.publish("topic", event(&i.to_string())).unwrap();
+            }
+            let record = next(&mut sub).await;
+            sub.ack(&record.topic, record.offset).unwrap();
+            bus.sync();
+        }
+
+        // the log got compacted, only the retained events remain, skipping the missed ones
+        let bus = EventBus::with_retention(Some(dir.clone()), 2).unwrap();
+        let mut sub = bus.subscribe("topic", Some("group".into())).unwrap();
+        assert_eq!(next(&mut sub).await.offset, 8);
+        assert_eq!(next(&mut sub).await.offset, 9);
+
+        bus.publish("topic", event("10")).unwrap();
+        assert_eq!(next(&mut sub).await.offset, 10);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
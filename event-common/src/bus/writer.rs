@@ -0,0 +1,263 @@
+use super::{BusError, Record};
+use cloudevents::Event;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+};
+
+/// A line of the log of a topic.
+#[derive(Serialize, Deserialize)]
+struct LogEntry {
+    offset: u64,
+    event: Event,
+}
+
+enum Operation {
+    Append { topic: String, line: Vec<u8> },
+    Commit { file: String, offset: u64 },
+    Sync(mpsc::Sender<()>),
+}
+
+/// Stores the data of a disk backed bus, using a dedicated thread.
+///
+/// The log of a topic is compacted to the retained events, once it holds twice as many.
+#[derive(Clone)]
+pub(crate) struct Writer {
+    tx: mpsc::Sender<Operation>,
+}
+
+impl Writer {
+    pub fn new(dir: PathBuf, retention: usize) -> Result<Self, BusError> {
+        let (tx, rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("event-bus-writer".into())
+            .spawn(move || run(dir, retention, rx))?;
+
+        Ok(Self { tx })
+    }
+
+    pub fn append(&self, record: &Record) -> Result<(), BusError> {
+        let mut line = serde_json::to_vec(&LogEntry {
+            offset: record.offset,
+            event: record.event.clone(),
+        })?;
+        line.push(b'\n');
+
+        self.send(Operation::Append {
+            topic: record.topic.clone(),
+            line,
+        });
+
+        Ok(())
+    }
+
+    pub fn commit(&self, topic: &str, group: &str, offset: u64) {
+        self.send(Operation::Commit {
+            file: offset_file(topic, group),
+            offset,
+        });
+    }
+
+    /// Wait until all previous operations are stored.
+    pub fn sync(&self) {
+        let (tx, rx) = mpsc::channel();
+        self.send(Operation::Sync(tx));
+        let _ = rx.recv();
+    }
+
+    fn send(&self, operation: Operation) {
+        if self.tx.send(operation).is_err() {
+            log::warn!("Event bus writer stopped, dropping data");
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    entries: usize,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        let entries = match File::open(&path) {
+            Ok(file) => BufReader::new(file).lines().count(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        Ok(Self {
+            path,
+            file,
+            entries,
+        })
+    }
+
+    fn append(&mut self, line: &[u8], retention: usize) -> std::io::Result<()> {
+        self.file.write_all(line)?;
+        self.entries += 1;
+
+        if self.entries > retention * 2 {
+            self.compact(retention)?;
+        }
+
+        Ok(())
+    }
+
+    /// Rewrite the log, only keeping the retained entries.
+    fn compact(&mut self, retention: usize) -> std::io::Result<()> {
+        let lines = BufReader::new(File::open(&self.path)?)
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        let skip = lines.len().saturating_sub(retention);
+
+        let tmp = self.path.with_extension("log.tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            for line in &lines[skip..] {
+                file.write_all(line.as_bytes())?;
+                file.write_all(b"\n")?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+
+        *self = Self::open(self.path.clone())?;
+
+        Ok(())
+    }
+}
+
+fn run(dir: PathBuf, retention: usize, rx: mpsc::Receiver<Operation>) {
+    let mut logs = HashMap::<String, LogFile>::new();
+
+    for operation in rx {
+        let result = match operation {
+            Operation::Append { topic, line } => match logs.entry(topic) {
+                Entry::Occupied(entry) => Ok(entry.into_mut()),
+                Entry::Vacant(entry) => {
+                    LogFile::open(dir.join(log_file(entry.key()))).map(|log| entry.insert(log))
+                }
+            }
+            .and_then(|log| log.append(&line, retention)),
+            Operation::Commit { file, offset } => fs::write(dir.join(file), offset.to_string()),
+            Operation::Sync(tx) => {
+                let _ = tx.send(());
+                Ok(())
+            }
+        };
+
+        if let Err(err) = result {
+            log::warn!("Failed to store event bus data: {err}");
+        }
+    }
+}
+
+/// Data of a disk backed bus, loaded on startup.
+#[derive(Default)]
+pub(crate) struct Stored {
+    /// The retained records, by topic.
+    pub topics: HashMap<String, VecDeque<Record>>,
+    /// The committed offsets, by topic and consumer group.
+    pub offsets: HashMap<(String, String), u64>,
+}
+
+/// Load the retained records and committed offsets from a directory.
+pub(crate) fn load(dir: &Path, retention: usize) -> Result<Stored, BusError> {
+    let mut result = Stored::default();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        if let Some(topic) = name.strip_suffix(".log").and_then(decode_name) {
+            let mut records = VecDeque::new();
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let entry: LogEntry = serde_json::from_str(&line?)?;
+                records.push_back(Record {
+                    topic: topic.clone(),
+                    offset: entry.offset,
+                    event: entry.event,
+                });
+                if records.len() > retention {
+                    records.pop_front();
+                }
+            }
+            result.topics.insert(topic, records);
+        } else if let Some((topic, group)) = name
+            .strip_suffix(".offset")
+            .and_then(|name| name.split_once('.'))
+        {
+            if let (Some(topic), Some(group)) = (decode_name(topic), decode_name(group)) {
+                if let Ok(offset) = fs::read_to_string(&path)?.trim().parse() {
+                    result.offsets.insert((topic, group), offset);
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+fn log_file(topic: &str) -> String {
+    format!("{}.log", encode_name(topic))
+}
+
+fn offset_file(topic: &str, group: &str) -> String {
+    format!("{}.{}.offset", encode_name(topic), encode_name(group))
+}
+
+/// Make a name safe for being used as a file name, in a way that it can be decoded again.
+fn encode_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    for b in name.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' => result.push(b as char),
+            _ => {
+                let _ = write!(result, "_{b:02X}");
+            }
+        }
+    }
+    result
+}
+
+fn decode_name(name: &str) -> Option<String> {
+    let mut result = Vec::with_capacity(name.len());
+    let mut bytes = name.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'_' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                result.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b => result.push(b),
+        }
+    }
+    String::from_utf8(result).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for name in ["events-foo", "events.foo_bar", "a/b c", "ünicode"] {
+            let encoded = encode_name(name);
+            assert!(!encoded.contains('.'));
+            assert_eq!(decode_name(&encoded).as_deref(), Some(name));
+        }
+        assert_eq!(decode_name("foo_4"), None);
+    }
+}
@@ -0,0 +1,11 @@
+//! Accessing extensions of cloud events.
+
+use cloudevents::{event::ExtensionValue, Event};
+
+/// Get the value of an extension, if it is a string.
+pub fn extension<'e>(event: &'e Event, name: &str) -> Option<&'e str> {
+    match event.extension(name) {
+        Some(ExtensionValue::String(value)) => Some(value.as_str()),
+        _ => None,
+    }
+}
@@ -1 +1,3 @@
+pub mod bus;
+pub mod ext;
 pub mod stream;
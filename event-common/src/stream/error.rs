@@ -1,3 +1,4 @@
+use crate::bus::BusError;
 use rdkafka::error::KafkaError;
 use thiserror::Error;
 
@@ -7,6 +8,8 @@ pub enum EventStreamError {
     Kafka(#[from] KafkaError),
     #[error("Missing metadata")]
     MissingMetadata,
+    #[error("Event bus error: {0}")]
+    Bus(#[from] BusError),
     #[error("Cloud event error: {0}")]
     CloudEvent(#[from] cloudevents::message::Error),
 }
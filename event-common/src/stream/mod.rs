@@ -2,6 +2,7 @@ mod error;
 
 pub use error::*;
 
+use crate::bus::{self, Subscription};
 use cloudevents::{binding::rdkafka::MessageExt, AttributesReader, AttributesWriter, Data, Event};
 use drogue_cloud_service_api::kafka::KafkaConfig;
 use futures::{
@@ -86,10 +87,29 @@ where
     Ack: AckMode,
 {
     _marker: PhantomData<Ack>,
-    upstream: OwningHandle<Box<StreamConsumer>, Box<rdkafka::consumer::MessageStream<'s>>>,
+    upstream: Upstream<'s>,
     topic: String,
 }
 
+enum Upstream<'s> {
+    Kafka(OwningHandle<Box<StreamConsumer>, Box<rdkafka::consumer::MessageStream<'s>>>),
+    Bus(Subscription),
+}
+
+/// The position of an event in a partition of a Kafka topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Offset {
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// The position of an event in the upstream source.
+#[derive(Debug)]
+enum Position<'s> {
+    Kafka(BorrowedMessage<'s>),
+    Bus(String, u64),
+}
+
 impl<Ack> Debug for EventStream<'_, Ack>
 where
     Ack: AckMode,
@@ -101,11 +121,21 @@ where
     }
 }
 
-impl<Ack> EventStream<'_, Ack>
+impl<'s, Ack> EventStream<'s, Ack>
 where
     Ack: AckMode,
 {
     pub fn new(cfg: EventStreamConfig) -> Result<Self, EventStreamError> {
+        if let Some(bus) = bus::from_config(&cfg.kafka.client)? {
+            log::debug!("Subscribing to event bus");
+            let subscription = bus.subscribe(&cfg.kafka.topic, cfg.consumer_group)?;
+            return Ok(Self {
+                _marker: PhantomData,
+                upstream: Upstream::Bus(subscription),
+                topic: cfg.kafka.topic,
+            });
+        }
+
         match &cfg.consumer_group {
             Some(consumer_group) => Self::new_with_group(&cfg, consumer_group.clone()),
             None => {
@@ -116,6 +146,52 @@ where
         }
     }
 
+    /// Create a new stream, continuing after the provided offsets.
+    ///
+    /// Partitions of the topic are assigned manually, so the stream doesn't take part in the
+    /// balancing of a consumer group. Partitions without an offset start with the next event.
+    ///
+    /// The event bus doesn't support resuming, the stream starts with the next event instead.
+    pub fn resume(cfg: EventStreamConfig, offsets: &[Offset]) -> Result<Self, EventStreamError> {
+        if offsets.is_empty() || bus::from_config(&cfg.kafka.client)?.is_some() {
+            return Self::new(cfg);
+        }
+
+        let group_id = cfg
+            .consumer_group
+            .clone()
+            .unwrap_or_else(|| format!("anonymous.{}", Uuid::new_v4()));
+
+        let mut consumer = Self::new_config(&cfg);
+        consumer.set("group.id", &group_id);
+
+        Ack::configure(&mut consumer);
+
+        let consumer: StreamConsumer<DefaultConsumerContext> = consumer.create()?;
+
+        log::debug!("Created consumer");
+
+        let topic = cfg.kafka.topic.clone();
+
+        let partitions = Self::partitions(&consumer, &topic)?;
+        let mut assignment = TopicPartitionList::with_capacity(partitions.len());
+        for partition in partitions {
+            let offset = offsets
+                .iter()
+                .find(|offset| offset.partition == partition)
+                .map(|offset| rdkafka::Offset::Offset(offset.offset + 1))
+                .unwrap_or(rdkafka::Offset::End);
+            log::debug!("Adding partition: {partition} ({offset:?})");
+            assignment.add_partition_offset(&topic, partition, offset)?;
+        }
+
+        consumer.assign(&assignment)?;
+
+        log::debug!("Resumed");
+
+        Ok(Self::wrap(topic, consumer))
+    }
+
     /// Create a new common client config
     fn new_config(cfg: &EventStreamConfig) -> ClientConfig {
         let mut config = ClientConfig::new();
@@ -159,8 +235,24 @@ where
 
         let topic = cfg.kafka.topic.clone();
 
+        let partitions = Self::partitions(&consumer, &topic)?;
+        let mut assignment = TopicPartitionList::with_capacity(partitions.len());
+        for partition in partitions {
+            log::debug!("Adding partition: {partition}");
+            assignment.add_partition(&topic, partition);
+        }
+
+        consumer.assign(&assignment)?;
+
+        log::debug!("Subscribed");
+
+        Ok(Self::wrap(topic, consumer))
+    }
+
+    /// Get the partition ids of a topic.
+    fn partitions(consumer: &StreamConsumer, topic: &str) -> Result<Vec<i32>, EventStreamError> {
         let metadata =
-            consumer.fetch_metadata(Some(&topic), Timeout::After(Duration::from_secs(10)))?;
+            consumer.fetch_metadata(Some(topic), Timeout::After(Duration::from_secs(10)))?;
 
         let partitions = metadata
             .topics()
@@ -174,17 +266,7 @@ where
 
         log::debug!("Topic has {} partitions", partitions.len());
 
-        let mut assignment = TopicPartitionList::with_capacity(partitions.len());
-        for part in partitions {
-            log::debug!("Adding partition: {}", part.id());
-            assignment.add_partition(&topic, part.id());
-        }
-
-        consumer.assign(&assignment)?;
-
-        log::debug!("Subscribed");
-
-        Ok(Self::wrap(topic, consumer))
+        Ok(partitions.iter().map(|p| p.id()).collect())
     }
 
     fn new_with_group(cfg: &EventStreamConfig, group_id: String) -> Result<Self, EventStreamError> {
@@ -207,19 +289,74 @@ where
     fn wrap(topic: String, consumer: StreamConsumer) -> Self {
         Self {
             _marker: PhantomData,
-            upstream: OwningHandle::new_with_fn(Box::new(consumer), |c| {
+            upstream: Upstream::Kafka(OwningHandle::new_with_fn(Box::new(consumer), |c| {
                 Box::new(unsafe { &*c }.stream())
-            }),
+            })),
             topic,
         }
     }
 
     pub fn ack<T>(&self, handle: Handle<'_, T>) -> KafkaResult<()> {
-        self.do_ack(&handle.msg)
+        self.do_ack(&handle.position)
     }
 
-    fn do_ack(&self, msg: &BorrowedMessage) -> KafkaResult<()> {
-        self.upstream.as_owner().store_offset_from_message(msg)
+    fn do_ack(&self, position: &Position) -> KafkaResult<()> {
+        match (&self.upstream, position) {
+            (Upstream::Kafka(upstream), Position::Kafka(msg)) => {
+                upstream.as_owner().store_offset_from_message(msg)
+            }
+            (Upstream::Bus(subscription), Position::Bus(topic, offset)) => {
+                if let Err(err) = subscription.ack(topic, *offset) {
+                    log::warn!("Failed to acknowledge event: {err}");
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll_upstream(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(Event, Position<'s>), EventStreamError>>> {
+        match &mut self.upstream {
+            Upstream::Kafka(upstream) => match upstream.poll_next_unpin(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
+                Poll::Ready(Some(Ok(msg))) => {
+                    log::debug!(
+                        "Message - partition: {}, offset: {}",
+                        msg.partition(),
+                        msg.offset()
+                    );
+
+                    let event = msg.to_event()?;
+                    let event = fixup_data_type(event);
+
+                    Poll::Ready(Some(Ok((event, Position::Kafka(msg)))))
+                }
+            },
+            Upstream::Bus(subscription) => match subscription.poll_recv(cx) {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(None) => Poll::Ready(None),
+                Poll::Ready(Some(record)) => {
+                    log::debug!(
+                        "Message - topic: {}, offset: {}",
+                        record.topic,
+                        record.offset
+                    );
+
+                    let event = fixup_data_type(record.event);
+
+                    Poll::Ready(Some(Ok((
+                        event,
+                        Position::Bus(record.topic, record.offset),
+                    ))))
+                }
+            },
+        }
     }
 }
 
@@ -303,14 +440,25 @@ where
 #[derive(Debug)]
 pub struct Handle<'s, T> {
     event: T,
-    msg: BorrowedMessage<'s>,
+    position: Position<'s>,
 }
 
 impl<'s, T> Handle<'s, T> {
+    /// The Kafka offset of the event, `None` for events of the event bus.
+    pub fn offset(&self) -> Option<Offset> {
+        match &self.position {
+            Position::Kafka(msg) => Some(Offset {
+                partition: msg.partition(),
+                offset: msg.offset(),
+            }),
+            Position::Bus(..) => None,
+        }
+    }
+
     pub fn replace<U>(self, event: U) -> Handle<'s, U> {
         Handle {
             event,
-            msg: self.msg,
+            position: self.position,
         }
     }
 
@@ -320,7 +468,7 @@ impl<'s, T> Handle<'s, T> {
     {
         Handle {
             event: f(self.event),
-            msg: self.msg,
+            position: self.position,
         }
     }
 
@@ -330,7 +478,7 @@ impl<'s, T> Handle<'s, T> {
     {
         Ok(Handle {
             event: f(self.event)?,
-            msg: self.msg,
+            position: self.position,
         })
     }
 }
@@ -353,26 +501,17 @@ impl Stream for EventStream<'_, AutoAck> {
     type Item = Result<Event, EventStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let next = self.upstream.poll_next_unpin(cx);
-
-        match next {
+        match self.poll_upstream(cx) {
             Poll::Pending => Poll::Pending,
-            Poll::Ready(next) => match next {
-                None => Poll::Ready(None),
-                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-                Some(Ok(msg)) => {
-                    log::debug!(
-                        "Message - partition: {}, offset: {}",
-                        msg.partition(),
-                        msg.offset()
-                    );
-
-                    let event = msg.to_event()?;
-                    let event = fixup_data_type(event);
-
-                    Poll::Ready(Some(Ok(event)))
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(Some(Ok((event, position)))) => {
+                // Kafka stores the offset automatically, the event bus needs to be told
+                if let Position::Bus(..) = position {
+                    self.do_ack(&position)?;
                 }
-            },
+                Poll::Ready(Some(Ok(event)))
+            }
         }
     }
 }
@@ -381,26 +520,13 @@ impl<'s> Stream for EventStream<'s, CustomAck> {
     type Item = Result<Handle<'s, Event>, EventStreamError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let next = self.upstream.poll_next_unpin(cx);
-
-        match next {
+        match self.poll_upstream(cx) {
             Poll::Pending => Poll::Pending,
-            Poll::Ready(next) => match next {
-                None => Poll::Ready(None),
-                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
-                Some(Ok(msg)) => {
-                    log::debug!(
-                        "Message - partition: {}, offset: {}",
-                        msg.partition(),
-                        msg.offset()
-                    );
-                    let event = msg.to_event()?;
-                    let event = fixup_data_type(event);
-
-                    let event = Handle { event, msg };
-                    Poll::Ready(Some(Ok(event)))
-                }
-            },
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(Some(Ok((event, position)))) => {
+                Poll::Ready(Some(Ok(Handle { event, position })))
+            }
         }
     }
 }
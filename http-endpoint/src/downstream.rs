@@ -4,7 +4,10 @@ use drogue_client::error::ErrorInformation;
 use drogue_cloud_endpoint_common::{
     command::{CommandFilter, Commands},
     error::HttpEndpointError,
-    sender::{DownstreamSender, Publish, PublishOutcome, Publisher, DOWNSTREAM_EVENTS_COUNTER},
+    sender::{
+        BatchItem, BatchItemOutcome, DownstreamSender, Publish, PublishOutcome, Publisher,
+        DOWNSTREAM_EVENTS_COUNTER,
+    },
 };
 use drogue_cloud_service_api::webapp::{web, HttpResponse};
 
@@ -65,6 +68,14 @@ impl HttpCommandSender for DownstreamSender {
                 Ok(HttpResponse::build(http::StatusCode::SERVICE_UNAVAILABLE).finish())
             }
 
+            // ok, but quota exceeded
+            Ok(PublishOutcome::RateLimited) => {
+                DOWNSTREAM_EVENTS_COUNTER
+                    .with_label_values(&["http", "RateLimited"])
+                    .inc();
+                Ok(HttpResponse::build(http::StatusCode::TOO_MANY_REQUESTS).finish())
+            }
+
             // internal error
             Err(err) => {
                 DOWNSTREAM_EVENTS_COUNTER
@@ -78,3 +89,38 @@ impl HttpCommandSender for DownstreamSender {
         }
     }
 }
+
+/// Publish a batch of events, responding with the outcome of each item.
+///
+/// If all items were accepted, this responds with `202 Accepted`, otherwise with
+/// `207 Multi-Status`. Batches don't wait for commands.
+pub async fn publish_batch(
+    sender: &DownstreamSender,
+    publish: Publish<'_>,
+    items: Vec<BatchItem>,
+) -> HttpResponse {
+    let outcomes = sender
+        .publish_batch(publish, items)
+        .await
+        .iter()
+        .map(BatchItemOutcome::from)
+        .collect::<Vec<_>>();
+
+    for outcome in &outcomes {
+        let label = match outcome.outcome {
+            Some(PublishOutcome::Accepted) => "Accepted",
+            Some(PublishOutcome::Rejected) => "Rejected",
+            Some(PublishOutcome::QueueFull) => "QueueFull",
+            Some(PublishOutcome::RateLimited) => "RateLimited",
+            None => "Error",
+        };
+        DOWNSTREAM_EVENTS_COUNTER
+            .with_label_values(&["http", label])
+            .inc();
+    }
+
+    match outcomes.iter().all(BatchItemOutcome::is_accepted) {
+        true => HttpResponse::Accepted().json(outcomes),
+        false => HttpResponse::build(http::StatusCode::MULTI_STATUS).json(outcomes),
+    }
+}
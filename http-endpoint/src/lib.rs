@@ -9,8 +9,8 @@ use drogue_cloud_endpoint_common::{
     auth::{AuthConfig, DeviceAuthenticator},
     command::{Commands, KafkaCommandSource, KafkaCommandSourceConfig},
     psk::{set_ssl_identity, Identity, VerifiedIdentity},
-    sender::{DownstreamSender, ExternalClientPoolConfig},
-    sink::KafkaSink,
+    sender::{BatchConfig, DownstreamSender, ExternalClientPoolConfig, QuotaConfig},
+    sink::MessagingSink,
 };
 use drogue_cloud_service_api::auth::device::authn::PreSharedKeyOutcome;
 use drogue_cloud_service_api::{
@@ -20,7 +20,9 @@ use drogue_cloud_service_api::{
 use drogue_cloud_service_common::{
     actix::http::{HttpBuilder, HttpConfig},
     app::{Startup, StartupExt},
+    client::ClientConfig,
     defaults,
+    state::{PresenceConfiguration, PresenceReporter},
     tls::TlsAuthConfig,
 };
 use serde::Deserialize;
@@ -42,6 +44,22 @@ pub struct Config {
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
 
+    /// Registry access, enables processing of the device twin
+    #[serde(default)]
+    pub registry: Option<ClientConfig>,
+
+    /// Report the presence of devices to the device state service
+    #[serde(default)]
+    pub presence: Option<PresenceConfiguration>,
+
+    /// Quotas for ingesting events
+    #[serde(default)]
+    pub quota: QuotaConfig,
+
+    /// Limits for batches of events
+    #[serde(default)]
+    pub batch: BatchConfig,
+
     #[serde(default)]
     pub http: HttpConfig,
 }
@@ -53,17 +71,25 @@ async fn index() -> impl Responder {
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
     log::info!("Starting HTTP service endpoint");
 
-    let sender = DownstreamSender::new(
-        KafkaSink::from_config(
+    let mut sender = DownstreamSender::new(
+        MessagingSink::from_config(
             config.kafka_downstream_config,
             config.check_kafka_topic_ready,
         )?,
         config.instance,
         config.endpoint_pool,
     )?;
+    if let Some(registry) = config.registry {
+        sender = sender.with_twin(registry.into_client().await?);
+    }
+    if let Some(presence) = config.presence {
+        sender = sender.with_presence(PresenceReporter::new(presence).await?);
+    }
+    sender = sender.with_quota(config.quota).with_batch(config.batch);
     let commands = Commands::new();
 
     let http_server_commands = commands.clone();
+    let downstream = sender.clone();
 
     let device_authenticator = DeviceAuthenticator::new(config.auth).await?;
 
@@ -159,7 +185,11 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // spawn
 
-    startup.spawn(main);
+    startup.spawn(async move {
+        let result = main.await;
+        downstream.close().await;
+        result
+    });
     startup.check(command_source);
 
     // done
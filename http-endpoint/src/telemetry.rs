@@ -1,10 +1,10 @@
-use crate::downstream::HttpCommandSender;
+use crate::downstream::{publish_batch, HttpCommandSender};
 use drogue_cloud_endpoint_common::{
     auth::DeviceAuthenticator,
     command::Commands,
     error::{EndpointError, HttpEndpointError},
     psk::VerifiedIdentity,
-    sender::{self, DownstreamSender, PublishIdPair},
+    sender::{self, DownstreamSender, PublishIdPair, CONTENT_TYPE_CLOUDEVENTS_BATCH},
     x509::ClientCertificateChain,
 };
 use drogue_cloud_service_api::{
@@ -31,6 +31,10 @@ pub struct PublishOptions {
 
     #[serde(alias = "commandTimeout")]
     pub ct: Option<u64>,
+
+    /// The body is a batch of events.
+    #[serde(default)]
+    pub batch: bool,
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -129,6 +133,18 @@ pub async fn publish(
 
     // publish
 
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let batch = opts.batch
+        || content_type
+            .as_deref()
+            .map(|t| t.starts_with(CONTENT_TYPE_CLOUDEVENTS_BATCH))
+            .unwrap_or_default();
+
     let publish = sender::Publish {
         channel,
         application: &application,
@@ -137,15 +153,18 @@ pub async fn publish(
         options: sender::PublishOptions {
             data_schema: opts.common.data_schema,
             topic: suffix,
-            content_type: req
-                .headers()
-                .get(header::CONTENT_TYPE)
-                .and_then(|v| v.to_str().ok())
-                .map(|s| s.to_string()),
+            content_type,
             ..Default::default()
         },
     };
 
+    if batch {
+        let items = downstream
+            .parse_batch(&body)
+            .map_err(|err| HttpEndpointError(err.into()))?;
+        return Ok(publish_batch(&downstream, publish, items).await);
+    }
+
     downstream
         .publish_and_await(publish, commands, opts.ct, body)
         .await
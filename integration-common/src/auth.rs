@@ -0,0 +1,94 @@
+use drogue_client::user;
+use drogue_cloud_service_api::auth::user::UserInformation;
+use drogue_cloud_service_common::auth::openid::{Authenticator, AuthenticatorError};
+
+/// Authenticate a client from the credentials it provided when connecting.
+///
+/// A username and password are treated as user ID and access token, if user authentication is
+/// available. A username or password alone is treated as a token.
+pub async fn authenticate(
+    username: Option<&str>,
+    password: Option<&[u8]>,
+    auth: &Authenticator,
+    user_auth: Option<&user::v1::Client>,
+) -> Result<UserInformation, anyhow::Error> {
+    let user = match ((username, password), user_auth) {
+        ((Some(username), Some(password)), Some(user_auth)) => {
+            log::debug!("Authenticate with username and password");
+            // we have a username and password, and are allowed to test this against SSO
+            let username = username.to_string();
+            let password = String::from_utf8(password.to_vec())?;
+
+            match user_auth
+                .authenticate_access_token(user::v1::authn::AuthenticationRequest {
+                    user_id: username,
+                    access_token: password,
+                })
+                .await?
+                .outcome
+            {
+                user::v1::authn::Outcome::Known(details) => UserInformation::Authenticated(details),
+                user::v1::authn::Outcome::Unknown => {
+                    log::debug!("Unknown API key");
+                    return Err(AuthenticatorError::Failed.into());
+                }
+            }
+        }
+        ((Some(username), None), _) => {
+            log::debug!("Authenticate with token (username only)");
+            // username but no username is treated as a token
+            let token = auth.validate_token(&username).await?.into();
+            UserInformation::Authenticated(token)
+        }
+        ((None, Some(password)), _) => {
+            log::debug!("Authenticate with token (password only)");
+            // password but no username is treated as a token
+            let password = String::from_utf8(password.to_vec())?;
+            let token = auth.validate_token(&password).await?.into();
+            UserInformation::Authenticated(token)
+        }
+        ((None, None), _) => {
+            // anonymous authentication, but using user auth
+            log::debug!("Anonymous auth");
+            UserInformation::Anonymous
+        }
+        _ => {
+            log::debug!("Unknown authentication method");
+            anyhow::bail!("Unknown authentication scheme");
+        }
+    };
+
+    Ok(user)
+}
+
+/// Authorize a user for an application.
+pub async fn authorize(
+    user: &UserInformation,
+    application: String,
+    user_auth: &user::v1::Client,
+    permission: user::v1::authz::Permission,
+) -> Result<(), ()> {
+    log::debug!(
+        "Authorizing - user: {:?}, app: {}, permission: {:?}",
+        user,
+        application,
+        permission
+    );
+
+    let response = user_auth
+        .authorize(user::v1::authz::AuthorizationRequest {
+            application,
+            permission,
+            user_id: user.user_id().map(ToString::to_string),
+            roles: user.roles().clone(),
+        })
+        .await
+        .map_err(|_| ())?;
+
+    log::debug!("Outcome: {:?}", response);
+
+    match response.outcome {
+        user::v1::authz::Outcome::Allow => Ok(()),
+        user::v1::authz::Outcome::Deny => Err(()),
+    }
+}
@@ -1,5 +1,8 @@
+mod response;
 mod sender;
 
+pub use response::*;
+
 use drogue_client::{registry, Translator};
 use drogue_cloud_endpoint_common::{
     error::HttpEndpointError,
@@ -8,8 +11,9 @@ use drogue_cloud_endpoint_common::{
         UpstreamSender,
     },
 };
-use drogue_cloud_service_api::webapp::HttpResponse;
+use drogue_cloud_service_api::{webapp::HttpResponse, EXT_CORRELATION_ID, EXT_RESPONSE_CHANNEL};
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Deserialize)]
 pub struct CommandOptions {
@@ -18,6 +22,26 @@ pub struct CommandOptions {
 
     pub command: String,
     pub content_type: Option<String>,
+
+    /// Correlation id, in case the requester expects a response.
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    /// The channel the device should publish the response to.
+    #[serde(default)]
+    pub response_channel: Option<String>,
+}
+
+impl CommandOptions {
+    fn extensions(&self) -> HashMap<String, String> {
+        let mut extensions = HashMap::new();
+        if let Some(correlation_id) = &self.correlation_id {
+            extensions.insert(EXT_CORRELATION_ID.to_string(), correlation_id.clone());
+            if let Some(response_channel) = &self.response_channel {
+                extensions.insert(EXT_RESPONSE_CHANNEL.to_string(), response_channel.clone());
+            }
+        }
+        extensions
+    }
 }
 
 /// Main entrypoint for processing commands
@@ -80,6 +104,7 @@ pub async fn process_command(
                     sender: target.into_id(),
                     options: PublishOptions {
                         content_type: opts.content_type.clone(),
+                        extensions: opts.extensions(),
                         ..Default::default()
                     },
                 },
@@ -96,6 +121,9 @@ pub async fn process_command(
             Ok(PublishOutcome::QueueFull) => {
                 return Ok(HttpResponse::ServiceUnavailable().finish());
             }
+            Ok(PublishOutcome::RateLimited) => {
+                return Ok(HttpResponse::TooManyRequests().finish());
+            }
             Err(err) => {
                 return Ok(HttpResponse::InternalServerError()
                     .content_type("text/plain")
@@ -0,0 +1,355 @@
+//! Correlating device responses with commands.
+
+use cloudevents::Event;
+use drogue_client::registry;
+use drogue_cloud_event_common::{
+    ext::extension,
+    stream::{EventStream, EventStreamConfig, EventStreamError},
+};
+use drogue_cloud_service_api::{
+    kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType},
+    EXT_CORRELATION_ID, EXT_DEVICE,
+};
+use futures::StreamExt;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::oneshot, task::JoinHandle};
+
+/// The time a stream is kept open, after the last request of its application.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Get the correlation id of an event, if it has one.
+pub fn correlation_id(event: &Event) -> Option<&str> {
+    extension(event, EXT_CORRELATION_ID)
+}
+
+/// A request, waiting for the response of a device.
+struct Pending {
+    device: String,
+    expires: Instant,
+    tx: oneshot::Sender<Event>,
+}
+
+/// A stream receiving the responses of an application.
+struct Listener {
+    id: u64,
+    handle: JoinHandle<()>,
+    last_used: Instant,
+}
+
+/// Routes responses of devices back to the requests waiting for them.
+///
+/// Responses are received from a single stream per application, which is created on first use.
+/// It is dropped when it fails, or when there were no pending requests of the application for
+/// some time. Responses are correlated by their correlation id, and are
+/// only accepted from the device the command was sent to.
+///
+/// The streams run on the current thread, so this must be used from within a local task set, as
+/// it is provided by the actix and ntex runtimes.
+#[derive(Clone)]
+pub struct ResponseRouter {
+    kafka: KafkaClientConfig,
+    /// Pending requests, by application and correlation id.
+    pending: Arc<Mutex<HashMap<(String, String), Pending>>>,
+    /// Streams receiving the responses, by application.
+    streams: Arc<Mutex<HashMap<String, Listener>>>,
+    next_id: Arc<AtomicU64>,
+    idle_timeout: Duration,
+}
+
+impl ResponseRouter {
+    pub fn new(kafka: KafkaClientConfig) -> Self {
+        Self {
+            kafka,
+            pending: Default::default(),
+            streams: Default::default(),
+            next_id: Default::default(),
+            idle_timeout: IDLE_TIMEOUT,
+        }
+    }
+
+    /// Expect a response of a device, for a command with the correlation id.
+    ///
+    /// This must be called before sending the command. The receiver resolves once the response
+    /// was received, and fails if there was no response before the timeout.
+    pub fn expect(
+        &self,
+        application: &registry::v1::Application,
+        device: &str,
+        correlation_id: String,
+        timeout: Duration,
+    ) -> Result<oneshot::Receiver<Event>, EventStreamError> {
+        self.ensure_stream(application)?;
+        Ok(self.register(
+            application.metadata.name.clone(),
+            device,
+            correlation_id,
+            timeout,
+        ))
+    }
+
+    fn register(
+        &self,
+        application: String,
+        device: &str,
+        correlation_id: String,
+        timeout: Duration,
+    ) -> oneshot::Receiver<Event> {
+        let (tx, rx) = oneshot::channel();
+
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, pending| pending.expires > now && !pending.tx.is_closed());
+        pending.insert(
+            (application, correlation_id),
+            Pending {
+                device: device.to_string(),
+                expires: now + timeout,
+                tx,
+            },
+        );
+
+        rx
+    }
+
+    fn ensure_stream(
+        &self,
+        application: &registry::v1::Application,
+    ) -> Result<(), EventStreamError> {
+        let mut streams = self.streams.lock().unwrap();
+        if let Some(listener) = streams.get_mut(&application.metadata.name) {
+            listener.last_used = Instant::now();
+            return Ok(());
+        }
+
+        let target = match application.kafka_target(KafkaEventType::Events, &self.kafka) {
+            Ok(target) => target,
+            Err(err) => match err {},
+        };
+        let stream = EventStream::new(EventStreamConfig {
+            kafka: target.into(),
+            consumer_group: None,
+        })?;
+
+        log::debug!(
+            "Listening for responses of application: {}",
+            application.metadata.name
+        );
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let handle = tokio::task::spawn_local(self.clone().run(
+            id,
+            application.metadata.name.clone(),
+            stream,
+        ));
+        streams.insert(
+            application.metadata.name.clone(),
+            Listener {
+                id,
+                handle,
+                last_used: Instant::now(),
+            },
+        );
+
+        Ok(())
+    }
+
+    async fn run(self, id: u64, application: String, mut stream: EventStream<'static>) {
+        let mut check = tokio::time::interval(self.idle_timeout);
+        loop {
+            tokio::select! {
+                event = stream.next() => match event {
+                    Some(Ok(event)) => self.deliver(&application, event),
+                    Some(Err(err)) => {
+                        log::info!("Failed to receive responses of {application}: {err}");
+                        break;
+                    }
+                    None => break,
+                },
+                _ = check.tick() => {
+                    if self.remove_idle(id, &application) {
+                        log::debug!("Stop listening for responses of application: {application}");
+                        return;
+                    }
+                }
+            }
+        }
+
+        // the next request will create a new stream
+        let mut streams = self.streams.lock().unwrap();
+        if streams.get(&application).map(|listener| listener.id) == Some(id) {
+            streams.remove(&application);
+        }
+    }
+
+    /// Remove the stream, if there were no pending requests of the application for a while.
+    fn remove_idle(&self, id: u64, application: &str) -> bool {
+        // requests mark the stream as used while holding the same lock
+        let mut streams = self.streams.lock().unwrap();
+        let listener = match streams.get(application) {
+            Some(listener) if listener.id == id => listener,
+            _ => return true,
+        };
+
+        let now = Instant::now();
+        if now.duration_since(listener.last_used) < self.idle_timeout {
+            return false;
+        }
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|((app, _), pending)| app == application && pending.expires > now);
+        if pending {
+            return false;
+        }
+
+        streams.remove(application);
+        true
+    }
+
+    fn deliver(&self, application: &str, event: Event) {
+        let key = match correlation_id(&event) {
+            Some(correlation_id) => (application.to_string(), correlation_id.to_string()),
+            None => return,
+        };
+
+        let mut pending = self.pending.lock().unwrap();
+        match pending.get(&key) {
+            Some(request) if extension(&event, EXT_DEVICE) == Some(request.device.as_str()) => {}
+            Some(_) => {
+                log::debug!(
+                    "Ignoring response from a device other than the target of the command: {}",
+                    key.1
+                );
+                return;
+            }
+            None => return,
+        }
+
+        if let Some(request) = pending.remove(&key) {
+            if request.expires > Instant::now() {
+                // the requester might have given up already
+                let _ = request.tx.send(event);
+            }
+        }
+    }
+
+    /// Stop receiving responses.
+    pub fn close(&self) {
+        for (_, listener) in self.streams.lock().unwrap().drain() {
+            listener.handle.abort();
+        }
+        self.pending.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+
+    fn event(correlation_id: Option<&str>, device: &str) -> Event {
+        let mut event = EventBuilderV10::new()
+            .id("id1")
+            .ty("type")
+            .source("source")
+            .build()
+            .unwrap();
+        event.set_extension(EXT_DEVICE, device);
+        if let Some(correlation_id) = correlation_id {
+            event.set_extension(EXT_CORRELATION_ID, correlation_id);
+        }
+        event
+    }
+
+    fn router() -> ResponseRouter {
+        ResponseRouter::new(KafkaClientConfig {
+            bootstrap_servers: "localhost:9092".into(),
+            properties: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_deliver() {
+        let router = router();
+        let rx = router.register("app".into(), "dev1", "id".into(), Duration::from_secs(60));
+
+        router.deliver("app", event(None, "dev1"));
+        router.deliver("app", event(Some("other"), "dev1"));
+        router.deliver("other", event(Some("id"), "dev1"));
+        router.deliver("app", event(Some("id"), "dev2"));
+        router.deliver("app", event(Some("id"), "dev1"));
+
+        assert_eq!(rx.await.unwrap(), event(Some("id"), "dev1"));
+        assert!(router.pending.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_reused_and_dropped() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let mut router = router();
+                router.idle_timeout = Duration::from_millis(100);
+
+                let mut app = registry::v1::Application::default();
+                app.metadata.name = "app".into();
+
+                let listener = |router: &ResponseRouter| {
+                    router
+                        .streams
+                        .lock()
+                        .unwrap()
+                        .get("app")
+                        .map(|listener| listener.id)
+                };
+
+                let _rx1 = router
+                    .expect(&app, "dev1", "id1".into(), Duration::from_millis(50))
+                    .unwrap();
+                let id = listener(&router);
+                assert!(id.is_some());
+
+                // the stream is reused for further requests
+
+                let _rx2 = router
+                    .expect(&app, "dev1", "id2".into(), Duration::from_millis(50))
+                    .unwrap();
+                assert_eq!(listener(&router), id);
+
+                // and dropped, once the application was idle for a while
+
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                assert_eq!(listener(&router), None);
+
+                // the next request creates a new stream
+
+                let _rx3 = router
+                    .expect(&app, "dev1", "id3".into(), Duration::from_millis(50))
+                    .unwrap();
+                assert!(listener(&router).is_some());
+                assert_ne!(listener(&router), id);
+
+                router.close();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_expired() {
+        let router = router();
+        let rx = router.register("app".into(), "dev1", "id".into(), Duration::ZERO);
+
+        router.deliver("app", event(Some("id"), "dev1"));
+
+        assert!(rx.await.is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Tracking events which are processed, but not yet acknowledged.
+
+use std::{collections::VecDeque, fmt::Debug};
+
+/// Events being processed, but not yet acknowledged.
+///
+/// Kafka only tracks the offset of a partition, so acknowledging an event implicitly acknowledges all events before it.
+/// Events are therefore released in the order they were received, once they and all their predecessors got
+/// acknowledged.
+///
+/// The number of events is limited, so that consumers can stop receiving further events while it is full.
+#[derive(Debug)]
+pub struct InFlight<K, T> {
+    max: usize,
+    entries: VecDeque<Entry<K, T>>,
+}
+
+#[derive(Debug)]
+struct Entry<K, T> {
+    id: K,
+    acked: bool,
+    value: T,
+}
+
+impl<K, T> InFlight<K, T>
+where
+    K: PartialEq + Debug,
+{
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Check if no more events may be received before receiving acknowledgements.
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.max
+    }
+
+    /// Track a received event.
+    pub fn push(&mut self, id: K, value: T) {
+        self.entries.push_back(Entry {
+            id,
+            acked: false,
+            value,
+        });
+    }
+
+    /// Acknowledge an event, returning all events which can be released.
+    pub fn ack(&mut self, id: &K) -> Vec<T> {
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| !entry.acked && entry.id == *id)
+        {
+            Some(entry) => entry.acked = true,
+            None => log::debug!("Ignoring acknowledgement of unknown event: {id:?}"),
+        }
+
+        let mut result = Vec::new();
+        while matches!(self.entries.front(), Some(entry) if entry.acked) {
+            if let Some(entry) = self.entries.pop_front() {
+                result.push(entry.value);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_in_order() {
+        let mut in_flight = InFlight::new(2);
+        in_flight.push("a".to_string(), 1);
+        assert!(!in_flight.is_full());
+        in_flight.push("b".to_string(), 2);
+        assert!(in_flight.is_full());
+
+        assert_eq!(in_flight.ack(&"a".into()), vec![1]);
+        assert!(!in_flight.is_full());
+        assert_eq!(in_flight.ack(&"b".into()), vec![2]);
+    }
+
+    #[test]
+    fn test_out_of_order() {
+        let mut in_flight = InFlight::new(10);
+        in_flight.push("a".to_string(), 1);
+        in_flight.push("b".to_string(), 2);
+        in_flight.push("c".to_string(), 3);
+
+        assert_eq!(in_flight.ack(&"c".into()), Vec::<i32>::new());
+        assert_eq!(in_flight.ack(&"b".into()), Vec::<i32>::new());
+        assert_eq!(in_flight.ack(&"a".into()), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let mut in_flight = InFlight::new(10);
+        in_flight.push(1u64, 1);
+
+        assert_eq!(in_flight.ack(&2), Vec::<i32>::new());
+        assert_eq!(in_flight.ack(&1), vec![1]);
+        assert_eq!(in_flight.ack(&1), Vec::<i32>::new());
+    }
+}
@@ -1,2 +1,5 @@
+pub mod auth;
 pub mod commands;
+pub mod inflight;
+pub mod retry;
 pub mod stream;
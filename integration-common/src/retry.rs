@@ -0,0 +1,29 @@
+//! Retrying failed operations.
+
+use std::time::Duration;
+
+/// The delay after a failed attempt, starting with `1`.
+///
+/// The delay starts with `initial`, and is doubled with each further attempt, up to `max`.
+pub fn backoff(initial: Duration, max: Duration, attempt: usize) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(31) as u32);
+    initial.saturating_mul(factor).min(max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let initial = Duration::from_secs(1);
+        let max = Duration::from_secs(10);
+
+        assert_eq!(backoff(initial, max, 1), Duration::from_secs(1));
+        assert_eq!(backoff(initial, max, 2), Duration::from_secs(2));
+        assert_eq!(backoff(initial, max, 3), Duration::from_secs(4));
+        assert_eq!(backoff(initial, max, 4), Duration::from_secs(8));
+        assert_eq!(backoff(initial, max, 5), Duration::from_secs(10));
+        assert_eq!(backoff(initial, max, 100), Duration::from_secs(10));
+    }
+}
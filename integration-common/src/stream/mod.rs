@@ -4,7 +4,7 @@ mod actix;
 #[cfg(feature = "with_actix")]
 pub use self::actix::*;
 
-use drogue_cloud_event_common::stream::{self, AckMode, AutoAck, EventStreamError};
+use drogue_cloud_event_common::stream::{self, AckMode, AutoAck, EventStreamError, Offset};
 use drogue_cloud_service_api::kafka::KafkaConfig;
 use std::ops::{Deref, DerefMut};
 
@@ -34,6 +34,19 @@ where
 
         Ok(Self { stream })
     }
+
+    /// Create a new stream, continuing after the provided offsets.
+    pub fn resume(cfg: EventStreamConfig, offsets: &[Offset]) -> Result<Self, EventStreamError> {
+        let stream = stream::EventStream::resume(
+            stream::EventStreamConfig {
+                kafka: cfg.kafka,
+                consumer_group: cfg.consumer_group,
+            },
+            offsets,
+        )?;
+
+        Ok(Self { stream })
+    }
 }
 
 impl<'s, Ack> From<EventStream<'s, Ack>> for stream::EventStream<'s, Ack>
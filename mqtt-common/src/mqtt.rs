@@ -35,6 +35,8 @@ where
 
 #[async_trait(?Send)]
 pub trait Session {
+    /// Called once the connection was acknowledged to the client.
+    async fn connected(&self) {}
     async fn publish(&self, publish: Publish<'_>) -> Result<(), PublishError>;
     async fn subscribe(&self, subscribe: Subscribe<'_>) -> Result<(), ServerError>;
     async fn unsubscribe(&self, unsubscribe: Unsubscribe<'_>) -> Result<(), ServerError>;
@@ -99,6 +101,27 @@ where
     }
 }
 
+/// Notify the session that the connection was acknowledged.
+///
+/// The services of a connection are only created once the CONNACK was sent, so this gets called
+/// when creating them.
+pub fn connected_v3<S>(session: v3::Session<S>)
+where
+    S: Session + 'static,
+{
+    ntex::rt::spawn(async move { session.connected().await });
+}
+
+/// Notify the session that the connection was acknowledged.
+///
+/// See [`connected_v3`].
+pub fn connected_v5<S>(session: v5::Session<S>)
+where
+    S: Session + 'static,
+{
+    ntex::rt::spawn(async move { session.connected().await });
+}
+
 pub async fn publish_v3<S>(session: v3::Session<S>, publish: v3::Publish) -> Result<(), ServerError>
 where
     S: Session,
@@ -260,6 +283,16 @@ impl<'a> Connect<'a> {
         }
     }
 
+    /// The session expiry interval requested by the client, in seconds.
+    ///
+    /// Returns `None` for MQTT v3.1.1, which has no session expiry.
+    pub fn session_expiry(&self) -> Option<u32> {
+        match self {
+            Self::V3(_) => None,
+            Self::V5(connect) => Some(connect.packet().session_expiry_interval_secs),
+        }
+    }
+
     /// Return the MQTT sink.
     pub fn sink(&self) -> Sink {
         match self {
@@ -398,7 +431,6 @@ impl<'a> Subscription<'a> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn qos(&self) -> QoS {
         match self {
             Self::V3(sub) => sub.qos(),
@@ -406,6 +438,16 @@ impl<'a> Subscription<'a> {
         }
     }
 
+    /// The handling of retained messages, requested by the client.
+    ///
+    /// MQTT v3.1.1 always sends retained messages on subscribe.
+    pub fn retain_handling(&self) -> v5::codec::RetainHandling {
+        match self {
+            Self::V3(_) => v5::codec::RetainHandling::AtSubscribe,
+            Self::V5(sub) => sub.options().retain_handling,
+        }
+    }
+
     pub fn fail(&mut self, reason: v5::codec::SubscribeAckReason) {
         match self {
             Self::V3(sub) => sub.fail(),
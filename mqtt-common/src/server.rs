@@ -100,6 +100,7 @@ where
             ok::<_, ServerError>(fn_service(move |req| control_v3(session.clone(), req)))
         }))
         .publish(fn_factory_with_config(|session: v3::Session<S>| {
+            connected_v3(session.clone());
             ok::<_, ServerError>(fn_service(move |req| publish_v3(session.clone(), req)))
         })))
         // MQTTv5
@@ -112,6 +113,7 @@ where
             ok::<_, ServerError>(fn_service(move |req| control_v5(session.clone(), req)))
         }))
         .publish(fn_factory_with_config(|session: v5::Session<S>| {
+            connected_v5(session.clone());
             ok::<_, ServerError>(fn_service(move |req| publish_v5(session.clone(), req)))
         })))
 }
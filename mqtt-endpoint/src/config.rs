@@ -1,11 +1,13 @@
 use drogue_cloud_endpoint_common::{
-    auth::AuthConfig, command::KafkaCommandSourceConfig, sender::ExternalClientPoolConfig,
+    auth::AuthConfig,
+    command::KafkaCommandSourceConfig,
+    sender::{ExternalClientPoolConfig, QuotaConfig},
 };
 use drogue_cloud_mqtt_common::server::{MqttServerOptions, TlsConfig};
 use drogue_cloud_service_api::kafka::KafkaClientConfig;
-use drogue_cloud_service_common::defaults;
 use drogue_cloud_service_common::state::StateControllerConfiguration;
-use serde::Deserialize;
+use drogue_cloud_service_common::{client::ClientConfig, defaults};
+use serde::{Deserialize, Deserializer};
 use std::time::Duration;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -18,6 +20,9 @@ pub struct EndpointConfig {
     #[serde(default = "default_state_attempts")]
     /// Number of attempts to claim the device state
     pub state_attempts: usize,
+    /// Mapping of MQTT v5 user properties
+    #[serde(default)]
+    pub user_properties: UserPropertiesConfig,
 }
 
 const fn default_cache_size() -> usize {
@@ -38,10 +43,58 @@ impl Default for EndpointConfig {
             cache_duration: default_cache_duration(),
             cache_size: default_cache_size(),
             state_attempts: default_state_attempts(),
+            user_properties: Default::default(),
         }
     }
 }
 
+/// Mapping of MQTT v5 user properties to CloudEvent extensions.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UserPropertiesConfig {
+    /// A comma separated list of user properties to map, or `*` to map all of them.
+    ///
+    /// If empty, no user properties are mapped.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub allow: Vec<String>,
+    /// The prefix of the extension names.
+    #[serde(default = "default_user_properties_prefix")]
+    pub prefix: String,
+}
+
+fn default_user_properties_prefix() -> String {
+    "mqtt".into()
+}
+
+impl UserPropertiesConfig {
+    /// Check if a user property should be mapped.
+    pub fn allows(&self, name: &str) -> bool {
+        self.allow
+            .iter()
+            .any(|allowed| allowed == "*" || allowed == name)
+    }
+}
+
+impl Default for UserPropertiesConfig {
+    fn default() -> Self {
+        Self {
+            allow: vec![],
+            prefix: default_user_properties_prefix(),
+        }
+    }
+}
+
+fn comma_separated<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect())
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Config {
     #[serde(default)]
@@ -79,6 +132,14 @@ pub struct Config {
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
 
+    /// Registry access, enables processing of the device twin
+    #[serde(default)]
+    pub registry: Option<ClientConfig>,
+
+    /// Quotas for ingesting events
+    #[serde(default)]
+    pub quota: QuotaConfig,
+
     pub state: StateControllerConfiguration,
 }
 
@@ -10,7 +10,7 @@ use drogue_cloud_endpoint_common::{
     command::{Commands, KafkaCommandSource},
     psk::Identity,
     sender::DownstreamSender,
-    sink::KafkaSink,
+    sink::MessagingSink,
 };
 use drogue_cloud_mqtt_common::server::build;
 use drogue_cloud_service_common::{
@@ -40,16 +40,24 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     let (states, runner) = StateController::new(config.state.clone()).await?;
 
+    // downstream sender
+
+    let mut downstream = DownstreamSender::new(
+        MessagingSink::from_config(
+            config.kafka_downstream_config.clone(),
+            config.check_kafka_topic_ready,
+        )?,
+        config.instance.clone(),
+        config.endpoint_pool.clone(),
+    )?;
+    if let Some(registry) = config.registry.clone() {
+        downstream = downstream.with_twin(registry.into_client().await?);
+    }
+    downstream = downstream.with_quota(config.quota.clone());
+
     let app = App {
         config: config.endpoint.clone(),
-        downstream: DownstreamSender::new(
-            KafkaSink::from_config(
-                config.kafka_downstream_config.clone(),
-                config.check_kafka_topic_ready,
-            )?,
-            config.instance.clone(),
-            config.endpoint_pool.clone(),
-        )?,
+        downstream: downstream.clone(),
 
         authenticator: DeviceAuthenticator(
             drogue_cloud_endpoint_common::auth::DeviceAuthenticator::new(config.auth.clone())
@@ -117,8 +125,12 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     // run
 
-    let srv = srv.err_into();
-    startup.spawn(srv);
+    let srv = async move {
+        let result = srv.await;
+        downstream.close().await;
+        result
+    };
+    startup.spawn(srv.err_into());
     startup.spawn(runner.run());
     startup.check(command_source);
 
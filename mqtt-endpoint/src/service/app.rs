@@ -1,4 +1,11 @@
-use crate::{auth::DeviceAuthenticator, config::EndpointConfig, service::session::Session};
+use crate::{
+    auth::DeviceAuthenticator,
+    config::EndpointConfig,
+    service::session::{
+        persistent::{Expiry, SessionStore},
+        Session,
+    },
+};
 use async_trait::async_trait;
 use drogue_client::{
     registry::v1::{Application, Device, MqttSpec},
@@ -20,7 +27,10 @@ use drogue_cloud_service_api::{
     auth::device::authn::{PreSharedKeyOutcome, PreSharedKeyResponse},
     services::device_state::LastWillTestament,
 };
-use drogue_cloud_service_common::state::{CreateOptions, CreationOutcome, StateController};
+use drogue_cloud_service_common::{
+    state::{CreateOptions, CreationOutcome, StateController},
+    Id,
+};
 use std::fmt::Debug;
 use tracing::instrument;
 
@@ -72,6 +82,8 @@ impl App {
             application = %application.metadata.name,
             device = %device.metadata.name,
             lwt = ?lwt,
+            resume,
+            expiry = ?expiry,
         ),
         err(Debug)
     )]
@@ -81,7 +93,9 @@ impl App {
         device: Device,
         sink: Sink,
         lwt: Option<LastWillTestament>,
-    ) -> Result<Session, ServerError> {
+        resume: bool,
+        expiry: Expiry,
+    ) -> Result<(Session, bool), ServerError> {
         // eval dialect
         let dialect = match device
             .section::<MqttSpec>()
@@ -120,9 +134,22 @@ impl App {
             }
         };
 
+        // open persistent session, after the state was acquired
+
+        let id = Id::new(
+            application.metadata.name.clone(),
+            device.metadata.name.clone(),
+        );
+        let (persistent, restored) = SessionStore::open(self.states.client(), &id, resume, expiry)
+            .await
+            .map_err(|err| {
+                ServerError::InternalError(format!("Failed to open persistent session: {err}"))
+            })?;
+        let session_present = restored.present;
+
         // return
 
-        Ok(Session::new(
+        let session = Session::new(
             &self.config,
             self.authenticator.clone(),
             self.downstream.clone(),
@@ -132,7 +159,11 @@ impl App {
             device,
             self.commands.clone(),
             *state,
-        ))
+            persistent,
+        );
+        session.restore(restored).await;
+
+        Ok((session, session_present))
     }
 
     fn make_lwt(connect: &Connect<'_>) -> Option<LastWillTestament> {
@@ -188,9 +219,8 @@ impl Service<Session> for App {
     ) -> Result<ConnectAck<Session>, ServerError> {
         log::info!("new connection: {:?}", connect);
 
-        if !connect.clean_session() {
-            return Err(ServerError::UnsupportedOperation);
-        }
+        let resume = !connect.clean_session();
+        let expiry = Expiry::from_connect(&connect);
 
         let certs = connect.io().client_certs();
         let verified_identity = if self.disable_psk {
@@ -231,21 +261,24 @@ impl Service<Session> for App {
                 device,
                 r#as: _,
             }) => {
-                let session = self
+                let (session, session_present) = self
                     .create_session(
                         application,
                         device,
                         connect.sink(),
                         Self::make_lwt(&connect),
+                        resume,
+                        expiry,
                     )
                     .await?;
 
                 Ok(ConnectAck {
                     session,
                     ack: AckOptions {
+                        session_present,
                         wildcard_subscription_available: Some(true),
-                        shared_subscription_available: Some(false),
-                        subscription_identifiers_available: Some(false),
+                        shared_subscription_available: Some(true),
+                        subscription_identifiers_available: Some(true),
                         ..Default::default()
                     },
                 })
@@ -16,6 +16,11 @@ pub trait DefaultTopicParser {
 
     /// Parse a topic from a SUB request
     fn parse_subscribe<'a>(&self, path: &'a str) -> Result<ParsedSubscribeTopic<'a>, ParseError>;
+
+    /// Encode the topic a PUB request must use for publishing to a channel.
+    ///
+    /// This is the inverse of [`DefaultTopicParser::parse_publish`].
+    fn encode_publish_topic(&self, channel: &str, device: Option<&str>) -> String;
 }
 
 #[derive(Debug)]
@@ -51,6 +56,23 @@ pub enum ParseError {
     Empty,
 }
 
+/// Split a shared subscription (`$share/<group>/<filter>`) into its group and topic filter.
+///
+/// Other topic filters are returned as they are, without a group.
+pub fn split_shared(path: &str) -> Result<(Option<&str>, &str), ParseError> {
+    match path.strip_prefix("$share/") {
+        Some(path) => match path.split_once('/') {
+            Some((group, filter))
+                if !group.is_empty() && !filter.is_empty() && !group.contains(['+', '#']) =>
+            {
+                Ok((Some(group), filter))
+            }
+            _ => Err(ParseError::Syntax),
+        },
+        None => Ok((None, path)),
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParsedPublishTopic<'a> {
     pub channel: &'a str,
@@ -195,6 +217,25 @@ impl DefaultTopicParser for MqttDialect {
             }
         }
     }
+
+    fn encode_publish_topic(&self, channel: &str, device: Option<&str>) -> String {
+        match (self, device) {
+            (Self::DrogueV1, None) => channel.to_string(),
+            (Self::DrogueV1, Some(device)) => format!("{channel}/{device}"),
+            (
+                Self::PlainTopic {
+                    device_prefix: false,
+                },
+                _,
+            ) => channel.to_string(),
+            (
+                Self::PlainTopic {
+                    device_prefix: true,
+                },
+                device,
+            ) => format!("{}/{channel}", device.unwrap_or_default()),
+        }
+    }
 }
 
 impl TopicEncoder for DefaultCommandTopicEncoder {
@@ -323,6 +364,53 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_encode_publish() {
+        for dialect in [
+            json!({"type": "drogue/v1"}),
+            json!({"type": "plainTopic"}),
+            json!({"type": "plainTopic", "devicePrefix": true}),
+        ] {
+            let spec: MqttSpec = serde_json::from_value(json!({ "dialect": dialect })).unwrap();
+            let topic = spec.dialect.encode_publish_topic("foo", None);
+            assert_parse(
+                &spec,
+                &topic,
+                Ok(ParsedPublishTopic {
+                    channel: "foo",
+                    device: None,
+                }),
+            );
+        }
+
+        let spec: MqttSpec =
+            serde_json::from_value(json!({"dialect": {"type": "drogue/v1"}})).unwrap();
+        let topic = spec.dialect.encode_publish_topic("foo", Some("device"));
+        assert_parse(
+            &spec,
+            &topic,
+            Ok(ParsedPublishTopic {
+                channel: "foo",
+                device: Some("device"),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_split_shared() {
+        assert_eq!(
+            split_shared("command/inbox/#"),
+            Ok((None, "command/inbox/#"))
+        );
+        assert_eq!(
+            split_shared("$share/ha/command/inbox/#"),
+            Ok((Some("ha"), "command/inbox/#"))
+        );
+        assert_eq!(split_shared("$share/ha"), Err(ParseError::Syntax));
+        assert_eq!(split_shared("$share//command"), Err(ParseError::Syntax));
+        assert_eq!(split_shared("$share/+/command"), Err(ParseError::Syntax));
+    }
+
     fn assert_parse(spec: &MqttSpec, path: &str, expected: Result<ParsedPublishTopic, ParseError>) {
         assert_eq!(spec.dialect.parse_publish(path), expected);
     }
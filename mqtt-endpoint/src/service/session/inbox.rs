@@ -1,15 +1,28 @@
-use crate::service::session::dialect::SubscriptionTopicEncoder;
+use crate::service::session::dialect::{DefaultTopicParser, SubscriptionTopicEncoder};
+use drogue_client::registry::v1::MqttDialect;
 use drogue_cloud_endpoint_common::command::{
     Command, CommandFilter, Commands, Subscription, SubscriptionHandle,
 };
 use drogue_cloud_mqtt_common::mqtt;
 use ntex::util::{ByteString, Bytes};
+use ntex_mqtt::types::QoS;
+use std::{num::NonZeroU32, sync::Arc};
 
 pub struct InboxSubscription {
     filter: CommandFilter,
+    sender: Arc<InboxSender>,
     handle: Option<InboxSubscriptionHandle>,
 }
 
+/// Sends commands to the device, as requested by its subscription.
+pub struct InboxSender {
+    sink: mqtt::Sink,
+    encoder: SubscriptionTopicEncoder,
+    dialect: MqttDialect,
+    qos: QoS,
+    id: Option<NonZeroU32>,
+}
+
 struct InboxSubscriptionHandle {
     handle: SubscriptionHandle,
     commands: Commands,
@@ -28,6 +41,9 @@ impl InboxSubscription {
         commands: Commands,
         sink: mqtt::Sink,
         encoder: SubscriptionTopicEncoder,
+        dialect: MqttDialect,
+        qos: QoS,
+        id: Option<NonZeroU32>,
     ) -> Self {
         // TODO: try to reduce cloning
 
@@ -36,12 +52,21 @@ impl InboxSubscription {
             handle,
         } = commands.subscribe(filter.clone()).await;
 
+        let sender = Arc::new(InboxSender {
+            sink,
+            encoder,
+            dialect,
+            qos,
+            id,
+        });
+
         let sub_filter = filter.clone();
+        let sub_sender = sender.clone();
 
         ntex::rt::spawn(async move {
             log::debug!("Starting inbox command loop: {:?}", sub_filter);
             while let Some(cmd) = receiver.recv().await {
-                match Self::send_command(&sink, cmd, &encoder).await {
+                match sub_sender.send(cmd).await {
                     Ok(_) => {
                         log::debug!("Command sent to device subscription {:?}", sub_filter);
                     }
@@ -55,36 +80,19 @@ impl InboxSubscription {
 
         Self {
             filter,
+            sender,
             handle: Some(InboxSubscriptionHandle { handle, commands }),
         }
     }
 
-    async fn send_command(
-        sink: &mqtt::Sink,
-        cmd: Command,
-        encoder: &SubscriptionTopicEncoder,
-    ) -> Result<(), String> {
-        let topic = encoder.encode_command_topic(&cmd);
-
-        log::debug!("Topic '{topic}' for command: {cmd:?} (encoder: {encoder:?})");
-
-        let topic = ByteString::from(topic);
-
-        let payload = match cmd.payload {
-            Some(payload) => Bytes::from(payload),
-            None => Bytes::new(),
-        };
+    /// Check if a command is meant for this subscription.
+    pub fn matches(&self, cmd: &Command) -> bool {
+        self.filter.matches(cmd)
+    }
 
-        match sink {
-            mqtt::Sink::V3(sink) => match sink.publish(topic, payload).send_at_most_once() {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e.to_string()),
-            },
-            mqtt::Sink::V5(sink) => match sink.publish(topic, payload).send_at_most_once() {
-                Ok(_) => Ok(()),
-                Err(e) => Err(e.to_string()),
-            },
-        }
+    /// The sender of this subscription, which can send commands directly to the device.
+    pub fn sender(&self) -> Arc<InboxSender> {
+        self.sender.clone()
     }
 
     pub async fn close(mut self) {
@@ -97,6 +105,60 @@ impl InboxSubscription {
     }
 }
 
+impl InboxSender {
+    /// Send a command, waiting for the acknowledgement of the device in case of QoS 1.
+    pub async fn send(&self, cmd: Command) -> Result<(), String> {
+        let topic = self.encoder.encode_command_topic(&cmd);
+
+        // the topic the device needs to publish its response to, if a response is requested
+        let response_topic = cmd.response_channel().map(|channel| {
+            let device = (cmd.address.gateway_id != cmd.address.device_id)
+                .then_some(cmd.address.device_id.as_str());
+            ByteString::from(self.dialect.encode_publish_topic(channel, device))
+        });
+        let correlation_data = cmd.correlation_id.clone().map(Bytes::from);
+
+        log::debug!(
+            "Topic '{topic}' for command: {cmd:?} (encoder: {:?})",
+            self.encoder
+        );
+
+        let topic = ByteString::from(topic);
+
+        let payload = match cmd.payload {
+            Some(payload) => Bytes::from(payload),
+            None => Bytes::new(),
+        };
+
+        match (&self.sink, self.qos) {
+            (mqtt::Sink::V3(sink), QoS::AtMostOnce) => sink
+                .publish(topic, payload)
+                .send_at_most_once()
+                .map_err(|e| e.to_string()),
+            (mqtt::Sink::V3(sink), _) => sink
+                .publish(topic, payload)
+                .send_at_least_once()
+                .await
+                .map_err(|e| e.to_string()),
+            (mqtt::Sink::V5(sink), qos) => {
+                let publish = sink.publish(topic, payload).properties(|p| {
+                    p.response_topic = response_topic;
+                    p.correlation_data = correlation_data;
+                    p.subscription_ids = self.id.map(|id| vec![id]);
+                });
+                match qos {
+                    QoS::AtMostOnce => publish.send_at_most_once().map_err(|e| e.to_string()),
+                    _ => publish
+                        .send_at_least_once()
+                        .await
+                        .map(|_| ())
+                        .map_err(|e| e.to_string()),
+                }
+            }
+        }
+    }
+}
+
 impl Drop for InboxSubscription {
     fn drop(&mut self) {
         // unsubscribe from commands
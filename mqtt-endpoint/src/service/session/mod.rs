@@ -2,13 +2,15 @@ mod cache;
 mod dialect;
 mod disconnect;
 mod inbox;
+pub mod persistent;
+mod properties;
 
 use self::disconnect::*;
 use crate::{
     auth::DeviceAuthenticator,
-    config::EndpointConfig,
+    config::{EndpointConfig, UserPropertiesConfig},
     service::session::dialect::{
-        DefaultTopicParser, ParsedSubscribeTopic, SubscriptionTopicEncoder,
+        split_shared, DefaultTopicParser, ParsedSubscribeTopic, SubscriptionTopicEncoder,
     },
     CONNECTIONS_COUNTER,
 };
@@ -16,10 +18,9 @@ use async_trait::async_trait;
 use cache::DeviceCache;
 use drogue_client::registry;
 use drogue_cloud_endpoint_common::{
-    command::{CommandFilter, Commands},
+    command::{Command, CommandFilter, Commands},
     sender::{
-        self, DownstreamSender, PublishOptions, PublishOutcome, Publisher, ToPublishId,
-        DOWNSTREAM_EVENTS_COUNTER,
+        self, DownstreamSender, PublishOutcome, Publisher, ToPublishId, DOWNSTREAM_EVENTS_COUNTER,
     },
 };
 use drogue_cloud_mqtt_common::{
@@ -39,9 +40,11 @@ use ntex_mqtt::{
     types::QoS,
     v5::codec::{self, DisconnectReasonCode},
 };
+use persistent::{Restored, SessionStore};
 use std::{
     cell::Cell,
     collections::{hash_map::Entry, HashMap},
+    num::NonZeroU32,
     sync::Arc,
 };
 use tracing::instrument;
@@ -59,6 +62,10 @@ pub struct Session {
     id: Id,
     handle: Cell<Option<StateHandle>>,
     disconnect: DisconnectHandle,
+    persistent: Option<Arc<SessionStore>>,
+    /// Commands queued for a restored session, delivered once the connection was acknowledged.
+    queued: Cell<Vec<(i64, Command)>>,
+    user_properties: UserPropertiesConfig,
 }
 
 impl Session {
@@ -72,6 +79,7 @@ impl Session {
         device: registry::v1::Device,
         commands: Commands,
         state: State,
+        persistent: Option<SessionStore>,
     ) -> Self {
         let id = Id::new(
             application.metadata.name.clone(),
@@ -116,6 +124,75 @@ impl Session {
             id,
             handle: Cell::new(Some(handle)),
             disconnect: DisconnectHandle::new(),
+            persistent: persistent.map(Arc::new),
+            queued: Default::default(),
+            user_properties: config.user_properties.clone(),
+        }
+    }
+
+    /// Restore the subscriptions of a previous session.
+    ///
+    /// The commands queued for the session are delivered once the connection was acknowledged.
+    pub async fn restore(&self, restored: Restored) {
+        for sub in restored.subscriptions {
+            let parsed = split_shared(&sub.topic)
+                .and_then(|(group, topic)| Ok((group, self.dialect.parse_subscribe(topic)?)));
+            match parsed {
+                Ok((group, ParsedSubscribeTopic { filter, encoder })) => {
+                    let qos = match sub.qos {
+                        0 => QoS::AtMostOnce,
+                        _ => QoS::AtLeastOnce,
+                    };
+                    self.subscribe_inbox(
+                        sub.topic.clone(),
+                        filter
+                            .into_command_filter(&self.id)
+                            .with_group(group.map(ToString::to_string)),
+                        encoder,
+                        qos,
+                        sub.id.and_then(NonZeroU32::new),
+                    )
+                    .await;
+                }
+                Err(err) => {
+                    log::info!("Unable to restore subscription {:?}: {err}", sub.topic);
+                }
+            }
+        }
+
+        self.queued.set(restored.commands);
+    }
+
+    /// Deliver the commands queued for a restored session.
+    ///
+    /// Each command is removed from the queue once the device acknowledged it. If the device
+    /// fails to acknowledge a command, it and the following commands stay queued for the next
+    /// connection.
+    async fn deliver_queued(&self) {
+        for (id, command) in self.queued.take() {
+            let sender = self
+                .inbox_reader
+                .lock()
+                .await
+                .values()
+                .find(|sub| sub.matches(&command))
+                .map(InboxSubscription::sender);
+
+            match sender {
+                Some(sender) => {
+                    if let Err(err) = sender.send(command).await {
+                        log::info!("Failed to deliver queued command of {:?}: {err}", self.id);
+                        break;
+                    }
+                }
+                None => {
+                    log::debug!("Dropping queued command without subscription: {command:?}");
+                }
+            }
+
+            if let Some(persistent) = &self.persistent {
+                persistent.ack(id).await;
+            }
         }
     }
 
@@ -125,6 +202,8 @@ impl Session {
         topic_filter: F,
         filter: CommandFilter,
         encoder: SubscriptionTopicEncoder,
+        qos: QoS,
+        id: Option<NonZeroU32>,
     ) where
         F: Into<String>,
     {
@@ -144,6 +223,9 @@ impl Session {
                     self.commands.clone(),
                     self.sink.clone(),
                     encoder,
+                    self.dialect.clone(),
+                    qos,
+                    id,
                 )
                 .await;
                 entry.insert(subscription);
@@ -186,14 +268,19 @@ impl Session {
 
 #[async_trait(? Send)]
 impl mqtt::Session for Session {
+    async fn connected(&self) {
+        self.deliver_queued().await;
+    }
+
     #[instrument(level = "debug", skip(self), fields(self.id = ?self.id), err)]
     async fn publish(&self, publish: Publish<'_>) -> Result<(), PublishError> {
         let _lock = self.disconnect.ensure().await?;
 
-        let content_type = publish
-            .properties()
-            .and_then(|p| p.content_type.as_ref())
-            .map(|s| s.to_string());
+        let options = properties::publish_options(
+            &self.user_properties,
+            publish.properties(),
+            publish.payload(),
+        )?;
 
         let (channel, device) = self.eval_device(&publish).await?;
 
@@ -213,10 +300,7 @@ impl mqtt::Session for Session {
                     application: &self.application,
                     device: device.metadata.to_id(),
                     sender: self.device.metadata.to_id(),
-                    options: PublishOptions {
-                        content_type,
-                        ..Default::default()
-                    },
+                    options,
                 },
                 publish.payload(),
             )
@@ -240,6 +324,12 @@ impl mqtt::Session for Session {
                     .inc();
                 Err(PublishError::QuotaExceeded)
             }
+            Ok(PublishOutcome::RateLimited) => {
+                DOWNSTREAM_EVENTS_COUNTER
+                    .with_label_values(&["mqtt", "RateLimited"])
+                    .inc();
+                Err(PublishError::QuotaExceeded)
+            }
             Err(err) => {
                 DOWNSTREAM_EVENTS_COUNTER
                     .with_label_values(&["mqtt", "Error"])
@@ -251,26 +341,36 @@ impl mqtt::Session for Session {
 
     #[instrument(skip(self),fields(self.id = ?self.id))]
     async fn subscribe(&self, sub: Subscribe<'_>) -> Result<(), ServerError> {
-        if sub.id().is_some() {
-            log::info!("Rejecting request with subscription IDs");
-            for mut sub in sub {
-                sub.fail(codec::SubscribeAckReason::SubscriptionIdentifiersNotSupported);
-            }
-            return Ok(());
-        }
+        let id = sub.id();
 
         for mut sub in sub {
             log::debug!("Checking subscription request: {sub:?}");
 
-            match self.dialect.parse_subscribe(sub.topic()) {
-                Ok(ParsedSubscribeTopic { filter, encoder }) => {
+            // shared subscriptions map to a group of command subscriptions
+            let parsed = split_shared(sub.topic())
+                .and_then(|(group, topic)| Ok((group, self.dialect.parse_subscribe(topic)?)));
+
+            match parsed {
+                Ok((group, ParsedSubscribeTopic { filter, encoder })) => {
+                    // we support delivering commands with QoS 0 and 1
+                    let qos = match sub.qos() {
+                        QoS::AtMostOnce => QoS::AtMostOnce,
+                        _ => QoS::AtLeastOnce,
+                    };
                     self.subscribe_inbox(
                         sub.topic().to_string(),
-                        filter.into_command_filter(&self.id),
+                        filter
+                            .into_command_filter(&self.id)
+                            .with_group(group.map(ToString::to_string)),
                         encoder,
+                        qos,
+                        id,
                     )
                     .await;
-                    sub.confirm(QoS::AtMostOnce);
+                    if let Some(persistent) = &self.persistent {
+                        persistent.subscribed(sub.topic(), qos, id).await;
+                    }
+                    sub.confirm(qos);
                 }
                 Err(err) => {
                     log::info!("Subscribing to topic {:?} not allowed: {err}", sub.topic());
@@ -290,6 +390,9 @@ impl mqtt::Session for Session {
             match subscriptions.remove(unsub.topic().as_ref()) {
                 Some(subscription) => {
                     subscription.close().await;
+                    if let Some(persistent) = &self.persistent {
+                        persistent.unsubscribed(unsub.topic()).await;
+                    }
                     unsub.success();
                 }
                 None => {
@@ -327,6 +430,11 @@ impl mqtt::Session for Session {
         // lock and check lwt flag
         let skip_lwt = self.disconnect.close().await;
 
+        // must be done before releasing the state, which allows the device to reconnect
+        if let Some(persistent) = &self.persistent {
+            persistent.close().await;
+        }
+
         if let Some(mut handle) = self.handle.take() {
             handle.delete(DeleteOptions { skip_lwt }).await;
         } else {
@@ -345,11 +453,24 @@ impl Drop for Session {
     fn drop(&mut self) {
         log::info!("Dropping session: {:?}", self.id);
 
+        let persistent = self
+            .persistent
+            .take()
+            .filter(|persistent| !persistent.is_closed());
+
         if let Some(mut handle) = self.handle.take() {
             log::warn!("Late handling session state deletion");
             ntex_rt::spawn(async move {
+                if let Some(persistent) = persistent {
+                    persistent.close().await;
+                }
                 handle.delete(DeleteOptions { skip_lwt: false }).await;
             });
+        } else if let Some(persistent) = persistent {
+            log::warn!("Late closing of persistent session");
+            ntex_rt::spawn(async move {
+                persistent.close().await;
+            });
         }
 
         CONNECTIONS_COUNTER.dec();
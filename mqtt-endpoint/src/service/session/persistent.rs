@@ -0,0 +1,182 @@
+use drogue_client::error::ClientError;
+use drogue_cloud_endpoint_common::command::Command;
+use drogue_cloud_mqtt_common::mqtt::Connect;
+use drogue_cloud_service_api::services::device_state::{PersistentSession, SessionSubscription};
+use drogue_cloud_service_common::{client::DeviceStateClient, Id};
+use futures::lock::Mutex;
+use ntex_mqtt::types::QoS;
+use std::{
+    num::NonZeroU32,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// When a session ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expiry {
+    /// The session ends with the connection.
+    Connection,
+    /// The session ends the number of seconds after the connection was closed.
+    After(u32),
+    /// The session never ends.
+    Never,
+}
+
+impl Expiry {
+    /// Evaluate the expiry requested by the client.
+    ///
+    /// For MQTT v3.1.1, a session without the "clean session" flag never expires.
+    pub fn from_connect(connect: &Connect<'_>) -> Self {
+        match connect.session_expiry() {
+            None if connect.clean_session() => Self::Connection,
+            None => Self::Never,
+            Some(0) => Self::Connection,
+            Some(u32::MAX) => Self::Never,
+            Some(seconds) => Self::After(seconds),
+        }
+    }
+}
+
+/// The state restored from a previous session.
+#[derive(Debug, Default)]
+pub struct Restored {
+    /// If a previous session was present.
+    pub present: bool,
+    pub subscriptions: Vec<SessionSubscription>,
+    /// Commands queued while the device was disconnected, with their id in the queue.
+    pub commands: Vec<(i64, Command)>,
+}
+
+/// Keeps the state of a persistent session in the device state service.
+pub struct SessionStore {
+    client: DeviceStateClient,
+    id: Id,
+    expiry: Option<u32>,
+    subscriptions: Mutex<Vec<SessionSubscription>>,
+    closed: AtomicBool,
+}
+
+impl SessionStore {
+    /// Open the session of a device, which just connected.
+    ///
+    /// Unless the client requested to resume the session, a previous session is discarded. If the
+    /// session ends with the connection, no store is returned.
+    pub async fn open(
+        client: &DeviceStateClient,
+        id: &Id,
+        resume: bool,
+        expiry: Expiry,
+    ) -> Result<(Option<Self>, Restored), ClientError> {
+        let mut restored = Restored::default();
+
+        if resume {
+            if let Some(session) = client.get_persistent(&id.app_id, &id.device_id).await? {
+                restored.present = true;
+                restored.subscriptions = session.subscriptions;
+                restored.commands = client
+                    .get_commands(&id.app_id, &id.device_id)
+                    .await?
+                    .into_iter()
+                    .filter_map(|queued| {
+                        serde_json::from_value::<cloudevents::Event>(queued.event)
+                            .ok()
+                            .and_then(|event| Command::try_from(event).ok())
+                            .map(|command| (queued.id, command))
+                    })
+                    .collect();
+            }
+        }
+
+        if !resume || expiry == Expiry::Connection {
+            client.delete_persistent(&id.app_id, &id.device_id).await?;
+        }
+
+        let expiry = match expiry {
+            Expiry::Connection => return Ok((None, restored)),
+            Expiry::After(seconds) => Some(seconds),
+            Expiry::Never => None,
+        };
+
+        let store = Self {
+            client: client.clone(),
+            id: id.clone(),
+            expiry,
+            subscriptions: Mutex::new(restored.subscriptions.clone()),
+            closed: AtomicBool::new(false),
+        };
+        store.store(&restored.subscriptions).await?;
+
+        Ok((Some(store), restored))
+    }
+
+    async fn store(&self, subscriptions: &[SessionSubscription]) -> Result<(), ClientError> {
+        self.client
+            .put_persistent(
+                &self.id.app_id,
+                &self.id.device_id,
+                &PersistentSession {
+                    subscriptions: subscriptions.to_vec(),
+                    expiry: self.expiry,
+                },
+            )
+            .await
+    }
+
+    /// Record a new subscription, replacing an existing one with the same topic filter.
+    pub async fn subscribed(&self, topic: &str, qos: QoS, id: Option<NonZeroU32>) {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|sub| sub.topic != topic);
+        subscriptions.push(SessionSubscription {
+            topic: topic.to_string(),
+            qos: qos as u8,
+            id: id.map(NonZeroU32::get),
+        });
+
+        if let Err(err) = self.store(&subscriptions).await {
+            log::warn!("Failed to store subscriptions of {:?}: {err}", self.id);
+        }
+    }
+
+    /// Remove a subscription.
+    pub async fn unsubscribed(&self, topic: &str) {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|sub| sub.topic != topic);
+
+        if let Err(err) = self.store(&subscriptions).await {
+            log::warn!("Failed to store subscriptions of {:?}: {err}", self.id);
+        }
+    }
+
+    /// Remove a queued command, once it was delivered to the device.
+    pub async fn ack(&self, id: i64) {
+        if let Err(err) = self
+            .client
+            .ack_command(&self.id.app_id, &self.id.device_id, id)
+            .await
+        {
+            log::warn!(
+                "Failed to acknowledge queued command of {:?}: {err}",
+                self.id
+            );
+        }
+    }
+
+    /// Check if the session was already closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Mark the session as disconnected, starting its expiry.
+    pub async fn close(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        if let Err(err) = self
+            .client
+            .disconnect_persistent(&self.id.app_id, &self.id.device_id)
+            .await
+        {
+            log::warn!("Failed to close persistent session of {:?}: {err}", self.id);
+        }
+    }
+}
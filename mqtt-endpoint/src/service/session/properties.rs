@@ -0,0 +1,215 @@
+use crate::config::UserPropertiesConfig;
+use chrono::{Duration, Utc};
+use drogue_cloud_endpoint_common::{command::correlation_id_from_data, sender::PublishOptions};
+use drogue_cloud_mqtt_common::error::PublishError;
+use drogue_cloud_service_api::{
+    EXT_APPLICATION, EXT_APPLICATION_UID, EXT_CORRELATION_ID, EXT_DEVICE, EXT_DEVICE_UID,
+    EXT_EXPIRES, EXT_INSTANCE, EXT_RESPONSE_CHANNEL, EXT_SENDER, EXT_SENDER_UID,
+};
+use ntex_mqtt::v5::codec::PublishProperties;
+use std::collections::HashMap;
+
+/// Content type of UTF-8 payloads, which are not JSON.
+const CONTENT_TYPE_TEXT: &str = "text/plain; charset=utf-8";
+
+/// Names which must not be set from user properties.
+const RESERVED: &[&str] = &[
+    "id",
+    "source",
+    "specversion",
+    "type",
+    "datacontenttype",
+    "dataschema",
+    "subject",
+    "time",
+    "data",
+    "partitionkey",
+    EXT_INSTANCE,
+    EXT_APPLICATION,
+    EXT_DEVICE,
+    EXT_SENDER,
+    EXT_APPLICATION_UID,
+    EXT_DEVICE_UID,
+    EXT_SENDER_UID,
+    EXT_CORRELATION_ID,
+    EXT_RESPONSE_CHANNEL,
+    EXT_EXPIRES,
+];
+
+/// Create the publish options from the MQTT v5 properties of a message.
+pub fn publish_options(
+    config: &UserPropertiesConfig,
+    properties: Option<&PublishProperties>,
+    payload: &[u8],
+) -> Result<PublishOptions, PublishError> {
+    let properties = match properties {
+        Some(properties) => properties,
+        None => return Ok(Default::default()),
+    };
+
+    let mut content_type = properties.content_type.as_ref().map(|s| s.to_string());
+
+    // payload format indicator
+    if properties.is_utf8_payload == Some(true) {
+        if std::str::from_utf8(payload).is_err() {
+            return Err(PublishError::PayloadFormatInvalid);
+        }
+        // if it isn't JSON, it is text
+        if content_type.is_none() && serde_json::from_slice::<serde_json::Value>(payload).is_err() {
+            content_type = Some(CONTENT_TYPE_TEXT.to_string());
+        }
+    }
+
+    let mut extensions = user_properties(config, &properties.user_properties);
+
+    // request/response
+    if let Some(correlation_data) = &properties.correlation_data {
+        extensions.insert(
+            EXT_CORRELATION_ID.to_string(),
+            correlation_id_from_data(correlation_data),
+        );
+    }
+    if let Some(response_topic) = &properties.response_topic {
+        extensions.insert(EXT_RESPONSE_CHANNEL.to_string(), response_topic.to_string());
+    }
+
+    // message expiry
+    if let Some(expiry) = properties.message_expiry_interval {
+        let expires = Utc::now() + Duration::seconds(i64::from(expiry.get()));
+        extensions.insert(EXT_EXPIRES.to_string(), expires.to_rfc3339());
+    }
+
+    Ok(PublishOptions {
+        content_type,
+        extensions,
+        ..Default::default()
+    })
+}
+
+/// Map the allowed user properties to extensions.
+///
+/// Extension names only allow lowercase alphanumeric characters, so the name is converted, and
+/// prefixed. Multiple values of the same property are joined with a comma.
+fn user_properties<K, V>(
+    config: &UserPropertiesConfig,
+    properties: &[(K, V)],
+) -> HashMap<String, String>
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut result = HashMap::<String, String>::new();
+
+    for (key, value) in properties {
+        let key = key.as_ref();
+        if !config.allows(key) {
+            continue;
+        }
+
+        let name = format!("{}{}", config.prefix, key)
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect::<String>();
+        if name.is_empty() || RESERVED.contains(&name.as_str()) {
+            log::debug!("Skipping user property: {key}");
+            continue;
+        }
+
+        result
+            .entry(name)
+            .and_modify(|current| {
+                current.push(',');
+                current.push_str(value.as_ref());
+            })
+            .or_insert_with(|| value.as_ref().to_string());
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(allow: &[&str], prefix: &str) -> UserPropertiesConfig {
+        UserPropertiesConfig {
+            allow: allow.iter().map(ToString::to_string).collect(),
+            prefix: prefix.into(),
+        }
+    }
+
+    #[test]
+    fn test_user_properties() {
+        let properties = [
+            ("firmware-version", "1.2.3"),
+            ("seq", "42"),
+            ("tag", "a"),
+            ("tag", "b"),
+        ];
+
+        // nothing is mapped, unless allowed
+        assert!(user_properties(&config(&[], "mqtt"), &properties).is_empty());
+
+        let result = user_properties(&config(&["*"], "mqtt"), &properties);
+        assert_eq!(
+            result,
+            HashMap::from([
+                ("mqttfirmwareversion".to_string(), "1.2.3".to_string()),
+                ("mqttseq".to_string(), "42".to_string()),
+                ("mqtttag".to_string(), "a,b".to_string()),
+            ])
+        );
+
+        let result = user_properties(&config(&["seq"], ""), &properties);
+        assert_eq!(
+            result,
+            HashMap::from([("seq".to_string(), "42".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_reserved() {
+        let properties = [("Application", "other"), ("-", "empty"), ("app", "ok")];
+
+        let result = user_properties(&config(&["*"], ""), &properties);
+        assert_eq!(
+            result,
+            HashMap::from([("app".to_string(), "ok".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_payload_format() {
+        let properties = PublishProperties {
+            is_utf8_payload: Some(true),
+            ..Default::default()
+        };
+
+        let options = publish_options(&config(&[], "mqtt"), Some(&properties), b"hello").unwrap();
+        assert_eq!(options.content_type.as_deref(), Some(CONTENT_TYPE_TEXT));
+
+        let options =
+            publish_options(&config(&[], "mqtt"), Some(&properties), br#"{"a":1}"#).unwrap();
+        assert_eq!(options.content_type, None);
+
+        assert_eq!(
+            publish_options(&config(&[], "mqtt"), Some(&properties), &[0xff]).unwrap_err(),
+            PublishError::PayloadFormatInvalid
+        );
+    }
+
+    #[test]
+    fn test_message_expiry() {
+        let properties = PublishProperties {
+            message_expiry_interval: std::num::NonZeroU32::new(60),
+            ..Default::default()
+        };
+
+        let options = publish_options(&config(&[], "mqtt"), Some(&properties), b"{}").unwrap();
+        let expires = chrono::DateTime::parse_from_rfc3339(&options.extensions[EXT_EXPIRES])
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(expires > Utc::now() + Duration::seconds(50));
+    }
+}
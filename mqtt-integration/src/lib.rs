@@ -4,14 +4,14 @@ pub use crate::service::ServiceConfig;
 
 use drogue_cloud_endpoint_common::{
     sender::{ExternalClientPoolConfig, UpstreamSender},
-    sink::KafkaSink,
+    sink::MessagingSink,
 };
 use drogue_cloud_mqtt_common::server::{build, MqttServerOptions, TlsConfig};
 use drogue_cloud_service_api::kafka::KafkaClientConfig;
 use drogue_cloud_service_common::{
     app::{Startup, StartupExt},
     auth::openid::AuthenticatorConfig,
-    client::ClientConfig,
+    client::{ClientConfig, DeviceStateClient, DeviceStateClientConfig},
     defaults,
     reqwest::ClientFactory,
 };
@@ -70,6 +70,13 @@ pub struct Config {
 
     #[serde(default)]
     pub endpoint_pool: ExternalClientPoolConfig,
+
+    /// The device state service, providing the events for retained messages.
+    ///
+    /// The device state service must collect the last values, otherwise there are no events to
+    /// retain.
+    #[serde(default)]
+    pub device_state: Option<DeviceStateClientConfig>,
 }
 
 impl TlsConfig for Config {
@@ -128,10 +135,14 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     };
 
     let registry = config.registry.into_client().await?;
+    let device_state = match config.device_state {
+        Some(device_state) => Some(DeviceStateClient::from_config(device_state).await?),
+        None => None,
+    };
 
     let sender = UpstreamSender::new(
         config.instance,
-        KafkaSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
+        MessagingSink::from_config(config.command_kafka_sink, config.check_kafka_topic_ready)?,
         config.endpoint_pool,
     )?;
 
@@ -147,6 +158,7 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
         sender,
         client: ClientFactory::new().build()?,
         registry,
+        device_state,
     };
 
     // create server
@@ -2,9 +2,10 @@ use crate::service::{session::Session, ServiceConfig};
 use async_trait::async_trait;
 use drogue_client::{registry, user};
 use drogue_cloud_endpoint_common::sender::UpstreamSender;
+use drogue_cloud_integration_common::auth::authenticate;
 use drogue_cloud_mqtt_common::{error::ServerError, mqtt::*};
 use drogue_cloud_service_api::auth::user::UserInformation;
-use drogue_cloud_service_common::auth::openid::{Authenticator, AuthenticatorError};
+use drogue_cloud_service_common::{auth::openid::Authenticator, client::DeviceStateClient};
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
@@ -15,6 +16,7 @@ pub struct App {
     pub sender: UpstreamSender,
     pub client: reqwest::Client,
     pub registry: registry::v1::Client,
+    pub device_state: Option<DeviceStateClient>,
 }
 
 impl App {
@@ -24,55 +26,14 @@ impl App {
         connect: &Connect<'_>,
         auth: &Authenticator,
     ) -> Result<UserInformation, anyhow::Error> {
-        let user = match (connect.credentials(), &self.user_auth) {
-            ((Some(username), Some(password)), Some(user_auth)) => {
-                log::debug!("Authenticate with username and password");
-                // we have a username and password, and are allowed to test this against SSO
-                let username = username.to_string();
-                let password = String::from_utf8(password.to_vec())?;
-
-                match user_auth
-                    .authenticate_access_token(user::v1::authn::AuthenticationRequest {
-                        user_id: username,
-                        access_token: password,
-                    })
-                    .await?
-                    .outcome
-                {
-                    user::v1::authn::Outcome::Known(details) => {
-                        UserInformation::Authenticated(details)
-                    }
-                    user::v1::authn::Outcome::Unknown => {
-                        log::debug!("Unknown API key");
-                        return Err(AuthenticatorError::Failed.into());
-                    }
-                }
-            }
-            ((Some(username), None), _) => {
-                log::debug!("Authenticate with token (username only)");
-                // username but no username is treated as a token
-                let token = auth.validate_token(&username).await?.into();
-                UserInformation::Authenticated(token)
-            }
-            ((None, Some(password)), _) => {
-                log::debug!("Authenticate with token (password only)");
-                // password but no username is treated as a token
-                let password = String::from_utf8(password.to_vec())?;
-                let token = auth.validate_token(&password).await?.into();
-                UserInformation::Authenticated(token)
-            }
-            ((None, None), _) => {
-                // anonymous authentication, but using user auth
-                log::debug!("Anonymous auth");
-                UserInformation::Anonymous
-            }
-            _ => {
-                log::debug!("Unknown authentication method");
-                anyhow::bail!("Unknown authentication scheme");
-            }
-        };
-
-        Ok(user)
+        let (username, password) = connect.credentials();
+        authenticate(
+            username.map(|username| &**username),
+            password.map(|password| &**password),
+            auth,
+            self.user_auth.as_deref(),
+        )
+        .await
     }
 }
 
@@ -119,12 +80,13 @@ impl Service<Session> for App {
                 self.sender.clone(),
                 self.client.clone(),
                 self.registry.clone(),
+                self.device_state.clone(),
                 token,
             ),
             ack: AckOptions {
                 wildcard_subscription_available: Some(true),
                 shared_subscription_available: Some(true),
-                retain_available: Some(false),
+                retain_available: Some(true),
                 ..Default::default()
             },
         })
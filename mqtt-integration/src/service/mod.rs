@@ -1,4 +1,6 @@
 mod app;
+mod response;
+mod retained;
 mod session;
 mod stream;
 
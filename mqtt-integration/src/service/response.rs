@@ -0,0 +1,78 @@
+use cloudevents::{AttributesReader, Data};
+use drogue_client::registry;
+use drogue_cloud_integration_common::commands::ResponseRouter;
+use drogue_cloud_mqtt_common::mqtt::Sink;
+use drogue_cloud_service_api::kafka::KafkaClientConfig;
+use ntex::util::ByteString;
+use ntex_bytes::Bytes;
+use std::time::Duration;
+
+/// The time we wait for a device to respond to a command.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Routes responses of devices back to the MQTT v5 client which sent the command.
+#[derive(Clone)]
+pub struct Responses {
+    sink: Sink,
+    router: ResponseRouter,
+}
+
+impl Responses {
+    pub fn new(sink: Sink, kafka: KafkaClientConfig) -> Self {
+        Self {
+            sink,
+            router: ResponseRouter::new(kafka),
+        }
+    }
+
+    /// Expect a response of the device, on the events of the application.
+    pub fn expect(
+        &self,
+        application: &registry::v1::Application,
+        device: &str,
+        correlation_id: String,
+        response_topic: ByteString,
+        correlation_data: Option<Bytes>,
+    ) -> Result<(), anyhow::Error> {
+        let response = self
+            .router
+            .expect(application, device, correlation_id, RESPONSE_TIMEOUT)?;
+
+        let sink = self.sink.clone();
+        ntex_rt::spawn(async move {
+            // fails when the request expired, or the session was closed
+            let mut event = match tokio::time::timeout(RESPONSE_TIMEOUT, response).await {
+                Ok(Ok(event)) => event,
+                _ => return,
+            };
+
+            let content_type = event.datacontenttype().map(ByteString::from);
+            let payload = match event.take_data().2 {
+                Some(Data::Binary(data)) => Bytes::from(data),
+                Some(Data::String(data)) => Bytes::from(data),
+                Some(Data::Json(data)) => Bytes::from(data.to_string()),
+                None => Bytes::new(),
+            };
+
+            if let Sink::V5(sink) = &sink {
+                let result = sink
+                    .publish(response_topic, payload)
+                    .properties(|p| {
+                        p.correlation_data = correlation_data;
+                        p.content_type = content_type;
+                    })
+                    .send_at_most_once();
+                if let Err(err) = result {
+                    log::info!("Failed to send response: {err}");
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Stop receiving responses.
+    pub fn close(&self) {
+        self.router.close();
+    }
+}
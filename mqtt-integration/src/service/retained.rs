@@ -0,0 +1,84 @@
+use cloudevents::Event;
+use drogue_client::{dialect, registry::v1::Application, Section, Translator};
+use drogue_cloud_service_api::EXT_DEVICE;
+use drogue_cloud_service_common::client::DeviceStateClient;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The MQTT integration settings of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MqttIntegrationSpec {
+    /// Deliver the last event of each device and channel on subscribe, as retained message.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub retain: bool,
+}
+
+dialect!(MqttIntegrationSpec [Section::Spec => "mqttIntegration"]);
+
+/// Check if retained messages are enabled for an application.
+pub fn enabled(application: &Application) -> bool {
+    match application.section::<MqttIntegrationSpec>() {
+        Some(Ok(spec)) => spec.retain,
+        Some(Err(err)) => {
+            log::info!("Invalid MQTT integration spec: {err}");
+            false
+        }
+        None => false,
+    }
+}
+
+/// The number of events fetched with a single request.
+const PAGE_SIZE: u32 = 100;
+
+/// The maximum number of retained events sent for a single subscription.
+const MAX_RETAINED: usize = 1000;
+
+/// Fetch the last event of each device and channel of an application.
+///
+/// Values which can't be parsed as event are skipped. At most [`MAX_RETAINED`] events are
+/// fetched.
+pub async fn fetch(client: &DeviceStateClient, application: &str) -> anyhow::Result<Vec<Event>> {
+    let mut result = Vec::new();
+    let mut after: Option<(String, String)> = None;
+
+    loop {
+        let page = client
+            .get_last_values(
+                application,
+                after.as_ref().map(|(d, c)| (d.as_str(), c.as_str())),
+                PAGE_SIZE,
+            )
+            .await?;
+        let complete = page.len() < PAGE_SIZE as usize;
+
+        for value in page {
+            after = cursor(&value);
+            match serde_json::from_value::<Event>(value) {
+                Ok(event) => {
+                    result.push(event);
+                }
+                Err(err) => {
+                    log::debug!("Skipping invalid retained event: {err}");
+                }
+            }
+
+            if result.len() >= MAX_RETAINED {
+                log::info!("Limiting retained events of {application} to {MAX_RETAINED}");
+                return Ok(result);
+            }
+        }
+
+        if complete || after.is_none() {
+            return Ok(result);
+        }
+    }
+}
+
+/// The position of a value in the last values, used to fetch the next page.
+fn cursor(value: &Value) -> Option<(String, String)> {
+    match (value[EXT_DEVICE].as_str(), value["subject"].as_str()) {
+        (Some(device), Some(channel)) => Some((device.to_string(), channel.to_string())),
+        _ => None,
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
     service::{
+        response::Responses,
+        retained,
         stream::{self, ContentMode, Stream},
         ServiceConfig,
     },
@@ -8,10 +10,11 @@ use crate::{
 use async_trait::async_trait;
 use drogue_client::registry;
 use drogue_client::user;
-use drogue_cloud_endpoint_common::sender::UpstreamSender;
+use drogue_cloud_endpoint_common::{command::correlation_id_from_data, sender::UpstreamSender};
 use drogue_cloud_event_common::stream::CustomAck;
 use drogue_cloud_integration_common::{
     self,
+    auth::authorize,
     commands::CommandOptions,
     stream::{EventStream, EventStreamConfig},
 };
@@ -23,6 +26,7 @@ use drogue_cloud_service_api::{
     auth::user::UserInformation,
     kafka::{KafkaConfigExt, KafkaEventType},
 };
+use drogue_cloud_service_common::client::DeviceStateClient;
 use futures::lock::Mutex;
 use ntex_mqtt::{types::QoS, v5};
 use std::{collections::HashMap, num::NonZeroU32, sync::Arc};
@@ -37,10 +41,12 @@ pub struct Session {
     pub user: UserInformation,
 
     streams: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    responses: Responses,
 
     pub sender: UpstreamSender,
     pub client: reqwest::Client,
     pub registry: registry::v1::Client,
+    pub device_state: Option<DeviceStateClient>,
 
     pub token: Option<String>,
 }
@@ -56,9 +62,11 @@ impl Session {
         sender: UpstreamSender,
         client: reqwest::Client,
         registry: registry::v1::Client,
+        device_state: Option<DeviceStateClient>,
         token: Option<String>,
     ) -> Self {
         CONNECTIONS_COUNTER.inc();
+        let responses = Responses::new(sink.clone(), config.kafka.clone());
         Session {
             config,
             user_auth,
@@ -66,9 +74,11 @@ impl Session {
             sink,
             client_id,
             streams: Arc::new(Mutex::new(HashMap::new())),
+            responses,
             sender,
             client,
             registry,
+            device_state,
             token,
         }
     }
@@ -79,29 +89,7 @@ impl Session {
         user_auth: &Arc<user::v1::Client>,
         permission: user::v1::authz::Permission,
     ) -> Result<(), ()> {
-        log::debug!(
-            "Authorizing - user: {:?}, app: {}, permission: {:?}",
-            self.user,
-            application,
-            permission
-        );
-
-        let response = user_auth
-            .authorize(user::v1::authz::AuthorizationRequest {
-                application,
-                permission,
-                user_id: self.user.user_id().map(ToString::to_string),
-                roles: self.user.roles().clone(),
-            })
-            .await
-            .map_err(|_| ())?;
-
-        log::debug!("Outcome: {:?}", response);
-
-        match response.outcome {
-            user::v1::authz::Outcome::Allow => Ok(()),
-            user::v1::authz::Outcome::Deny => Err(()),
-        }
+        authorize(&self.user, application, user_auth, permission).await
     }
 
     async fn subscribe_to(
@@ -109,23 +97,24 @@ impl Session {
         id: Option<NonZeroU32>,
         original_topic: String,
         qos: QoS,
+        retain_handling: v5::codec::RetainHandling,
         content_mode: ContentMode,
     ) -> Result<QoS, v5::codec::SubscribeAckReason> {
         // split topic into path segments
         let topic = original_topic.split('/').collect::<Vec<_>>();
 
         // extract the shared named, which we use as kafka consumer group id
-        let (group_id, topic) = match topic.as_slice() {
-            ["$share", group_id, topic @ ..] => (Some(*group_id), topic),
+        let (group_id, topic, shared) = match topic.as_slice() {
+            ["$share", group_id, topic @ ..] => (Some(*group_id), topic, true),
             // keep incorrect topic prefix for a bit, to not break existing stuff
-            ["$shared", group_id, topic @ ..] => (Some(*group_id), topic),
+            ["$shared", group_id, topic @ ..] => (Some(*group_id), topic, true),
             other => {
                 let group_id = if self.client_id.is_empty() {
                     None
                 } else {
                     Some(self.client_id.as_str())
                 };
-                (group_id, other)
+                (group_id, other, false)
             }
         };
 
@@ -197,14 +186,37 @@ impl Session {
             v5::codec::SubscribeAckReason::UnspecifiedError
         })?;
 
+        // fetch retained events, shared subscriptions don't receive them
+
+        let topic = topic.join("/");
+        let send_retained = !shared
+            && match retain_handling {
+                v5::codec::RetainHandling::AtSubscribe => true,
+                v5::codec::RetainHandling::AtSubscribeNew => {
+                    !self.streams.lock().await.contains_key(&topic)
+                }
+                v5::codec::RetainHandling::NoAtSubscribe => false,
+            };
+
+        let retained = match &self.device_state {
+            Some(device_state) if send_retained && retained::enabled(&app_res) => {
+                retained::fetch(device_state, app).await.map_err(|err| {
+                    log::info!("Failed to fetch retained events: {err}");
+                    v5::codec::SubscribeAckReason::UnspecifiedError
+                })?
+            }
+            _ => vec![],
+        };
+
         // we started the stream, now hold on to it ...
 
         let stream = Stream {
-            topic: topic.join("/").into(),
+            topic: topic.into(),
             qos,
             id,
             event_stream,
             content_mode,
+            retained,
         };
 
         self.attach_stream(stream).await;
@@ -286,11 +298,45 @@ impl mqtt::Session for Session {
 
             match response {
                 Ok((Some(application), Some(device_gateways))) => {
+                    // MQTT v5 request/response
+                    let correlation_id = match publish
+                        .properties()
+                        .and_then(|p| p.response_topic.clone().map(|topic| (topic, p)))
+                    {
+                        Some((response_topic, properties)) => {
+                            let correlation_data = properties.correlation_data.clone();
+                            let correlation_id = correlation_data
+                                .as_ref()
+                                .map(|data| correlation_id_from_data(data))
+                                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+                            self.responses
+                                .expect(
+                                    &application,
+                                    device,
+                                    correlation_id.clone(),
+                                    response_topic,
+                                    correlation_data,
+                                )
+                                .map_err(|err| {
+                                    log::info!("Failed to listen for responses: {err}");
+                                    PublishError::InternalError(
+                                        "Failed to listen for responses".to_string(),
+                                    )
+                                })?;
+
+                            Some(correlation_id)
+                        }
+                        None => None,
+                    };
+
                     let opts = CommandOptions {
                         application: app.to_string(),
                         device: device.to_string(),
                         command: command.to_string(),
                         content_type: None,
+                        correlation_id,
+                        response_channel: None,
                     };
 
                     match drogue_cloud_integration_common::commands::process_command(
@@ -355,7 +401,13 @@ impl mqtt::Session for Session {
 
         for mut sub in subscribe {
             let res = self
-                .subscribe_to(id, sub.topic().to_string(), sub.qos(), content_mode)
+                .subscribe_to(
+                    id,
+                    sub.topic().to_string(),
+                    sub.qos(),
+                    sub.retain_handling(),
+                    content_mode,
+                )
                 .await;
             log::debug!("Subscribing to: {:?} -> {:?}", sub.topic(), res);
             match res {
@@ -404,6 +456,7 @@ impl Drop for Session {
 
         log::debug!("Dropping session");
         let streams = self.streams.clone();
+        let responses = self.responses.clone();
         ntex_rt::spawn(async move {
             log::debug!("Dropping streams");
             let mut streams = streams.lock().await;
@@ -411,6 +464,8 @@ impl Drop for Session {
             for (_, stream) in streams.drain() {
                 stream.abort();
             }
+
+            responses.close();
         });
     }
 }
@@ -1,14 +1,33 @@
 use anyhow::anyhow;
-use cloudevents::Data;
+use chrono::{DateTime, Utc};
+use cloudevents::{event::ExtensionValue, Data, Event};
 use drogue_cloud_event_common::stream::CustomAck;
 use drogue_cloud_integration_common::{self, stream::EventStream};
 use drogue_cloud_mqtt_common::mqtt::Sink;
+use drogue_cloud_service_api::EXT_EXPIRES;
 use futures_util::StreamExt;
 use ntex::util::ByteString;
 use ntex_bytes::Bytes;
 use ntex_mqtt::{error::SendPacketError, v3, v5};
 use std::num::NonZeroU32;
-use std::ops::{Deref, DerefMut};
+use std::ops::DerefMut;
+
+/// Evaluate the remaining time to live of an event, from its `expires` extension.
+///
+/// Returns the number of seconds, rounded up, or `Some(0)` if the event is expired.
+fn time_to_live(event: &Event) -> Option<u32> {
+    let expires = match event.extension(EXT_EXPIRES) {
+        Some(ExtensionValue::String(expires)) => DateTime::parse_from_rfc3339(expires).ok()?,
+        _ => return None,
+    };
+
+    let remaining = (expires.with_timezone(&Utc) - Utc::now()).num_milliseconds();
+    Some(if remaining > 0 {
+        u32::try_from((remaining + 999) / 1000).unwrap_or(u32::MAX)
+    } else {
+        0
+    })
+}
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum QoS {
@@ -44,6 +63,8 @@ pub struct Stream<'s> {
     pub id: Option<NonZeroU32>,
     pub event_stream: EventStream<'s, CustomAck>,
     pub content_mode: ContentMode,
+    /// Events sent as retained messages, before the events of the stream.
+    pub retained: Vec<Event>,
 }
 
 impl Drop for Stream<'_> {
@@ -80,13 +101,34 @@ impl Stream<'_> {
         }
     }
 
+    /// Take the retained events, which are not yet expired.
+    fn take_retained(&mut self) -> Vec<(Event, Option<u32>)> {
+        std::mem::take(&mut self.retained)
+            .into_iter()
+            .filter_map(|event| match time_to_live(&event) {
+                Some(0) => None,
+                ttl => Some((event, ttl)),
+            })
+            .collect()
+    }
+
     pub async fn run_v3(mut self, sink: &mut v3::MqttSink) -> Result<(), anyhow::Error> {
+        for (event, _) in self.take_retained() {
+            let builder = self.publish_v3(sink, &event)?.retain();
+            self.qos.send_v3(builder).await?;
+        }
+
         while let Some(handle) = self.event_stream.next().await {
             log::debug!("Event: {:?}", handle);
 
             let handle = handle?;
-            let event = serde_json::to_vec(handle.deref())?;
-            let builder = sink.publish(self.topic.clone(), event.into());
+            if time_to_live(&handle) == Some(0) {
+                log::debug!("Skipping expired event");
+                self.event_stream.ack(handle)?;
+                continue;
+            }
+
+            let builder = self.publish_v3(sink, &handle)?;
 
             self.qos.send_v3(builder).await?;
             self.event_stream.ack(handle)?;
@@ -97,21 +139,33 @@ impl Stream<'_> {
         Ok(())
     }
 
+    fn publish_v3(
+        &self,
+        sink: &v3::MqttSink,
+        event: &Event,
+    ) -> Result<v3::PublishBuilder, anyhow::Error> {
+        let event = serde_json::to_vec(event)?;
+        Ok(sink.publish(self.topic.clone(), event.into()))
+    }
+
     pub async fn run_v5_structured(mut self, sink: &mut v5::MqttSink) -> Result<(), anyhow::Error> {
-        let sub_ids = self.id.map(|id| vec![id]);
+        for (event, ttl) in self.take_retained() {
+            let builder = self.publish_v5_structured(sink, &event, ttl)?.retain();
+            self.qos.send_v5(builder).await?;
+        }
 
         while let Some(handle) = self.event_stream.next().await {
             log::debug!("Event: {:?}", handle);
 
             let handle = handle?;
-            let event = serde_json::to_vec(handle.deref())?;
-            let builder = sink
-                .publish(self.topic.clone(), event.into())
-                .properties(|p| {
-                    p.content_type = Some("application/cloudevents+json; charset=utf-8".into());
-                    p.is_utf8_payload = Some(true);
-                    p.subscription_ids = sub_ids.clone();
-                });
+            let ttl = time_to_live(&handle);
+            if ttl == Some(0) {
+                log::debug!("Skipping expired event");
+                self.event_stream.ack(handle)?;
+                continue;
+            }
+
+            let builder = self.publish_v5_structured(sink, &handle, ttl)?;
 
             self.qos.send_v5(builder).await?;
             self.event_stream.ack(handle)?;
@@ -122,35 +176,46 @@ impl Stream<'_> {
         Ok(())
     }
 
-    pub async fn run_v5_binary(mut self, sink: &mut v5::MqttSink) -> Result<(), anyhow::Error> {
+    fn publish_v5_structured(
+        &self,
+        sink: &v5::MqttSink,
+        event: &Event,
+        ttl: Option<u32>,
+    ) -> Result<v5::PublishBuilder, anyhow::Error> {
         let sub_ids = self.id.map(|id| vec![id]);
 
+        let payload = serde_json::to_vec(event)?;
+        Ok(sink
+            .publish(self.topic.clone(), payload.into())
+            .properties(|p| {
+                for (k, v) in event.iter_extensions() {
+                    p.user_properties.push((k.into(), v.to_string().into()));
+                }
+                p.content_type = Some("application/cloudevents+json; charset=utf-8".into());
+                p.is_utf8_payload = Some(true);
+                p.message_expiry_interval = ttl.and_then(NonZeroU32::new);
+                p.subscription_ids = sub_ids;
+            }))
+    }
+
+    pub async fn run_v5_binary(mut self, sink: &mut v5::MqttSink) -> Result<(), anyhow::Error> {
+        for (mut event, ttl) in self.take_retained() {
+            let builder = self.publish_v5_binary(sink, &mut event, ttl)?.retain();
+            self.qos.send_v5(builder).await?;
+        }
+
         while let Some(handle) = self.event_stream.next().await {
             log::debug!("Event: {:?}", handle);
 
             let mut handle = handle?;
-            let event = handle.deref_mut();
-            let topic = self.topic.clone();
-
-            let (content_type, _, data) = event.take_data();
-            let builder = match data {
-                Some(Data::Binary(data)) => sink.publish(topic, data.into()),
-                Some(Data::String(data)) => sink.publish(topic, data.into()),
-                Some(Data::Json(data)) => {
-                    sink.publish(topic.clone(), serde_json::to_vec(&data)?.into())
-                }
-                None => sink.publish(topic.clone(), Bytes::new()),
-            };
+            let ttl = time_to_live(&handle);
+            if ttl == Some(0) {
+                log::debug!("Skipping expired event");
+                self.event_stream.ack(handle)?;
+                continue;
+            }
 
-            // convert attributes and extensions ...
-
-            let builder = builder.properties(|p| {
-                for (k, v) in event.iter() {
-                    p.user_properties.push((k.into(), v.to_string().into()));
-                }
-                p.content_type = content_type.map(Into::into);
-                p.subscription_ids = sub_ids.clone();
-            });
+            let builder = self.publish_v5_binary(sink, handle.deref_mut(), ttl)?;
 
             // ... and send
             self.qos.send_v5(builder).await?;
@@ -161,4 +226,33 @@ impl Stream<'_> {
 
         Ok(())
     }
+
+    fn publish_v5_binary(
+        &self,
+        sink: &v5::MqttSink,
+        event: &mut Event,
+        ttl: Option<u32>,
+    ) -> Result<v5::PublishBuilder, anyhow::Error> {
+        let sub_ids = self.id.map(|id| vec![id]);
+        let topic = self.topic.clone();
+
+        let (content_type, _, data) = event.take_data();
+        let builder = match data {
+            Some(Data::Binary(data)) => sink.publish(topic, data.into()),
+            Some(Data::String(data)) => sink.publish(topic, data.into()),
+            Some(Data::Json(data)) => sink.publish(topic, serde_json::to_vec(&data)?.into()),
+            None => sink.publish(topic, Bytes::new()),
+        };
+
+        // convert attributes and extensions ...
+
+        Ok(builder.properties(|p| {
+            for (k, v) in event.iter() {
+                p.user_properties.push((k.into(), v.to_string().into()));
+            }
+            p.content_type = content_type.map(Into::into);
+            p.message_expiry_interval = ttl.and_then(NonZeroU32::new);
+            p.subscription_ids = sub_ids;
+        }))
+    }
 }
@@ -0,0 +1,128 @@
+use crate::{keys::encode, users::LocalUser};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const CODE_LENGTH: usize = 32;
+const CODE_LIFETIME: Duration = Duration::from_secs(60);
+
+/// The PKCE challenge of an authorization request.
+#[derive(Clone, Debug)]
+pub enum CodeChallenge {
+    Plain(String),
+    S256(String),
+}
+
+impl CodeChallenge {
+    pub fn new(challenge: String, method: Option<&str>) -> Option<Self> {
+        match method {
+            None | Some("plain") => Some(Self::Plain(challenge)),
+            Some("S256") => Some(Self::S256(challenge)),
+            Some(_) => None,
+        }
+    }
+
+    pub fn verify(&self, verifier: &str) -> bool {
+        match self {
+            Self::Plain(challenge) => challenge == verifier,
+            Self::S256(challenge) => {
+                *challenge == encode(&openssl::sha::sha256(verifier.as_bytes()))
+            }
+        }
+    }
+}
+
+/// A granted authorization, waiting to be exchanged for a token.
+#[derive(Clone, Debug)]
+pub struct Authorization {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub user: LocalUser,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub challenge: Option<CodeChallenge>,
+}
+
+/// Authorization codes, kept in memory.
+///
+/// Codes are short lived and can only be used once.
+#[derive(Clone, Default)]
+pub struct CodeStore {
+    codes: Arc<Mutex<HashMap<String, (Instant, Authorization)>>>,
+}
+
+impl CodeStore {
+    /// Store the authorization and return its code.
+    pub fn insert(&self, authorization: Authorization) -> String {
+        let code: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(CODE_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let mut codes = self.codes.lock().unwrap();
+
+        let now = Instant::now();
+        codes.retain(|_, (expires, _)| *expires > now);
+        codes.insert(code.clone(), (now + CODE_LIFETIME, authorization));
+
+        code
+    }
+
+    /// Take the authorization for a code, if the code exists and is not expired.
+    pub fn take(&self, code: &str) -> Option<Authorization> {
+        match self.codes.lock().unwrap().remove(code) {
+            Some((expires, authorization)) if expires > Instant::now() => Some(authorization),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn authorization() -> Authorization {
+        Authorization {
+            client_id: "drogue".into(),
+            redirect_uri: "http://localhost".into(),
+            user: LocalUser {
+                username: "foo".into(),
+                roles: vec![],
+            },
+            scope: "openid".into(),
+            nonce: None,
+            challenge: None,
+        }
+    }
+
+    #[test]
+    fn test_single_use() {
+        let store = CodeStore::default();
+        let code = store.insert(authorization());
+
+        assert!(store.take("unknown").is_none());
+        assert_eq!(store.take(&code).unwrap().user.username, "foo");
+        assert!(store.take(&code).is_none());
+    }
+
+    #[test]
+    fn test_challenge() {
+        // from RFC 7636, appendix B
+        let challenge = CodeChallenge::new(
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".into(),
+            Some("S256"),
+        )
+        .unwrap();
+        assert!(challenge.verify("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"));
+        assert!(!challenge.verify("foo"));
+
+        let challenge = CodeChallenge::new("foo".into(), None).unwrap();
+        assert!(challenge.verify("foo"));
+
+        assert!(CodeChallenge::new("foo".into(), Some("S512")).is_none());
+    }
+}
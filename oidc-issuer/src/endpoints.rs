@@ -0,0 +1,444 @@
+use crate::{
+    codes::{Authorization, CodeChallenge},
+    error::Error,
+    issuer::{secure_eq, Issuer},
+};
+use drogue_cloud_service_api::webapp::{
+    cookie::{Cookie, SameSite},
+    http::header::{self, HeaderMap},
+    web, HttpRequest, HttpResponse,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+/// The cookie holding the CSRF token of the login form.
+const CSRF_COOKIE: &str = "drogue-oidc-csrf";
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+pub async fn discovery(issuer: web::Data<Issuer>) -> HttpResponse {
+    HttpResponse::Ok().json(issuer.discovery())
+}
+
+pub async fn jwks(issuer: web::Data<Issuer>) -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::Ok().json(issuer.jwks()?))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuthorizationRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    #[serde(default)]
+    pub code_challenge: Option<String>,
+    #[serde(default)]
+    pub code_challenge_method: Option<String>,
+}
+
+impl AuthorizationRequest {
+    /// Validate the request, before showing the login form or redirecting back to the client.
+    fn validate(&self, issuer: &Issuer) -> Result<Option<CodeChallenge>, Error> {
+        if self.response_type != "code" {
+            return Err(Error::InvalidRequest(format!(
+                "Unsupported response type: {}",
+                self.response_type
+            )));
+        }
+
+        let client = issuer
+            .client(&self.client_id)
+            .ok_or_else(|| Error::InvalidRequest(format!("Unknown client: {}", self.client_id)))?;
+
+        if !client.allows_redirect(&self.redirect_uri) {
+            return Err(Error::InvalidRequest(format!(
+                "Redirect URI not allowed: {}",
+                self.redirect_uri
+            )));
+        }
+
+        let challenge = match &self.code_challenge {
+            Some(challenge) => Some(
+                CodeChallenge::new(challenge.clone(), self.code_challenge_method.as_deref())
+                    .ok_or_else(|| {
+                        Error::InvalidRequest("Unsupported code challenge method".into())
+                    })?,
+            ),
+            None => None,
+        };
+
+        if client.require_pkce && !matches!(challenge, Some(CodeChallenge::S256(_))) {
+            return Err(Error::InvalidRequest(
+                "Client requires a code challenge, using the S256 method".into(),
+            ));
+        }
+
+        Ok(challenge)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LoginRequest {
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub csrf_token: Option<String>,
+}
+
+/// Show the login form.
+pub async fn authorize(
+    issuer: web::Data<Issuer>,
+    request: web::Query<AuthorizationRequest>,
+) -> Result<HttpResponse, Error> {
+    request.validate(&issuer)?;
+    Ok(login_page(&request, None))
+}
+
+/// Process the login form, redirecting back to the client on success.
+///
+/// The form must carry the same CSRF token as the cookie, which was set when showing the form.
+pub async fn login(
+    req: HttpRequest,
+    issuer: web::Data<Issuer>,
+    login: web::Form<LoginRequest>,
+) -> Result<HttpResponse, Error> {
+    let LoginRequest {
+        request,
+        username,
+        password,
+        csrf_token,
+    } = login.into_inner();
+
+    let challenge = request.validate(&issuer)?;
+
+    let valid_csrf = match (req.cookie(CSRF_COOKIE), csrf_token) {
+        (Some(cookie), Some(token)) => secure_eq(cookie.value(), &token),
+        _ => false,
+    };
+    if !valid_csrf {
+        log::debug!("Invalid CSRF token for login of user: {username}");
+        return Ok(login_page(
+            &request,
+            Some("The login form expired, please try again"),
+        ));
+    }
+
+    let user = match issuer.authenticate_user(&username, &password).await? {
+        Some(user) => user,
+        None => {
+            log::debug!("Failed login for user: {username}");
+            return Ok(login_page(&request, Some("Invalid username or password")));
+        }
+    };
+
+    let code = issuer.codes.insert(Authorization {
+        client_id: request.client_id.clone(),
+        redirect_uri: request.redirect_uri.clone(),
+        user,
+        scope: request.scope.clone().unwrap_or_default(),
+        nonce: request.nonce.clone(),
+        challenge,
+    });
+
+    let mut location = Url::parse(&request.redirect_uri)
+        .map_err(|err| Error::InvalidRequest(format!("Invalid redirect URI: {err}")))?;
+    {
+        let mut query = location.query_pairs_mut();
+        query.append_pair("code", &code);
+        if let Some(state) = &request.state {
+            query.append_pair("state", state);
+        }
+    }
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, location.as_str()))
+        .finish())
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default)]
+    pub scope: Option<String>,
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    #[serde(default)]
+    pub code_verifier: Option<String>,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+pub async fn token(
+    req: HttpRequest,
+    issuer: web::Data<Issuer>,
+    request: web::Form<TokenRequest>,
+) -> Result<HttpResponse, Error> {
+    let request = request.into_inner();
+
+    // authenticate the client, either using basic auth, or the form parameters
+
+    let (client_id, client_secret) = match basic_auth(req.headers()) {
+        Some((id, secret)) => (id, Some(secret)),
+        None => (
+            request.client_id.clone().ok_or(Error::InvalidClient)?,
+            request.client_secret.clone(),
+        ),
+    };
+    let client = issuer.authenticate_client(&client_id, client_secret.as_deref())?;
+
+    let missing = |name: &str| Error::InvalidRequest(format!("Missing parameter: {name}"));
+
+    let response = match request.grant_type.as_str() {
+        "authorization_code" => {
+            let code = request.code.ok_or_else(|| missing("code"))?;
+            let authorization = issuer
+                .codes
+                .take(&code)
+                .ok_or_else(|| Error::InvalidGrant("Unknown or expired code".into()))?;
+
+            if authorization.client_id != client_id {
+                return Err(Error::InvalidGrant(
+                    "Code was issued to a different client".into(),
+                ));
+            }
+            if request.redirect_uri.as_deref() != Some(authorization.redirect_uri.as_str()) {
+                return Err(Error::InvalidGrant("Redirect URI mismatch".into()));
+            }
+            if let Some(challenge) = &authorization.challenge {
+                let verifier = request
+                    .code_verifier
+                    .ok_or_else(|| missing("code_verifier"))?;
+                if !challenge.verify(&verifier) {
+                    return Err(Error::InvalidGrant("Code verifier mismatch".into()));
+                }
+            }
+
+            issuer.issue(
+                &client_id,
+                &authorization.user,
+                authorization.scope,
+                authorization.nonce,
+            )?
+        }
+        "client_credentials" => {
+            issuer.issue_service_account(&client_id, client, request.scope.unwrap_or_default())?
+        }
+        "password" => {
+            let username = request.username.ok_or_else(|| missing("username"))?;
+            let password = request.password.ok_or_else(|| missing("password"))?;
+            let user = issuer
+                .authenticate_user(&username, &password)
+                .await?
+                .ok_or_else(|| Error::InvalidGrant("Invalid username or password".into()))?;
+
+            issuer.issue(&client_id, &user, request.scope.unwrap_or_default(), None)?
+        }
+        "refresh_token" => {
+            let refresh_token = request
+                .refresh_token
+                .ok_or_else(|| missing("refresh_token"))?;
+            issuer.refresh(&client_id, &refresh_token).await?
+        }
+        grant_type => return Err(Error::UnsupportedGrantType(grant_type.into())),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(response))
+}
+
+pub async fn userinfo(req: HttpRequest, issuer: web::Data<Issuer>) -> Result<HttpResponse, Error> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(Error::InvalidToken)?;
+
+    let claims = issuer.verify_access_token(token)?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "sub": claims.sub,
+        "preferred_username": claims.preferred_username,
+        "name": claims.preferred_username,
+    })))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LogoutRequest {
+    #[serde(default)]
+    pub post_logout_redirect_uri: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+/// End the session.
+///
+/// The issuer doesn't keep a session, so there is nothing to clean up. We only redirect back, if
+/// requested.
+pub async fn logout(issuer: web::Data<Issuer>, request: web::Query<LogoutRequest>) -> HttpResponse {
+    if let Some(uri) = &request.post_logout_redirect_uri {
+        if issuer.allows_redirect(uri) {
+            if let Ok(mut location) = Url::parse(uri) {
+                if let Some(state) = &request.state {
+                    location.query_pairs_mut().append_pair("state", state);
+                }
+                return HttpResponse::Found()
+                    .insert_header((header::LOCATION, location.as_str()))
+                    .finish();
+            }
+        }
+    }
+
+    html_page("Signed out", "<p>You have been signed out.</p>")
+}
+
+fn basic_auth(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let value = String::from_utf8(base64::decode(value).ok()?).ok()?;
+    let (id, secret) = value.split_once(':')?;
+    Some((id.to_string(), secret.to_string()))
+}
+
+/// Show the login form, along with a new CSRF token.
+fn login_page(request: &AuthorizationRequest, error: Option<&str>) -> HttpResponse {
+    let csrf_token: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CSRF_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+
+    let hidden = [
+        ("response_type", Some(&request.response_type)),
+        ("client_id", Some(&request.client_id)),
+        ("redirect_uri", Some(&request.redirect_uri)),
+        ("scope", request.scope.as_ref()),
+        ("state", request.state.as_ref()),
+        ("nonce", request.nonce.as_ref()),
+        ("code_challenge", request.code_challenge.as_ref()),
+        (
+            "code_challenge_method",
+            request.code_challenge_method.as_ref(),
+        ),
+        ("csrf_token", Some(&csrf_token)),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        value.map(|value| {
+            format!(
+                r#"<input type="hidden" name="{name}" value="{}">"#,
+                escape(value)
+            )
+        })
+    })
+    .collect::<String>();
+
+    let error = error
+        .map(|error| format!(r#"<p class="error">{}</p>"#, escape(error)))
+        .unwrap_or_default();
+
+    let mut response = html_page(
+        "Log in",
+        &format!(
+            r#"{error}
+<form method="post">
+{hidden}
+<label>Username <input name="username" autocomplete="username" autofocus required></label>
+<label>Password <input name="password" type="password" autocomplete="current-password" required></label>
+<button type="submit">Log in</button>
+</form>"#
+        ),
+    );
+
+    let cookie = Cookie::build(CSRF_COOKIE, csrf_token)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    if let Err(err) = response.add_cookie(&cookie) {
+        log::warn!("Failed to set CSRF cookie: {err}");
+    }
+
+    response
+}
+
+fn html_page(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Drogue IoT - {title}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 20em; margin: 4em auto; }}
+label, button {{ display: block; margin: 1em 0; }}
+input {{ display: block; width: 100%; }}
+.error {{ color: #c9190b; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{body}
+</body>
+</html>"#
+        ))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use drogue_cloud_service_api::webapp::http::header::HeaderValue;
+
+    #[test]
+    fn test_basic_auth() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(basic_auth(&headers), None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic c2VydmljZXM6Zm9vOmJhcg=="),
+        );
+        assert_eq!(
+            basic_auth(&headers),
+            Some(("services".to_string(), "foo:bar".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(r#""><script>&"#), "&quot;&gt;&lt;script&gt;&amp;");
+    }
+}
@@ -0,0 +1,56 @@
+use crate::keys::KeyError;
+use drogue_cloud_database_common::error::ServiceError;
+use drogue_cloud_service_api::webapp::{http::StatusCode, HttpResponse, ResponseError};
+use serde_json::json;
+
+/// Errors, as defined by RFC 6749, section 5.2.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Invalid client")]
+    InvalidClient,
+    #[error("Invalid grant: {0}")]
+    InvalidGrant(String),
+    #[error("Unauthorized client")]
+    UnauthorizedClient,
+    #[error("Unsupported grant type: {0}")]
+    UnsupportedGrantType(String),
+    #[error("Invalid token")]
+    InvalidToken,
+    #[error("Key error: {0}")]
+    Key(#[from] KeyError),
+    #[error("Database error: {0}")]
+    Database(#[from] ServiceError),
+}
+
+impl Error {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant(_) => "invalid_grant",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::UnsupportedGrantType(_) => "unsupported_grant_type",
+            Self::InvalidToken => "invalid_token",
+            Self::Key(_) | Self::Database(_) => "server_error",
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidClient | Self::InvalidToken => StatusCode::UNAUTHORIZED,
+            Self::Key(_) | Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({
+            "error": self.code(),
+            "error_description": self.to_string(),
+        }))
+    }
+}
@@ -0,0 +1,173 @@
+use openssl::{
+    bn::BigNumRef,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    sign::{Signer, Verifier},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+const ALGORITHM: &str = "RS256";
+const KEY_SIZE: u32 = 2048;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Crypto error: {0}")]
+    Crypto(#[from] openssl::error::ErrorStack),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Invalid token")]
+    InvalidToken,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+/// The key signing the issued tokens.
+#[derive(Clone)]
+pub struct SigningKey {
+    key: PKey<Private>,
+    kid: String,
+}
+
+impl SigningKey {
+    /// Generate a new, random key.
+    pub fn generate() -> Result<Self, KeyError> {
+        Self::from_rsa(Rsa::generate(KEY_SIZE)?)
+    }
+
+    /// Load the key from a PEM encoded file, creating the file if it doesn't exist yet.
+    pub fn load_or_generate(path: &Path) -> Result<Self, KeyError> {
+        if path.exists() {
+            log::info!("Loading signing key: {}", path.display());
+            Self::from_rsa(Rsa::private_key_from_pem(&std::fs::read(path)?)?)
+        } else {
+            log::info!("Creating new signing key: {}", path.display());
+            let rsa = Rsa::generate(KEY_SIZE)?;
+            std::fs::write(path, rsa.private_key_to_pem()?)?;
+            Self::from_rsa(rsa)
+        }
+    }
+
+    fn from_rsa(rsa: Rsa<Private>) -> Result<Self, KeyError> {
+        let kid = encode(&openssl::sha::sha256(&rsa.public_key_to_der()?)[..8]);
+        Ok(Self {
+            key: PKey::from_rsa(rsa)?,
+            kid,
+        })
+    }
+
+    /// The public key, as JSON web key.
+    pub fn jwk(&self) -> Result<Value, KeyError> {
+        let rsa = self.key.rsa()?;
+        Ok(json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": ALGORITHM,
+            "kid": self.kid,
+            "n": encode_bn(rsa.n()),
+            "e": encode_bn(rsa.e()),
+        }))
+    }
+
+    /// Create a signed JWT, carrying the claims.
+    pub fn sign<C: Serialize>(&self, claims: &C) -> Result<String, KeyError> {
+        let header = Header {
+            alg: ALGORITHM.into(),
+            typ: "JWT".into(),
+            kid: self.kid.clone(),
+        };
+
+        let mut token = format!(
+            "{}.{}",
+            encode(&serde_json::to_vec(&header)?),
+            encode(&serde_json::to_vec(claims)?)
+        );
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(token.as_bytes())?;
+        let signature = signer.sign_to_vec()?;
+
+        token.push('.');
+        token.push_str(&encode(&signature));
+
+        Ok(token)
+    }
+
+    /// Verify the signature of a JWT issued by this key, and return its claims.
+    ///
+    /// This only checks the signature, validating the claims is up to the caller.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, KeyError> {
+        let (content, signature) = token.rsplit_once('.').ok_or(KeyError::InvalidToken)?;
+        let (header, claims) = content.split_once('.').ok_or(KeyError::InvalidToken)?;
+
+        let header: Header = serde_json::from_slice(&decode(header)?)?;
+        if header.alg != ALGORITHM || header.kid != self.kid {
+            return Err(KeyError::InvalidToken);
+        }
+
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &self.key)?;
+        verifier.update(content.as_bytes())?;
+        if !verifier.verify(&decode(signature)?)? {
+            return Err(KeyError::InvalidToken);
+        }
+
+        Ok(serde_json::from_slice(&decode(claims)?)?)
+    }
+}
+
+pub(crate) fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode(data: &str) -> Result<Vec<u8>, KeyError> {
+    base64::decode_config(data, base64::URL_SAFE_NO_PAD).map_err(|_| KeyError::InvalidToken)
+}
+
+fn encode_bn(bn: &BigNumRef) -> String {
+    encode(&bn.to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign_verify() {
+        let key = SigningKey::generate().unwrap();
+        let token = key.sign(&json!({"sub": "foo"})).unwrap();
+
+        let claims: Value = key.verify(&token).unwrap();
+        assert_eq!(claims["sub"], "foo");
+
+        // tampering with the claims must be detected
+
+        let mut parts: Vec<_> = token.split('.').map(ToString::to_string).collect();
+        parts[1] = encode(br#"{"sub":"bar"}"#);
+        assert!(key.verify::<Value>(&parts.join(".")).is_err());
+
+        // a different key must not verify
+
+        let other = SigningKey::generate().unwrap();
+        assert!(other.verify::<Value>(&token).is_err());
+    }
+
+    #[test]
+    fn test_jwk() {
+        let key = SigningKey::generate().unwrap();
+        let jwk = key.jwk().unwrap();
+
+        assert_eq!(jwk["kty"], "RSA");
+        assert_eq!(jwk["kid"], key.kid);
+        // 65537
+        assert_eq!(jwk["e"], "AQAB");
+    }
+}
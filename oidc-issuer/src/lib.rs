@@ -0,0 +1,133 @@
+//! A minimal, embedded OpenID Connect issuer.
+//!
+//! This can be used instead of Keycloak, in cases where running Keycloak is not an option. It
+//! manages local users in the database, and supports the authorization code (with PKCE), client
+//! credentials, password, and refresh token grants.
+
+pub mod codes;
+pub mod endpoints;
+pub mod error;
+pub mod issuer;
+pub mod keys;
+pub mod lockout;
+pub mod users;
+
+use crate::{
+    issuer::{ClientConfig, Issuer},
+    keys::SigningKey,
+    users::{LocalUserConfig, PostgresUserStore},
+};
+use drogue_cloud_database_common::postgres;
+use drogue_cloud_service_api::webapp::{web, web::ServiceConfig};
+use drogue_cloud_service_common::{
+    actix::http::{CorsConfig, HttpBuilder, HttpConfig},
+    app::{Startup, StartupExt},
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+use url::Url;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    /// The public URL of the issuer, the endpoints are served relative to it.
+    pub issuer_url: Url,
+
+    pub pg: postgres::Config,
+
+    /// The PEM encoded RSA key to sign tokens with, created if missing.
+    ///
+    /// If there is no key file, a new key will be generated on every start, invalidating all
+    /// previously issued tokens.
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+
+    #[serde(default = "default_access_token_lifetime", with = "humantime_serde")]
+    pub access_token_lifetime: Duration,
+
+    #[serde(default = "default_refresh_token_lifetime", with = "humantime_serde")]
+    pub refresh_token_lifetime: Duration,
+
+    #[serde(default)]
+    pub clients: HashMap<String, ClientConfig>,
+
+    /// Users to create on startup, if they don't exist yet.
+    #[serde(default)]
+    pub users: Vec<LocalUserConfig>,
+
+    #[serde(default)]
+    pub http: HttpConfig,
+}
+
+const fn default_access_token_lifetime() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+const fn default_refresh_token_lifetime() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+pub async fn configurator(
+    config: Config,
+) -> anyhow::Result<(
+    impl Fn(&mut ServiceConfig) + Send + Sync + Clone,
+    PostgresUserStore,
+)> {
+    let key = match &config.key_file {
+        Some(key_file) => SigningKey::load_or_generate(key_file)?,
+        None => SigningKey::generate()?,
+    };
+
+    let users = PostgresUserStore::new(config.pg)?;
+    for user in &config.users {
+        users.provision(user).await?;
+    }
+
+    let issuer = web::Data::new(Issuer::new(
+        &config.issuer_url,
+        key,
+        config.clients,
+        config.access_token_lifetime,
+        config.refresh_token_lifetime,
+        users.clone(),
+    ));
+
+    Ok((
+        move |cfg: &mut ServiceConfig| {
+            cfg.app_data(issuer.clone())
+                .service(
+                    web::resource("/.well-known/openid-configuration")
+                        .route(web::get().to(endpoints::discovery)),
+                )
+                .service(web::resource("/jwks").route(web::get().to(endpoints::jwks)))
+                .service(
+                    web::resource("/auth")
+                        .route(web::get().to(endpoints::authorize))
+                        .route(web::post().to(endpoints::login)),
+                )
+                .service(web::resource("/token").route(web::post().to(endpoints::token)))
+                .service(
+                    web::resource("/userinfo")
+                        .route(web::get().to(endpoints::userinfo))
+                        .route(web::post().to(endpoints::userinfo)),
+                )
+                .service(web::resource("/logout").route(web::get().to(endpoints::logout)));
+        },
+        users,
+    ))
+}
+
+pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
+    log::info!(
+        "Running embedded OpenID Connect issuer: {}",
+        config.issuer_url
+    );
+
+    let (cfg, users) = configurator(config.clone()).await?;
+    HttpBuilder::new(config.http, Some(startup.runtime_config()), cfg)
+        .default_cors(CorsConfig::permissive())
+        .start(startup)?;
+
+    startup.check(users);
+
+    Ok(())
+}
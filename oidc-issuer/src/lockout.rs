@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The number of failed logins, after which a user gets locked out.
+const MAX_FAILURES: u32 = 5;
+/// The time a user stays locked out, and the time after which failed logins are forgotten.
+const LOCKOUT_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Clone, Copy, Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Temporarily locks out users, after too many failed logins.
+///
+/// The state is kept in memory, and so only applies to the current instance.
+#[derive(Clone, Default)]
+pub struct Lockout {
+    failures: Arc<Mutex<HashMap<String, Failures>>>,
+}
+
+impl Lockout {
+    pub fn is_locked(&self, username: &str) -> bool {
+        self.is_locked_at(username, Instant::now())
+    }
+
+    pub fn failed(&self, username: &str) {
+        self.failed_at(username, Instant::now())
+    }
+
+    pub fn succeeded(&self, username: &str) {
+        self.failures.lock().unwrap().remove(username);
+    }
+
+    fn is_locked_at(&self, username: &str, now: Instant) -> bool {
+        match self.failures.lock().unwrap().get(username) {
+            Some(failures) => {
+                failures.count >= MAX_FAILURES && now.duration_since(failures.last) < LOCKOUT_PERIOD
+            }
+            None => false,
+        }
+    }
+
+    fn failed_at(&self, username: &str, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+
+        failures.retain(|_, failures| now.duration_since(failures.last) < LOCKOUT_PERIOD);
+
+        let entry = failures.entry(username.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+        });
+        entry.count += 1;
+        entry.last = now;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lockout() {
+        let lockout = Lockout::default();
+        let now = Instant::now();
+
+        for _ in 0..MAX_FAILURES - 1 {
+            lockout.failed_at("foo", now);
+        }
+        assert!(!lockout.is_locked_at("foo", now));
+
+        lockout.failed_at("foo", now);
+        assert!(lockout.is_locked_at("foo", now));
+        assert!(!lockout.is_locked_at("bar", now));
+        assert!(!lockout.is_locked_at("foo", now + LOCKOUT_PERIOD));
+
+        lockout.succeeded("foo");
+        assert!(!lockout.is_locked_at("foo", now));
+    }
+}
@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{error::ServiceError, postgres, DatabaseService};
+use drogue_cloud_service_api::health::{HealthCheckError, HealthChecked};
+use serde::Deserialize;
+
+/// A user, managed by the embedded issuer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalUser {
+    pub username: String,
+    pub roles: Vec<String>,
+}
+
+/// A user to provision when starting up.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LocalUserConfig {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct PostgresUserStore {
+    pool: Pool,
+}
+
+impl PostgresUserStore {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+
+    /// Create the user, unless it already exists.
+    ///
+    /// An existing user will keep its password and roles.
+    pub async fn provision(&self, user: &LocalUserConfig) -> Result<(), ServiceError> {
+        let hash = bcrypt::hash(&user.password, bcrypt::DEFAULT_COST)
+            .map_err(|err| ServiceError::Internal(format!("Failed to hash password: {err}")))?;
+
+        let c = self.pool.get().await?;
+        c.execute(
+            r#"
+INSERT INTO local_users (
+    USERNAME, PASSWORD_HASH, ROLES, CREATED
+) VALUES (
+    $1, $2, $3, $4
+)
+ON CONFLICT (USERNAME) DO NOTHING
+"#,
+            &[&user.username, &hash, &user.roles, &Utc::now()],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Look up a user.
+    pub async fn get(&self, username: &str) -> Result<Option<LocalUser>, ServiceError> {
+        let c = self.pool.get().await?;
+        let row = c
+            .query_opt(
+                "SELECT USERNAME, ROLES FROM local_users WHERE USERNAME = $1",
+                &[&username],
+            )
+            .await?;
+
+        Ok(row.map(|row| LocalUser {
+            username: row.get("USERNAME"),
+            roles: row.get("ROLES"),
+        }))
+    }
+
+    /// Look up a user, verifying its password.
+    pub async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<LocalUser>, ServiceError> {
+        let c = self.pool.get().await?;
+        let row = c
+            .query_opt(
+                "SELECT USERNAME, PASSWORD_HASH, ROLES FROM local_users WHERE USERNAME = $1",
+                &[&username],
+            )
+            .await?;
+
+        Ok(row.and_then(|row| {
+            let hash: String = row.get("PASSWORD_HASH");
+            match bcrypt::verify(password, &hash).unwrap_or(false) {
+                true => Some(LocalUser {
+                    username: row.get("USERNAME"),
+                    roles: row.get("ROLES"),
+                }),
+                false => None,
+            }
+        }))
+    }
+}
+
+impl DatabaseService for PostgresUserStore {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+#[async_trait]
+impl HealthChecked for PostgresUserStore {
+    async fn is_ready(&self) -> Result<(), HealthCheckError> {
+        Ok(DatabaseService::is_ready(self)
+            .await
+            .map_err(HealthCheckError::from)?)
+    }
+}
@@ -5,6 +5,7 @@ use cloudevents::{
     event::ExtensionValue,
     AttributesReader,
 };
+use drogue_cloud_event_common::bus::{self, BusError, EventBus};
 use drogue_cloud_service_api::kafka::KafkaConfig;
 use rdkafka::{
     error::KafkaError,
@@ -30,20 +31,35 @@ pub struct KafkaSenderConfig {
 pub enum KafkaSenderError {
     #[error("Kafka error")]
     Kafka(#[from] KafkaError),
+    #[error("Event bus error")]
+    Bus(#[from] BusError),
     #[error("Transmission canceled")]
     Canceled,
 }
 
+#[derive(Clone)]
+enum Producer {
+    Kafka(FutureProducer),
+    /// The in-process event bus, selected by the bootstrap servers.
+    Bus(EventBus),
+}
+
 #[derive(Clone)]
 pub struct KafkaEventSender {
-    producer: FutureProducer,
+    producer: Producer,
     topic: String,
     queue_timeout: Timeout,
 }
 
 impl KafkaEventSender {
     pub fn new(config: KafkaSenderConfig) -> anyhow::Result<Self> {
-        let client_config: ClientConfig = config.client.client.into();
+        let producer = match bus::from_config(&config.client.client)? {
+            Some(bus) => Producer::Bus(bus),
+            None => {
+                let client_config: ClientConfig = config.client.client.into();
+                Producer::Kafka(client_config.create()?)
+            }
+        };
 
         let queue_timeout = match config.queue_timeout {
             Some(duration) => Timeout::After(duration),
@@ -51,7 +67,7 @@ impl KafkaEventSender {
         };
 
         Ok(Self {
-            producer: client_config.create()?,
+            producer,
             topic: config.client.topic,
             queue_timeout,
         })
@@ -77,6 +93,15 @@ impl EventSender for KafkaEventSender {
         for event in events.into_iter() {
             let event: cloudevents::Event = event.try_into().map_err(EventSenderError::Event)?;
 
+            let producer = match &self.producer {
+                Producer::Kafka(producer) => producer,
+                Producer::Bus(bus) => {
+                    bus.publish(&self.topic, event)
+                        .map_err(KafkaSenderError::Bus)?;
+                    continue;
+                }
+            };
+
             let key = match event.extension(EXT_PARTITIONKEY) {
                 Some(ExtensionValue::String(key)) => key,
                 _ => event.id(),
@@ -92,7 +117,7 @@ impl EventSender for KafkaEventSender {
                 .key(&key)
                 .message_record(&message_record);
 
-            self.producer
+            producer
                 .send(record, self.queue_timeout)
                 .await
                 .map_err(|(err, _)| KafkaSenderError::Kafka(err))?;
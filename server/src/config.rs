@@ -1,4 +1,5 @@
 use clap::ArgMatches;
+use drogue_cloud_event_common::bus;
 use drogue_cloud_service_api::{
     endpoints::*,
     kafka::{KafkaClientConfig, KafkaConfig},
@@ -35,9 +36,11 @@ pub struct ServerConfig {
     pub device_auth: Endpoint,
     pub user_auth: Endpoint,
     pub device_state: Endpoint,
+    pub oidc: Endpoint,
     pub database: Database,
     pub drogue: Drogue,
     pub keycloak: Keycloak,
+    pub embedded_oidc: bool,
     pub kafka: KafkaClientConfig,
     pub tls_insecure: bool,
     pub tls_ca_certificates: Vec<String>,
@@ -78,13 +81,10 @@ impl ServerConfig {
 
         ServerConfig {
             tls_insecure: matches.get_flag("insecure"),
+            embedded_oidc: matches.get_flag("embedded-oidc"),
             tls_ca_certificates: vec![],
             kafka: KafkaClientConfig {
-                bootstrap_servers: matches
-                    .get_one::<String>("kafka-bootstrap-servers")
-                    .map(|s| s.as_str())
-                    .unwrap_or("localhost:9092")
-                    .to_string(),
+                bootstrap_servers: bootstrap_servers(matches),
                 properties: HashMap::new(),
             },
             database: Database {
@@ -210,13 +210,32 @@ impl ServerConfig {
                 port: 10006,
             },
             device_state: Endpoint {
-                host: iface,
+                host: iface.to_string(),
                 port: 10007,
             },
+            oidc: Endpoint {
+                host: iface,
+                port: 10008,
+            },
         }
     }
 }
 
+/// Evaluate the bootstrap servers, which may select the in-process event bus instead of Kafka.
+fn bootstrap_servers(matches: &ArgMatches) -> String {
+    if let Some(dir) = matches.get_one::<String>("event-bus-dir") {
+        format!("{}{}", bus::FILE_SCHEME, dir)
+    } else if matches.get_flag("event-bus") {
+        bus::MEMORY_SCHEME.to_string()
+    } else {
+        matches
+            .get_one::<String>("kafka-bootstrap-servers")
+            .map(|s| s.as_str())
+            .unwrap_or("localhost:9092")
+            .to_string()
+    }
+}
+
 pub fn endpoints(config: &ServerConfig, tls: bool) -> Endpoints {
     let http_prefix = if tls { "https" } else { "http" };
     let ws_prefix = if tls { "wss" } else { "ws" };
@@ -276,10 +295,10 @@ pub fn endpoints(config: &ServerConfig, tls: bool) -> Endpoints {
                 ws_prefix, config.websocket_integration.host, config.websocket_integration.port
             ),
         }),
-        issuer_url: Some(format!(
-            "{}/realms/{}",
-            config.keycloak.url, config.keycloak.realm
-        )),
+        issuer_url: Some(match config.embedded_oidc {
+            true => format!("http://{}:{}", config.oidc.host, config.oidc.port),
+            false => format!("{}/realms/{}", config.keycloak.url, config.keycloak.realm),
+        }),
         redirect_url: Some(format!(
             "http://{}:{}",
             config.frontend.host, config.frontend.port
@@ -287,7 +306,11 @@ pub fn endpoints(config: &ServerConfig, tls: bool) -> Endpoints {
         registry: Some(RegistryEndpoint { url: api.clone() }),
         command_url: Some(api),
         local_certs: false,
-        kafka_bootstrap_servers: Some(config.kafka.bootstrap_servers.clone()),
+        kafka_bootstrap_servers: match bus::Backend::from_config(&config.kafka) {
+            bus::Backend::Kafka => Some(config.kafka.bootstrap_servers.clone()),
+            // there is no Kafka cluster to connect to
+            bus::Backend::Bus(_) => None,
+        },
     }
 }
 
@@ -2,8 +2,9 @@ mod config;
 mod db;
 mod kafka;
 mod keycloak;
+mod oidc;
 
-use crate::{config::*, keycloak::*};
+use crate::{config::*, keycloak::*, oidc::*};
 use anyhow::anyhow;
 use clap::{crate_version, value_parser, Arg, ArgAction, ArgMatches, Command};
 use drogue_cloud_authentication_service::service::AuthenticationServiceConfig;
@@ -11,9 +12,13 @@ use drogue_cloud_database_common::postgres;
 use drogue_cloud_device_management_service::service::PostgresManagementServiceConfig;
 use drogue_cloud_device_state_service::service::postgres::PostgresServiceConfiguration;
 use drogue_cloud_endpoint_common::{auth::AuthConfig, command::KafkaCommandSourceConfig};
+use drogue_cloud_event_common::bus::Backend;
 use drogue_cloud_mqtt_common::server::{MqttServerOptions, Transport};
 use drogue_cloud_registry_events::sender::KafkaSenderConfig; //, stream::KafkaStreamConfig};
-use drogue_cloud_service_api::{kafka::KafkaClientConfig, webapp::HttpServer};
+use drogue_cloud_service_api::{
+    kafka::{KafkaClientConfig, EVENTS_TOPIC_PATTERN},
+    webapp::HttpServer,
+};
 use drogue_cloud_service_common::{
     actix::http::{CorsConfig, HttpBuilder, HttpConfig},
     app::{Main, Startup, StartupExt, SubMain},
@@ -22,7 +27,7 @@ use drogue_cloud_service_common::{
     },
     client::{ClientConfig, DeviceStateClientConfig},
     keycloak::{client::KeycloakAdminClient, KeycloakAdminClientConfig},
-    state::StateControllerConfiguration,
+    state::{PresenceConfiguration, StateControllerConfiguration},
 };
 use drogue_cloud_user_auth_service::service::AuthorizationServiceConfig;
 use futures::TryFutureExt;
@@ -34,7 +39,7 @@ fn args() -> Command {
     Command::new("Drogue Cloud Server")
         .about("Running Drogue Cloud in a single process")
         .version(crate_version!())
-        .long_about("Drogue Server runs all the Drogue Cloud services in a single process, with an external dependency on PostgreSQL, Kafka and Keycloak for storing data, device management and user management")
+        .long_about("Drogue Server runs all the Drogue Cloud services in a single process, with an external dependency on PostgreSQL, Kafka and Keycloak for storing data, device management and user management. Kafka can be replaced with an in-process event bus, and Keycloak with an embedded OpenID Connect issuer.")
         .arg(
             Arg::new("verbose")
                 .global(true)
@@ -149,6 +154,18 @@ fn args() -> Command {
                         .action(ArgAction::SetTrue)
                         .help("enable websocket integration"),
                 )
+                .arg(
+                    Arg::new("enable-webhook-integration")
+                        .long("enable-webhook-integration")
+                        .action(ArgAction::SetTrue)
+                        .help("enable webhook integration"),
+                )
+                .arg(
+                    Arg::new("enable-timeseries-integration")
+                        .long("enable-timeseries-integration")
+                        .action(ArgAction::SetTrue)
+                        .help("enable time-series integration"),
+                )
                 .arg(
                     Arg::new("enable-command-endpoint")
                         .long("enable-command-endpoint")
@@ -222,6 +239,20 @@ fn args() -> Command {
                         .value_name("PASSWORD")
                         .help("Keycloak realm admin password"),
                 )
+                .arg(
+                    Arg::new("embedded-oidc")
+                        .long("embedded-oidc")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["keycloak-url", "keycloak-realm", "keycloak-user", "keycloak-password"])
+                        .help("Use the embedded OpenID Connect issuer instead of Keycloak"),
+                )
+                .arg(
+                    Arg::new("oidc-key-file")
+                        .long("oidc-key-file")
+                        .value_name("FILE")
+                        .requires("embedded-oidc")
+                        .help("private key for signing tokens of the embedded issuer, created if missing"),
+                )
                 .arg(
                     Arg::new("drogue-admin-user")
                         .long("drogue-admin-user")
@@ -240,6 +271,20 @@ fn args() -> Command {
                         .value_name("HOSTS")
                         .help("Kafka bootstrap servers"),
                 )
+                .arg(
+                    Arg::new("event-bus")
+                        .long("event-bus")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("kafka-bootstrap-servers")
+                        .help("Use the in-process event bus instead of Kafka"),
+                )
+                .arg(
+                    Arg::new("event-bus-dir")
+                        .long("event-bus-dir")
+                        .value_name("DIR")
+                        .conflicts_with("kafka-bootstrap-servers")
+                        .help("Use the in-process event bus, storing events in the directory"),
+                )
                 .arg(
                     Arg::new("ui-dist")
                         .long("ui-dist")
@@ -286,7 +331,9 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
 
     db::run_migrations(&server.database).await.unwrap();
 
-    configure_keycloak(&server).await.unwrap();
+    if !server.embedded_oidc {
+        configure_keycloak(&server).await.unwrap();
+    }
     /*
     let kafka_stream = |topic: &str, consumer_group: &str| KafkaStreamConfig {
         client: kafka_config(topic),
@@ -308,7 +355,9 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
         consumer_group: consumer_group.to_string(),
     };
 
-    kafka::create_topics(server.kafka.clone(), ["iot-commands"]).await?;
+    if Backend::from_config(&server.kafka) == Backend::Kafka {
+        kafka::create_topics(server.kafka.clone(), ["iot-commands"]).await?;
+    }
 
     let token_config = TokenConfig {
         client_id: "services".to_string(),
@@ -356,13 +405,16 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
         },
     );
 
-    let keycloak = KeycloakAdminClientConfig {
-        url: Url::parse(&server.keycloak.url)?,
-        realm: server.keycloak.realm.clone(),
-        admin_username: server.keycloak.user.clone(),
-        admin_password: server.keycloak.password.clone(),
-        tls_insecure: server.tls_insecure,
-        tls_ca_certificates: server.tls_ca_certificates.clone().into(),
+    let keycloak = match server.embedded_oidc {
+        true => None,
+        false => Some(KeycloakAdminClientConfig {
+            url: Url::parse(&server.keycloak.url)?,
+            realm: server.keycloak.realm.clone(),
+            admin_username: server.keycloak.user.clone(),
+            admin_password: server.keycloak.password.clone(),
+            tls_insecure: server.tls_insecure,
+            tls_ca_certificates: server.tls_ca_certificates.clone().into(),
+        }),
     };
 
     let registry = ClientConfig {
@@ -384,6 +436,12 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
         tls: Default::default(),
     };
 
+    if server.embedded_oidc {
+        let key_file = matches.get_one::<String>("oidc-key-file").map(Into::into);
+        let config = oidc_issuer_config(&server, pg.clone(), key_file)?;
+        start_oidc_issuer(config, &mut main).await?;
+    }
+
     let authurl: String = server.device_auth.clone().into();
     let auth = AuthConfig {
         auth_disabled: false,
@@ -438,6 +496,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             oauth: oauth.clone(),
             service: PostgresServiceConfiguration {
                 session_timeout: Duration::from_secs(10),
+                presence_timeout: Duration::from_secs(5 * 60),
                 pg: pg.clone(),
             },
             instance: "drogue".to_string(),
@@ -445,6 +504,10 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             kafka_downstream_config: kafka,
             endpoint_pool: Default::default(),
             registry: registry.clone(),
+            user_auth: user_auth.clone(),
+            last_values: Some(Default::default()),
+            history: Default::default(),
+            command_queue: Some(Default::default()),
         };
 
         drogue_cloud_device_state_service::run(config, &mut main).await?;
@@ -497,8 +560,10 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                 kafka: server.kafka.clone(),
                 keycloak: keycloak.clone(),
                 registry: registry.clone(),
+                pg: Some(pg.clone()),
                 console_token_config: Some(console_token_config),
-                disable_account_url: false,
+                // the account console is a Keycloak feature
+                disable_account_url: server.embedded_oidc,
                 scopes: "openid profile email".into(),
                 user_auth: user_auth.clone(),
             }
@@ -532,6 +597,12 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             }
         };
 
+        let config_device_state = drogue_cloud_device_state_service::UserApiConfig {
+            pg: pg.clone(),
+            oauth: oauth.clone(),
+            user_auth: user_auth.clone(),
+        };
+
         let http = HttpConfig {
             bind_addr: server.console.clone().into(),
             disable_tls: true,
@@ -554,10 +625,16 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             .await
             .unwrap();
 
+        let (device_state, _) =
+            drogue_cloud_device_state_service::user_api_configurator(config_device_state)
+                .await
+                .unwrap();
+
         HttpBuilder::new(http, Some(main.runtime_config()), move |cfg| {
             console_backend(cfg);
             registry(cfg);
             command(cfg);
+            device_state(cfg);
         })
         .default_cors(CorsConfig::permissive())
         .start(&mut main)?;
@@ -588,6 +665,14 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             kafka_command_config: kafka,
             check_kafka_topic_ready: false,
             endpoint_pool: Default::default(),
+            registry: Some(registry.clone()),
+            presence: Some(PresenceConfiguration {
+                client: state.client.clone(),
+                endpoint: "http".to_string(),
+                ..Default::default()
+            }),
+            quota: Default::default(),
+            batch: Default::default(),
         };
 
         drogue_cloud_http_endpoint::run(config, &mut main).await?;
@@ -615,6 +700,11 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             registry: registry.clone(),
             kafka,
             user_auth,
+            command_kafka_sink: None,
+            check_kafka_topic_ready: false,
+            instance: "drogue".to_string(),
+            endpoint_pool: Default::default(),
+            max_in_flight: 100,
         };
 
         // The websocket integration uses the actix actors, so for now, that must run
@@ -645,6 +735,42 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
         });
     }
 
+    if matches.get_flag("enable-webhook-integration") || matches.get_flag("enable-all") {
+        log::info!("Enabling webhook integration");
+        let config = drogue_cloud_webhook_integration::Config {
+            kafka: server.kafka.clone(),
+            registry: registry.clone(),
+            topic: EVENTS_TOPIC_PATTERN.to_string(),
+            consumer_group: "webhook-integration".to_string(),
+            dead_letter_kafka_sink: None,
+            check_kafka_topic_ready: false,
+            endpoint_pool: Default::default(),
+            retry: Default::default(),
+            overflow: Default::default(),
+            max_in_flight: 1000,
+            application_cache_duration: Duration::from_secs(30),
+        };
+
+        drogue_cloud_webhook_integration::run(config, &mut main).await?;
+    }
+
+    if matches.get_flag("enable-timeseries-integration") || matches.get_flag("enable-all") {
+        log::info!("Enabling time-series integration");
+        let config = drogue_cloud_timeseries_integration::Config {
+            kafka: server.kafka.clone(),
+            registry: registry.clone(),
+            topic: EVENTS_TOPIC_PATTERN.to_string(),
+            consumer_group: "timeseries-integration".to_string(),
+            timescale: Some(pg.clone()),
+            influx: None,
+            retry: Default::default(),
+            batch: Default::default(),
+            application_cache_duration: Duration::from_secs(30),
+        };
+
+        drogue_cloud_timeseries_integration::run(config, &mut main).await?;
+    }
+
     // ntex related tasks
     {
         let oauth = oauth.clone();
@@ -684,6 +810,8 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                 kafka_command_config: kafka,
                 check_kafka_topic_ready: false,
                 endpoint_pool: Default::default(),
+                registry: Some(registry.clone()),
+                quota: Default::default(),
                 state: state.clone(),
             };
 
@@ -731,6 +859,7 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
                 instance: "drogue".to_string(),
                 command_kafka_sink: kafka,
                 endpoint_pool: Default::default(),
+                device_state: Some(state.client.clone()),
             };
 
             // tasks.push(Box::pin(drogue_cloud_mqtt_integration::run(config.clone())));
@@ -794,6 +923,14 @@ async fn cmd_run(matches: &ArgMatches) -> anyhow::Result<()> {
             kafka_command_config: kafka,
             check_kafka_topic_ready: false,
             endpoint_pool: Default::default(),
+            registry: Some(registry.clone()),
+            presence: Some(PresenceConfiguration {
+                client: state.client.clone(),
+                endpoint: "coap".to_string(),
+                ..Default::default()
+            }),
+            quota: Default::default(),
+            batch: Default::default(),
             disable_dtls: !(key_file.is_some() && cert_bundle_file.is_some()),
             disable_client_certificates: false,
             disable_psk: false,
@@ -911,9 +1048,19 @@ async fn run(ctx: Context<'_>, server: ServerConfig, mut main: Main<'_>) -> anyh
     );
     println!();
 
-    println!("Keycloak Credentials:");
-    println!("\tUser:\t\t {}", server.keycloak.user);
-    println!("\tPassword:\t {}", server.keycloak.password);
+    if server.embedded_oidc {
+        println!("OpenID Connect issuer:");
+        println!(
+            "\tURL:\t\t http://{}:{}",
+            server.oidc.host, server.oidc.port
+        );
+        println!("\tUser:\t\t {}", server.drogue.admin_user);
+        println!("\tPassword:\t {}", server.drogue.admin_password);
+    } else {
+        println!("Keycloak Credentials:");
+        println!("\tUser:\t\t {}", server.keycloak.user);
+        println!("\tPassword:\t {}", server.keycloak.password);
+    }
     println!();
 
     println!("Logging in:");
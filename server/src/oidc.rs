@@ -0,0 +1,116 @@
+use crate::{config::ServerConfig, keycloak::SERVICE_CLIENT_SECRET};
+use drogue_cloud_database_common::postgres;
+use drogue_cloud_oidc_issuer::{issuer::ClientConfig, users::LocalUserConfig, Config};
+use drogue_cloud_service_common::{
+    actix::http::HttpConfig,
+    app::{Main, Startup, SubMain},
+};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+use tokio::runtime::Handle;
+use url::Url;
+
+/// The roles every user gets, in the same way the Keycloak realm is set up.
+const DEFAULT_ROLES: [&str; 2] = ["drogue-admin", "drogue-user"];
+
+pub fn oidc_issuer_config(
+    config: &ServerConfig,
+    pg: postgres::Config,
+    key_file: Option<PathBuf>,
+) -> anyhow::Result<Config> {
+    let roles: Vec<String> = DEFAULT_ROLES.iter().map(ToString::to_string).collect();
+
+    let mut clients = HashMap::new();
+    // used by the console, and by `drg login`, which listens on a local port
+    clients.insert(
+        "drogue".to_string(),
+        ClientConfig {
+            secret: None,
+            redirect_uris: vec![format!(
+                "http://{}:{}/*",
+                config.frontend.host, config.frontend.port
+            )],
+            loopback_redirect: true,
+            require_pkce: true,
+            ..Default::default()
+        },
+    );
+    clients.insert(
+        "services".to_string(),
+        ClientConfig {
+            secret: Some(SERVICE_CLIENT_SECRET.to_string()),
+            service_account: true,
+            service_account_roles: roles.clone(),
+            ..Default::default()
+        },
+    );
+
+    Ok(Config {
+        issuer_url: Url::parse(&format!("http://{}:{}", config.oidc.host, config.oidc.port))?,
+        pg,
+        key_file,
+        access_token_lifetime: Duration::from_secs(5 * 60),
+        refresh_token_lifetime: Duration::from_secs(30 * 60),
+        clients,
+        users: vec![LocalUserConfig {
+            username: config.drogue.admin_user.clone(),
+            password: config.drogue.admin_password.clone(),
+            roles,
+        }],
+        http: HttpConfig {
+            bind_addr: config.oidc.clone().into(),
+            disable_tls: true,
+            workers: Some(1),
+            metrics_namespace: Some("oidc_issuer".into()),
+            ..Default::default()
+        },
+    })
+}
+
+/// Start the embedded issuer, and wait until it serves its discovery document.
+///
+/// Other services discover the issuer while starting up, so it must be running before them.
+pub async fn start_oidc_issuer(config: Config, main: &mut Main<'_>) -> anyhow::Result<()> {
+    print!("Starting OpenID Connect issuer... ");
+
+    let discovery = config.issuer_url.join(".well-known/openid-configuration")?;
+
+    let sub_main = main.sub_main_seed();
+    let issuer = Handle::current().spawn_blocking(move || {
+        let runner = actix_rt::System::with_tokio_rt(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .thread_name("oidc-issuer")
+                .build()
+                .unwrap()
+        });
+
+        runner.block_on(async move {
+            let mut sub_main: SubMain = sub_main.into();
+            drogue_cloud_oidc_issuer::run(config, &mut sub_main).await?;
+            sub_main.run().await
+        })
+    });
+
+    let client = reqwest::Client::new();
+    let mut attempts = 0;
+    loop {
+        match client.get(discovery.clone()).send().await {
+            Ok(response) if response.status().is_success() => break,
+            _ if issuer.is_finished() => {
+                issuer.await??;
+                anyhow::bail!("OpenID Connect issuer stopped unexpectedly");
+            }
+            _ if attempts >= 50 => anyhow::bail!("Timeout waiting for OpenID Connect issuer"),
+            _ => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }
+    }
+
+    main.spawn(async move { issuer.await? });
+
+    println!("done!");
+
+    Ok(())
+}
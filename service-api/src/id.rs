@@ -6,3 +6,10 @@ pub const EXT_SENDER: &str = "sender";
 pub const EXT_APPLICATION_UID: &str = "applicationuid";
 pub const EXT_DEVICE_UID: &str = "deviceuid";
 pub const EXT_SENDER_UID: &str = "senderuid";
+
+/// Correlates a command with the response of the device.
+pub const EXT_CORRELATION_ID: &str = "correlationid";
+/// The channel the response to a command or event should be sent to.
+pub const EXT_RESPONSE_CHANNEL: &str = "responsechannel";
+/// The time after which an event should no longer be delivered, as RFC 3339 timestamp.
+pub const EXT_EXPIRES: &str = "expires";
@@ -1,6 +1,8 @@
 mod config;
+mod spec;
 
 pub use self::config::*;
+pub use self::spec::*;
 use std::convert::Infallible;
 
 use drogue_client::registry;
@@ -10,6 +12,7 @@ use regex::Regex;
 #[derive(Clone, Debug)]
 pub enum ResourceType<'a> {
     Events(&'a str),
+    DeadLetters(&'a str),
     Commands(&'a str),
     Users(&'a str),
     Passwords(&'a str),
@@ -20,6 +23,7 @@ impl<'a> ResourceType<'a> {
         match self {
             Self::Commands(app) => app,
             Self::Events(app) => app,
+            Self::DeadLetters(app) => app,
             Self::Users(app) => app,
             Self::Passwords(app) => app,
         }
@@ -30,6 +34,8 @@ impl<'a> ResourceType<'a> {
 pub enum KafkaEventType {
     Commands,
     Events,
+    /// Events which were rejected, or failed to be processed.
+    DeadLetters,
 }
 
 impl KafkaEventType {
@@ -37,6 +43,7 @@ impl KafkaEventType {
         make_kafka_resource_name(match self {
             Self::Commands => ResourceType::Commands(name),
             Self::Events => ResourceType::Events(name),
+            Self::DeadLetters => ResourceType::DeadLetters(name),
         })
     }
 }
@@ -110,9 +117,16 @@ fn resource_name(prefix: &str, hashed_prefix: &str, resource: &str) -> String {
     }
 }
 
+/// A topic pattern, matching the event topics of all applications.
+///
+/// This includes the hashed names of [`make_kafka_resource_name`], used for applications whose
+/// names can't be used as topic names directly.
+pub const EVENTS_TOPIC_PATTERN: &str = "^(events|evt)-.*";
+
 pub fn make_kafka_resource_name(target: ResourceType) -> String {
     let name = match target {
         ResourceType::Events(app) => resource_name("events", "evt", app),
+        ResourceType::DeadLetters(app) => resource_name("dead-letters", "dlt", app),
         ResourceType::Users(app) => resource_name("user", "usr", app),
         ResourceType::Passwords(app) => resource_name("password", "pwd", app),
         ResourceType::Commands(_) => return "iot-commands".to_string(),
@@ -150,4 +164,27 @@ mod test {
             assert_eq!(i.1, make_kafka_resource_name(ResourceType::Events(i.0)))
         }
     }
+
+    #[test]
+    fn events_topic_pattern() {
+        let pattern = Regex::new(EVENTS_TOPIC_PATTERN).unwrap();
+        for app in ["foo", "FOO", "foo-", &"0123456789".repeat(7)] {
+            assert!(pattern.is_match(&make_kafka_resource_name(ResourceType::Events(app))));
+            assert!(!pattern.is_match(&make_kafka_resource_name(ResourceType::DeadLetters(app))));
+        }
+        assert!(!pattern.is_match("iot-commands"));
+    }
+
+    #[test]
+    fn dead_letter_topic_names() {
+        for i in [
+            ("foo", "dead-letters-foo"),
+            ("FOO", "dlt-901890a8e9c8cf6d5a1a542b229febff-foo"),
+        ] {
+            assert_eq!(
+                i.1,
+                make_kafka_resource_name(ResourceType::DeadLetters(i.0))
+            )
+        }
+    }
 }
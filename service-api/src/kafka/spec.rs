@@ -0,0 +1,198 @@
+use drogue_client::{dialect, Section};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, num::NonZeroU32, time::Duration};
+
+/// Settings of the Kafka topic of an application, part of the application spec.
+///
+/// Settings which are not present fall back to the defaults of the operator creating the topic.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KafkaTopicSpec {
+    /// The number of partitions.
+    ///
+    /// The number of partitions of an existing topic can only be increased.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partitions: Option<NonZeroU32>,
+    /// The number of replicas.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<NonZeroU32>,
+    /// The time events are retained.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "humantime_serde"
+    )]
+    pub retention: Option<Duration>,
+    /// Compact the topic, keeping only the latest event per partition key.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compaction: bool,
+    /// Provide a dead-letter topic, receiving events which were rejected or failed to be
+    /// processed.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dead_letters: bool,
+}
+
+dialect!(KafkaTopicSpec[Section::Spec => "topic"]);
+
+impl KafkaTopicSpec {
+    /// The topic configuration properties, using the Kafka names.
+    pub fn properties(&self) -> BTreeMap<String, String> {
+        let mut properties = BTreeMap::new();
+
+        if let Some(retention) = self.retention {
+            properties.insert("retention.ms".into(), retention.as_millis().to_string());
+        }
+
+        if self.compaction {
+            properties.insert(
+                "cleanup.policy".into(),
+                match self.retention {
+                    // compacted, but still expire old events
+                    Some(_) => "compact,delete",
+                    None => "compact",
+                }
+                .into(),
+            );
+        }
+
+        properties
+    }
+
+    /// The settings of the dead-letter topic, if enabled.
+    ///
+    /// The dead-letter topic uses the same replication and retention, but is never compacted, as
+    /// events might share the same key.
+    pub fn dead_letters(&self) -> Option<Self> {
+        self.dead_letters.then(|| Self {
+            partitions: None,
+            replicas: self.replicas,
+            retention: self.retention,
+            compaction: false,
+            dead_letters: false,
+        })
+    }
+}
+
+/// Settings for publishing events of an application, part of the application spec.
+///
+/// This uses its own section, as the `publish` section holds the rules of the application.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsSpec {
+    #[serde(default)]
+    pub partition_key: PartitionKey,
+    /// Normalize SenML payloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub senml: Option<SenmlNormalization>,
+    /// Convert CBOR events to JSON, and JSON commands to CBOR.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub convert_cbor: bool,
+}
+
+dialect!(EventsSpec[Section::Spec => "events"]);
+
+/// The information used to partition events.
+///
+/// Events with the same key end up in the same partition, and so keep their order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PartitionKey {
+    /// The device which sent the event. For events sent on behalf of another device, this is the
+    /// gateway.
+    #[default]
+    Sender,
+    /// The device the event originated from.
+    Device,
+    /// The device the event originated from, and the channel.
+    Channel,
+    /// The value of a cloud events extension, falling back to the sender if it is missing.
+    Extension(String),
+}
+
+/// The normalization of SenML (JSON or CBOR) payloads.
+///
+/// Records are resolved, applying base values, and using absolute times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SenmlNormalization {
+    /// Publish the resolved pack as a single JSON event.
+    Pack,
+    /// Publish each resolved record as its own JSON event.
+    Records,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_topic_properties() {
+        let spec: KafkaTopicSpec = serde_json::from_value(json!({
+            "partitions": 6,
+            "retention": "7d",
+            "compaction": true,
+        }))
+        .unwrap();
+
+        assert_eq!(spec.partitions, NonZeroU32::new(6));
+        assert_eq!(spec.replicas, None);
+        assert_eq!(
+            spec.properties().into_iter().collect::<Vec<_>>(),
+            vec![
+                ("cleanup.policy".to_string(), "compact,delete".to_string()),
+                ("retention.ms".to_string(), "604800000".to_string()),
+            ]
+        );
+
+        assert!(KafkaTopicSpec::default().properties().is_empty());
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        let spec: KafkaTopicSpec = serde_json::from_value(json!({
+            "partitions": 6,
+            "retention": "7d",
+            "compaction": true,
+            "deadLetters": true,
+        }))
+        .unwrap();
+
+        let dead_letters = spec.dead_letters().unwrap();
+        assert_eq!(dead_letters.partitions, None);
+        assert_eq!(dead_letters.retention, spec.retention);
+        assert!(!dead_letters.compaction);
+
+        assert_eq!(KafkaTopicSpec::default().dead_letters(), None);
+    }
+
+    #[test]
+    fn test_partition_key() {
+        let spec: EventsSpec = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(spec.partition_key, PartitionKey::Sender);
+
+        let spec: EventsSpec = serde_json::from_value(json!({"partitionKey": "channel"})).unwrap();
+        assert_eq!(spec.partition_key, PartitionKey::Channel);
+
+        let spec: EventsSpec =
+            serde_json::from_value(json!({"partitionKey": {"extension": "region"}})).unwrap();
+        assert_eq!(spec.partition_key, PartitionKey::Extension("region".into()));
+        assert_eq!(spec.senml, None);
+
+        let spec: EventsSpec = serde_json::from_value(json!({"senml": "records"})).unwrap();
+        assert_eq!(spec.senml, Some(SenmlNormalization::Records));
+        assert!(!spec.convert_cbor);
+
+        let spec: EventsSpec = serde_json::from_value(json!({"convertCbor": true})).unwrap();
+        assert!(spec.convert_cbor);
+    }
+
+    #[test]
+    fn test_sections() {
+        use drogue_client::{registry::v1, Dialect};
+
+        // must not share a section with the publishing rules
+        assert_ne!(EventsSpec::key(), v1::PublishSpec::key());
+        assert_ne!(EventsSpec::key(), v1::CommandSpec::key());
+    }
+}
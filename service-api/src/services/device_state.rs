@@ -1,6 +1,8 @@
 use crate::serde::is_default;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
 
 pub const CONNECTION_TYPE_EVENT: &str = "io.drogue.connection.v1";
 
@@ -87,3 +89,133 @@ pub struct DeleteOptions {
     #[serde(default, skip_serializing_if = "is_default")]
     pub skip_lwt: bool,
 }
+
+/// Refresh the presence of a device, which is not connected through a session.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceRequest {
+    pub device_uid: String,
+    pub endpoint: String,
+}
+
+/// The reason a connection ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DisconnectReason {
+    /// The endpoint reported the device as disconnected.
+    Disconnected,
+    /// The session of the endpoint expired, the device was considered disconnected.
+    SessionExpired,
+    /// The device was not seen for longer than the presence timeout.
+    Inactive,
+}
+
+impl DisconnectReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Disconnected => "disconnected",
+            Self::SessionExpired => "sessionExpired",
+            Self::Inactive => "inactive",
+        }
+    }
+}
+
+/// A value which doesn't represent a [`DisconnectReason`].
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("Unknown disconnect reason: {0}")]
+pub struct UnknownDisconnectReason(pub String);
+
+impl FromStr for DisconnectReason {
+    type Err = UnknownDisconnectReason;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "disconnected" => Ok(Self::Disconnected),
+            "sessionExpired" => Ok(Self::SessionExpired),
+            "inactive" => Ok(Self::Inactive),
+            _ => Err(UnknownDisconnectReason(value.to_string())),
+        }
+    }
+}
+
+/// A single connection of a device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionHistoryEntry {
+    pub endpoint: String,
+    pub connected: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disconnected: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<DisconnectReason>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub lwt_sent: bool,
+}
+
+/// Connectivity information of a device, for a period of time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceUptime {
+    /// If the device is currently connected.
+    pub connected: bool,
+    /// The last time the device was seen connected, `None` if it never was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// The start of the period.
+    pub since: DateTime<Utc>,
+    /// The number of seconds the device was connected during the period.
+    pub uptime_seconds: f64,
+    /// The ratio of the period the device was connected, between `0.0` and `1.0`.
+    pub uptime_ratio: f64,
+}
+
+/// A device which is disconnected, and wasn't seen for a while.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InactiveDevice {
+    pub device: String,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// A subscription of a persistent session.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSubscription {
+    /// The topic filter, as requested by the device.
+    pub topic: String,
+    /// The granted QoS level.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub qos: u8,
+    /// The subscription identifier (MQTT v5).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+}
+
+/// The state of a session, which is kept while the device is disconnected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistentSession {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subscriptions: Vec<SessionSubscription>,
+    /// The number of seconds to keep the session after the device disconnected.
+    ///
+    /// If not set, the session never expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u32>,
+}
+
+impl PersistentSession {
+    /// Check if commands should be queued while the device is disconnected.
+    pub fn queue_commands(&self) -> bool {
+        self.subscriptions.iter().any(|sub| sub.qos > 0)
+    }
+}
+
+/// A command, queued for a disconnected persistent session.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QueuedCommand {
+    /// The id, used to acknowledge the command once it was delivered.
+    pub id: i64,
+    /// The command, as cloud event.
+    pub event: serde_json::Value,
+}
@@ -1 +1,2 @@
 pub mod device_state;
+pub mod twin;
@@ -0,0 +1,280 @@
+use chrono::{DateTime, Utc};
+use drogue_client::{dialect, Section};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Channel devices use to report their current state.
+pub const TWIN_REPORTED_CHANNEL: &str = "$twin/reported";
+/// Channel (for events) and command name (for devices) carrying the delta between the desired
+/// and the reported state.
+pub const TWIN_DELTA_CHANNEL: &str = "$twin/delta";
+/// Event type of the delta event.
+pub const TWIN_DELTA_TYPE_EVENT: &str = "io.drogue.twin.delta.v1";
+
+/// The desired state of a device, part of the device spec.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTwinSpec {
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub desired: Map<String, Value>,
+}
+
+dialect!(DeviceTwinSpec[Section::Spec => "twin"]);
+
+/// The state reported by the device, part of the device status.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTwinStatus {
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub reported: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+dialect!(DeviceTwinStatus[Section::Status => "twin"]);
+
+impl DeviceTwinSpec {
+    /// Evaluate the delta between the desired and the reported state.
+    ///
+    /// The delta contains all desired values which are not reported with the same value. Objects
+    /// are compared field by field. An empty delta means that the device is in sync.
+    pub fn delta(&self, status: &DeviceTwinStatus) -> Map<String, Value> {
+        delta(&self.desired, &status.reported)
+    }
+}
+
+impl DeviceTwinStatus {
+    /// Apply a reported state, using JSON merge patch semantics.
+    ///
+    /// A `null` value removes the field from the reported state.
+    pub fn apply(&mut self, reported: Map<String, Value>) {
+        merge(&mut self.reported, reported);
+    }
+
+    /// Apply a number of reported states at once.
+    pub fn apply_all(&mut self, reports: &ReportedStates) {
+        merge(&mut self.reported, reports.reset.clone());
+        merge(&mut self.reported, reports.patch.clone());
+    }
+}
+
+/// Reported states of a device, combined as they arrive.
+///
+/// Applying the combined states has the same effect as applying each of them in order, but only
+/// requires memory for the fields they touch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReportedStates {
+    /// Fields to remove first, as they get replaced by an object.
+    reset: Map<String, Value>,
+    /// Fields to merge afterwards.
+    patch: Map<String, Value>,
+}
+
+impl ReportedStates {
+    /// Add a reported state, after the already combined ones.
+    pub fn push(&mut self, reported: Map<String, Value>) {
+        combine(&mut self.reset, &mut self.patch, reported);
+    }
+}
+
+/// Combine a merge patch with a previous one, which is applied after `reset`.
+fn combine(
+    reset: &mut Map<String, Value>,
+    patch: &mut Map<String, Value>,
+    next: Map<String, Value>,
+) {
+    for (key, value) in next {
+        match (value, patch.get_mut(&key)) {
+            (Value::Object(next), Some(Value::Object(patch))) => match reset.get_mut(&key) {
+                Some(Value::Object(reset)) => combine(reset, patch, next),
+                // the field was removed before, so the patch is applied to nothing
+                Some(_) => merge(patch, next),
+                None => {
+                    let mut nested = Map::new();
+                    combine(&mut nested, patch, next);
+                    if !nested.is_empty() {
+                        reset.insert(key, Value::Object(nested));
+                    }
+                }
+            },
+            (Value::Object(next), Some(_)) => {
+                // an object replacing a value, remove what might be there before
+                let mut value = Map::new();
+                merge(&mut value, next);
+                reset.insert(key.clone(), Value::Null);
+                patch.insert(key, Value::Object(value));
+            }
+            (value, _) => {
+                if !value.is_object() {
+                    reset.remove(&key);
+                }
+                patch.insert(key, value);
+            }
+        }
+    }
+}
+
+fn delta(desired: &Map<String, Value>, reported: &Map<String, Value>) -> Map<String, Value> {
+    let mut result = Map::new();
+
+    for (key, desired) in desired {
+        match (desired, reported.get(key)) {
+            (Value::Object(desired), Some(Value::Object(reported))) => {
+                let delta = delta(desired, reported);
+                if !delta.is_empty() {
+                    result.insert(key.clone(), Value::Object(delta));
+                }
+            }
+            // desired to be absent, and it is
+            (Value::Null, None) => {}
+            (desired, Some(reported)) if desired == reported => {}
+            (desired, _) => {
+                result.insert(key.clone(), desired.clone());
+            }
+        }
+    }
+
+    result
+}
+
+fn merge(target: &mut Map<String, Value>, patch: Map<String, Value>) {
+    for (key, value) in patch {
+        match value {
+            Value::Null => {
+                target.remove(&key);
+            }
+            Value::Object(patch) => match target.get_mut(&key) {
+                Some(Value::Object(target)) => merge(target, patch),
+                _ => {
+                    let mut value = Map::new();
+                    merge(&mut value, patch);
+                    target.insert(key, Value::Object(value));
+                }
+            },
+            value => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn map(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("Must be an object"),
+        }
+    }
+
+    #[test]
+    fn test_delta() {
+        let spec = DeviceTwinSpec {
+            desired: map(json!({
+                "interval": 10,
+                "led": {"color": "red", "on": true},
+                "obsolete": null,
+            })),
+        };
+        let status = DeviceTwinStatus {
+            reported: map(json!({
+                "interval": 10,
+                "led": {"color": "blue", "on": true},
+                "temperature": 21.5,
+            })),
+            last_update: None,
+        };
+
+        assert_eq!(
+            spec.delta(&status),
+            map(json!({
+                "led": {"color": "red"},
+            }))
+        );
+    }
+
+    #[test]
+    fn test_delta_in_sync() {
+        let spec = DeviceTwinSpec {
+            desired: map(json!({"interval": 10})),
+        };
+        let status = DeviceTwinStatus {
+            reported: map(json!({"interval": 10, "other": 1})),
+            last_update: None,
+        };
+
+        assert!(spec.delta(&status).is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut status = DeviceTwinStatus {
+            reported: map(json!({
+                "interval": 10,
+                "led": {"color": "blue", "on": true},
+                "temperature": 21.5,
+            })),
+            last_update: None,
+        };
+
+        status.apply(map(json!({
+            "led": {"color": "red"},
+            "temperature": null,
+            "firmware": {"version": "1.0"},
+        })));
+
+        assert_eq!(
+            status.reported,
+            map(json!({
+                "interval": 10,
+                "led": {"color": "red", "on": true},
+                "firmware": {"version": "1.0"},
+            }))
+        );
+    }
+
+    #[test]
+    fn test_apply_all() {
+        let initial = DeviceTwinStatus {
+            reported: map(json!({
+                "interval": 10,
+                "led": {"color": "blue", "on": true},
+                "mode": {"name": "eco", "level": 1},
+                "temperature": 21.5,
+            })),
+            last_update: None,
+        };
+
+        let reports = vec![
+            json!({"led": {"color": "red"}, "temperature": null}),
+            json!({"led": {"on": null}, "mode": "off", "firmware": {"version": "1.0"}}),
+            json!({"mode": {"name": "boost"}, "temperature": 22}),
+            json!({"led": 1, "firmware": {"version": null, "build": 2}}),
+            json!({"led": {"color": "green"}, "interval": null}),
+        ];
+
+        let mut expected = initial.clone();
+        let mut states = ReportedStates::default();
+        for report in reports {
+            expected.apply(map(report.clone()));
+            states.push(map(report));
+        }
+
+        let mut actual = initial;
+        actual.apply_all(&states);
+
+        assert_eq!(actual, expected);
+        assert_eq!(
+            actual.reported,
+            map(json!({
+                "led": {"color": "green"},
+                "mode": {"name": "boost"},
+                "temperature": 22,
+                "firmware": {"build": 2},
+            }))
+        );
+    }
+}
@@ -6,7 +6,7 @@ use drogue_client::{
 };
 use drogue_cloud_service_api::services::device_state::{
     CreateRequest, CreateResponse, DeleteOptions, DeleteRequest, DeviceState, InitResponse,
-    PingResponse,
+    PersistentSession, PingResponse, PresenceRequest, QueuedCommand,
 };
 use k8s_openapi::percent_encoding::{percent_encode, NON_ALPHANUMERIC};
 use reqwest::{Response, StatusCode};
@@ -181,6 +181,263 @@ impl DeviceStateClient {
         }
     }
 
+    #[instrument(level = "debug", err)]
+    pub async fn refresh(
+        &self,
+        application: &str,
+        device: &str,
+        presence: &PresenceRequest,
+    ) -> Result<(), ClientError> {
+        let url = self.url.join(&format!(
+            "/api/state/v1alpha1/presence/{}/{}",
+            percent_encode(application.as_bytes(), NON_ALPHANUMERIC),
+            percent_encode(device.as_bytes(), NON_ALPHANUMERIC)
+        ))?;
+
+        let req = self
+            .client
+            .put(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?
+            .json(presence);
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            code => super::default_error(code, response).await,
+        }
+    }
+
+    /// Get a page of the last values of all devices of an application, ordered by device and
+    /// channel.
+    ///
+    /// The page starts after the provided device and channel.
+    #[instrument(err)]
+    pub async fn get_last_values(
+        &self,
+        application: &str,
+        after: Option<(&str, &str)>,
+        limit: u32,
+    ) -> Result<Vec<serde_json::Value>, ClientError> {
+        let mut url = self.url.join(&format!(
+            "/api/state/v1alpha1/values/{}",
+            percent_encode(application.as_bytes(), NON_ALPHANUMERIC)
+        ))?;
+
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some((device, channel)) = after {
+                query
+                    .append_pair("after_device", device)
+                    .append_pair("after_channel", channel);
+            }
+            query.append_pair("limit", &limit.to_string());
+        }
+
+        let req = self
+            .client
+            .get(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        handle_response(response, StatusCode::OK).await
+    }
+
+    /// Get the persistent session of a device.
+    #[instrument(err)]
+    pub async fn get_persistent(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Option<PersistentSession>, ClientError> {
+        let url = self.persistent_url(application, device, None)?;
+
+        let req = self
+            .client
+            .get(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => handle_response(response, StatusCode::OK).await.map(Some),
+        }
+    }
+
+    /// Create or update the persistent session of a connected device.
+    #[instrument(err)]
+    pub async fn put_persistent(
+        &self,
+        application: &str,
+        device: &str,
+        session: &PersistentSession,
+    ) -> Result<(), ClientError> {
+        let url = self.persistent_url(application, device, None)?;
+
+        let req = self
+            .client
+            .put(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?
+            .json(session);
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            code => super::default_error(code, response).await,
+        }
+    }
+
+    /// Delete the persistent session of a device.
+    #[instrument(err)]
+    pub async fn delete_persistent(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<(), ClientError> {
+        let url = self.persistent_url(application, device, None)?;
+
+        let req = self
+            .client
+            .delete(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            code => super::default_error(code, response).await,
+        }
+    }
+
+    /// Mark the device of a persistent session as disconnected.
+    #[instrument(err)]
+    pub async fn disconnect_persistent(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<(), ClientError> {
+        let url = self.persistent_url(application, device, Some("disconnect"))?;
+
+        let req = self
+            .client
+            .post(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(()),
+            code => super::default_error(code, response).await,
+        }
+    }
+
+    /// Get the commands queued for a persistent session, oldest first.
+    ///
+    /// Commands stay queued until they are acknowledged.
+    #[instrument(err)]
+    pub async fn get_commands(
+        &self,
+        application: &str,
+        device: &str,
+    ) -> Result<Vec<QueuedCommand>, ClientError> {
+        let url = self.persistent_url(application, device, Some("commands"))?;
+
+        let req = self
+            .client
+            .get(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        handle_response(response, StatusCode::OK).await
+    }
+
+    /// Acknowledge a queued command, once it was delivered to the device.
+    ///
+    /// Acknowledging a command which is no longer queued is not an error.
+    #[instrument(err)]
+    pub async fn ack_command(
+        &self,
+        application: &str,
+        device: &str,
+        id: i64,
+    ) -> Result<(), ClientError> {
+        let url = self.persistent_url(application, device, Some(&format!("commands/{id}")))?;
+
+        let req = self
+            .client
+            .delete(url)
+            .propagate_current_context()
+            .inject_token(&self.token_provider)
+            .await?;
+
+        let response: Response = req
+            .send()
+            .await
+            .map_err(|err| ClientError::Client(Box::new(err)))?;
+
+        match response.status() {
+            StatusCode::NO_CONTENT | StatusCode::NOT_FOUND => Ok(()),
+            code => super::default_error(code, response).await,
+        }
+    }
+
+    fn persistent_url(
+        &self,
+        application: &str,
+        device: &str,
+        suffix: Option<&str>,
+    ) -> Result<Url, ClientError> {
+        let mut path = format!(
+            "/api/state/v1alpha1/persistent/{}/{}",
+            percent_encode(application.as_bytes(), NON_ALPHANUMERIC),
+            percent_encode(device.as_bytes(), NON_ALPHANUMERIC)
+        );
+        if let Some(suffix) = suffix {
+            path.push('/');
+            path.push_str(suffix);
+        }
+        Ok(self.url.join(&path)?)
+    }
+
     fn state_url(
         &self,
         session: &str,
@@ -0,0 +1,62 @@
+use crate::keycloak::{
+    client::KeycloakAdminClient, error::Error, KeycloakAdminClientConfig, KeycloakClient,
+};
+use async_trait::async_trait;
+use keycloak::KeycloakAdmin;
+
+/// A user directory, which is either backed by Keycloak, or by the embedded OpenID Connect issuer.
+///
+/// The embedded issuer uses the username as the subject of its tokens, so the local directory
+/// maps IDs and usernames onto each other. It has no admin API.
+#[derive(Clone)]
+pub enum UserDirectory {
+    Keycloak(KeycloakAdminClient),
+    Local,
+}
+
+impl UserDirectory {
+    /// Create a new directory, falling back to the local one if there is no Keycloak configuration.
+    pub fn from_config(config: Option<KeycloakAdminClientConfig>) -> Result<Self, Error> {
+        match config {
+            Some(config) => Self::new(config),
+            None => Ok(Self::Local),
+        }
+    }
+}
+
+#[async_trait]
+impl KeycloakClient for UserDirectory {
+    fn new(config: KeycloakAdminClientConfig) -> Result<Self, Error> {
+        Ok(Self::Keycloak(KeycloakAdminClient::new(config)?))
+    }
+
+    async fn username_from_id(&self, id: &str) -> Result<String, Error> {
+        match self {
+            Self::Keycloak(client) => client.username_from_id(id).await,
+            Self::Local => Ok(id.to_string()),
+        }
+    }
+
+    async fn id_from_username(&self, username: &str) -> Result<String, Error> {
+        match self {
+            Self::Keycloak(client) => client.id_from_username(username).await,
+            Self::Local => Ok(username.to_string()),
+        }
+    }
+
+    async fn admin<'a>(&self) -> Result<KeycloakAdmin, Error> {
+        match self {
+            Self::Keycloak(client) => client.admin().await,
+            Self::Local => Err(Error::Internal(
+                "The local user directory has no admin API".into(),
+            )),
+        }
+    }
+
+    fn realm(&self) -> String {
+        match self {
+            Self::Keycloak(client) => client.realm(),
+            Self::Local => String::new(),
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod client;
+pub mod directory;
 pub mod error;
 pub mod mock;
 
@@ -1,8 +1,10 @@
 mod config;
 mod mux;
+mod presence;
 
 pub use self::config::*;
 pub use mux::*;
+pub use presence::*;
 
 use crate::client::DeviceStateClient;
 use anyhow::anyhow;
@@ -135,6 +137,11 @@ impl StateController {
         ))
     }
 
+    /// Access the client of the device state service.
+    pub fn client(&self) -> &DeviceStateClient {
+        &self.client
+    }
+
     pub async fn create(
         &self,
         application: &registry::v1::Application,
@@ -0,0 +1,109 @@
+use crate::client::{DeviceStateClient, DeviceStateClientConfig};
+use drogue_cloud_service_api::services::device_state::{Id, PresenceRequest};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Number of entries, after which the refresh cache gets cleaned up.
+const CLEANUP_THRESHOLD: usize = 10_000;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PresenceConfiguration {
+    #[serde(default)]
+    pub client: DeviceStateClientConfig,
+    pub endpoint: String,
+    /// The minimum time between two refreshes of the same device.
+    #[serde(with = "humantime_serde", default = "default_min_refresh")]
+    pub min_refresh: Duration,
+}
+
+impl Default for PresenceConfiguration {
+    fn default() -> Self {
+        Self {
+            client: Default::default(),
+            endpoint: "default".to_string(),
+            min_refresh: default_min_refresh(),
+        }
+    }
+}
+
+const fn default_min_refresh() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Reports the presence of devices, which are not connected through a session.
+///
+/// This is intended for stateless endpoints, like HTTP or CoAP, where devices are considered
+/// connected as long as they keep sending requests.
+#[derive(Clone, Debug)]
+pub struct PresenceReporter {
+    client: DeviceStateClient,
+    endpoint: String,
+    min_refresh: Duration,
+    refreshed: Arc<Mutex<HashMap<Id, Instant>>>,
+}
+
+impl PresenceReporter {
+    pub async fn new(config: PresenceConfiguration) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: DeviceStateClient::from_config(config.client).await?,
+            endpoint: config.endpoint,
+            min_refresh: config.min_refresh,
+            refreshed: Default::default(),
+        })
+    }
+
+    /// Report a device as seen.
+    ///
+    /// Refreshing the presence happens in the background, and is skipped if the device was
+    /// refreshed recently.
+    pub fn seen(&self, application: &str, device: &str, device_uid: &str) {
+        let id = Id {
+            application: application.to_string(),
+            device: device.to_string(),
+        };
+
+        if !self.should_refresh(&id) {
+            return;
+        }
+
+        let client = self.client.clone();
+        let presence = PresenceRequest {
+            device_uid: device_uid.to_string(),
+            endpoint: self.endpoint.clone(),
+        };
+
+        tokio::spawn(async move {
+            if let Err(err) = client.refresh(&id.application, &id.device, &presence).await {
+                log::info!(
+                    "Failed to refresh presence of {}/{}: {err}",
+                    id.application,
+                    id.device
+                );
+            }
+        });
+    }
+
+    fn should_refresh(&self, id: &Id) -> bool {
+        let now = Instant::now();
+        let mut refreshed = self.refreshed.lock().unwrap();
+
+        if let Some(last) = refreshed.get(id) {
+            if now.duration_since(*last) < self.min_refresh {
+                return false;
+            }
+        }
+
+        if refreshed.len() > CLEANUP_THRESHOLD {
+            let min_refresh = self.min_refresh;
+            refreshed.retain(|_, last| now.duration_since(*last) < min_refresh);
+        }
+
+        refreshed.insert(id.clone(), now);
+
+        true
+    }
+}
@@ -0,0 +1,61 @@
+use crate::spec::Measurements;
+use drogue_client::registry;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Caches the measurements of applications.
+pub struct ApplicationCache {
+    registry: registry::v1::Client,
+    duration: Duration,
+    entries: HashMap<String, (Instant, Option<Measurements>)>,
+    last_prune: Instant,
+}
+
+impl ApplicationCache {
+    pub fn new(registry: registry::v1::Client, duration: Duration) -> Self {
+        Self {
+            registry,
+            duration,
+            entries: Default::default(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Get the measurements of an application, `None` if it doesn't have any.
+    pub async fn get(&mut self, application: &str) -> anyhow::Result<Option<Measurements>> {
+        self.prune();
+
+        if let Some((timestamp, measurements)) = self.entries.get(application) {
+            if timestamp.elapsed() < self.duration {
+                return Ok(measurements.clone());
+            }
+        }
+
+        let measurements = self
+            .registry
+            .get_app(application)
+            .await?
+            .and_then(Measurements::from_application);
+
+        self.entries.insert(
+            application.to_string(),
+            (Instant::now(), measurements.clone()),
+        );
+
+        Ok(measurements)
+    }
+
+    /// Remove expired entries, so that deleted applications don't pile up.
+    fn prune(&mut self) {
+        if self.last_prune.elapsed() < self.duration {
+            return;
+        }
+        self.last_prune = Instant::now();
+
+        let duration = self.duration;
+        self.entries
+            .retain(|_, (timestamp, _)| timestamp.elapsed() < duration);
+    }
+}
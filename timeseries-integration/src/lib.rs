@@ -0,0 +1,208 @@
+mod applications;
+mod point;
+mod spec;
+mod writer;
+
+pub use point::*;
+pub use spec::*;
+pub use writer::{BatchConfig, InfluxConfig, RetryConfig};
+
+use crate::{
+    applications::ApplicationCache,
+    writer::{InfluxWriter, TimescaleWriter, Writer, Writers},
+};
+use drogue_cloud_database_common::postgres;
+use drogue_cloud_event_common::{
+    ext::extension,
+    stream::{CustomAck, Handle},
+};
+use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
+use drogue_cloud_service_api::{
+    kafka::{KafkaClientConfig, KafkaConfig, EVENTS_TOPIC_PATTERN},
+    EXT_APPLICATION,
+};
+use drogue_cloud_service_common::{app::Startup, client::ClientConfig};
+use futures::{FutureExt, StreamExt};
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub kafka: KafkaClientConfig,
+
+    pub registry: ClientConfig,
+
+    /// The topic to consume events from.
+    ///
+    /// If the topic starts with `^`, it is used as a pattern.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+
+    #[serde(default = "default_consumer_group")]
+    pub consumer_group: String,
+
+    /// Write points to the `timeseries` table of this database.
+    #[serde(default)]
+    pub timescale: Option<postgres::Config>,
+
+    /// Write points to this InfluxDB bucket.
+    #[serde(default)]
+    pub influx: Option<InfluxConfig>,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// Points of multiple events are written together.
+    #[serde(default)]
+    pub batch: BatchConfig,
+
+    /// The time the time-series settings of an application are cached.
+    #[serde(default = "default_application_cache_duration")]
+    #[serde(with = "humantime_serde")]
+    pub application_cache_duration: Duration,
+}
+
+fn default_topic() -> String {
+    EVENTS_TOPIC_PATTERN.into()
+}
+
+fn default_consumer_group() -> String {
+    "timeseries-integration".into()
+}
+
+const fn default_application_cache_duration() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
+    log::info!("Starting time-series integration");
+    log::info!("Kafka servers: {}", config.kafka.bootstrap_servers);
+
+    let mut writers = Vec::new();
+
+    if let Some(pg) = config.timescale {
+        let writer = TimescaleWriter::new(pg)?;
+        startup.check(writer.clone());
+        writers.push(Writer::Timescale(writer));
+    }
+
+    if let Some(influx) = config.influx {
+        writers.push(Writer::Influx(InfluxWriter::new(influx)));
+    }
+
+    if writers.is_empty() {
+        anyhow::bail!("Neither TimescaleDB nor InfluxDB is configured");
+    }
+
+    let registry = config.registry.into_client().await?;
+    let applications = ApplicationCache::new(registry, config.application_cache_duration);
+
+    let stream = EventStream::<CustomAck>::new(EventStreamConfig {
+        kafka: KafkaConfig {
+            topic: config.topic,
+            client: config.kafka,
+        },
+        consumer_group: Some(config.consumer_group),
+    })?;
+
+    startup.spawn(
+        run_writer(
+            stream,
+            applications,
+            Writers::new(writers, config.retry),
+            config.batch,
+        )
+        .boxed(),
+    );
+
+    Ok(())
+}
+
+/// Consume events, and write the measurements of their application.
+///
+/// Points are written in batches, once the batch is full or its delay expired. Events are
+/// acknowledged once the batch containing their points is written.
+async fn run_writer(
+    mut stream: EventStream<'static, CustomAck>,
+    mut applications: ApplicationCache,
+    writers: Writers,
+    batch: BatchConfig,
+) -> anyhow::Result<()> {
+    let mut points = Vec::new();
+    let mut handles = Vec::new();
+    // the time the current batch must be written at
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+        let next = match deadline {
+            Some(at) => match tokio::time::timeout_at(at, stream.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    flush(&stream, &writers, &mut points, &mut handles).await?;
+                    deadline = None;
+                    continue;
+                }
+            },
+            None => stream.next().await,
+        };
+
+        let handle = match next {
+            Some(Ok(handle)) => handle,
+            Some(Err(err)) => {
+                log::info!("Failed to read next event: {err}");
+                continue;
+            }
+            None => break,
+        };
+
+        if let Some(application) = extension(&handle, EXT_APPLICATION) {
+            match applications.get(application).await {
+                Ok(Some(measurements)) => points.extend(measurements.points(&handle)),
+                Ok(None) => {}
+                Err(err) => {
+                    log::info!(
+                        "Failed to look up application '{application}', dropping event: {err}"
+                    )
+                }
+            }
+        }
+
+        if handles.is_empty() && points.is_empty() {
+            // nothing to wait for
+            stream.ack(handle)?;
+            continue;
+        }
+
+        handles.push(handle.replace(()));
+        deadline.get_or_insert_with(|| Instant::now() + batch.max_delay);
+
+        if points.len() >= batch.max_points {
+            flush(&stream, &writers, &mut points, &mut handles).await?;
+            deadline = None;
+        }
+    }
+
+    flush(&stream, &writers, &mut points, &mut handles).await?;
+
+    anyhow::bail!("Event stream closed")
+}
+
+/// Write the points of the batch, and acknowledge its events.
+async fn flush(
+    stream: &EventStream<'static, CustomAck>,
+    writers: &Writers,
+    points: &mut Vec<Point>,
+    handles: &mut Vec<Handle<'static, ()>>,
+) -> anyhow::Result<()> {
+    if !points.is_empty() {
+        writers.write(std::mem::take(points)).await;
+    }
+
+    for handle in handles.drain(..) {
+        stream.ack(handle)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// The values of a measurement, extracted from an event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub measurement: String,
+    pub time: DateTime<Utc>,
+    pub application: String,
+    pub device: String,
+    pub channel: String,
+    /// Additional tags, extracted from the event payload.
+    pub tags: BTreeMap<String, String>,
+    pub fields: BTreeMap<String, f64>,
+}
@@ -0,0 +1,255 @@
+use crate::point::Point;
+use chrono::Utc;
+use cloudevents::{AttributesReader, Data, Event};
+use drogue_client::{dialect, registry::v1::Application, Section, Translator};
+use drogue_cloud_event_common::ext::extension;
+use drogue_cloud_service_api::{EXT_APPLICATION, EXT_DEVICE};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// The time-series settings of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeseriesSpec {
+    /// The measurements extracted from events.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub measurements: Vec<Measurement>,
+}
+
+dialect!(TimeseriesSpec [Section::Spec => "timeseries"]);
+
+/// A mapping of event payload values to a measurement.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Measurement {
+    /// The name of the measurement.
+    pub name: String,
+    /// Only use events of this channel, defaults to all channels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Fields of the measurement, mapped to JSON pointers of numeric values.
+    pub fields: BTreeMap<String, String>,
+    /// Tags of the measurement, mapped to JSON pointers of string, numeric, or boolean values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Measurement {
+    /// Extract the point of this measurement from an event payload, `None` if none of the fields
+    /// are present.
+    fn point(&self, origin: &Origin, payload: &Value) -> Option<Point> {
+        let fields = self
+            .fields
+            .iter()
+            .filter_map(|(name, pointer)| {
+                payload
+                    .pointer(pointer)
+                    .and_then(Value::as_f64)
+                    .filter(|value| value.is_finite())
+                    .map(|value| (name.clone(), value))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        if fields.is_empty() {
+            return None;
+        }
+
+        let tags = self
+            .tags
+            .iter()
+            .filter_map(|(name, pointer)| {
+                let value = match payload.pointer(pointer)? {
+                    Value::String(value) => value.clone(),
+                    value @ (Value::Number(_) | Value::Bool(_)) => value.to_string(),
+                    _ => return None,
+                };
+                Some((name.clone(), value))
+            })
+            .collect();
+
+        Some(Point {
+            measurement: self.name.clone(),
+            time: origin.time,
+            application: origin.application.to_string(),
+            device: origin.device.to_string(),
+            channel: origin.channel.to_string(),
+            tags,
+            fields,
+        })
+    }
+}
+
+struct Origin<'e> {
+    application: &'e str,
+    device: &'e str,
+    channel: &'e str,
+    time: chrono::DateTime<Utc>,
+}
+
+/// The measurements of an application.
+#[derive(Clone, Debug)]
+pub struct Measurements(Arc<Vec<Measurement>>);
+
+impl Measurements {
+    /// Get the measurements of an application, `None` if it doesn't have any.
+    pub fn from_application(application: Application) -> Option<Self> {
+        match application.section::<TimeseriesSpec>() {
+            Some(Ok(spec)) if !spec.measurements.is_empty() => {
+                Some(Self(Arc::new(spec.measurements)))
+            }
+            Some(Err(err)) => {
+                log::info!(
+                    "Invalid time-series spec of application '{}': {err}",
+                    application.metadata.name
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Extract the points of all measurements from an event.
+    ///
+    /// Events without an application, device, channel, or JSON payload don't have any points.
+    pub fn points(&self, event: &Event) -> Vec<Point> {
+        let (application, device, channel) = match (
+            extension(event, EXT_APPLICATION),
+            extension(event, EXT_DEVICE),
+            event.subject(),
+        ) {
+            (Some(application), Some(device), Some(channel)) => (application, device, channel),
+            _ => return vec![],
+        };
+
+        let payload = match payload(event) {
+            Some(payload) => payload,
+            None => return vec![],
+        };
+
+        let origin = Origin {
+            application,
+            device,
+            channel,
+            time: event.time().cloned().unwrap_or_else(Utc::now),
+        };
+
+        self.0
+            .iter()
+            .filter(|measurement| match &measurement.channel {
+                Some(filter) => filter == channel,
+                None => true,
+            })
+            .filter_map(|measurement| measurement.point(&origin, &payload))
+            .collect()
+    }
+}
+
+fn payload(event: &Event) -> Option<Value> {
+    match event.data()? {
+        Data::Json(value) => Some(value.clone()),
+        Data::Binary(data) => serde_json::from_slice(data).ok(),
+        Data::String(data) => serde_json::from_str(data).ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cloudevents::{EventBuilder, EventBuilderV10};
+    use serde_json::json;
+
+    fn measurements() -> Measurements {
+        let spec: TimeseriesSpec = serde_json::from_value(json!({
+            "measurements": [
+                {
+                    "name": "climate",
+                    "channel": "state",
+                    "fields": {
+                        "temperature": "/temp",
+                        "humidity": "/hum",
+                    },
+                    "tags": {
+                        "room": "/room",
+                        "floor": "/location/floor",
+                    },
+                },
+                {
+                    "name": "battery",
+                    "fields": {
+                        "level": "/battery",
+                    },
+                },
+            ],
+        }))
+        .unwrap();
+
+        Measurements(Arc::new(spec.measurements))
+    }
+
+    fn event(channel: &str, payload: Value) -> Event {
+        EventBuilderV10::new()
+            .id("1")
+            .source("drogue://app/device")
+            .ty("io.drogue.event.v1")
+            .subject(channel)
+            .extension(EXT_APPLICATION, "app")
+            .extension(EXT_DEVICE, "device")
+            .data("application/json", payload)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_points() {
+        let points = measurements().points(&event(
+            "state",
+            json!({
+                "temp": 21.5,
+                "hum": "unknown",
+                "room": "kitchen",
+                "location": { "floor": 1 },
+                "battery": 80,
+            }),
+        ));
+
+        assert_eq!(points.len(), 2);
+
+        assert_eq!(points[0].measurement, "climate");
+        assert_eq!(points[0].application, "app");
+        assert_eq!(points[0].device, "device");
+        assert_eq!(points[0].channel, "state");
+        assert_eq!(
+            points[0].fields,
+            BTreeMap::from([("temperature".to_string(), 21.5)])
+        );
+        assert_eq!(
+            points[0].tags,
+            BTreeMap::from([
+                ("floor".to_string(), "1".to_string()),
+                ("room".to_string(), "kitchen".to_string()),
+            ])
+        );
+
+        assert_eq!(points[1].measurement, "battery");
+        assert_eq!(
+            points[1].fields,
+            BTreeMap::from([("level".to_string(), 80.0)])
+        );
+    }
+
+    #[test]
+    fn test_channel_filter() {
+        let points = measurements().points(&event("other", json!({"temp": 21.5, "battery": 80})));
+
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].measurement, "battery");
+    }
+
+    #[test]
+    fn test_no_fields() {
+        let points = measurements().points(&event("state", json!({"room": "kitchen"})));
+
+        assert!(points.is_empty());
+    }
+}
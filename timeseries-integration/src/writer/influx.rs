@@ -0,0 +1,238 @@
+use super::WriteError;
+use crate::point::Point;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt::Write;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct InfluxConfig {
+    /// The base URL of the InfluxDB server, e.g. `http://localhost:8086`.
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    /// The API token.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Writes points using the InfluxDB v2 line protocol API.
+#[derive(Clone)]
+pub struct InfluxWriter {
+    client: reqwest::Client,
+    url: String,
+    config: InfluxConfig,
+}
+
+impl InfluxWriter {
+    pub fn new(config: InfluxConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: format!("{}/api/v2/write", config.url.trim_end_matches('/')),
+            config,
+        }
+    }
+
+    pub async fn write(&self, points: &[Point]) -> Result<(), WriteError> {
+        let lines = to_lines(points);
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .query(&[
+                ("org", self.config.org.as_str()),
+                ("bucket", self.config.bucket.as_str()),
+                ("precision", "ns"),
+            ])
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(lines);
+
+        if let Some(token) = &self.config.token {
+            request = request.header("Authorization", format!("Token {token}"));
+        }
+
+        let response = request.send().await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(WriteError::Status(
+                status,
+                response.text().await.unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+/// Encode points using the line protocol.
+fn to_lines(points: &[Point]) -> String {
+    let mut lines = String::new();
+
+    for point in points {
+        let time = match timestamp_nanos(&point.time) {
+            Some(time) => time,
+            None => {
+                log::warn!(
+                    "Skipping point of measurement '{}' with unsupported time: {}",
+                    point.measurement,
+                    point.time
+                );
+                continue;
+            }
+        };
+
+        lines.push_str(&escape(&point.measurement, &[',', ' ']));
+
+        let tags = [
+            ("application", &point.application),
+            ("device", &point.device),
+            ("channel", &point.channel),
+        ]
+        .into_iter()
+        .chain(
+            point
+                .tags
+                .iter()
+                .filter(|(k, _)| !matches!(k.as_str(), "application" | "device" | "channel"))
+                .map(|(k, v)| (k.as_str(), v)),
+        );
+
+        for (key, value) in tags {
+            // empty tag values are not allowed
+            if !value.is_empty() {
+                let _ = write!(lines, ",{}={}", escape_key(key), escape_key(value));
+            }
+        }
+
+        for (i, (key, value)) in point.fields.iter().enumerate() {
+            let _ = write!(
+                lines,
+                "{}{}={}",
+                if i == 0 { ' ' } else { ',' },
+                escape_key(key),
+                value
+            );
+        }
+
+        let _ = writeln!(lines, " {time}");
+    }
+
+    lines
+}
+
+/// The nanoseconds since the epoch, `None` if they don't fit into an `i64`.
+///
+/// This only covers the years 1677 to 2262, which is the range InfluxDB supports.
+fn timestamp_nanos(time: &DateTime<Utc>) -> Option<i64> {
+    time.timestamp()
+        .checked_mul(1_000_000_000)?
+        .checked_add(time.timestamp_subsec_nanos() as i64)
+}
+
+/// Escape tag keys, tag values, and field keys.
+///
+/// Line breaks can't be escaped, so they are removed.
+fn escape_key(value: &str) -> String {
+    escape(value, &[',', '=', ' '])
+}
+
+fn escape(value: &str, special: &[char]) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\r' | '\n') {
+            continue;
+        }
+        if special.contains(&c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_lines() {
+        let points = vec![
+            Point {
+                measurement: "climate".into(),
+                time: Utc.timestamp_opt(1_600_000_000, 1).unwrap(),
+                application: "app".into(),
+                device: "device 1".into(),
+                channel: "state".into(),
+                tags: BTreeMap::from([
+                    ("room".to_string(), "kitchen,east".to_string()),
+                    ("empty".to_string(), "".to_string()),
+                ]),
+                fields: BTreeMap::from([
+                    ("humidity".to_string(), 40.0),
+                    ("temperature".to_string(), 21.5),
+                ]),
+            },
+            Point {
+                measurement: "battery level".into(),
+                time: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+                application: "app".into(),
+                device: "a=b".into(),
+                channel: "state".into(),
+                tags: Default::default(),
+                fields: BTreeMap::from([("level".to_string(), 80.0)]),
+            },
+        ];
+
+        assert_eq!(
+            to_lines(&points),
+            "climate,application=app,device=device\\ 1,channel=state,room=kitchen\\,east humidity=40,temperature=21.5 1600000000000000001\n\
+             battery\\ level,application=app,device=a\\=b,channel=state level=80 1600000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let points = vec![Point {
+            measurement: "climate\n".into(),
+            time: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            application: "app".into(),
+            device: "device\r\n1".into(),
+            channel: "state".into(),
+            tags: BTreeMap::from([("room\n".to_string(), "kitchen\nfoo=bar".to_string())]),
+            fields: BTreeMap::from([("temp\nerature".to_string(), 21.5)]),
+        }];
+
+        assert_eq!(
+            to_lines(&points),
+            "climate,application=app,device=device1,channel=state,room=kitchenfoo\\=bar temperature=21.5 1600000000000000000\n"
+        );
+    }
+
+    #[test]
+    fn test_time_out_of_range() {
+        let point = |time| Point {
+            measurement: "climate".into(),
+            time,
+            application: "app".into(),
+            device: "device".into(),
+            channel: "state".into(),
+            tags: Default::default(),
+            fields: BTreeMap::from([("temperature".to_string(), 21.5)]),
+        };
+
+        let points = vec![
+            point(Utc.with_ymd_and_hms(2300, 1, 1, 0, 0, 0).unwrap()),
+            point(Utc.with_ymd_and_hms(1600, 1, 1, 0, 0, 0).unwrap()),
+            point(Utc.timestamp_opt(1_600_000_000, 0).unwrap()),
+        ];
+
+        assert_eq!(
+            to_lines(&points),
+            "climate,application=app,device=device,channel=state temperature=21.5 1600000000000000000\n"
+        );
+        assert_eq!(to_lines(&points[..2]), "");
+    }
+}
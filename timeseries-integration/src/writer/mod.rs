@@ -0,0 +1,200 @@
+mod influx;
+mod timescale;
+
+pub use influx::*;
+pub use timescale::*;
+
+use crate::point::Point;
+use drogue_cloud_integration_common::retry;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{collections::BTreeMap, time::Duration};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum WriteError {
+    #[error("Pool error: {0}")]
+    Pool(#[from] deadpool_postgres::PoolError),
+    #[error("Database error: {0}")]
+    Database(#[from] tokio_postgres::Error),
+    #[error("Request error: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Unexpected response: {0}: {1}")]
+    Status(StatusCode, String),
+}
+
+impl WriteError {
+    /// Check if the error is permanent, so that retrying to write the same points won't help.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Pool(_) | Self::Request(_) => false,
+            // data exceptions and integrity constraint violations
+            Self::Database(err) => err
+                .code()
+                .map(|state| state.code().starts_with("22") || state.code().starts_with("23"))
+                .unwrap_or_default(),
+            Self::Status(status, _) => {
+                status.is_client_error()
+                    && !matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+                    )
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryConfig {
+    /// The delay before the first retry, doubled with each further attempt.
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts.
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+const fn default_initial_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+const fn default_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// The delay after a failed attempt, starting with `1`.
+    fn backoff(&self, attempt: usize) -> Duration {
+        retry::backoff(self.initial_backoff, self.max_backoff, attempt)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchConfig {
+    /// The number of points, after which a batch is written.
+    #[serde(default = "default_max_points")]
+    pub max_points: usize,
+    /// The time after which a batch is written, even if it isn't full.
+    #[serde(default = "default_max_delay", with = "humantime_serde")]
+    pub max_delay: Duration,
+}
+
+const fn default_max_points() -> usize {
+    1000
+}
+
+const fn default_max_delay() -> Duration {
+    Duration::from_secs(1)
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_points: default_max_points(),
+            max_delay: default_max_delay(),
+        }
+    }
+}
+
+/// A time-series store.
+#[derive(Clone)]
+pub enum Writer {
+    Timescale(TimescaleWriter),
+    Influx(InfluxWriter),
+}
+
+impl Writer {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Timescale(_) => "TimescaleDB",
+            Self::Influx(_) => "InfluxDB",
+        }
+    }
+
+    async fn write(&self, points: &[Point]) -> Result<(), WriteError> {
+        match self {
+            Self::Timescale(writer) => writer.write(points).await,
+            Self::Influx(writer) => writer.write(points).await,
+        }
+    }
+}
+
+/// Writes points to all configured stores.
+pub struct Writers {
+    writers: Vec<Writer>,
+    retry: RetryConfig,
+}
+
+impl Writers {
+    pub fn new(writers: Vec<Writer>, retry: RetryConfig) -> Self {
+        Self { writers, retry }
+    }
+
+    /// Write points to all stores.
+    ///
+    /// The points of each application are written separately, so that the points of one
+    /// application can't fail the write of others. Temporary errors are retried until the write
+    /// succeeds, holding back further points. Points failing with a permanent error are dropped
+    /// for that store.
+    pub async fn write(&self, points: Vec<Point>) {
+        let mut applications = BTreeMap::<String, Vec<Point>>::new();
+        for point in points {
+            applications
+                .entry(point.application.clone())
+                .or_default()
+                .push(point);
+        }
+
+        for writer in &self.writers {
+            for (application, points) in &applications {
+                self.write_to(writer, application, points).await;
+            }
+        }
+    }
+
+    async fn write_to(&self, writer: &Writer, application: &str, points: &[Point]) {
+        let mut attempt = 1;
+        loop {
+            match writer.write(points).await {
+                Ok(()) => break,
+                Err(err) if err.is_permanent() => {
+                    log::info!(
+                        "Failed to write points of application '{application}' to {}: {err}",
+                        writer.name()
+                    );
+                    break;
+                }
+                Err(err) => {
+                    let backoff = self.retry.backoff(attempt);
+                    log::info!(
+                        "Failed to write points of application '{application}' to {}, retrying in {backoff:?}: {err}",
+                        writer.name()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permanent() {
+        assert!(WriteError::Status(StatusCode::BAD_REQUEST, "".into()).is_permanent());
+        assert!(!WriteError::Status(StatusCode::TOO_MANY_REQUESTS, "".into()).is_permanent());
+        assert!(!WriteError::Status(StatusCode::SERVICE_UNAVAILABLE, "".into()).is_permanent());
+    }
+}
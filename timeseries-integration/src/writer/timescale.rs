@@ -0,0 +1,119 @@
+use super::WriteError;
+use crate::point::Point;
+use deadpool_postgres::Pool;
+use drogue_cloud_database_common::{postgres, DatabaseService};
+use drogue_cloud_service_api::health::HealthChecked;
+use tokio_postgres::types::{Json, Type};
+
+/// Writes points to the `timeseries` table, one row per field.
+///
+/// When the TimescaleDB extension is available, the table is created as a hypertable.
+#[derive(Clone)]
+pub struct TimescaleWriter {
+    pool: Pool,
+}
+
+impl TimescaleWriter {
+    pub fn new(pg: postgres::Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            pool: pg.create_pool()?,
+        })
+    }
+
+    /// Write all fields of the points, using a single statement.
+    pub async fn write(&self, points: &[Point]) -> Result<(), WriteError> {
+        let rows = points
+            .iter()
+            .flat_map(|point| point.fields.iter().map(move |field| (point, field)));
+
+        let mut times = Vec::new();
+        let mut applications = Vec::new();
+        let mut devices = Vec::new();
+        let mut channels = Vec::new();
+        let mut measurements = Vec::new();
+        let mut fields = Vec::new();
+        let mut values = Vec::new();
+        let mut tags = Vec::new();
+
+        for (point, (field, value)) in rows {
+            times.push(point.time);
+            applications.push(point.application.as_str());
+            devices.push(point.device.as_str());
+            channels.push(point.channel.as_str());
+            measurements.push(point.measurement.as_str());
+            fields.push(field.as_str());
+            values.push(*value);
+            tags.push(Json(&point.tags));
+        }
+
+        if times.is_empty() {
+            return Ok(());
+        }
+
+        let c = self.pool.get().await?;
+
+        let stmt = c
+            .prepare_typed(
+                r#"
+INSERT INTO
+    timeseries
+(
+    TIME,
+    APPLICATION,
+    DEVICE,
+    CHANNEL,
+    MEASUREMENT,
+    FIELD,
+    VALUE,
+    TAGS
+)
+SELECT * FROM UNNEST(
+    $1,
+    $2,
+    $3,
+    $4,
+    $5,
+    $6,
+    $7,
+    $8
+)
+"#,
+                &[
+                    Type::TIMESTAMPTZ_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::VARCHAR_ARRAY,
+                    Type::FLOAT8_ARRAY,
+                    Type::JSONB_ARRAY,
+                ],
+            )
+            .await?;
+
+        c.execute(
+            &stmt,
+            &[
+                &times,
+                &applications,
+                &devices,
+                &channels,
+                &measurements,
+                &fields,
+                &values,
+                &tags,
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl DatabaseService for TimescaleWriter {
+    fn pool(&self) -> &Pool {
+        &self.pool
+    }
+}
+
+impl HealthChecked for TimescaleWriter {}
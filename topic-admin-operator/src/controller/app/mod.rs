@@ -2,7 +2,7 @@ mod topic;
 
 use topic::*;
 
-use crate::{controller::ControllerConfig, kafka::TopicErrorConverter};
+use crate::controller::ControllerConfig;
 use async_trait::async_trait;
 use drogue_client::{
     core::v1::Conditions,
@@ -19,11 +19,7 @@ use drogue_cloud_operator_common::controller::{
     },
 };
 use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
-use rdkafka::{
-    admin::{AdminClient, AdminOptions},
-    client::DefaultClientContext,
-    error::{KafkaError, RDKafkaErrorCode},
-};
+use rdkafka::{admin::AdminClient, client::DefaultClientContext};
 use std::ops::Deref;
 
 const FINALIZER: &str = "kafka-topic";
@@ -157,37 +153,11 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
     ) -> Result<ProcessOutcome<Self::Output>, ReconcileError> {
         // delete
 
-        let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
-
-        match self
-            .admin
-            .delete_topics(&[&topic_name], &AdminOptions::new())
-            .await
-            .single_topic_response()
-        {
-            Ok(_) => {
-                log::info!("Topic {} deleted", topic_name);
-            }
-            Err(KafkaError::AdminOp(
-                RDKafkaErrorCode::UnknownTopic | RDKafkaErrorCode::UnknownTopicOrPartition,
-            )) => {
-                log::info!("Topic {} was already deleted", topic_name);
-            }
-            Err(KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure)) => {
-                let err = KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure);
-                log::warn!("Failed to create topic ({}): {:?}", topic_name, err);
-                return Err(ReconcileError::temporary(format!(
-                    "Failed to create topic: {}",
-                    err
-                )));
-            }
-            Err(err) => {
-                log::warn!("Failed to delete topic: {:?}", err);
-                return Err(ReconcileError::permanent(format!(
-                    "Failed to delete topic: {}",
-                    err
-                )));
-            }
+        for topic_name in [
+            make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name)),
+            make_kafka_resource_name(ResourceType::DeadLetters(&ctx.app.metadata.name)),
+        ] {
+            delete_topic(self.admin, &topic_name).await?;
         }
 
         // remove finalizer
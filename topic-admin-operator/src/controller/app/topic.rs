@@ -1,13 +1,17 @@
 use super::ConstructContext;
 use crate::{controller::ControllerConfig, kafka::TopicErrorConverter};
 use async_trait::async_trait;
+use drogue_client::Translator;
 use drogue_cloud_operator_common::controller::reconciler::{
     progress::{OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::{make_kafka_resource_name, KafkaTopicSpec, ResourceType};
 use rdkafka::{
-    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
+    admin::{
+        AdminClient, AdminOptions, AlterConfig, NewPartitions, NewTopic, ResourceSpecifier,
+        TopicReplication,
+    },
     client::DefaultClientContext,
     error::{KafkaError, RDKafkaErrorCode},
 };
@@ -17,6 +21,163 @@ pub struct CreateTopic<'o> {
     pub admin: &'o AdminClient<DefaultClientContext>,
 }
 
+impl CreateTopic<'_> {
+    /// Align an existing topic with the requested settings.
+    ///
+    /// The number of partitions can only be increased, and the number of replicas is not changed.
+    async fn update_topic(&self, topic: &NewTopic<'_>) -> Result<(), ReconcileError> {
+        let partitions =
+            NewPartitions::new(topic.name, topic.num_partitions.try_into().unwrap_or(1));
+        match self
+            .admin
+            .create_partitions(&[partitions], &AdminOptions::new())
+            .await
+            .single_topic_response()
+        {
+            Ok(_) => {
+                log::info!(
+                    "Increased partitions of topic {} to {}",
+                    topic.name,
+                    topic.num_partitions
+                );
+            }
+            Err(KafkaError::AdminOp(RDKafkaErrorCode::InvalidPartitions)) => {
+                // the topic already has at least the requested number of partitions
+                log::debug!("Partitions of topic {} unchanged", topic.name);
+            }
+            Err(err) => {
+                log::warn!("Failed to update partitions ({}): {:?}", topic.name, err);
+                return Err(ReconcileError::temporary(format!(
+                    "Failed to update partitions: {}",
+                    err
+                )));
+            }
+        }
+
+        let mut config = AlterConfig::new(ResourceSpecifier::Topic(topic.name));
+        for (k, v) in &topic.config {
+            config = config.set(k, v);
+        }
+
+        match self
+            .admin
+            .alter_configs(&[config], &AdminOptions::new())
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|mut r| match r.pop() {
+                Some(Ok(_)) => Ok(()),
+                Some(Err((_, err))) => Err(err.to_string()),
+                None => Err("Missing response".into()),
+            }) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                log::warn!("Failed to update topic config ({}): {}", topic.name, err);
+                Err(ReconcileError::temporary(format!(
+                    "Failed to update topic config: {}",
+                    err
+                )))
+            }
+        }
+    }
+
+    /// Create or delete the dead-letter topic, depending on the application's spec.
+    async fn ensure_dead_letter_topic(
+        &self,
+        app: &str,
+        spec: Option<&KafkaTopicSpec>,
+    ) -> Result<(), ReconcileError> {
+        let topic_name = make_kafka_resource_name(ResourceType::DeadLetters(app));
+
+        let spec = match spec.and_then(|spec| spec.dead_letters()) {
+            Some(spec) => spec,
+            None => return delete_topic(self.admin, &topic_name).await,
+        };
+
+        let mut properties = self.config.properties.clone();
+        properties.extend(spec.properties());
+
+        let config = properties
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+
+        let num_replicas = spec.replicas.unwrap_or(self.config.num_replicas);
+
+        let topic = NewTopic {
+            name: &topic_name,
+            num_partitions: self
+                .config
+                .num_partitions
+                .get()
+                .try_into()
+                .unwrap_or(i32::MAX),
+            config,
+            replication: TopicReplication::Fixed(num_replicas.get().try_into().unwrap_or(i32::MAX)),
+        };
+
+        match self
+            .admin
+            .create_topics(&[topic], &AdminOptions::new())
+            .await
+            .single_topic_response()
+        {
+            Ok(_) => {
+                log::debug!("Topic {} created", topic_name);
+                Ok(())
+            }
+            Err(KafkaError::AdminOp(RDKafkaErrorCode::TopicAlreadyExists)) => {
+                log::debug!("Topic {} already existed", topic_name);
+                self.update_topic(&topic).await
+            }
+            Err(err) => {
+                log::warn!("Failed to create topic ({}): {:?}", topic_name, err);
+                Err(ReconcileError::temporary(format!(
+                    "Failed to create dead-letter topic: {}",
+                    err
+                )))
+            }
+        }
+    }
+}
+
+/// Delete a topic, if it exists.
+pub async fn delete_topic(
+    admin: &AdminClient<DefaultClientContext>,
+    topic_name: &str,
+) -> Result<(), ReconcileError> {
+    match admin
+        .delete_topics(&[topic_name], &AdminOptions::new())
+        .await
+        .single_topic_response()
+    {
+        Ok(_) => {
+            log::info!("Topic {} deleted", topic_name);
+        }
+        Err(KafkaError::AdminOp(
+            RDKafkaErrorCode::UnknownTopic | RDKafkaErrorCode::UnknownTopicOrPartition,
+        )) => {
+            log::debug!("Topic {} was already deleted", topic_name);
+        }
+        Err(KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure)) => {
+            let err = KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure);
+            log::warn!("Failed to delete topic ({}): {:?}", topic_name, err);
+            return Err(ReconcileError::temporary(format!(
+                "Failed to delete topic: {}",
+                err
+            )));
+        }
+        Err(err) => {
+            log::warn!("Failed to delete topic: {:?}", err);
+            return Err(ReconcileError::permanent(format!(
+                "Failed to delete topic: {}",
+                err
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 #[async_trait]
 impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
     fn type_name(&self) -> String {
@@ -30,27 +191,40 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
     {
         let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
 
-        let mut config = Vec::with_capacity(self.config.properties.len());
-        for (k, v) in &self.config.properties {
+        let spec = ctx
+            .app
+            .section::<KafkaTopicSpec>()
+            .transpose()
+            .map_err(|err| {
+                ReconcileError::permanent(format!("Failed to parse topic spec: {}", err))
+            })?;
+
+        // the operator configuration, overridden by the application's spec
+
+        let mut properties = self.config.properties.clone();
+        if let Some(spec) = &spec {
+            properties.extend(spec.properties());
+        }
+
+        let mut config = Vec::with_capacity(properties.len());
+        for (k, v) in &properties {
             config.push((k.as_str(), v.as_str()));
         }
 
+        let num_partitions = spec
+            .as_ref()
+            .and_then(|spec| spec.partitions)
+            .unwrap_or(self.config.num_partitions);
+        let num_replicas = spec
+            .as_ref()
+            .and_then(|spec| spec.replicas)
+            .unwrap_or(self.config.num_replicas);
+
         let topic = NewTopic {
             name: &topic_name,
-            num_partitions: self
-                .config
-                .num_partitions
-                .get()
-                .try_into()
-                .unwrap_or(i32::MAX),
+            num_partitions: num_partitions.get().try_into().unwrap_or(i32::MAX),
             config,
-            replication: TopicReplication::Fixed(
-                self.config
-                    .num_replicas
-                    .get()
-                    .try_into()
-                    .unwrap_or(i32::MAX),
-            ),
+            replication: TopicReplication::Fixed(num_replicas.get().try_into().unwrap_or(i32::MAX)),
         };
 
         log::debug!("NewTopic: {:?}", topic);
@@ -66,6 +240,8 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
             }
             Err(KafkaError::AdminOp(RDKafkaErrorCode::TopicAlreadyExists)) => {
                 log::debug!("Topic {} already existed", topic_name);
+                // also when the spec is absent, reverting settings which were removed
+                self.update_topic(&topic).await?;
             }
             Err(KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure)) => {
                 let err = KafkaError::AdminOp(RDKafkaErrorCode::BrokerTransportFailure);
@@ -84,6 +260,9 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
             }
         }
 
+        self.ensure_dead_letter_topic(&ctx.app.metadata.name, spec.as_ref())
+            .await?;
+
         // done
 
         Ok(OperationOutcome::Continue(ctx))
@@ -205,17 +205,22 @@ impl<'a> Reconciler for ApplicationReconciler<'a> {
         // delete
 
         let topic_name = make_kafka_resource_name(ResourceType::Events(&ctx.app.metadata.name));
+        let dead_letters_topic_name =
+            make_kafka_resource_name(ResourceType::DeadLetters(&ctx.app.metadata.name));
 
         let user_name = make_kafka_resource_name(ResourceType::Users(&ctx.app.metadata.name));
 
         let password_name =
             make_kafka_resource_name(ResourceType::Passwords(&ctx.app.metadata.name));
 
-        // remove topic
+        // remove topics
 
         self.kafka_topics
             .delete_optionally(&topic_name, &Default::default())
             .await?;
+        self.kafka_topics
+            .delete_optionally(&dead_letters_topic_name, &Default::default())
+            .await?;
         self.kafka_users
             .delete_optionally(&user_name, &Default::default())
             .await?;
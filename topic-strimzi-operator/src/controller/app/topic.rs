@@ -9,14 +9,17 @@ use drogue_cloud_operator_common::controller::reconciler::{
     progress::{self, OperationOutcome, ProgressOperation},
     ReconcileError,
 };
-use drogue_cloud_service_api::kafka::{make_kafka_resource_name, ResourceType};
+use drogue_cloud_service_api::kafka::{make_kafka_resource_name, KafkaTopicSpec, ResourceType};
 use kube::{
     api::{ApiResource, DynamicObject},
     Api, Resource,
 };
-use operator_framework::{process::create_or_update_by, utils::UseOrCreate};
+use operator_framework::{install::Delete, process::create_or_update_by, utils::UseOrCreate};
 use serde_json::json;
 
+const DEFAULT_PARTITIONS: u32 = 3;
+const DEFAULT_REPLICAS: u32 = 1;
+
 pub struct CreateTopic<'o> {
     pub api: &'o Api<DynamicObject>,
     pub resource: &'o ApiResource,
@@ -29,6 +32,7 @@ impl CreateTopic<'_> {
         kafka_topic_resource: &ApiResource,
         config: &ControllerConfig,
         target: ResourceType<'_>,
+        spec: &KafkaTopicSpec,
     ) -> Result<(DynamicObject, String), ReconcileError> {
         let topic_name = make_kafka_resource_name(target.clone());
 
@@ -57,9 +61,9 @@ impl CreateTopic<'_> {
 
                 // set config
                 topic.data["spec"] = json!({
-                    "config": {},
-                    "partitions": 3,
-                    "replicas": 1,
+                    "config": spec.properties(),
+                    "partitions": spec.partitions.map(|p| p.get()).unwrap_or(DEFAULT_PARTITIONS),
+                    "replicas": spec.replicas.map(|r| r.get()).unwrap_or(DEFAULT_REPLICAS),
                     "topicName": topic_name,
                 });
 
@@ -86,17 +90,50 @@ impl<'o> ProgressOperation<ConstructContext> for CreateTopic<'o> {
         mut ctx: ConstructContext,
     ) -> drogue_cloud_operator_common::controller::reconciler::progress::Result<ConstructContext>
     {
+        let spec = ctx
+            .app
+            .section::<KafkaTopicSpec>()
+            .transpose()
+            .map_err(|err| {
+                ReconcileError::permanent(format!("Failed to parse topic spec: {}", err))
+            })?
+            .unwrap_or_default();
+
         let (topic, topic_name) = Self::ensure_kafka_topic(
             self.api,
             self.resource,
             self.config,
             ResourceType::Events(&ctx.app.metadata.name),
+            &spec,
         )
         .await?;
 
         ctx.events_topic = Some(topic);
         ctx.events_topic_name = Some(topic_name);
 
+        match spec.dead_letters() {
+            Some(spec) => {
+                Self::ensure_kafka_topic(
+                    self.api,
+                    self.resource,
+                    self.config,
+                    ResourceType::DeadLetters(&ctx.app.metadata.name),
+                    &spec,
+                )
+                .await?;
+            }
+            None => {
+                self.api
+                    .delete_optionally(
+                        &make_kafka_resource_name(ResourceType::DeadLetters(
+                            &ctx.app.metadata.name,
+                        )),
+                        &Default::default(),
+                    )
+                    .await?;
+            }
+        }
+
         // done
 
         Ok(OperationOutcome::Continue(ctx))
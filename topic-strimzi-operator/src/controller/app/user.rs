@@ -43,6 +43,7 @@ impl CreateUser<'_> {
     ) -> Result<(DynamicObject, String), ReconcileError> {
         let user_name = make_kafka_resource_name(ResourceType::Users(&app));
         let topic_name = make_kafka_resource_name(ResourceType::Events(&app));
+        let dead_letters_topic_name = make_kafka_resource_name(ResourceType::DeadLetters(&app));
         let password_name = make_kafka_resource_name(ResourceType::Passwords(&app));
 
         let user = create_or_update_by(
@@ -95,6 +96,15 @@ impl CreateUser<'_> {
                                     "patternType": "literal",
                                 },
                             },
+                            {
+                                "host": "*",
+                                "operation": "Read",
+                                "resource": {
+                                    "type": "topic",
+                                    "name": dead_letters_topic_name,
+                                    "patternType": "literal",
+                                },
+                            },
                             {
                                 "host": "*",
                                 "operation": "Read",
@@ -3,14 +3,14 @@ pub mod service;
 
 use actix_web::web;
 use drogue_cloud_access_token_service::{
-    endpoints::WebData as KeycloakWebData, service::KeycloakAccessTokenService,
+    backend::AccessTokenBackend, endpoints::WebData as AccessTokenWebData,
 };
 use drogue_cloud_service_api::webapp as actix_web;
 use drogue_cloud_service_common::{
     actix::http::{HttpBuilder, HttpConfig},
     app::{Startup, StartupExt},
     auth::openid::{Authenticator, AuthenticatorConfig},
-    keycloak::{client::KeycloakAdminClient, KeycloakAdminClientConfig, KeycloakClient},
+    keycloak::{KeycloakAdminClientConfig, KeycloakClient},
     openid_auth,
 };
 use serde::Deserialize;
@@ -30,7 +30,9 @@ pub struct Config {
 
     pub oauth: AuthenticatorConfig,
 
-    pub keycloak: KeycloakAdminClientConfig,
+    /// Keycloak instance storing the access tokens, uses the database when missing.
+    #[serde(default)]
+    pub keycloak: Option<KeycloakAdminClientConfig>,
 
     #[serde(default)]
     pub http: HttpConfig,
@@ -65,18 +67,18 @@ where
     let authenticator = config.oauth.into_client().await?;
     let enable_auth = authenticator.is_some();
 
+    let api_key = web::Data::new(AccessTokenWebData {
+        service: AccessTokenBackend::<K>::from_config(
+            config.keycloak,
+            Some(config.service.pg.clone()),
+        )?,
+    });
+
     let data = web::Data::new(WebData {
         authenticator,
         service: service::PostgresAuthorizationService::new(config.service)?,
     });
 
-    let keycloak_client = KeycloakAdminClient::new(config.keycloak)?;
-    let api_key = web::Data::new(KeycloakWebData {
-        service: KeycloakAccessTokenService {
-            client: keycloak_client,
-        },
-    });
-
     let data_service = data.service.clone();
 
     // main server
@@ -88,14 +90,7 @@ where
             .as_ref()
             .and_then(|data|data.authenticator.as_ref())
         });
-        app!(
-            cfg,
-            data,
-            KeycloakAccessTokenService<K>,
-            api_key,
-            enable_auth,
-            auth
-        );
+        app!(cfg, data, AccessTokenBackend<K>, api_key, enable_auth, auth);
     })
     .start(startup)?;
 
@@ -0,0 +1,63 @@
+use tokio::sync::mpsc;
+
+/// Reports that an event was processed, once dropped.
+pub struct Completion {
+    id: u64,
+    sender: mpsc::UnboundedSender<u64>,
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        // the receiver only goes away when the integration stops
+        let _ = self.sender.send(self.id);
+    }
+}
+
+/// Hands out completions, reporting the ids of processed events.
+///
+/// Events of different devices complete in any order. The ids are increasing, and are used to
+/// release the events in the order they were received.
+pub struct Completions {
+    next: u64,
+    sender: mpsc::UnboundedSender<u64>,
+}
+
+impl Completions {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<u64>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { next: 0, sender }, receiver)
+    }
+
+    /// Create the completion of the next event, and return its id.
+    pub fn next(&mut self) -> (u64, Completion) {
+        let id = self.next;
+        self.next += 1;
+        (
+            id,
+            Completion {
+                id,
+                sender: self.sender.clone(),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_completion() {
+        let (mut completions, mut receiver) = Completions::new();
+
+        let (first, first_completion) = completions.next();
+        let (second, second_completion) = completions.next();
+        assert_ne!(first, second);
+
+        drop(second_completion);
+        assert_eq!(receiver.recv().await, Some(second));
+
+        drop(first_completion);
+        assert_eq!(receiver.recv().await, Some(first));
+    }
+}
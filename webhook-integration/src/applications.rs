@@ -0,0 +1,59 @@
+use crate::spec::Webhooks;
+use drogue_client::registry;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Caches the webhooks of applications.
+pub struct ApplicationCache {
+    registry: registry::v1::Client,
+    duration: Duration,
+    entries: HashMap<String, (Instant, Option<Webhooks>)>,
+    last_prune: Instant,
+}
+
+impl ApplicationCache {
+    pub fn new(registry: registry::v1::Client, duration: Duration) -> Self {
+        Self {
+            registry,
+            duration,
+            entries: Default::default(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Get the webhooks of an application, `None` if it doesn't have any.
+    pub async fn get(&mut self, application: &str) -> anyhow::Result<Option<Webhooks>> {
+        self.prune();
+
+        if let Some((timestamp, webhooks)) = self.entries.get(application) {
+            if timestamp.elapsed() < self.duration {
+                return Ok(webhooks.clone());
+            }
+        }
+
+        let webhooks = self
+            .registry
+            .get_app(application)
+            .await?
+            .and_then(Webhooks::from_application);
+
+        self.entries
+            .insert(application.to_string(), (Instant::now(), webhooks.clone()));
+
+        Ok(webhooks)
+    }
+
+    /// Remove expired entries, so that deleted applications don't pile up.
+    fn prune(&mut self) {
+        if self.last_prune.elapsed() < self.duration {
+            return;
+        }
+        self.last_prune = Instant::now();
+
+        let duration = self.duration;
+        self.entries
+            .retain(|_, (timestamp, _)| timestamp.elapsed() < duration);
+    }
+}
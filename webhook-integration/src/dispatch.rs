@@ -0,0 +1,340 @@
+use crate::{
+    ack::Completion,
+    spec::{WebhookTarget, Webhooks},
+};
+use cloudevents::{AttributesReader, Event};
+use drogue_client::{registry::v1::Application, Translator};
+use drogue_cloud_endpoint_common::{
+    sender::{DeadLetterReason, ExternalClientPool, ExternalError, IntoPayload, PublishOutcome},
+    sink::{MessagingSink, Sink, SinkTarget},
+    EXT_DEAD_LETTER_ERROR, EXT_DEAD_LETTER_REASON,
+};
+use drogue_cloud_integration_common::retry;
+use drogue_cloud_service_api::kafka::KafkaTopicSpec;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// The number of events queued per device, before the overflow policy applies.
+const QUEUE_SIZE: usize = 64;
+/// The time after which workers of idle devices are stopped.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryConfig {
+    /// The number of attempts to deliver an event, before it is considered undeliverable.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: usize,
+    /// The delay before the first retry, doubled with each further attempt.
+    #[serde(default = "default_initial_backoff", with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    /// The maximum delay between two attempts.
+    #[serde(default = "default_max_backoff", with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+const fn default_max_attempts() -> usize {
+    5
+}
+
+const fn default_initial_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+const fn default_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+/// What happens to an event when the queue of its device is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverflowPolicy {
+    /// Send the event to the dead-letter topic of the application, or drop it if dead letters
+    /// are not enabled.
+    DeadLetter,
+    /// Wait until the queue has room, holding back the events of all other devices.
+    Wait,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        Self::DeadLetter
+    }
+}
+
+impl RetryConfig {
+    /// The delay after a failed attempt, starting with `1`.
+    pub(crate) fn backoff(&self, attempt: usize) -> Duration {
+        retry::backoff(self.initial_backoff, self.max_backoff, attempt)
+    }
+}
+
+#[derive(Debug, Error)]
+enum DeliveryError {
+    #[error("External endpoint error: {0}")]
+    External(#[from] ExternalError),
+    #[error("Unexpected response: {0}")]
+    Status(StatusCode),
+}
+
+impl DeliveryError {
+    /// Check if the error is permanent, so that retrying to deliver the same event won't help.
+    fn is_permanent(&self) -> bool {
+        match self {
+            Self::External(ExternalError::Request(_)) => false,
+            Self::External(_) => true,
+            Self::Status(status) => {
+                status.is_client_error()
+                    && !matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+                    )
+            }
+        }
+    }
+}
+
+/// Delivers events to the webhook targets of their application.
+#[derive(Clone)]
+struct Delivery {
+    pool: ExternalClientPool,
+    sink: MessagingSink,
+    retry: RetryConfig,
+}
+
+impl Delivery {
+    /// Deliver an event to all targets, sending it to the dead-letter topic for each target it
+    /// couldn't be delivered to.
+    async fn deliver(&self, webhooks: &Webhooks, event: Event) {
+        for target in webhooks.targets.iter() {
+            if let Err(err) = self.deliver_to(target, &event).await {
+                log::info!(
+                    "Failed to deliver event to webhook '{}': {err}",
+                    target.name
+                );
+                self.dead_letter(
+                    &webhooks.application,
+                    event.clone(),
+                    format!("Webhook '{}': {err}", target.name),
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn deliver_to(&self, target: &WebhookTarget, event: &Event) -> Result<(), DeliveryError> {
+        let mut attempt = 1;
+        loop {
+            match self.send(target, event.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(err) if err.is_permanent() || attempt >= self.retry.max_attempts => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    let backoff = self.retry.backoff(attempt);
+                    log::debug!(
+                        "Retrying webhook '{}' in {backoff:?} (attempt: {attempt}): {err}",
+                        target.name
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn send(&self, target: &WebhookTarget, event: Event) -> Result<(), DeliveryError> {
+        let client = self.pool.get(&target.endpoint).await?;
+        let response = client
+            .process(target.request.to_payload(event), &target.endpoint)
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(DeliveryError::Status(status)),
+        }
+    }
+
+    async fn dead_letter(&self, application: &Application, mut event: Event, error: String) {
+        let enabled = application
+            .section::<KafkaTopicSpec>()
+            .and_then(|s| s.ok())
+            .map(|spec| spec.dead_letters)
+            .unwrap_or_default();
+        if !enabled {
+            log::info!("Dropping event {}: {error}", event.id());
+            return;
+        }
+
+        event.set_extension(
+            EXT_DEAD_LETTER_REASON,
+            DeadLetterReason::Undelivered.as_str(),
+        );
+        event.set_extension(EXT_DEAD_LETTER_ERROR, error);
+
+        match self
+            .sink
+            .publish(SinkTarget::DeadLetters(application), event)
+            .await
+        {
+            Ok(PublishOutcome::Accepted) => {}
+            Ok(outcome) => log::info!("Dead letter not accepted: {outcome:?}"),
+            Err(err) => log::info!("Failed to send dead letter: {err}"),
+        }
+    }
+}
+
+/// An event to deliver, completing once it was delivered or sent to the dead-letter topic.
+type Job = (Webhooks, Event, Completion);
+
+struct Worker {
+    sender: mpsc::Sender<Job>,
+    /// The number of events queued or in delivery.
+    pending: Arc<AtomicUsize>,
+    last_used: Instant,
+}
+
+/// Dispatches events to a worker per device, delivering the events of a device in order.
+pub struct Dispatcher {
+    delivery: Delivery,
+    overflow: OverflowPolicy,
+    workers: HashMap<(String, String), Worker>,
+    last_prune: Instant,
+}
+
+impl Dispatcher {
+    pub fn new(
+        pool: ExternalClientPool,
+        sink: MessagingSink,
+        retry: RetryConfig,
+        overflow: OverflowPolicy,
+    ) -> Self {
+        Self {
+            delivery: Delivery { pool, sink, retry },
+            overflow,
+            workers: Default::default(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Dispatch an event of a device.
+    ///
+    /// If the queue of the device is full, the overflow policy applies. The completion is dropped
+    /// once the event was delivered, or given up on.
+    pub async fn dispatch(
+        &mut self,
+        device: String,
+        webhooks: Webhooks,
+        event: Event,
+        completion: Completion,
+    ) {
+        self.prune();
+
+        let key = (webhooks.application.metadata.name.clone(), device);
+        let delivery = &self.delivery;
+        let worker = self
+            .workers
+            .entry(key)
+            .or_insert_with(|| Self::spawn(delivery.clone()));
+
+        worker.pending.fetch_add(1, Ordering::SeqCst);
+        worker.last_used = Instant::now();
+
+        let result = match worker.sender.try_send((webhooks, event, completion)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(job)) => match self.overflow {
+                OverflowPolicy::Wait => worker.sender.send(job).await.map_err(|err| err.0),
+                OverflowPolicy::DeadLetter => {
+                    worker.pending.fetch_sub(1, Ordering::SeqCst);
+                    let (webhooks, event, _completion) = job;
+                    self.delivery
+                        .dead_letter(
+                            &webhooks.application,
+                            event,
+                            "Delivery queue of the device is full".to_string(),
+                        )
+                        .await;
+                    return;
+                }
+            },
+            Err(TrySendError::Closed(job)) => Err(job),
+        };
+
+        if result.is_err() {
+            // workers only stop once their sender is dropped
+            log::warn!("Failed to dispatch event: worker stopped");
+            worker.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn spawn(delivery: Delivery) -> Worker {
+        let (sender, mut receiver) = mpsc::channel::<Job>(QUEUE_SIZE);
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        let worker_pending = pending.clone();
+        tokio::spawn(async move {
+            while let Some((webhooks, event, _completion)) = receiver.recv().await {
+                delivery.deliver(&webhooks, event).await;
+                worker_pending.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+
+        Worker {
+            sender,
+            pending,
+            last_used: Instant::now(),
+        }
+    }
+
+    /// Stop the workers of idle devices.
+    ///
+    /// Only workers without pending events are stopped, so that a new worker of the same device
+    /// can't overtake the previous one.
+    fn prune(&mut self) {
+        if self.last_prune.elapsed() < IDLE_TIMEOUT {
+            return;
+        }
+        self.last_prune = Instant::now();
+
+        self.workers.retain(|_, worker| {
+            worker.pending.load(Ordering::SeqCst) > 0 || worker.last_used.elapsed() < IDLE_TIMEOUT
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_permanent() {
+        assert!(DeliveryError::Status(StatusCode::BAD_REQUEST).is_permanent());
+        assert!(DeliveryError::Status(StatusCode::NOT_FOUND).is_permanent());
+        assert!(!DeliveryError::Status(StatusCode::TOO_MANY_REQUESTS).is_permanent());
+        assert!(!DeliveryError::Status(StatusCode::REQUEST_TIMEOUT).is_permanent());
+        assert!(!DeliveryError::Status(StatusCode::SERVICE_UNAVAILABLE).is_permanent());
+        assert!(
+            DeliveryError::External(ExternalError::InvalidConfiguration("foo".into()))
+                .is_permanent()
+        );
+    }
+}
@@ -0,0 +1,199 @@
+mod ack;
+mod applications;
+mod dispatch;
+mod spec;
+
+pub use dispatch::{OverflowPolicy, RetryConfig};
+pub use spec::*;
+
+use crate::{ack::Completions, applications::ApplicationCache, dispatch::Dispatcher};
+use drogue_cloud_endpoint_common::{
+    sender::{ExternalClientPool, ExternalClientPoolConfig},
+    sink::MessagingSink,
+};
+use drogue_cloud_event_common::{
+    ext::extension,
+    stream::{CustomAck, EventStream, EventStreamConfig, Handle},
+};
+use drogue_cloud_integration_common::inflight::InFlight;
+use drogue_cloud_service_api::{
+    kafka::{KafkaClientConfig, KafkaConfig, EVENTS_TOPIC_PATTERN},
+    EXT_APPLICATION, EXT_DEVICE,
+};
+use drogue_cloud_service_common::{app::Startup, client::ClientConfig, defaults};
+use futures::{FutureExt, StreamExt};
+use serde::Deserialize;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub kafka: KafkaClientConfig,
+
+    pub registry: ClientConfig,
+
+    /// The topic to consume events from.
+    ///
+    /// If the topic starts with `^`, it is used as a pattern.
+    #[serde(default = "default_topic")]
+    pub topic: String,
+
+    #[serde(default = "default_consumer_group")]
+    pub consumer_group: String,
+
+    /// The Kafka config for sending dead letters, defaults to the `kafka` config.
+    #[serde(default)]
+    pub dead_letter_kafka_sink: Option<KafkaClientConfig>,
+
+    #[serde(default = "defaults::check_kafka_topic_ready")]
+    pub check_kafka_topic_ready: bool,
+
+    #[serde(default)]
+    pub endpoint_pool: ExternalClientPoolConfig,
+
+    #[serde(default)]
+    pub retry: RetryConfig,
+
+    /// What happens to an event when the delivery queue of its device is full.
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+
+    /// The maximum number of events being delivered, before no further events are consumed.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+
+    /// The time the webhook settings of an application are cached.
+    #[serde(default = "default_application_cache_duration")]
+    #[serde(with = "humantime_serde")]
+    pub application_cache_duration: Duration,
+}
+
+fn default_topic() -> String {
+    EVENTS_TOPIC_PATTERN.into()
+}
+
+fn default_consumer_group() -> String {
+    "webhook-integration".into()
+}
+
+const fn default_max_in_flight() -> usize {
+    1000
+}
+
+const fn default_application_cache_duration() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
+    log::info!("Starting webhook integration");
+    log::info!("Kafka servers: {}", config.kafka.bootstrap_servers);
+
+    let registry = config.registry.into_client().await?;
+    let applications = ApplicationCache::new(registry, config.application_cache_duration);
+
+    let sink = MessagingSink::from_config(
+        config
+            .dead_letter_kafka_sink
+            .unwrap_or_else(|| config.kafka.clone()),
+        config.check_kafka_topic_ready,
+    )?;
+    let dispatcher = Dispatcher::new(
+        ExternalClientPool::new(config.endpoint_pool),
+        sink,
+        config.retry.clone(),
+        config.overflow,
+    );
+
+    let stream = EventStream::<CustomAck>::new(EventStreamConfig {
+        kafka: KafkaConfig {
+            topic: config.topic,
+            client: config.kafka,
+        },
+        consumer_group: Some(config.consumer_group),
+    })?;
+
+    let in_flight = InFlight::new(config.max_in_flight);
+
+    startup
+        .spawn(run_dispatcher(stream, applications, dispatcher, in_flight, config.retry).boxed());
+
+    Ok(())
+}
+
+/// Consume events, and dispatch them to the webhooks of their application.
+///
+/// Events are acknowledged once they were delivered, or sent to the dead-letter topic, and all
+/// events received before them were as well. No further events are consumed while the maximum
+/// number of events is being delivered.
+async fn run_dispatcher(
+    mut stream: EventStream<'static, CustomAck>,
+    mut applications: ApplicationCache,
+    mut dispatcher: Dispatcher,
+    mut in_flight: InFlight<u64, Handle<'static, ()>>,
+    retry: RetryConfig,
+) -> anyhow::Result<()> {
+    let (mut completions, mut completed) = Completions::new();
+
+    loop {
+        let handle = tokio::select! {
+            Some(id) = completed.recv() => {
+                for handle in in_flight.ack(&id) {
+                    stream.ack(handle)?;
+                }
+                continue;
+            }
+            handle = stream.next(), if !in_flight.is_full() => handle,
+        };
+
+        let handle = match handle {
+            Some(Ok(handle)) => handle,
+            Some(Err(err)) => {
+                log::info!("Failed to read next event: {err}");
+                continue;
+            }
+            None => break,
+        };
+
+        let event = (*handle).clone();
+        let (id, completion) = completions.next();
+        in_flight.push(id, handle.replace(()));
+
+        let (application, device) = match (
+            extension(&event, EXT_APPLICATION),
+            extension(&event, EXT_DEVICE),
+        ) {
+            (Some(application), Some(device)) => (application.to_string(), device.to_string()),
+            _ => continue,
+        };
+
+        if let Some(webhooks) = lookup(&mut applications, &application, &retry).await {
+            dispatcher
+                .dispatch(device, webhooks, event, completion)
+                .await;
+        }
+    }
+
+    anyhow::bail!("Event stream closed")
+}
+
+/// Look up the webhooks of an application, retrying until the registry can be reached.
+async fn lookup(
+    applications: &mut ApplicationCache,
+    application: &str,
+    retry: &RetryConfig,
+) -> Option<Webhooks> {
+    let mut attempt = 1;
+    loop {
+        match applications.get(application).await {
+            Ok(webhooks) => return webhooks,
+            Err(err) => {
+                let backoff = retry.backoff(attempt);
+                log::warn!(
+                    "Failed to look up application '{application}', retrying in {backoff:?}: {err}"
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+use drogue_cloud_service_api::PROJECT;
+use drogue_cloud_service_common::runtime;
+use drogue_cloud_webhook_integration::run;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    runtime!(PROJECT).exec(run).await
+}
@@ -0,0 +1,82 @@
+use drogue_client::{
+    dialect,
+    registry::v1::{Application, ExternalEndpoint, RequestType},
+    Section, Translator,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The webhook settings of an application.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookSpec {
+    /// The targets events get sent to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<WebhookTarget>,
+}
+
+dialect!(WebhookSpec [Section::Spec => "webhooks"]);
+
+/// An HTTP endpoint, receiving the events of an application.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookTarget {
+    /// The name of the target, reported with dead letters.
+    pub name: String,
+    pub endpoint: ExternalEndpoint,
+    /// The encoding of the request, defaults to a binary encoded cloud event.
+    #[serde(default)]
+    pub request: RequestType,
+}
+
+/// An application, and its webhook targets.
+#[derive(Clone, Debug)]
+pub struct Webhooks {
+    pub application: Arc<Application>,
+    pub targets: Arc<Vec<WebhookTarget>>,
+}
+
+impl Webhooks {
+    /// Get the webhooks of an application, `None` if it doesn't have any targets.
+    pub fn from_application(application: Application) -> Option<Self> {
+        match application.section::<WebhookSpec>() {
+            Some(Ok(spec)) if !spec.targets.is_empty() => Some(Self {
+                application: Arc::new(application),
+                targets: Arc::new(spec.targets),
+            }),
+            Some(Err(err)) => {
+                log::info!(
+                    "Invalid webhook spec of application '{}': {err}",
+                    application.metadata.name
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec: WebhookSpec = serde_json::from_value(json!({
+            "targets": [
+                {
+                    "name": "backend",
+                    "endpoint": {
+                        "url": "https://example.com/events",
+                    },
+                },
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(spec.targets.len(), 1);
+        assert_eq!(spec.targets[0].name, "backend");
+        assert_eq!(spec.targets[0].endpoint.url, "https://example.com/events");
+    }
+}
@@ -0,0 +1,154 @@
+use crate::protocol::{CommandOutcome, SendCommand};
+use drogue_client::{
+    registry,
+    user::{self, v1::authz},
+};
+use drogue_cloud_endpoint_common::sender::UpstreamSender;
+use drogue_cloud_integration_common::commands::{process_command, CommandOptions};
+use drogue_cloud_service_api::{
+    auth::user::UserInformation,
+    webapp::{http::StatusCode, ResponseError},
+};
+use serde_json::Value;
+
+/// The services required for sending commands, shared by all connections.
+#[derive(Clone)]
+pub struct CommandServices {
+    pub sender: UpstreamSender,
+    pub client: reqwest::Client,
+    pub registry: registry::v1::Client,
+}
+
+/// Sends commands on behalf of the user of a websocket connection.
+#[derive(Clone)]
+pub struct CommandSender {
+    pub services: CommandServices,
+    pub user_auth: Option<user::v1::Client>,
+    pub user: UserInformation,
+}
+
+impl CommandSender {
+    /// Send a command to a device of the application.
+    pub async fn send(&self, application: String, request: SendCommand) -> CommandOutcome {
+        let id = request.id.clone();
+
+        match self.try_send(application, request).await {
+            Ok(status) => CommandOutcome {
+                id,
+                accepted: status.is_success(),
+                status: status.as_u16(),
+                reason: (!status.is_success())
+                    .then(|| status.canonical_reason().map(ToString::to_string))
+                    .flatten(),
+            },
+            Err((status, reason)) => CommandOutcome {
+                id,
+                accepted: false,
+                status: status.as_u16(),
+                reason: Some(reason),
+            },
+        }
+    }
+
+    async fn try_send(
+        &self,
+        application: String,
+        request: SendCommand,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        log::debug!(
+            "Send command '{}' to '{}' / '{}'",
+            request.command,
+            application,
+            request.device
+        );
+
+        self.authorize(&application).await?;
+
+        let response = futures::try_join!(
+            self.services.registry.get_app(&application),
+            self.services
+                .registry
+                .get_device_and_gateways(&application, &request.device)
+        );
+
+        let (application_res, device_gateways) = match response {
+            Ok((Some(application), Some(device_gateways))) => (application, device_gateways),
+            Ok(_) => {
+                return Err((
+                    StatusCode::NOT_ACCEPTABLE,
+                    "Unknown application or device".to_string(),
+                ))
+            }
+            Err(err) => {
+                log::info!("Error looking up registry info: {err}");
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to look up device".to_string(),
+                ));
+            }
+        };
+
+        let (content_type, payload) = match request.payload {
+            None => (request.content_type, Vec::new()),
+            Some(Value::String(payload)) => (request.content_type, payload.into_bytes()),
+            Some(payload) => (
+                request
+                    .content_type
+                    .or_else(|| Some("application/json".to_string())),
+                serde_json::to_vec(&payload)
+                    .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?,
+            ),
+        };
+
+        let response = process_command(
+            application_res,
+            device_gateways.0,
+            device_gateways.1,
+            &self.services.sender,
+            self.services.client.clone(),
+            CommandOptions {
+                application,
+                device: request.device,
+                command: request.command,
+                content_type,
+                correlation_id: None,
+                response_channel: None,
+            },
+            payload.into(),
+        )
+        .await
+        .map_err(|err| (err.status_code(), err.to_string()))?;
+
+        Ok(response.status())
+    }
+
+    /// Check if the user is allowed to send commands to the application.
+    async fn authorize(&self, application: &str) -> Result<(), (StatusCode, String)> {
+        let user_auth = match &self.user_auth {
+            Some(user_auth) => user_auth,
+            // authorization is disabled
+            None => return Ok(()),
+        };
+
+        let response = user_auth
+            .authorize(authz::AuthorizationRequest {
+                application: application.to_string(),
+                permission: authz::Permission::Write,
+                user_id: self.user.user_id().map(ToString::to_string),
+                roles: self.user.roles().clone(),
+            })
+            .await
+            .map_err(|err| {
+                log::info!("Failed to authorize user: {err}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to authorize".to_string(),
+                )
+            })?;
+
+        match response.outcome {
+            authz::Outcome::Allow => Ok(()),
+            authz::Outcome::Deny => Err((StatusCode::FORBIDDEN, "Not authorized".to_string())),
+        }
+    }
+}
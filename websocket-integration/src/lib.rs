@@ -1,12 +1,19 @@
+mod commands;
 mod messages;
+mod protocol;
 mod route;
 mod service;
+mod sse;
 mod wshandler;
 
-use crate::service::Service;
+use crate::{commands::CommandServices, service::Service, sse::EventServices};
 use actix::Actor;
 use actix_web::web;
 use drogue_client::user::v1::authz::Permission;
+use drogue_cloud_endpoint_common::{
+    sender::{ExternalClientPoolConfig, UpstreamSender},
+    sink::MessagingSink,
+};
 use drogue_cloud_service_api::{
     kafka::KafkaClientConfig,
     webapp::{self as actix_web},
@@ -18,6 +25,8 @@ use drogue_cloud_service_common::{
     auth::openid,
     auth::pat,
     client::ClientConfig,
+    defaults,
+    reqwest::ClientFactory,
 };
 use lazy_static::lazy_static;
 use prometheus::{labels, opts, register_int_gauge, IntGauge};
@@ -50,6 +59,27 @@ pub struct Config {
 
     #[serde(default)]
     pub http: HttpConfig,
+
+    /// The Kafka config for sending commands, defaults to the `kafka` config.
+    #[serde(default)]
+    pub command_kafka_sink: Option<KafkaClientConfig>,
+
+    #[serde(default = "defaults::check_kafka_topic_ready")]
+    pub check_kafka_topic_ready: bool,
+
+    #[serde(default = "defaults::instance")]
+    pub instance: String,
+
+    #[serde(default)]
+    pub endpoint_pool: ExternalClientPoolConfig,
+
+    /// The maximum number of unacknowledged events per connection, when using explicit acknowledgement.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+const fn default_max_in_flight() -> usize {
+    100
 }
 
 pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()> {
@@ -67,11 +97,35 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
 
     let registry = config.registry.into_client().await?;
 
+    // set up sending commands
+
+    let sender = UpstreamSender::new(
+        config.instance,
+        MessagingSink::from_config(
+            config
+                .command_kafka_sink
+                .unwrap_or_else(|| config.kafka.clone()),
+            config.check_kafka_topic_ready,
+        )?,
+        config.endpoint_pool,
+    )?;
+    let commands = web::Data::new(CommandServices {
+        sender,
+        client: ClientFactory::new().build()?,
+        registry: registry.clone(),
+    });
+
+    let events = web::Data::new(EventServices {
+        registry: registry.clone(),
+        kafka_config: config.kafka.clone(),
+    });
+
     // create and start the service actor
     let service_addr = Service {
         clients: HashMap::default(),
         kafka_config: config.kafka,
-        registry,
+        registry: registry.clone(),
+        max_in_flight: config.max_in_flight,
     }
     .start();
     let service_addr = web::Data::new(service_addr);
@@ -79,7 +133,9 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
     // main server
 
     HttpBuilder::new(config.http, Some(startup.runtime_config()), move |cfg| {
-        cfg.app_data(service_addr.clone());
+        cfg.app_data(service_addr.clone())
+            .app_data(commands.clone())
+            .app_data(events.clone());
         if let Some(authenticator) = authenticator.clone() {
             cfg.app_data(authenticator);
         }
@@ -87,6 +143,23 @@ pub async fn run(config: Config, startup: &mut dyn Startup) -> anyhow::Result<()
             cfg.app_data(user_auth);
         }
 
+        cfg.service(
+            web::scope("/api/v1/apps/{application}/events")
+                .wrap(ApplicationAuthorizer::wrapping(
+                    user_auth.clone(),
+                    Permission::Read,
+                ))
+                .wrap(AuthN::from((
+                    authenticator.clone(),
+                    user_auth.clone().map(pat::Authenticator::new),
+                )))
+                .service(
+                    web::resource("/{channel}")
+                        .route(web::get().to(sse::events_with_channel_filter)),
+                )
+                .service(web::resource("").route(web::get().to(sse::events))),
+        );
+
         cfg.service(
             web::scope("/{application}")
                 .wrap(ApplicationAuthorizer::wrapping(
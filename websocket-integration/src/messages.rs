@@ -1,8 +1,9 @@
+use crate::protocol::ClientMessage;
 use actix::prelude::{Message, Recipient};
 use cloudevents::Event;
-use drogue_client::integration::ws::v1::client;
 use drogue_cloud_integration_common::stream::EventStream;
 use drogue_cloud_service_common::error::ServiceError;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 // Service sends the kafka events in this message to WSHandler
@@ -19,6 +20,8 @@ pub struct Subscribe {
     pub application: String,
     pub consumer_group: Option<String>,
     pub id: Uuid,
+    /// The acknowledgements of the client, when using explicit acknowledgement.
+    pub acks: Option<mpsc::UnboundedReceiver<String>>,
 }
 
 // WsHandler sends this to the service to disconnect from the stream
@@ -47,4 +50,4 @@ pub struct StreamError {
 /// Protocol (client) message
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Protocol(pub client::Message);
+pub struct Protocol(pub ClientMessage);
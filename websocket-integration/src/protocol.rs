@@ -0,0 +1,139 @@
+use drogue_client::integration::ws::v1::client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A message sent by the client.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ClientMessage {
+    /// A message of the standard client protocol.
+    Client(client::Message),
+    /// A request, extending the client protocol.
+    Request(Request),
+}
+
+/// A request of the client, extending the client protocol.
+#[derive(Clone, Debug, Deserialize)]
+pub enum Request {
+    /// Send a command, answered with a [`ServerMessage::CommandOutcome`].
+    SendCommand(SendCommand),
+    /// Acknowledge the event with the provided id, when using explicit acknowledgement.
+    Ack(String),
+}
+
+/// How events of a connection get acknowledged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Acknowledgement {
+    /// Events are acknowledged when they are received from Kafka.
+    #[default]
+    Auto,
+    /// Events are acknowledged by the client.
+    Explicit,
+}
+
+/// Send a command to a device of the application.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendCommand {
+    /// An id chosen by the client, returned with the outcome.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub device: String,
+    pub command: String,
+    /// The payload of the command.
+    ///
+    /// A string is sent as is, any other value is sent as JSON.
+    #[serde(default)]
+    pub payload: Option<Value>,
+    /// The content type of the payload.
+    ///
+    /// Defaults to `application/json` for non-string payloads.
+    #[serde(default)]
+    pub content_type: Option<String>,
+}
+
+/// A message sent by the server, in addition to the events.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ServerMessage {
+    CommandOutcome(CommandOutcome),
+}
+
+/// The delivery outcome of a command.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandOutcome {
+    /// The id of the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// If the command was accepted for delivery.
+    pub accepted: bool,
+    /// The HTTP status code, as the command endpoint would have returned it.
+    pub status: u16,
+    /// The reason, in case the command was not accepted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_client_messages() {
+        let msg: ClientMessage = serde_json::from_value(json!({
+            "SendCommand": {
+                "id": "1",
+                "device": "device1",
+                "command": "set-temp",
+                "payload": {"temp": 21},
+            }
+        }))
+        .unwrap();
+
+        match msg {
+            ClientMessage::Request(Request::SendCommand(command)) => {
+                assert_eq!(command.id.as_deref(), Some("1"));
+                assert_eq!(command.device, "device1");
+                assert_eq!(command.command, "set-temp");
+                assert_eq!(command.payload, Some(json!({"temp": 21})));
+                assert_eq!(command.content_type, None);
+            }
+            other => panic!("Unexpected message: {other:?}"),
+        }
+
+        let msg: ClientMessage = serde_json::from_value(json!({
+            "RefreshAccessToken": "token",
+        }))
+        .unwrap();
+        assert!(matches!(msg, ClientMessage::Client(_)));
+
+        let msg: ClientMessage = serde_json::from_value(json!({
+            "Ack": "event1",
+        }))
+        .unwrap();
+        assert!(matches!(msg, ClientMessage::Request(Request::Ack(id)) if id == "event1"));
+    }
+
+    #[test]
+    fn test_serialize_outcome() {
+        let msg = ServerMessage::CommandOutcome(CommandOutcome {
+            id: Some("1".into()),
+            accepted: true,
+            status: 202,
+            reason: None,
+        });
+
+        assert_eq!(
+            serde_json::to_value(msg).unwrap(),
+            json!({
+                "CommandOutcome": {
+                    "id": "1",
+                    "accepted": true,
+                    "status": 202,
+                }
+            })
+        );
+    }
+}
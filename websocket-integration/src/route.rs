@@ -1,26 +1,36 @@
-use crate::{service::Service, wshandler::WsHandler};
+use crate::{
+    commands::{CommandSender, CommandServices},
+    protocol::Acknowledgement,
+    service::Service,
+    wshandler::WsHandler,
+};
 use actix::Addr;
 use actix_web::{
     web::{self, Payload},
     Error, HttpRequest, HttpResponse,
 };
 use actix_web_actors::ws;
-use drogue_cloud_service_api::webapp as actix_web;
+use drogue_cloud_service_api::{auth::user::UserInformation, webapp as actix_web};
 use drogue_cloud_service_common::actix_auth::authentication::AuthenticatedUntil;
 use serde::Deserialize;
 
 #[derive(Deserialize, Debug)]
-pub struct GroupId {
+pub struct ConnectionQuery {
     group_id: Option<String>,
+    #[serde(default)]
+    ack: Acknowledgement,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_connection(
     req: HttpRequest,
     stream: Payload,
     application: web::Path<String>,
     service_addr: web::Data<Addr<Service>>,
-    web::Query(group_id): web::Query<GroupId>,
+    web::Query(query): web::Query<ConnectionQuery>,
     auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    user: UserInformation,
+    commands: web::Data<CommandServices>,
 ) -> Result<HttpResponse, Error> {
     let application = application.into_inner();
 
@@ -30,18 +40,24 @@ pub async fn start_connection(
         application,
         None,
         service_addr,
-        group_id.group_id,
+        query.group_id,
+        query.ack,
         auth_expiration,
+        user,
+        commands,
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_connection_with_channel_filter(
     req: HttpRequest,
     stream: Payload,
     params: web::Path<(String, String)>,
     service_addr: web::Data<Addr<Service>>,
-    web::Query(group_id): web::Query<GroupId>,
+    web::Query(query): web::Query<ConnectionQuery>,
     auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    user: UserInformation,
+    commands: web::Data<CommandServices>,
 ) -> Result<HttpResponse, Error> {
     let (application, channel) = params.into_inner();
 
@@ -51,11 +67,15 @@ pub async fn start_connection_with_channel_filter(
         application,
         Some(channel),
         service_addr,
-        group_id.group_id,
+        query.group_id,
+        query.ack,
         auth_expiration,
+        user,
+        commands,
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn start_websocket(
     req: HttpRequest,
     stream: Payload,
@@ -63,12 +83,21 @@ fn start_websocket(
     channel: Option<String>,
     service_addr: web::Data<Addr<Service>>,
     group_id: Option<String>,
+    ack: Acknowledgement,
     auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    user: UserInformation,
+    commands: web::Data<CommandServices>,
 ) -> Result<HttpResponse, Error> {
     let auth_expiration = auth_expiration.map(|e| e.into_inner().0);
 
     let authenticator = req.app_data().cloned();
-    let user_auth = req.app_data().cloned();
+    let user_auth: Option<drogue_client::user::v1::Client> = req.app_data().cloned();
+
+    let commands = CommandSender {
+        services: commands.get_ref().clone(),
+        user_auth: user_auth.clone(),
+        user,
+    };
 
     log::debug!(
         "Auth state - authenticator: {}, userAuth: {}",
@@ -81,10 +110,12 @@ fn start_websocket(
         application,
         group_id,
         channel,
+        ack,
         service_addr.get_ref().clone(),
         auth_expiration,
         authenticator,
         user_auth,
+        commands,
     );
 
     ws::start(ws, &req, stream)
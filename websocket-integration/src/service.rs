@@ -1,12 +1,18 @@
 use crate::messages::{Disconnect, StreamError, Subscribe, WsEvent};
 use actix::{prelude::*, AsyncContext, SpawnHandle, WrapFuture};
 use anyhow::{anyhow, Result};
+use cloudevents::{AttributesReader, Event};
 use drogue_client::registry::v1::Client;
-use drogue_cloud_integration_common::stream::{EventStream, EventStreamConfig};
+use drogue_cloud_event_common::stream::{AckMode, CustomAck, Offset};
+use drogue_cloud_integration_common::{
+    inflight::InFlight,
+    stream::{EventStream, EventStreamConfig},
+};
 use drogue_cloud_service_api::kafka::{KafkaClientConfig, KafkaConfigExt, KafkaEventType};
 use drogue_cloud_service_common::error::ServiceError;
 use futures::StreamExt;
 use std::collections::HashMap;
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 // Service Actor.
@@ -15,6 +21,8 @@ pub struct Service {
     pub clients: HashMap<Uuid, Stream>,
     pub kafka_config: KafkaClientConfig,
     pub registry: Client,
+    /// The maximum number of unacknowledged events per connection, when using explicit acknowledgement.
+    pub max_in_flight: usize,
 }
 
 impl Actor for Service {
@@ -31,25 +39,26 @@ pub struct Stream {
 impl Handler<Subscribe> for Service {
     type Result = ();
 
-    fn handle(&mut self, msg: Subscribe, ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, mut msg: Subscribe, ctx: &mut Context<Self>) -> Self::Result {
         let app = msg.application.clone();
         let addr = msg.addr.clone();
         let registry_client = self.registry.clone();
         let kafka = self.kafka_config.clone();
         let consumer_group = msg.consumer_group.clone();
+        let acks = msg.acks.take();
+        let max_in_flight = self.max_in_flight;
 
         let fut = async move {
-            // set up a stream
-            let stream =
-                Service::get_stream(registry_client, &kafka, app.clone(), consumer_group).await;
-            // run the stream
-            let _ = match stream {
-                Ok(s) => Service::run_stream(s, addr.clone(), app.clone().as_str()).await,
-                Err(err) => {
-                    log::warn!("Stream failed: {err}");
-                    Err(anyhow!(err))
-                }
-            };
+            let _ = Service::run(
+                registry_client,
+                &kafka,
+                app,
+                consumer_group,
+                addr,
+                acks,
+                max_in_flight,
+            )
+            .await;
         }
         .into_actor(self);
         let fut = fut.map(move |_, _, ctx| {
@@ -122,12 +131,43 @@ impl Handler<StreamError> for Service {
 }
 
 impl Service {
-    async fn get_stream(
+    /// Set up and run the stream for a client.
+    async fn run(
+        registry: Client,
+        kafka_config: &KafkaClientConfig,
+        application: String,
+        group_id: Option<String>,
+        recipient: Recipient<WsEvent>,
+        acks: Option<mpsc::UnboundedReceiver<String>>,
+        max_in_flight: usize,
+    ) -> Result<(), anyhow::Error> {
+        let stream =
+            Service::get_stream(registry, kafka_config, application.clone(), group_id, &[])
+                .await
+                .map_err(Self::stream_failed)?;
+
+        match acks {
+            None => Service::run_stream(stream, recipient, &application).await,
+            Some(acks) => {
+                Service::run_acked_stream(stream, recipient, acks, max_in_flight, &application)
+                    .await
+            }
+        }
+    }
+
+    fn stream_failed(err: ServiceError) -> anyhow::Error {
+        log::warn!("Stream failed: {err}");
+        anyhow!(err)
+    }
+
+    /// Create an event stream for an application, continuing after the provided offsets.
+    pub(crate) async fn get_stream<Ack: AckMode>(
         registry: Client,
         kafka_config: &KafkaClientConfig,
         application: String,
         group_id: Option<String>,
-    ) -> Result<EventStream<'static>, ServiceError> {
+        offsets: &[Offset],
+    ) -> Result<EventStream<'static, Ack>, ServiceError> {
         // log the request
         log::debug!(
             "Request to attach to app stream: {} (group: {:?})",
@@ -142,13 +182,16 @@ impl Service {
             .ok_or_else(|| ServiceError::InternalError(String::from("Cannot find application")))?;
 
         // create stream
-        let stream = EventStream::new(EventStreamConfig {
-            kafka: app_res
-                .kafka_target(KafkaEventType::Events, kafka_config)
-                .map_err(|_| ServiceError::InternalError("This should be infallible".into()))?
-                .into(),
-            consumer_group: group_id.map(|group_id| format!("{application}.{group_id}")),
-        })
+        let stream = EventStream::resume(
+            EventStreamConfig {
+                kafka: app_res
+                    .kafka_target(KafkaEventType::Events, kafka_config)
+                    .map_err(|_| ServiceError::InternalError("This should be infallible".into()))?
+                    .into(),
+                consumer_group: group_id.map(|group_id| format!("{application}.{group_id}")),
+            },
+            offsets,
+        )
         .map_err(|err| {
             log::info!("Failed to subscribe to Kafka topic: {}", err);
             ServiceError::InternalError("Failed to subscribe to Kafka topic".to_string())
@@ -159,19 +202,22 @@ impl Service {
         Ok(stream)
     }
 
+    /// Run a stream, acknowledging events once they were sent to the client.
     async fn run_stream(
-        mut stream: EventStream<'_>,
+        mut stream: EventStream<'_, CustomAck>,
         recipient: Recipient<WsEvent>,
         application: &str,
     ) -> Result<(), anyhow::Error> {
         log::debug!("Running stream {:?}", application);
 
         // run event stream
-        while let Some(event) = stream.next().await {
-            log::debug!("Topic: {} - Event: {:?}", application, event);
+        while let Some(handle) = stream.next().await {
+            let handle = handle?;
+            log::debug!("Topic: {} - Event: {:?}", application, *handle);
 
             // Send the event as an Actor message
-            recipient.send(WsEvent(event?)).await?;
+            recipient.send(WsEvent((*handle).clone())).await?;
+            stream.ack(handle)?;
 
             log::debug!("Sent message - go back to sleep");
         }
@@ -180,6 +226,51 @@ impl Service {
 
         Err(anyhow!("Stream Error"))
     }
+
+    /// Run a stream, for which the client acknowledges the events.
+    ///
+    /// No more events are sent while the maximum number of unacknowledged events is reached.
+    async fn run_acked_stream(
+        mut stream: EventStream<'_, CustomAck>,
+        recipient: Recipient<WsEvent>,
+        mut acks: mpsc::UnboundedReceiver<String>,
+        max_in_flight: usize,
+        application: &str,
+    ) -> Result<(), anyhow::Error> {
+        log::debug!("Running acknowledged stream {:?}", application);
+
+        let mut in_flight = InFlight::new(max_in_flight);
+
+        loop {
+            tokio::select! {
+                next = stream.next(), if !in_flight.is_full() => {
+                    let handle = match next {
+                        Some(handle) => handle?,
+                        None => break,
+                    };
+                    log::debug!("Topic: {} - Event: {:?}", application, *handle);
+
+                    let event: Event = (*handle).clone();
+                    let id = event.id().to_string();
+                    recipient.send(WsEvent(event)).await?;
+                    in_flight.push(id, handle.replace(()));
+                }
+                ack = acks.recv() => {
+                    let id = match ack {
+                        Some(id) => id,
+                        None => break,
+                    };
+                    for handle in in_flight.ack(&id) {
+                        stream.ack(handle)?;
+                    }
+                }
+            }
+        }
+
+        log::info!("Event stream closed");
+
+        Err(anyhow!("Stream Error"))
+    }
 }
 
 // todo add tests
@@ -0,0 +1,293 @@
+use crate::service::Service;
+use actix_web::{
+    error::ErrorInternalServerError,
+    http::header,
+    web::{self, Bytes, BytesMut},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use cloudevents::{AttributesReader, Event};
+use drogue_client::registry;
+use drogue_cloud_event_common::stream::{CustomAck, EventStreamError, Handle, Offset};
+use drogue_cloud_integration_common::stream::EventStream;
+use drogue_cloud_service_api::{kafka::KafkaClientConfig, webapp as actix_web};
+use drogue_cloud_service_common::{
+    actix_auth::authentication::AuthenticatedUntil, error::ServiceError,
+};
+use futures::{future, stream, Stream, StreamExt};
+use serde::Deserialize;
+use std::{collections::BTreeMap, time::Duration};
+
+/// The interval of sending comments on an idle stream, keeping the connection open through proxies.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The services required for streaming events, shared by all connections.
+#[derive(Clone)]
+pub struct EventServices {
+    pub registry: registry::v1::Client,
+    pub kafka_config: KafkaClientConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StreamQuery {
+    group_id: Option<String>,
+    /// The id of the last received event, for clients which can't set the `Last-Event-ID` header.
+    last_event_id: Option<String>,
+}
+
+pub async fn events(
+    req: HttpRequest,
+    application: web::Path<String>,
+    web::Query(query): web::Query<StreamQuery>,
+    auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    services: web::Data<EventServices>,
+) -> Result<HttpResponse, ServiceError> {
+    let application = application.into_inner();
+
+    start_stream(req, application, None, query, auth_expiration, services).await
+}
+
+pub async fn events_with_channel_filter(
+    req: HttpRequest,
+    params: web::Path<(String, String)>,
+    web::Query(query): web::Query<StreamQuery>,
+    auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    services: web::Data<EventServices>,
+) -> Result<HttpResponse, ServiceError> {
+    let (application, channel) = params.into_inner();
+
+    start_stream(
+        req,
+        application,
+        Some(channel),
+        query,
+        auth_expiration,
+        services,
+    )
+    .await
+}
+
+async fn start_stream(
+    req: HttpRequest,
+    application: String,
+    channel: Option<String>,
+    query: StreamQuery,
+    auth_expiration: Option<web::ReqData<AuthenticatedUntil>>,
+    services: web::Data<EventServices>,
+) -> Result<HttpResponse, ServiceError> {
+    let auth_expiration = auth_expiration.map(|e| e.into_inner().0);
+
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok())
+        .map(ToString::to_string)
+        .or(query.last_event_id);
+    let positions = last_event_id
+        .as_deref()
+        .map(parse_event_id)
+        .unwrap_or_default();
+
+    // a consumer group continues with its committed offsets
+    let offsets = match query.group_id {
+        Some(_) => vec![],
+        None => positions
+            .iter()
+            .map(|(partition, offset)| Offset {
+                partition: *partition,
+                offset: *offset,
+            })
+            .collect(),
+    };
+
+    log::debug!("Starting event stream - resume: {offsets:?}");
+
+    let stream = Service::get_stream(
+        services.registry.clone(),
+        &services.kafka_config,
+        application,
+        query.group_id,
+        &offsets,
+    )
+    .await?;
+
+    let state = StreamState {
+        stream,
+        channel,
+        auth_expiration,
+        positions,
+        done: false,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(state.into_stream()))
+}
+
+struct StreamState {
+    stream: EventStream<'static, CustomAck>,
+    channel: Option<String>,
+    auth_expiration: Option<DateTime<Utc>>,
+    /// The offset of the last event of each partition.
+    positions: BTreeMap<i32, i64>,
+    done: bool,
+}
+
+enum Step {
+    Expired,
+    KeepAlive,
+    Next(Option<Result<Handle<'static, Event>, EventStreamError>>),
+}
+
+impl StreamState {
+    fn into_stream(self) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        stream::unfold(self, |mut state| async move {
+            if state.done {
+                return None;
+            }
+            let frame = state.next_frame().await;
+            Some((frame, state))
+        })
+    }
+
+    async fn next_frame(&mut self) -> Result<Bytes, actix_web::Error> {
+        loop {
+            let expired = expired(self.auth_expiration);
+
+            let step = tokio::select! {
+                _ = expired => Step::Expired,
+                _ = tokio::time::sleep(KEEP_ALIVE_INTERVAL) => Step::KeepAlive,
+                next = self.stream.next() => Step::Next(next),
+            };
+
+            match step {
+                Step::Expired => {
+                    log::info!("Closing event stream: JWT token expired");
+                    self.done = true;
+                    return Ok(Bytes::from_static(
+                        b"event: expired\ndata: JWT token expired\n\n",
+                    ));
+                }
+                Step::KeepAlive => return Ok(Bytes::from_static(b": keep-alive\n\n")),
+                Step::Next(None) => {
+                    log::info!("Event stream closed");
+                    self.done = true;
+                    return Err(ErrorInternalServerError("Event stream closed"));
+                }
+                Step::Next(Some(Err(err))) => {
+                    log::info!("Failed to process event: {err}");
+                    self.done = true;
+                    return Err(ErrorInternalServerError(err));
+                }
+                Step::Next(Some(Ok(handle))) => {
+                    let frame = self.frame(&handle)?;
+                    if let Err(err) = self.stream.ack(handle) {
+                        log::info!("Failed to acknowledge event: {err}");
+                    }
+                    if let Some(frame) = frame {
+                        return Ok(frame);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Create the frame for an event, `None` if the event is filtered out.
+    fn frame(&mut self, handle: &Handle<Event>) -> Result<Option<Bytes>, actix_web::Error> {
+        // record the position of filtered events too, so that a resumed stream skips them
+        if let Some(offset) = handle.offset() {
+            self.positions.insert(offset.partition, offset.offset);
+        }
+
+        if let Some(channel) = &self.channel {
+            if handle.subject() != Some(channel.as_str()) {
+                return Ok(None);
+            }
+        }
+
+        let event = serde_json::to_string(&**handle).map_err(ErrorInternalServerError)?;
+        let id = (!self.positions.is_empty()).then(|| format_event_id(&self.positions));
+
+        Ok(Some(make_frame(id.as_deref(), &event)))
+    }
+}
+
+/// Wait until the token expired, forever if it doesn't expire.
+async fn expired(expiration: Option<DateTime<Utc>>) {
+    match expiration {
+        Some(expiration) => {
+            let remaining = (expiration - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(remaining).await
+        }
+        None => future::pending().await,
+    }
+}
+
+/// Create an SSE frame, from an event already in string format.
+fn make_frame(id: Option<&str>, event: &str) -> Bytes {
+    let mut r = BytesMut::new();
+
+    if let Some(id) = id {
+        r.extend(b"id: ");
+        r.extend(id.as_bytes());
+        r.extend(b"\n");
+    }
+    r.extend(b"data: ");
+    r.extend(event.as_bytes());
+    r.extend(b"\n\n");
+
+    r.freeze()
+}
+
+/// Encode the offsets of all partitions as event id, e.g. `0:12,1:7`.
+fn format_event_id(positions: &BTreeMap<i32, i64>) -> String {
+    positions
+        .iter()
+        .map(|(partition, offset)| format!("{partition}:{offset}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Parse the offsets from an event id, skipping invalid entries.
+fn parse_event_id(id: &str) -> BTreeMap<i32, i64> {
+    id.split(',')
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parsed = entry.split_once(':').and_then(|(partition, offset)| {
+                Some((partition.parse().ok()?, offset.parse().ok()?))
+            });
+            if parsed.is_none() {
+                log::debug!("Skipping invalid event id entry: {entry}");
+            }
+            parsed
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_id() {
+        let positions = parse_event_id("0:12,1:7");
+        assert_eq!(positions, BTreeMap::from([(0, 12), (1, 7)]));
+        assert_eq!(format_event_id(&positions), "0:12,1:7");
+
+        assert_eq!(parse_event_id(""), BTreeMap::new());
+        assert_eq!(parse_event_id("0:12,foo,1:bar"), BTreeMap::from([(0, 12)]));
+    }
+
+    #[test]
+    fn test_frame() {
+        assert_eq!(
+            make_frame(Some("0:1"), r#"{"id":"1"}"#),
+            Bytes::from_static(b"id: 0:1\ndata: {\"id\":\"1\"}\n\n")
+        );
+        assert_eq!(
+            make_frame(None, r#"{"id":"1"}"#),
+            Bytes::from_static(b"data: {\"id\":\"1\"}\n\n")
+        );
+    }
+}
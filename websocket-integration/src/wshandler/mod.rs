@@ -1,5 +1,7 @@
 use crate::{
+    commands::CommandSender,
     messages::{Disconnect, Protocol, StreamError, Subscribe, WsEvent},
+    protocol::{Acknowledgement, ClientMessage, Request, ServerMessage},
     service::Service,
     CONNECTIONS_COUNTER,
 };
@@ -19,6 +21,7 @@ use drogue_cloud_service_common::auth::openid::{self, CustomClaims};
 use lazy_static::lazy_static;
 use prometheus::{register_int_counter_vec, IntCounterVec};
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
 use uuid::Uuid;
 
 const AUTH_CHECK_INTERVAL: Duration = Duration::from_secs(90);
@@ -44,7 +47,8 @@ struct AuthContext {
 }
 
 enum AuthOutcome {
-    Allow(DateTime<Utc>),
+    /// Allowed, until the token expires.
+    Allow(DateTime<Utc>, UserInformation),
     Deny,
 }
 
@@ -70,6 +74,7 @@ impl AuthContext {
                 outcome: authz::Outcome::Allow,
             } => Ok(AuthOutcome::Allow(
                 Utc.timestamp(token.standard_claims().exp, 0),
+                user,
             )),
             authz::AuthorizationResponse {
                 outcome: authz::Outcome::Deny,
@@ -86,6 +91,10 @@ pub struct WsHandler {
     group_id: Option<String>,
     /// the optional channel filter
     channel: Option<String>,
+    /// how events get acknowledged
+    ack: Acknowledgement,
+    /// forwarding acknowledgements to the stream, when using explicit acknowledgement
+    acks: Option<mpsc::UnboundedSender<String>>,
     /// to exit the actor if the client was disconnected
     heartbeat: Instant,
     service_addr: Addr<Service>,
@@ -94,17 +103,22 @@ pub struct WsHandler {
     /// It's optional, as some clients will use an access token, which are valid indefinitely
     auth_expiration: Option<DateTime<Utc>>,
     auth_context: Option<AuthContext>,
+    /// sending commands on behalf of the user
+    commands: CommandSender,
 }
 
 impl WsHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         application: String,
         group_id: Option<String>,
         channel: Option<String>,
+        ack: Acknowledgement,
         service_addr: Addr<Service>,
         auth_expiration: Option<DateTime<Utc>>,
         authenticator: Option<openid::Authenticator>,
         user_auth: Option<user::v1::Client>,
+        commands: CommandSender,
     ) -> WsHandler {
         CONNECTIONS_COUNTER.inc();
 
@@ -121,11 +135,14 @@ impl WsHandler {
             application,
             group_id,
             channel,
+            ack,
+            acks: None,
             heartbeat: Instant::now(),
             service_addr,
             id: Uuid::new_v4(),
             auth_expiration,
             auth_context,
+            commands,
         }
     }
 
@@ -159,7 +176,7 @@ impl WsHandler {
     /// Handle the parse result of a client protocol message.
     fn handle_protocol_message(
         ctx: &mut ws::WebsocketContext<Self>,
-        result: Result<client::Message, serde_json::Error>,
+        result: Result<ClientMessage, serde_json::Error>,
     ) {
         match result {
             Ok(msg) => ctx.address().do_send(Protocol(msg)),
@@ -192,6 +209,14 @@ impl Actor for WsHandler {
         // Address of self, the WSHandler actor
         let addr: Recipient<WsEvent> = ctx.address().recipient();
         let err_addr: Recipient<StreamError> = ctx.address().recipient();
+        let acks = match self.ack {
+            Acknowledgement::Auto => None,
+            Acknowledgement::Explicit => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                self.acks = Some(tx);
+                Some(rx)
+            }
+        };
         // Send a message to ask service to subscribe to Kafka stream.
         self.service_addr
             .send(Subscribe {
@@ -200,6 +225,7 @@ impl Actor for WsHandler {
                 application: self.application.clone(),
                 consumer_group: self.group_id.clone(),
                 id: self.id,
+                acks,
             })
             // We need to access the context when handling the future so we wrap it into an ActorFuture
             .into_actor(self)
@@ -243,14 +269,11 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsHandler {
             }
             Ok(ws::Message::Binary(data)) => {
                 INCOMING_MESSAGE.with_label_values(&["binary"]).inc();
-                Self::handle_protocol_message(
-                    ctx,
-                    serde_json::from_slice::<client::Message>(&data),
-                );
+                Self::handle_protocol_message(ctx, serde_json::from_slice::<ClientMessage>(&data));
             }
             Ok(ws::Message::Text(data)) => {
                 INCOMING_MESSAGE.with_label_values(&["text"]).inc();
-                Self::handle_protocol_message(ctx, serde_json::from_str::<client::Message>(&data));
+                Self::handle_protocol_message(ctx, serde_json::from_str::<ClientMessage>(&data));
             }
             Ok(ws::Message::Close(reason)) => {
                 INCOMING_MESSAGE.with_label_values(&["close"]).inc();
@@ -281,6 +304,10 @@ impl Handler<WsEvent> for WsHandler {
     fn handle(&mut self, msg: WsEvent, ctx: &mut Self::Context) {
         if let Some(channel) = &self.channel {
             if msg.0.subject() != Some(channel.as_str()) {
+                // the client will never see the event, so it can't acknowledge it
+                if let Some(acks) = &self.acks {
+                    let _ = acks.send(msg.0.id().to_string());
+                }
                 return;
             }
         }
@@ -315,7 +342,35 @@ impl Handler<Protocol> for WsHandler {
 
     fn handle(&mut self, msg: Protocol, _ctx: &mut Self::Context) -> Self::Result {
         match msg.0 {
-            client::Message::RefreshAccessToken(token) => {
+            ClientMessage::Request(Request::SendCommand(request)) => {
+                let commands = self.commands.clone();
+                let application = self.application.clone();
+
+                Box::pin(
+                    async move { commands.send(application, request).await }
+                        .into_actor(self)
+                        .map(|outcome, _, ctx| {
+                            match serde_json::to_string(&ServerMessage::CommandOutcome(outcome)) {
+                                Ok(msg) => ctx.text(msg),
+                                Err(err) => {
+                                    log::warn!("Could not serialize command outcome: {err}")
+                                }
+                            }
+                        }),
+                )
+            }
+            ClientMessage::Request(Request::Ack(id)) => {
+                match &self.acks {
+                    Some(acks) => {
+                        let _ = acks.send(id);
+                    }
+                    None => {
+                        log::debug!("Ignoring acknowledgement, not using explicit acknowledgement")
+                    }
+                }
+                Box::pin(fut::ready(()))
+            }
+            ClientMessage::Client(client::Message::RefreshAccessToken(token)) => {
                 let auth_context = self.auth_context.clone();
 
                 Box::pin(
@@ -331,7 +386,7 @@ impl Handler<Protocol> for WsHandler {
                         match result {
                             Ok(outcome) => {
                                 match outcome {
-                                    AuthOutcome::Allow(auth_expiration) => {
+                                    AuthOutcome::Allow(auth_expiration, user) => {
                                         // set new token
                                         log::info!(
                                             "Updating token expiration: {:?} -> {:?}",
@@ -339,6 +394,8 @@ impl Handler<Protocol> for WsHandler {
                                             auth_expiration
                                         );
                                         act.auth_expiration = Some(auth_expiration);
+                                        // commands are sent on behalf of the refreshed user
+                                        act.commands.user = user;
                                     }
                                     AuthOutcome::Deny => {
                                         ctx.close(Some(CloseReason {